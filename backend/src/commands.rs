@@ -0,0 +1,300 @@
+use futures_util::StreamExt;
+use mail_parser::MessageParser;
+use mailcrab::{Error, MailMessageMetadata, Result, send_mail};
+use serde::Deserialize;
+use std::{
+    io::Write,
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    path::{Path, PathBuf},
+};
+use tokio_tungstenite::tungstenite;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+use crate::{
+    mbox::{append_message, split_messages},
+    settings::{Command, ExportFormat, Settings},
+};
+
+/// a server listening on all interfaces is reached on the loopback interface
+fn connect_address(address: SocketAddr) -> SocketAddr {
+    match address.ip() {
+        IpAddr::V4(ip) if ip.is_unspecified() => (Ipv4Addr::LOCALHOST, address.port()).into(),
+        IpAddr::V6(ip) if ip.is_unspecified() => (Ipv6Addr::LOCALHOST, address.port()).into(),
+        _ => address,
+    }
+}
+
+/// base URL of the web server, including the path prefix
+fn base_url(settings: &Settings, scheme: &str) -> String {
+    let prefix = settings.prefix.as_deref().unwrap_or_default();
+    let prefix = prefix.trim_matches('/');
+    let address = connect_address(settings.http_address());
+
+    match prefix {
+        "" => format!("{scheme}://{address}"),
+        prefix => format!("{scheme}://{address}/{prefix}"),
+    }
+}
+
+fn http_error(e: reqwest::Error) -> Error {
+    Error::WebServer(e.to_string())
+}
+
+/// a test message, used when no files are provided
+fn sample_message() -> Vec<u8> {
+    let id = Uuid::new_v4();
+    let date = chrono::Local::now().to_rfc2822();
+
+    format!(
+        "From: MailCrab <sender@example.com>\r\n\
+         To: Developer <developer@example.com>\r\n\
+         Subject: Sample message {id}\r\n\
+         Date: {date}\r\n\
+         Message-ID: <{id}@mailcrab>\r\n\
+         Content-Type: text/plain; charset=utf-8\r\n\
+         \r\n\
+         This message was sent using the MailCrab command line interface.\r\n"
+    )
+    .into_bytes()
+}
+
+/// send a message over SMTP, the envelope defaults to the addresses in the headers
+async fn deliver(
+    settings: &Settings,
+    raw: &[u8],
+    from: Option<&str>,
+    to: &[String],
+) -> Result<Vec<String>> {
+    let headers = MessageParser::new().parse_headers(raw);
+    let header_addresses = |recipients: bool| -> Vec<String> {
+        let Some(headers) = &headers else {
+            return Vec::new();
+        };
+
+        let addresses = if recipients {
+            vec![headers.to(), headers.cc(), headers.bcc()]
+        } else {
+            vec![headers.from()]
+        };
+
+        addresses
+            .into_iter()
+            .flatten()
+            .flat_map(|address| address.iter())
+            .filter_map(|addr| addr.address())
+            .map(ToOwned::to_owned)
+            .collect()
+    };
+
+    let from = match from {
+        Some(from) => from.to_owned(),
+        None => header_addresses(false)
+            .into_iter()
+            .next()
+            .unwrap_or_default(),
+    };
+    let to = match to {
+        [] => header_addresses(true),
+        to => to.to_vec(),
+    };
+
+    if to.is_empty() {
+        return Err(Error::Smtp("message has no recipients".to_owned()));
+    }
+
+    let address = connect_address(settings.smtp_address()).to_string();
+    send_mail(&address, &from, &to, raw).await?;
+
+    Ok(to)
+}
+
+async fn send(
+    settings: &Settings,
+    files: &[PathBuf],
+    from: Option<&str>,
+    to: &[String],
+) -> Result<()> {
+    if files.is_empty() {
+        let recipients = deliver(settings, &sample_message(), from, to).await?;
+        info!("Sent sample message to {}", recipients.join(", "));
+    }
+
+    for file in files {
+        let raw = std::fs::read(file)?;
+        let recipients = deliver(settings, &raw, from, to).await?;
+        info!("Sent {} to {}", file.display(), recipients.join(", "));
+    }
+
+    Ok(())
+}
+
+async fn export(settings: &Settings, format: ExportFormat, output: Option<&Path>) -> Result<()> {
+    let client = reqwest::Client::new();
+    let base = base_url(settings, "http");
+
+    let mut messages: Vec<MailMessageMetadata> = client
+        .get(format!("{base}/api/messages"))
+        .send()
+        .await
+        .and_then(|response| response.error_for_status())
+        .map_err(http_error)?
+        .json()
+        .await
+        .map_err(http_error)?;
+    messages.sort_by_key(|message| message.time);
+
+    if format == ExportFormat::Eml {
+        let Some(directory) = output else {
+            return Err(Error::Config(
+                "an output directory is required for the eml format".to_owned(),
+            ));
+        };
+        std::fs::create_dir_all(directory)?;
+    }
+
+    let mut mbox = Vec::new();
+
+    for message in &messages {
+        let raw = client
+            .get(format!("{base}/api/message/{}/raw", message.id))
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(http_error)?
+            .bytes()
+            .await
+            .map_err(http_error)?;
+
+        match (format, output) {
+            (ExportFormat::Eml, Some(directory)) => {
+                std::fs::write(directory.join(format!("{}.eml", message.id)), &raw)?;
+            }
+            _ => append_message(&mut mbox, &message.envelope_from, message.time, &raw),
+        }
+    }
+
+    if format == ExportFormat::Mbox {
+        match output {
+            Some(file) => std::fs::write(file, &mbox)?,
+            None => std::io::stdout().write_all(&mbox)?,
+        }
+    }
+
+    info!("Exported {} messages", messages.len());
+
+    Ok(())
+}
+
+async fn import(settings: &Settings, path: &Path) -> Result<()> {
+    // (envelope sender, message) pairs, the sender is only known for mbox files
+    let mut messages: Vec<(Option<String>, Vec<u8>)> = Vec::new();
+
+    if path.is_dir() {
+        let mut files = std::fs::read_dir(path)?
+            .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+            .filter(|file| file.is_file())
+            .collect::<Vec<_>>();
+        files.sort();
+
+        for file in files {
+            messages.push((None, std::fs::read(file)?));
+        }
+    } else {
+        let contents = std::fs::read(path)?;
+
+        if contents.starts_with(b"From ") {
+            messages.extend(
+                split_messages(&contents)
+                    .into_iter()
+                    .map(|message| (message.envelope_from, message.raw)),
+            );
+        } else {
+            messages.push((None, contents));
+        }
+    }
+
+    let mut imported = 0;
+    for (index, (from, raw)) in messages.iter().enumerate() {
+        match deliver(settings, raw, from.as_deref(), &[]).await {
+            Ok(_) => imported += 1,
+            Err(e) => warn!("Could not import message {}: {e}", index + 1),
+        }
+    }
+
+    info!("Imported {imported} of {} messages", messages.len());
+
+    Ok(())
+}
+
+/// the fields of the message metadata that are printed
+#[derive(Deserialize)]
+struct Summary {
+    from: Contact,
+    to: Vec<Contact>,
+    subject: String,
+    date: String,
+}
+
+#[derive(Deserialize)]
+struct Contact {
+    name: Option<String>,
+    email: Option<String>,
+}
+
+impl std::fmt::Display for Contact {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match (&self.name, &self.email) {
+            (Some(name), Some(email)) => write!(f, "{name} <{email}>"),
+            (None, Some(email)) => write!(f, "{email}"),
+            (Some(name), None) => write!(f, "{name}"),
+            (None, None) => write!(f, "-"),
+        }
+    }
+}
+
+async fn tail(settings: &Settings) -> Result<()> {
+    let url = format!("{}/ws", base_url(settings, "ws"));
+    let (mut socket, _) = tokio_tungstenite::connect_async(url.as_str())
+        .await
+        .map_err(|e| Error::WebServer(e.to_string()))?;
+
+    info!("Waiting for new messages on {url}");
+
+    while let Some(message) = socket.next().await {
+        let tungstenite::Message::Text(text) =
+            message.map_err(|e| Error::WebServer(e.to_string()))?
+        else {
+            continue;
+        };
+
+        let Ok(summary) = serde_json::from_str::<Summary>(&text) else {
+            continue;
+        };
+
+        let to = summary
+            .to
+            .iter()
+            .map(ToString::to_string)
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        println!(
+            "{}  {} -> {}  {}",
+            summary.date, summary.from, to, summary.subject
+        );
+    }
+
+    Ok(())
+}
+
+/// run a subcommand that interacts with a running MailCrab
+pub(crate) async fn execute(command: Command, settings: &Settings) -> Result<()> {
+    match command {
+        Command::Serve => Ok(()),
+        Command::Send { files, from, to } => send(settings, &files, from.as_deref(), &to).await,
+        Command::Export { format, output } => export(settings, format, output.as_deref()).await,
+        Command::Import { path } => import(settings, &path).await,
+        Command::Tail => tail(settings).await,
+    }
+}
@@ -0,0 +1,131 @@
+use mailcrab::{Error, Result, SmtpConfig};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+use tokio::time::Duration;
+use tracing::info;
+
+use crate::AppState;
+
+/// settings that can be changed while MailCrab is running, using the HTTP API
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub(crate) struct RuntimeConfig {
+    /// remove messages older than this number of seconds, 0 keeps messages forever
+    pub(crate) retention_period: u64,
+    #[serde(flatten)]
+    pub(crate) smtp: SmtpConfig,
+}
+
+/// check that the relay for delivery status notifications is formatted as host:port
+pub(crate) fn validate_relay(relay: &str) -> Result<()> {
+    if !relay
+        .rsplit_once(':')
+        .is_some_and(|(host, port)| !host.is_empty() && port.parse::<u16>().is_ok())
+    {
+        return Err(Error::Config(format!(
+            "bounce relay '{relay}' should be formatted as host:port"
+        )));
+    }
+
+    Ok(())
+}
+
+impl RuntimeConfig {
+    /// check the values that are accepted by the type but can not be used
+    pub(crate) fn validate(&self) -> Result<()> {
+        if let Some(relay) = &self.smtp.bounce.relay {
+            validate_relay(relay)?;
+        }
+
+        Ok(())
+    }
+
+    /// read a previously persisted configuration, returns None when there is none
+    pub(crate) fn load(path: &Path) -> Result<Option<Self>> {
+        if !path.exists() {
+            return Ok(None);
+        }
+
+        let contents = std::fs::read(path)?;
+
+        let config: Self = serde_json::from_slice(&contents)
+            .map_err(|e| Error::WebServer(format!("invalid configuration file: {e}")))?;
+        config.validate()?;
+
+        Ok(Some(config))
+    }
+
+    /// persist the configuration, using a temporary file so a partial write is never read back
+    pub(crate) fn save(&self, path: &Path) -> Result<()> {
+        let contents = serde_json::to_vec_pretty(self)
+            .map_err(|e| Error::WebServer(format!("could not serialize configuration: {e}")))?;
+
+        let temporary = path.with_extension("tmp");
+        std::fs::write(&temporary, contents)?;
+        std::fs::rename(&temporary, path)?;
+
+        Ok(())
+    }
+}
+
+impl AppState {
+    pub(crate) fn runtime_config(&self) -> Result<RuntimeConfig> {
+        let poisoned = || Error::WebServer("configuration lock poisoned".to_owned());
+
+        Ok(RuntimeConfig {
+            retention_period: self
+                .retention_period
+                .read()
+                .map_err(|_| poisoned())?
+                .as_secs(),
+            smtp: self.smtp_config.read().map_err(|_| poisoned())?.clone(),
+        })
+    }
+
+    /// replace the runtime configuration, new SMTP sessions use the new settings
+    pub(crate) fn update_runtime_config(&self, config: RuntimeConfig) -> Result<()> {
+        let poisoned = || Error::WebServer("configuration lock poisoned".to_owned());
+
+        if let Some(path) = &self.config_path {
+            config.save(path)?;
+        }
+
+        *self.retention_period.write().map_err(|_| poisoned())? =
+            Duration::from_secs(config.retention_period);
+        *self.smtp_config.write().map_err(|_| poisoned())? = config.smtp;
+
+        info!("runtime configuration updated");
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn flattened_json() {
+        let config: RuntimeConfig = serde_json::from_value(serde_json::json!({
+            "retention_period": 3600,
+            "max_message_size": 1024,
+            "rejected_recipients": ["*@invalid.test"]
+        }))
+        .unwrap();
+
+        assert_eq!(config.retention_period, 3600);
+        assert_eq!(config.smtp.max_message_size, 1024);
+        assert_eq!(config.smtp.rejected_recipients, vec!["*@invalid.test"]);
+        assert!(!config.smtp.require_auth);
+    }
+
+    #[test]
+    fn invalid_relay() {
+        let mut config = RuntimeConfig::default();
+        config.smtp.bounce.relay = Some("localhost:25".to_owned());
+        assert!(config.validate().is_ok());
+
+        config.smtp.bounce.relay = Some("localhost".to_owned());
+        assert!(config.validate().is_err());
+    }
+}
@@ -0,0 +1,147 @@
+use mailcrab::{DnsRecords, Error, Result, dkim_record_from_pem};
+use serde::Deserialize;
+use std::{collections::HashMap, ffi::OsStr, fmt::Display, path::Path};
+
+use crate::AppState;
+
+/// one or more TXT records for a name in the DNS records file
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum TxtRecords {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// read a TOML, YAML or JSON file that maps names to TXT records, other files are read as a zone file
+fn read_records(path: &Path, records: &mut DnsRecords) -> Result<()> {
+    let invalid = |e: &dyn Display| Error::Config(format!("{}: {e}", path.display()));
+    let contents = std::fs::read_to_string(path).map_err(|e| invalid(&e))?;
+
+    let map: HashMap<String, TxtRecords> = match path.extension().and_then(OsStr::to_str) {
+        Some("toml") => toml::from_str(&contents).map_err(|e| invalid(&e))?,
+        Some("yaml" | "yml") => serde_yaml::from_str(&contents).map_err(|e| invalid(&e))?,
+        Some("json") => serde_json::from_str(&contents).map_err(|e| invalid(&e))?,
+        _ => return records.add_zone(&contents).map_err(|e| invalid(&e)),
+    };
+
+    for (name, values) in map {
+        match values {
+            TxtRecords::One(value) => records.add_txt(&name, value),
+            TxtRecords::Many(values) => {
+                for value in values {
+                    records.add_txt(&name, value);
+                }
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// read a directory of DKIM keys, the file name (without .txt or .pem extension) is the record name
+fn read_keys(directory: &Path, records: &mut DnsRecords) -> Result<()> {
+    let invalid = |e: &dyn Display| Error::Config(format!("{}: {e}", directory.display()));
+
+    for entry in std::fs::read_dir(directory).map_err(|e| invalid(&e))? {
+        let path = entry.map_err(|e| invalid(&e))?.path();
+        let Some(file_name) = path.file_name().and_then(OsStr::to_str) else {
+            continue;
+        };
+
+        if !path.is_file() || !file_name.contains("._domainkey.") {
+            continue;
+        }
+
+        let contents = std::fs::read_to_string(&path).map_err(|e| invalid(&e))?;
+        let name = file_name
+            .strip_suffix(".txt")
+            .or_else(|| file_name.strip_suffix(".pem"))
+            .unwrap_or(file_name);
+
+        let record = match contents.contains("-----BEGIN") {
+            true => dkim_record_from_pem(&contents)
+                .ok_or_else(|| Error::Config(format!("{}: invalid public key", path.display())))?,
+            // a record may be split in multiple quoted strings, like in a zone file
+            false => contents
+                .split('"')
+                .filter(|part| !part.trim().is_empty())
+                .collect::<Vec<&str>>()
+                .join("")
+                .trim()
+                .to_owned(),
+        };
+
+        records.add_txt(name, record);
+    }
+
+    Ok(())
+}
+
+impl AppState {
+    /// the configured DNS records and DKIM keys, read on every use so changes apply immediately
+    pub(crate) fn dns_records(&self) -> Result<DnsRecords> {
+        let mut records = DnsRecords::default();
+
+        if let Some(path) = &self.dns_records {
+            read_records(path, &mut records)?;
+        }
+
+        if let Some(directory) = &self.dkim_keys {
+            read_keys(directory, &mut records)?;
+        }
+
+        Ok(records)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keys_and_records() {
+        let directory = std::env::temp_dir().join(format!("mailcrab-dns-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("brisbane._domainkey.example.com.pem"),
+            "-----BEGIN PUBLIC KEY-----\n\
+             MCowBQYDK2VwAyEA11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=\n\
+             -----END PUBLIC KEY-----\n",
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("test._domainkey.example.com.txt"),
+            "\"v=DKIM1; k=rsa; \" \"p=MIGfMA0\"\n",
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("example.org.zone"),
+            "$ORIGIN example.org.\n@ IN TXT \"v=spf1 mx -all\"\n@ IN MX 10 mail\n",
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("records.toml"),
+            "\"example.com\" = \"v=spf1 -all\"\n\
+             \"_dmarc.example.com\" = [\"v=DMARC1; p=reject\"]\n",
+        )
+        .unwrap();
+
+        let mut records = DnsRecords::default();
+        read_keys(&directory, &mut records).unwrap();
+        read_records(&directory.join("records.toml"), &mut records).unwrap();
+        read_records(&directory.join("example.org.zone"), &mut records).unwrap();
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert_eq!(
+            records.txt("brisbane._domainkey.example.com"),
+            ["v=DKIM1; k=ed25519; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo="]
+        );
+        assert_eq!(
+            records.txt("test._domainkey.example.com"),
+            ["v=DKIM1; k=rsa; p=MIGfMA0"]
+        );
+        assert_eq!(records.txt("EXAMPLE.com."), ["v=spf1 -all"]);
+        assert_eq!(records.txt("_dmarc.example.com"), ["v=DMARC1; p=reject"]);
+        assert_eq!(records.mx("example.org"), ["mail.example.org"]);
+    }
+}
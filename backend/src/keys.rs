@@ -0,0 +1,102 @@
+use mailcrab::{Error, OpenPgpKeys, Result, SmimeKeys};
+use std::{
+    ffi::OsStr,
+    path::{Path, PathBuf},
+};
+
+/// PEM files with private keys and certificates
+const SMIME_EXTENSIONS: &[&str] = &["pem", "crt", "cer", "key"];
+
+/// ASCII armored or binary OpenPGP keyrings
+const OPENPGP_EXTENSIONS: &[&str] = &["asc", "gpg", "pgp", "key"];
+
+fn invalid(path: &Path, e: &dyn std::fmt::Display) -> Error {
+    Error::Config(format!("{}: {e}", path.display()))
+}
+
+/// the file itself, or the files with one of the extensions in the directory
+fn key_files(path: &Path, extensions: &[&str]) -> Result<Vec<PathBuf>> {
+    if !path.is_dir() {
+        return Ok(vec![path.to_path_buf()]);
+    }
+
+    Ok(std::fs::read_dir(path)
+        .map_err(|e| invalid(path, &e))?
+        .filter_map(|entry| entry.ok().map(|entry| entry.path()))
+        .filter(|path| {
+            path.is_file()
+                && path
+                    .extension()
+                    .and_then(OsStr::to_str)
+                    .is_some_and(|extension| extensions.contains(&extension))
+        })
+        .collect())
+}
+
+/// read the S/MIME private keys and certificates of a PEM file, or of all PEM files in a directory
+pub(crate) fn read_smime_keys(path: &Path) -> Result<SmimeKeys> {
+    let mut keys = SmimeKeys::default();
+
+    for file in key_files(path, SMIME_EXTENSIONS)? {
+        let pem = std::fs::read_to_string(&file).map_err(|e| invalid(&file, &e))?;
+        keys.add_pem(&pem).map_err(|e| invalid(&file, &e))?;
+    }
+
+    Ok(keys)
+}
+
+/// read the OpenPGP public and secret keys of a keyring, or of all keyrings in a directory
+pub(crate) fn read_openpgp_keys(path: &Path) -> Result<OpenPgpKeys> {
+    let mut keys = OpenPgpKeys::default();
+
+    for file in key_files(path, OPENPGP_EXTENSIONS)? {
+        let keyring = std::fs::read(&file).map_err(|e| invalid(&file, &e))?;
+        keys.add_keyring(&keyring).map_err(|e| invalid(&file, &e))?;
+    }
+
+    Ok(keys)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn directory() {
+        let directory = std::env::temp_dir().join(format!("mailcrab-keys-{}", std::process::id()));
+        std::fs::create_dir_all(&directory).unwrap();
+        std::fs::write(
+            directory.join("ca.pem"),
+            include_str!("../../mailcrab/src/analysis/testdata/smime/ca.pem"),
+        )
+        .unwrap();
+        std::fs::write(
+            directory.join("joe.asc"),
+            include_str!("../../mailcrab/src/analysis/testdata/openpgp/joe.asc"),
+        )
+        .unwrap();
+        std::fs::write(directory.join("notes.txt"), "-----BEGIN CERTIFICATE-----").unwrap();
+
+        let keys = read_smime_keys(&directory);
+        let keyring = read_openpgp_keys(&directory);
+        std::fs::write(
+            directory.join("invalid.crt"),
+            "-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----\n",
+        )
+        .unwrap();
+        std::fs::write(directory.join("invalid.gpg"), "not a keyring").unwrap();
+        let invalid = read_smime_keys(&directory);
+        let invalid_keyring = read_openpgp_keys(&directory);
+        std::fs::remove_dir_all(&directory).unwrap();
+
+        assert!(keys.is_ok());
+        assert!(keyring.is_ok());
+        assert!(invalid.unwrap_err().to_string().contains("invalid.crt"));
+        assert!(
+            invalid_keyring
+                .unwrap_err()
+                .to_string()
+                .contains("invalid.gpg")
+        );
+    }
+}
@@ -1,20 +1,39 @@
-use mailcrab::{Error, MailMessage, MessageId, Result, mail_server};
+use clap::Parser;
+use mailcrab::{
+    Bounce, Error, Limits, MailMessage, MessageId, OpenPgpKeys, ProxyProtocol, Result,
+    SharedConfig, SmimeKeys, SmtpConfig, SmtpOptions, mail_server,
+};
 use rust_embed::{EmbeddedFile, RustEmbed};
 use std::{
     collections::HashMap,
-    env,
-    net::IpAddr,
+    path::PathBuf,
     process,
-    str::FromStr,
     sync::{Arc, RwLock},
 };
-use tokio::{signal, sync::broadcast::Receiver, task::JoinSet, time::Duration};
+use tokio::{
+    signal,
+    sync::broadcast::{Receiver, Sender},
+    task::JoinSet,
+    time::Duration,
+};
 use tokio_util::sync::CancellationToken;
 use tracing::{error, info};
 use tracing_subscriber::{prelude::__tracing_subscriber_SubscriberExt, util::SubscriberInitExt};
 
-use crate::{storage::storage, web_server::web_server};
+use crate::{
+    commands::execute,
+    config::RuntimeConfig,
+    settings::{Cli, Command, Settings},
+    storage::storage,
+    web_server::web_server,
+};
 
+mod commands;
+mod config;
+mod dns;
+mod keys;
+mod mbox;
+mod settings;
 mod storage;
 mod web_server;
 
@@ -28,24 +47,24 @@ pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 /// application state, holds all messages, a message queue and configuration
 pub struct AppState {
     rx: Receiver<MailMessage>,
+    /// imported messages, only to notify websocket clients, they are stored directly
+    imported: Sender<MailMessage>,
     storage: RwLock<HashMap<MessageId, MailMessage>>,
     prefix: String,
     index: Option<String>,
-    retention_period: Duration,
+    retention_period: RwLock<Duration>,
+    smtp_config: SharedConfig,
+    config_path: Option<PathBuf>,
+    dkim_keys: Option<PathBuf>,
+    dns_records: Option<PathBuf>,
+    smime_keys: SmimeKeys,
+    openpgp_keys: OpenPgpKeys,
 }
 
 #[derive(RustEmbed)]
 #[folder = "../frontend/dist"]
 pub struct Asset;
 
-/// get a configuration from the environment or return default value
-fn parse_env_var<T: FromStr>(name: &'static str, default: T) -> T {
-    env::var(name)
-        .unwrap_or_default()
-        .parse::<T>()
-        .unwrap_or(default)
-}
-
 /// preload the HTML for the index, replace dynamic values
 fn load_index(path_prefix: &str) -> Result<String> {
     let index: EmbeddedFile = Asset::get("index.html")
@@ -62,25 +81,121 @@ fn load_index(path_prefix: &str) -> Result<String> {
         ))
 }
 
-async fn run() -> i32 {
-    let smtp_host: IpAddr = parse_env_var("SMTP_HOST", [0, 0, 0, 0].into());
-    let http_host: IpAddr = parse_env_var("HTTP_HOST", [127, 0, 0, 1].into());
-    let smtp_port: u16 = parse_env_var("SMTP_PORT", 1025);
-    let http_port: u16 = parse_env_var("HTTP_PORT", 1080);
-    let queue_capacity: usize = parse_env_var("QUEUE_CAPACITY", 32);
+async fn run(settings: Settings) -> i32 {
+    let (smtp_host, smtp_port) = (settings.smtp_address().ip(), settings.smtp_address().port());
+    let (http_host, http_port) = (settings.http_address().ip(), settings.http_address().port());
+    let queue_capacity: usize = settings.queue_capacity.unwrap_or(32);
 
     // Enable auth implicitly enable TLS
-    let enable_tls_auth: bool = std::env::var("ENABLE_TLS_AUTH").map_or_else(
-        |_| false,
-        |v| v.to_ascii_lowercase().parse().unwrap_or(false),
-    );
+    let enable_tls_auth: bool = settings.enable_tls_auth.unwrap_or(false);
+
+    // expect a PROXY protocol header from a load balancer: off, optional or required
+    let proxy_protocol: ProxyProtocol = settings.proxy_protocol.unwrap_or_default();
+    let trusted_proxies = settings.trusted_proxies.unwrap_or_default();
+
+    // SMTP resource limits, 0 disables a limit, timeouts are in seconds
+    let default_limits = Limits::default();
+    let limits = Limits {
+        max_connections: settings
+            .smtp_max_connections
+            .unwrap_or(default_limits.max_connections),
+        connections_per_minute: settings
+            .smtp_connections_per_minute
+            .unwrap_or(default_limits.connections_per_minute),
+        messages_per_minute: settings
+            .smtp_messages_per_minute
+            .unwrap_or(default_limits.messages_per_minute),
+        command_timeout: settings
+            .smtp_command_timeout
+            .map_or(default_limits.command_timeout, Duration::from_secs),
+        data_timeout: settings
+            .smtp_data_timeout
+            .map_or(default_limits.data_timeout, Duration::from_secs),
+    };
+
+    // list of recipient patterns
+    let patterns = |patterns: Option<Vec<String>>| -> Vec<String> {
+        patterns
+            .unwrap_or_default()
+            .into_iter()
+            .map(|pattern| pattern.trim().to_owned())
+            .filter(|pattern| !pattern.is_empty())
+            .collect()
+    };
+
+    // SMTP behaviour that can be changed at runtime using the HTTP API
+    let smtp_config = SmtpConfig {
+        // maximum message size in bytes, 0 means unlimited
+        max_message_size: settings.smtp_max_message_size.unwrap_or(0),
+        require_auth: settings.smtp_require_auth.unwrap_or(false),
+        rejected_recipients: patterns(settings.reject_recipients),
+        // recipients for which a bounce is sent to the envelope sender,
+        // optionally delivered to another SMTP server instead of MailCrab itself
+        bounce: Bounce {
+            recipients: patterns(settings.bounce_recipients),
+            relay: settings.bounce_relay,
+        },
+        latency: Default::default(),
+    };
 
     // construct path prefix
-    let prefix = std::env::var("MAILCRAB_PREFIX").unwrap_or_default();
+    let prefix = settings.prefix.unwrap_or_default();
     let prefix = format!("/{}", prefix.trim_matches('/'));
 
     // optional retention period, the default is 0 - which means messages are kept forever
-    let retention_period: u64 = parse_env_var("MAILCRAB_RETENTION_PERIOD", 0);
+    let retention_period: u64 = settings.retention_period.unwrap_or(0);
+
+    // changes to the runtime configuration are persisted in this (JSON) file, when set,
+    // a persisted configuration takes precedence over the startup settings
+    let config_path = settings.runtime_config;
+    let mut runtime_config = RuntimeConfig {
+        retention_period,
+        smtp: smtp_config,
+    };
+
+    if let Some(path) = &config_path {
+        match RuntimeConfig::load(path) {
+            Ok(Some(config)) => {
+                info!("Loaded runtime configuration from {}", path.display());
+                runtime_config = config;
+            }
+            Ok(None) => {}
+            Err(e) => {
+                error!(
+                    "Could not load runtime configuration from {}: {e}",
+                    path.display()
+                );
+
+                return 1;
+            }
+        }
+    }
+
+    // private keys and certificates to decrypt and verify S/MIME messages
+    let smime_keys = match &settings.smime_keys {
+        Some(path) => match keys::read_smime_keys(path) {
+            Ok(keys) => keys,
+            Err(e) => {
+                error!("Could not load S/MIME keys: {e}");
+
+                return 1;
+            }
+        },
+        None => SmimeKeys::default(),
+    };
+
+    // keyring to decrypt and verify OpenPGP/MIME messages
+    let openpgp_keys = match &settings.openpgp_keys {
+        Some(path) => match keys::read_openpgp_keys(path) {
+            Ok(keys) => keys,
+            Err(e) => {
+                error!("Could not load OpenPGP keys: {e}");
+
+                return 1;
+            }
+        },
+        None => OpenPgpKeys::default(),
+    };
 
     info!(
         "MailCrab HTTP server starting on {http_host}:{http_port} and SMTP server on {smtp_host}:{smtp_port}"
@@ -89,12 +204,22 @@ async fn run() -> i32 {
     // initialize internal broadcast queue
     let (tx, rx) = tokio::sync::broadcast::channel::<MailMessage>(queue_capacity);
     let storage_rx = rx.resubscribe();
+
+    let smtp_config = SharedConfig::new(RwLock::new(runtime_config.smtp));
+
     let app_state = Arc::new(AppState {
         rx,
+        imported: tokio::sync::broadcast::channel(queue_capacity).0,
         storage: Default::default(),
         index: load_index(&prefix).ok(),
         prefix,
-        retention_period: Duration::from_secs(retention_period),
+        retention_period: RwLock::new(Duration::from_secs(runtime_config.retention_period)),
+        smtp_config: smtp_config.clone(),
+        config_path,
+        dkim_keys: settings.dkim_keys,
+        dns_records: settings.dns_records,
+        smime_keys: smime_keys.clone(),
+        openpgp_keys: openpgp_keys.clone(),
     });
 
     // store broadcasted messages in a key/value store
@@ -110,7 +235,15 @@ async fn run() -> i32 {
         smtp_host,
         smtp_port,
         tx,
-        enable_tls_auth,
+        SmtpOptions {
+            enable_tls_auth,
+            proxy_protocol,
+            trusted_proxies,
+            limits,
+            config: smtp_config,
+            smime_keys,
+            openpgp_keys,
+        },
         token.clone(),
     ));
     set.spawn(web_server(http_host, http_port, app_state, token.clone()));
@@ -169,16 +302,41 @@ async fn shutdown_signal() {
 
 #[tokio::main]
 async fn main() {
-    // initialize logging
+    // parse flags and environment variables, exits on invalid values or --help
+    let mut cli = Cli::parse();
+    let command = cli.command.take().unwrap_or(Command::Serve);
+    let serve = matches!(command, Command::Serve);
+
+    // initialize logging, subcommands log to stderr so their output can be piped
     tracing_subscriber::registry()
         .with(tracing_subscriber::EnvFilter::new(
             std::env::var("RUST_LOG")
                 .unwrap_or_else(|_| "mailcrab_backend=info,tower_http=info".into()),
         ))
-        .with(tracing_subscriber::fmt::layer())
+        .with(serve.then(tracing_subscriber::fmt::layer))
+        .with((!serve).then(|| tracing_subscriber::fmt::layer().with_writer(std::io::stderr)))
         .init();
 
-    let exit_code = run().await;
+    let settings = match cli.into_settings() {
+        Ok(settings) => settings,
+        Err(e) => {
+            error!("{e}");
+
+            process::exit(2);
+        }
+    };
+
+    if !serve {
+        if let Err(e) = execute(command, &settings).await {
+            error!("{e}");
+
+            process::exit(1);
+        }
+
+        return;
+    }
+
+    let exit_code = run(settings).await;
 
     process::exit(exit_code);
 }
@@ -0,0 +1,118 @@
+use chrono::DateTime;
+
+/// sender used in the separator line when the envelope sender is unknown or empty
+const UNKNOWN_SENDER: &str = "MAILER-DAEMON";
+
+/// a message read from an mbox file
+#[derive(Debug, PartialEq)]
+pub(crate) struct MboxMessage {
+    pub(crate) envelope_from: Option<String>,
+    pub(crate) raw: Vec<u8>,
+}
+
+/// is this line a separator line, after removing any number of quotes
+fn is_quoted_from_line(line: &[u8]) -> bool {
+    let quotes = line.iter().take_while(|b| **b == b'>').count();
+
+    line[quotes..].starts_with(b"From ")
+}
+
+/// append a message in the mboxrd format, lines starting with (quoted) "From " get an extra ">"
+pub(crate) fn append_message(mbox: &mut Vec<u8>, envelope_from: &str, time: i64, raw: &[u8]) {
+    let sender = match envelope_from {
+        "" => UNKNOWN_SENDER,
+        sender => sender,
+    };
+    let date = DateTime::from_timestamp(time, 0)
+        .unwrap_or_default()
+        .format("%a %b %e %H:%M:%S %Y");
+
+    mbox.extend_from_slice(format!("From {sender} {date}\n").as_bytes());
+
+    for line in raw.split_inclusive(|b| *b == b'\n') {
+        if is_quoted_from_line(line) {
+            mbox.push(b'>');
+        }
+        mbox.extend_from_slice(line);
+    }
+
+    if !mbox.ends_with(b"\n") {
+        mbox.push(b'\n');
+    }
+    mbox.push(b'\n');
+}
+
+/// split an mbox file in messages, undoing the quoting of "From " lines
+pub(crate) fn split_messages(mbox: &[u8]) -> Vec<MboxMessage> {
+    let mut messages: Vec<MboxMessage> = Vec::new();
+
+    for line in mbox.split_inclusive(|b| *b == b'\n') {
+        if line.starts_with(b"From ") {
+            let sender = String::from_utf8_lossy(&line[5..])
+                .split_whitespace()
+                .next()
+                .filter(|sender| *sender != UNKNOWN_SENDER)
+                .map(ToOwned::to_owned);
+
+            messages.push(MboxMessage {
+                envelope_from: sender,
+                raw: Vec::new(),
+            });
+
+            continue;
+        }
+
+        // content before the first separator line is ignored
+        let Some(message) = messages.last_mut() else {
+            continue;
+        };
+
+        if line.starts_with(b">") && is_quoted_from_line(line) {
+            message.raw.extend_from_slice(&line[1..]);
+        } else {
+            message.raw.extend_from_slice(line);
+        }
+    }
+
+    // remove the empty line that separates messages
+    for message in &mut messages {
+        if message.raw.ends_with(b"\r\n\n") || message.raw.ends_with(b"\n\n") {
+            message.raw.pop();
+        }
+    }
+
+    messages
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn round_trip() {
+        let first = b"Subject: first\r\n\r\nFrom the start\r\n>From quoted\r\n".to_vec();
+        let second = b"Subject: second\r\n\r\nbody\r\n".to_vec();
+
+        let mut mbox = Vec::new();
+        append_message(&mut mbox, "sender@example.com", 0, &first);
+        append_message(&mut mbox, "", 0, &second);
+
+        assert!(mbox.starts_with(b"From sender@example.com Thu Jan  1 00:00:00 1970\n"));
+
+        let messages = split_messages(&mbox);
+
+        assert_eq!(
+            messages,
+            vec![
+                MboxMessage {
+                    envelope_from: Some("sender@example.com".to_owned()),
+                    raw: first,
+                },
+                MboxMessage {
+                    envelope_from: None,
+                    raw: second,
+                },
+            ]
+        );
+    }
+}
@@ -0,0 +1,397 @@
+use clap::{Args, Parser, Subcommand, ValueEnum, builder::BoolishValueParser};
+use mailcrab::{Error, ProxyProtocol, Result};
+use serde::{Deserialize, Deserializer};
+use std::{
+    ffi::OsStr,
+    fmt::Display,
+    net::{IpAddr, SocketAddr},
+    path::Path,
+    path::PathBuf,
+    str::FromStr,
+};
+
+use crate::config::validate_relay;
+
+/// Email test server for development
+#[derive(Debug, Parser)]
+#[command(version, about)]
+pub(crate) struct Cli {
+    /// read settings from a TOML or YAML file, environment variables and flags take precedence
+    #[arg(short, long, env = "MAILCRAB_CONFIG", value_name = "FILE")]
+    pub(crate) config: Option<PathBuf>,
+
+    #[command(flatten)]
+    pub(crate) settings: Settings,
+
+    #[command(subcommand)]
+    pub(crate) command: Option<Command>,
+}
+
+#[derive(Debug, Subcommand)]
+pub(crate) enum Command {
+    /// run the SMTP server and web interface, the default
+    Serve,
+    /// send a sample message, or the given EML files, to a running MailCrab
+    Send {
+        /// EML files to send, a sample message is sent when omitted
+        files: Vec<PathBuf>,
+        /// envelope sender, the From header is used when omitted
+        #[arg(long)]
+        from: Option<String>,
+        /// envelope recipients, the To, Cc and Bcc headers are used when omitted
+        #[arg(long)]
+        to: Vec<String>,
+    },
+    /// export all messages from a running MailCrab
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Mbox)]
+        format: ExportFormat,
+        /// file (mbox) or directory (eml) to write to, an mbox is written to stdout when omitted
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+    /// send the messages from an mbox file, an EML file or a directory of EML files to a running MailCrab
+    Import { path: PathBuf },
+    /// print new messages as they arrive in a running MailCrab
+    Tail,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, ValueEnum)]
+pub(crate) enum ExportFormat {
+    Mbox,
+    Eml,
+}
+
+/// startup settings, every setting can be provided as a flag, an environment variable
+/// or in the configuration file, in that order of precedence
+#[derive(Args, Debug, Default, Deserialize, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub(crate) struct Settings {
+    /// address of the SMTP server [default: 0.0.0.0]
+    #[arg(long, env = "SMTP_HOST")]
+    pub(crate) smtp_host: Option<IpAddr>,
+
+    /// port of the SMTP server [default: 1025]
+    #[arg(long, env = "SMTP_PORT")]
+    pub(crate) smtp_port: Option<u16>,
+
+    /// address of the web interface and API [default: 127.0.0.1]
+    #[arg(long, env = "HTTP_HOST")]
+    pub(crate) http_host: Option<IpAddr>,
+
+    /// port of the web interface and API [default: 1080]
+    #[arg(long, env = "HTTP_PORT")]
+    pub(crate) http_port: Option<u16>,
+
+    /// serve the web interface and API under this path
+    #[arg(long, env = "MAILCRAB_PREFIX")]
+    pub(crate) prefix: Option<String>,
+
+    /// number of messages that can be queued before they are stored [default: 32]
+    #[arg(long, env = "QUEUE_CAPACITY")]
+    pub(crate) queue_capacity: Option<usize>,
+
+    /// remove messages older than this number of seconds, 0 keeps messages forever [default: 0]
+    #[arg(long, env = "MAILCRAB_RETENTION_PERIOD", value_name = "SECONDS")]
+    pub(crate) retention_period: Option<u64>,
+
+    /// persist changes made using the configuration API to this (JSON) file
+    #[arg(long, env = "MAILCRAB_RUNTIME_CONFIG", value_name = "FILE")]
+    pub(crate) runtime_config: Option<PathBuf>,
+
+    /// enable TLS and accept any username/password combination [default: false]
+    #[arg(
+        long,
+        env = "ENABLE_TLS_AUTH",
+        value_parser = BoolishValueParser::new(),
+        num_args = 0..=1,
+        default_missing_value = "true"
+    )]
+    pub(crate) enable_tls_auth: Option<bool>,
+
+    /// expect a PROXY protocol header: off, optional or required [default: off]
+    #[arg(long, env = "PROXY_PROTOCOL")]
+    #[serde(deserialize_with = "from_str")]
+    pub(crate) proxy_protocol: Option<ProxyProtocol>,
+
+    /// (comma separated) addresses of the proxies that may send a PROXY protocol header,
+    /// required in optional mode
+    #[arg(
+        long,
+        env = "TRUSTED_PROXIES",
+        value_delimiter = ',',
+        value_name = "ADDRESSES"
+    )]
+    pub(crate) trusted_proxies: Option<Vec<IpAddr>>,
+
+    /// maximum number of concurrent SMTP connections, 0 is unlimited [default: 0]
+    #[arg(long, env = "SMTP_MAX_CONNECTIONS")]
+    pub(crate) smtp_max_connections: Option<usize>,
+
+    /// maximum number of new connections per client IP per minute, 0 is unlimited [default: 0]
+    #[arg(long, env = "SMTP_CONNECTIONS_PER_MINUTE")]
+    pub(crate) smtp_connections_per_minute: Option<usize>,
+
+    /// maximum number of messages per client IP per minute, 0 is unlimited [default: 0]
+    #[arg(long, env = "SMTP_MESSAGES_PER_MINUTE")]
+    pub(crate) smtp_messages_per_minute: Option<usize>,
+
+    /// seconds to wait for the next SMTP command [default: 300]
+    #[arg(long, env = "SMTP_COMMAND_TIMEOUT", value_name = "SECONDS")]
+    pub(crate) smtp_command_timeout: Option<u64>,
+
+    /// seconds to wait for message data [default: 600]
+    #[arg(long, env = "SMTP_DATA_TIMEOUT", value_name = "SECONDS")]
+    pub(crate) smtp_data_timeout: Option<u64>,
+
+    /// maximum message size in bytes, 0 is unlimited [default: 0]
+    #[arg(long, env = "SMTP_MAX_MESSAGE_SIZE", value_name = "BYTES")]
+    pub(crate) smtp_max_message_size: Option<usize>,
+
+    /// only accept messages after authentication [default: false]
+    #[arg(
+        long,
+        env = "SMTP_REQUIRE_AUTH",
+        value_parser = BoolishValueParser::new(),
+        num_args = 0..=1,
+        default_missing_value = "true"
+    )]
+    pub(crate) smtp_require_auth: Option<bool>,
+
+    /// reject recipients matching these (comma separated) patterns
+    #[arg(
+        long,
+        env = "REJECT_RECIPIENTS",
+        value_delimiter = ',',
+        value_name = "PATTERNS"
+    )]
+    pub(crate) reject_recipients: Option<Vec<String>>,
+
+    /// send a bounce for recipients matching these (comma separated) patterns
+    #[arg(
+        long,
+        env = "BOUNCE_RECIPIENTS",
+        value_delimiter = ',',
+        value_name = "PATTERNS"
+    )]
+    pub(crate) bounce_recipients: Option<Vec<String>>,
+
+    /// deliver bounces to this SMTP server (host:port), instead of storing them
+    #[arg(long, env = "BOUNCE_RELAY", value_name = "HOST:PORT")]
+    pub(crate) bounce_relay: Option<String>,
+
+    /// directory with DKIM public keys, named after the DNS record (selector._domainkey.domain),
+    /// containing a DKIM TXT record or a PEM encoded public key
+    #[arg(long, env = "MAILCRAB_DKIM_KEYS", value_name = "DIRECTORY")]
+    pub(crate) dkim_keys: Option<PathBuf>,
+
+    /// DNS zone file, or a TOML, YAML or JSON file that maps names to TXT records,
+    /// used instead of DNS lookups for DKIM, SPF, DMARC and ARC
+    #[arg(long, env = "MAILCRAB_DNS_RECORDS", value_name = "FILE")]
+    pub(crate) dns_records: Option<PathBuf>,
+
+    /// PEM file, or directory of PEM files, with private keys to decrypt S/MIME messages
+    /// and certificates of trusted signers
+    #[arg(long, env = "MAILCRAB_SMIME_KEYS", value_name = "PATH")]
+    pub(crate) smime_keys: Option<PathBuf>,
+
+    /// OpenPGP keyring, or directory of keyrings, with secret keys to decrypt messages
+    /// and public keys of signers
+    #[arg(long, env = "MAILCRAB_OPENPGP_KEYS", value_name = "PATH")]
+    pub(crate) openpgp_keys: Option<PathBuf>,
+}
+
+/// parse a value from a string in the configuration file, the same way as flags and environment variables
+fn from_str<'de, D, T>(deserializer: D) -> std::result::Result<Option<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: FromStr,
+    T::Err: Display,
+{
+    let value = String::deserialize(deserializer)?;
+
+    value.parse().map(Some).map_err(serde::de::Error::custom)
+}
+
+impl Cli {
+    /// combine flags and environment variables with the configuration file and validate the result
+    pub(crate) fn into_settings(self) -> Result<Settings> {
+        let file = match &self.config {
+            Some(path) => Settings::from_file(path)?,
+            None => Settings::default(),
+        };
+
+        let settings = self.settings.or(file);
+        settings.validate()?;
+
+        Ok(settings)
+    }
+}
+
+impl Settings {
+    pub(crate) fn smtp_address(&self) -> SocketAddr {
+        SocketAddr::new(
+            self.smtp_host.unwrap_or([0, 0, 0, 0].into()),
+            self.smtp_port.unwrap_or(1025),
+        )
+    }
+
+    pub(crate) fn http_address(&self) -> SocketAddr {
+        SocketAddr::new(
+            self.http_host.unwrap_or([127, 0, 0, 1].into()),
+            self.http_port.unwrap_or(1080),
+        )
+    }
+
+    /// read settings from a TOML or YAML file, depending on the file extension
+    pub(crate) fn from_file(path: &Path) -> Result<Self> {
+        let invalid = |e: &dyn Display| Error::Config(format!("{}: {e}", path.display()));
+        let contents = std::fs::read_to_string(path).map_err(|e| invalid(&e))?;
+
+        match path.extension().and_then(OsStr::to_str) {
+            Some("toml") => toml::from_str(&contents).map_err(|e| invalid(&e)),
+            Some("yaml" | "yml") => serde_yaml::from_str(&contents).map_err(|e| invalid(&e)),
+            _ => Err(invalid(&"unsupported file type, use .toml, .yaml or .yml")),
+        }
+    }
+
+    /// use the values from other for settings that are not set
+    fn or(self, other: Settings) -> Settings {
+        Settings {
+            smtp_host: self.smtp_host.or(other.smtp_host),
+            smtp_port: self.smtp_port.or(other.smtp_port),
+            http_host: self.http_host.or(other.http_host),
+            http_port: self.http_port.or(other.http_port),
+            prefix: self.prefix.or(other.prefix),
+            queue_capacity: self.queue_capacity.or(other.queue_capacity),
+            retention_period: self.retention_period.or(other.retention_period),
+            runtime_config: self.runtime_config.or(other.runtime_config),
+            enable_tls_auth: self.enable_tls_auth.or(other.enable_tls_auth),
+            proxy_protocol: self.proxy_protocol.or(other.proxy_protocol),
+            trusted_proxies: self.trusted_proxies.or(other.trusted_proxies),
+            smtp_max_connections: self.smtp_max_connections.or(other.smtp_max_connections),
+            smtp_connections_per_minute: self
+                .smtp_connections_per_minute
+                .or(other.smtp_connections_per_minute),
+            smtp_messages_per_minute: self
+                .smtp_messages_per_minute
+                .or(other.smtp_messages_per_minute),
+            smtp_command_timeout: self.smtp_command_timeout.or(other.smtp_command_timeout),
+            smtp_data_timeout: self.smtp_data_timeout.or(other.smtp_data_timeout),
+            smtp_max_message_size: self.smtp_max_message_size.or(other.smtp_max_message_size),
+            smtp_require_auth: self.smtp_require_auth.or(other.smtp_require_auth),
+            reject_recipients: self.reject_recipients.or(other.reject_recipients),
+            bounce_recipients: self.bounce_recipients.or(other.bounce_recipients),
+            bounce_relay: self.bounce_relay.or(other.bounce_relay),
+            dkim_keys: self.dkim_keys.or(other.dkim_keys),
+            dns_records: self.dns_records.or(other.dns_records),
+            smime_keys: self.smime_keys.or(other.smime_keys),
+            openpgp_keys: self.openpgp_keys.or(other.openpgp_keys),
+        }
+    }
+
+    /// check combinations of values that can not be rejected while parsing
+    fn validate(&self) -> Result<()> {
+        if self.queue_capacity == Some(0) {
+            return Err(Error::Config(
+                "queue capacity must be at least 1".to_owned(),
+            ));
+        }
+
+        let (smtp, http) = (self.smtp_address(), self.http_address());
+        let overlapping_hosts =
+            smtp.ip() == http.ip() || smtp.ip().is_unspecified() || http.ip().is_unspecified();
+
+        if overlapping_hosts && smtp.port() == http.port() {
+            return Err(Error::Config(
+                "the SMTP and HTTP server can not use the same port".to_owned(),
+            ));
+        }
+
+        if let Some(relay) = &self.bounce_relay {
+            validate_relay(relay)?;
+        }
+
+        // any client could claim another address, and every direct client would have to wait
+        if self.proxy_protocol == Some(ProxyProtocol::Optional)
+            && self.trusted_proxies.as_ref().is_none_or(Vec::is_empty)
+        {
+            return Err(Error::Config(
+                "optional PROXY protocol requires trusted proxies".to_owned(),
+            ));
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn precedence() {
+        let file: Settings = toml::from_str(
+            r#"
+            smtp_port = 2525
+            http_port = 8080
+            proxy_protocol = "optional"
+            trusted_proxies = ["10.0.0.1"]
+            bounce_recipients = ["*@bounce.test"]
+            "#,
+        )
+        .unwrap();
+
+        let cli = Cli::parse_from([
+            "mailcrab",
+            "--smtp-port",
+            "3525",
+            "--enable-tls-auth",
+            "--trusted-proxies",
+            "10.0.0.2,::1",
+        ]);
+        let settings = cli.settings.or(file);
+
+        assert_eq!(settings.smtp_port, Some(3525));
+        assert_eq!(settings.http_port, Some(8080));
+        assert_eq!(settings.enable_tls_auth, Some(true));
+        assert_eq!(settings.proxy_protocol, Some(ProxyProtocol::Optional));
+        assert_eq!(
+            settings.trusted_proxies,
+            Some(vec![
+                [10, 0, 0, 2].into(),
+                std::net::Ipv6Addr::LOCALHOST.into()
+            ])
+        );
+        assert!(settings.validate().is_ok());
+        assert_eq!(
+            settings.bounce_recipients,
+            Some(vec!["*@bounce.test".to_owned()])
+        );
+    }
+
+    #[test]
+    fn invalid_values() {
+        assert!(serde_yaml::from_str::<Settings>("smtp_prot: 2525").is_err());
+        assert!(serde_yaml::from_str::<Settings>("proxy_protocol: sometimes").is_err());
+        assert!(Cli::try_parse_from(["mailcrab", "--smtp-port", "abc"]).is_err());
+
+        let settings = Settings {
+            smtp_port: Some(1080),
+            ..Settings::default()
+        };
+        assert!(settings.validate().is_err());
+
+        let settings = Settings {
+            bounce_relay: Some("localhost".to_owned()),
+            ..Settings::default()
+        };
+        assert!(settings.validate().is_err());
+
+        let settings = Settings {
+            proxy_protocol: Some(ProxyProtocol::Optional),
+            ..Settings::default()
+        };
+        assert!(settings.validate().is_err());
+    }
+}
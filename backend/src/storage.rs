@@ -14,15 +14,9 @@ pub(crate) async fn storage(
     token: CancellationToken,
 ) -> Result<()> {
     let mut running = true;
-    // every retention_period / 10 seconds the messages will be filtered, keeping only messages
-    // that are older than retention_period
-    let min_retention_interval = Duration::from_secs(60);
-    let mut retention_interval =
-        tokio::time::interval(if state.retention_period / 10 < min_retention_interval {
-            min_retention_interval
-        } else {
-            state.retention_period / 10
-        });
+    // every minute the messages will be filtered, keeping only messages that are newer than
+    // the retention period, which can be changed at runtime
+    let mut retention_interval = tokio::time::interval(Duration::from_secs(60));
 
     info!("Storage server ready for events");
 
@@ -34,9 +28,10 @@ pub(crate) async fn storage(
                 }
             },
             _ = retention_interval.tick() => {
-                if state.retention_period > Duration::from_secs(0) && let Ok(mut storage) = state.storage.write() {
+                let retention_period = state.retention_period.read().map(|r| *r).unwrap_or_default();
+                if retention_period > Duration::from_secs(0) && let Ok(mut storage) = state.storage.write() {
                     let remove_before = std::time::SystemTime::now()
-                        .sub(state.retention_period)
+                        .sub(retention_period)
                         .duration_since(SystemTime::UNIX_EPOCH)?
                         .as_secs() as i64;
 
@@ -1,3 +1,4 @@
+use clap::Parser;
 use fake::{
     Fake,
     faker::{
@@ -15,10 +16,18 @@ use lettre::{
 };
 use mailcrab::MailMessageMetadata;
 use reqwest::Client;
-use std::ffi::OsStr;
+use std::{env, ffi::OsStr, str::FromStr};
 use tokio::time::{Duration, sleep};
 
-use crate::{parse_env_var, run};
+use crate::{run, settings::Cli};
+
+/// get a configuration from the environment or return default value
+fn parse_env_var<T: FromStr>(name: &'static str, default: T) -> T {
+    env::var(name)
+        .unwrap_or_default()
+        .parse::<T>()
+        .unwrap_or(default)
+}
 
 async fn send_message(
     with_html: bool,
@@ -114,7 +123,10 @@ async fn test_receive_messages() -> Result<Vec<Response>, Box<dyn std::error::Er
 
 #[tokio::test]
 async fn functional() {
-    let join = tokio::task::spawn(run());
+    let settings = Cli::parse_from(["mailcrab"])
+        .into_settings()
+        .expect("invalid settings");
+    let join = tokio::task::spawn(run(settings));
 
     // wait for mailcrab to startup
     for _i in 0..60 {
@@ -165,6 +177,97 @@ async fn functional() {
     assert!(sorted_messages[2].has_plain);
     assert_eq!(sorted_messages[2].attachments.len(), 1);
 
+    // the compatibility report lists issues for messages with HTML
+    let report: Vec<serde_json::Value> = Client::new()
+        .get(format!(
+            "http://127.0.0.1:{}/api/message/{}/compatibility",
+            parse_env_var("HTTP_PORT", 1080),
+            sorted_messages[0].id
+        ))
+        .send()
+        .await
+        .expect("compatibility request failed")
+        .json()
+        .await
+        .expect("invalid compatibility report");
+    assert!(report.is_empty());
+
+    // the link report includes the link in the HTML body
+    let links: Vec<serde_json::Value> = Client::new()
+        .get(format!(
+            "http://127.0.0.1:{}/api/message/{}/links",
+            parse_env_var("HTTP_PORT", 1080),
+            sorted_messages[0].id
+        ))
+        .send()
+        .await
+        .expect("links request failed")
+        .json()
+        .await
+        .expect("invalid link report");
+    let link = links
+        .iter()
+        .find(|link| link["source"] == "html")
+        .expect("link not found");
+    assert_eq!(link["url"], "https://github.com/tweedegolf/mailcrab");
+    assert_eq!(link["issues"], serde_json::json!([]));
+
+    // messages sent by lettre have a date, but no message id
+    let spam: serde_json::Value = Client::new()
+        .get(format!(
+            "http://127.0.0.1:{}/api/message/{}/spam",
+            parse_env_var("HTTP_PORT", 1080),
+            sorted_messages[1].id
+        ))
+        .send()
+        .await
+        .expect("spam request failed")
+        .json()
+        .await
+        .expect("invalid spam report");
+    let rules = spam["rules"]
+        .as_array()
+        .expect("rules missing")
+        .iter()
+        .map(|rule| rule["name"].as_str().unwrap_or_default())
+        .collect::<Vec<&str>>();
+    assert_eq!(
+        rules,
+        vec!["MISSING_MID", "MISSING_LIST_UNSUBSCRIBE", "MIME_HTML_ONLY"]
+    );
+
+    // unsigned messages have no DKIM results
+    let dkim: Vec<serde_json::Value> = Client::new()
+        .get(format!(
+            "http://127.0.0.1:{}/api/message/{}/dkim",
+            parse_env_var("HTTP_PORT", 1080),
+            sorted_messages[0].id
+        ))
+        .send()
+        .await
+        .expect("dkim request failed")
+        .json()
+        .await
+        .expect("invalid dkim results");
+    assert!(dkim.is_empty());
+
+    // without DNS records there is nothing to authenticate against
+    let authentication: serde_json::Value = Client::new()
+        .get(format!(
+            "http://127.0.0.1:{}/api/message/{}/authentication",
+            parse_env_var("HTTP_PORT", 1080),
+            sorted_messages[0].id
+        ))
+        .send()
+        .await
+        .expect("authentication request failed")
+        .json()
+        .await
+        .expect("invalid authentication report");
+    assert_eq!(authentication["spf"]["result"], "none");
+    assert_eq!(authentication["dmarc"]["result"], "none");
+    assert_eq!(authentication["arc"]["result"], "none");
+
     // send a large attachment and verify it can be downloaded via the URL endpoint
     const SIZE: usize = 75 * 1024 * 1024; // 75 MiB
     send_large_file(SIZE).await.expect("send failed");
@@ -209,6 +312,446 @@ async fn functional() {
     let expected: Vec<u8> = (0..SIZE).map(|i| (i % 251) as u8).collect();
     assert_eq!(attachment_bytes.as_ref(), expected.as_slice());
 
+    // download a single message and export all messages
+    let eml = client
+        .get(format!(
+            "http://127.0.0.1:{http_port}/api/message/{}/eml",
+            meta.id
+        ))
+        .send()
+        .await
+        .expect("eml request failed");
+    assert_eq!(eml.headers()["content-type"], "message/rfc822");
+
+    let mbox = client
+        .get(format!(
+            "http://127.0.0.1:{http_port}/api/export?format=mbox&search=large%20attachment%20test"
+        ))
+        .send()
+        .await
+        .expect("mbox export failed")
+        .bytes()
+        .await
+        .expect("reading mbox failed");
+    assert_eq!(crate::mbox::split_messages(&mbox).len(), 1);
+
+    let zip = client
+        .get(format!(
+            "http://127.0.0.1:{http_port}/api/export?format=zip"
+        ))
+        .send()
+        .await
+        .expect("zip export failed")
+        .bytes()
+        .await
+        .expect("reading zip failed");
+    assert!(zip.starts_with(b"PK"));
+
+    // import the exported archive, the envelope is set using query parameters
+    let imported: Vec<String> = client
+        .post(format!(
+            "http://127.0.0.1:{http_port}/api/messages?from=fixtures@example.com&to=demo@example.com"
+        ))
+        .body(zip.clone())
+        .send()
+        .await
+        .expect("import request failed")
+        .json()
+        .await
+        .expect("invalid import response");
+    assert_eq!(imported.len(), 4);
+
+    let messages = get_messages_metadata().await.unwrap();
+    let fixture = messages
+        .iter()
+        .find(|m| m.id.to_string() == imported[0])
+        .expect("imported message not stored");
+    assert_eq!(fixture.envelope_from, "fixtures@example.com");
+    assert_eq!(fixture.envelope_recipients, vec!["demo@example.com"]);
+
+    // archives that decompress to more than the limit are rejected
+    let mut archive = zip::ZipWriter::new(std::io::Cursor::new(Vec::new()));
+    archive
+        .start_file("bomb.eml", zip::write::SimpleFileOptions::default())
+        .unwrap();
+    std::io::copy(
+        &mut std::io::Read::take(
+            std::io::repeat(b'a'),
+            crate::web_server::MAX_DECOMPRESSED_IMPORT_SIZE + 1,
+        ),
+        &mut archive,
+    )
+    .unwrap();
+    let bomb = archive.finish().unwrap().into_inner();
+    let response = client
+        .post(format!("http://127.0.0.1:{http_port}/api/messages"))
+        .body(bomb)
+        .send()
+        .await
+        .expect("import request failed");
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    // more messages than the queue capacity are all stored
+    let mut mbox = Vec::new();
+    for index in 0..100 {
+        let raw = format!("Subject: bulk import {index}\r\n\r\nHello\r\n");
+        crate::mbox::append_message(&mut mbox, "bulk@example.com", 0, raw.as_bytes());
+    }
+    let imported: Vec<String> = client
+        .post(format!("http://127.0.0.1:{http_port}/api/messages"))
+        .body(mbox)
+        .send()
+        .await
+        .expect("import request failed")
+        .json()
+        .await
+        .expect("invalid import response");
+    let messages = get_messages_metadata().await.unwrap();
+    assert_eq!(imported.len(), 100);
+    assert!(
+        imported
+            .iter()
+            .all(|id| messages.iter().any(|m| m.id.to_string() == *id))
+    );
+
+    // deleted messages are not stored again by the storage task
+    client
+        .post(format!(
+            "http://127.0.0.1:{http_port}/api/delete/{}",
+            imported[0]
+        ))
+        .send()
+        .await
+        .expect("delete request failed");
+    sleep(Duration::from_millis(100)).await;
+    let messages = get_messages_metadata().await.unwrap();
+    assert!(!messages.iter().any(|m| m.id.to_string() == imported[0]));
+
+    // forwarded messages are available with their own bodies and attachments
+    let imported: Vec<String> = client
+        .post(format!("http://127.0.0.1:{http_port}/api/messages"))
+        .body(std::fs::read("../samples/forwarded.email").unwrap())
+        .send()
+        .await
+        .expect("import request failed")
+        .json()
+        .await
+        .expect("invalid import response");
+    let message_url = format!("http://127.0.0.1:{http_port}/api/message/{}", imported[0]);
+    let get_text = |path: &str| {
+        let request = client.get(format!("{message_url}{path}"));
+        async move {
+            request
+                .send()
+                .await
+                .expect("embedded request failed")
+                .error_for_status()
+                .expect("embedded message not found")
+                .text()
+                .await
+                .unwrap()
+        }
+    };
+
+    let message: serde_json::Value =
+        serde_json::from_str(&get_text("").await).expect("invalid message response");
+    assert_eq!(message["embedded"][0]["subject"], "Quarterly report");
+    assert_eq!(message["embedded"][0]["embedded"][0]["subject"], "Request");
+    assert!(
+        get_text("/embedded/0/body")
+            .await
+            .contains("/embedded/0/attachment/0")
+    );
+    assert_eq!(
+        get_text("/embedded/0/attachment/1").await,
+        "quarter,revenue\nQ1,100\nQ2,120\n"
+    );
+    assert!(
+        get_text("/embedded/0.0/body")
+            .await
+            .contains("Could you send me the quarterly report?")
+    );
+
+    // attachments can be shown inline, text as plain text
+    let inline = client
+        .get(format!("{message_url}/embedded/0/attachment/1?inline=1"))
+        .send()
+        .await
+        .expect("attachment request failed");
+    assert_eq!(
+        inline.headers()[reqwest::header::CONTENT_DISPOSITION],
+        "inline; filename=\"report.csv\""
+    );
+    assert_eq!(
+        inline.headers()[reqwest::header::CONTENT_TYPE],
+        "text/plain; charset=utf-8"
+    );
+    assert_eq!(
+        inline.headers()[reqwest::header::CONTENT_SECURITY_POLICY],
+        "sandbox; default-src 'none'"
+    );
+
+    // control characters are removed from file names
+    let imported: Vec<String> = client
+        .post(format!("http://127.0.0.1:{http_port}/api/messages"))
+        .body(
+            "Subject: control characters\r\nContent-Type: multipart/mixed; boundary=b\r\n\r\n\
+            --b\r\nContent-Type: text/plain\r\n\r\nHi\r\n\
+            --b\r\nContent-Type: text/plain\r\n\
+            Content-Disposition: attachment; filename=\"=?utf-8?q?notes=0D=0A.txt?=\"\r\n\r\n\
+            notes\r\n--b--\r\n",
+        )
+        .send()
+        .await
+        .expect("import request failed")
+        .json()
+        .await
+        .expect("invalid import response");
+    let attachment = client
+        .get(format!(
+            "http://127.0.0.1:{http_port}/api/message/{}/attachment/0",
+            imported[0]
+        ))
+        .send()
+        .await
+        .expect("attachment request failed");
+    assert_eq!(
+        attachment.headers()[reqwest::header::CONTENT_DISPOSITION],
+        "attachment; filename=\"notes.txt\""
+    );
+
+    // the MIME structure, and the decoded content of a part
+    let parts: serde_json::Value =
+        serde_json::from_str(&get_text("/parts").await).expect("invalid parts response");
+    assert_eq!(parts["content_type"], "multipart/mixed");
+    assert_eq!(parts["parts"][1]["parts"][0]["parts"][1]["id"], "0.1.0.1");
+    assert_eq!(
+        get_text("/parts/0.1.0.1").await,
+        "quarter,revenue\nQ1,100\nQ2,120\n"
+    );
+
+    // all body parts are combined, the AMP body is served separately
+    let imported: Vec<String> = client
+        .post(format!("http://127.0.0.1:{http_port}/api/messages"))
+        .body(std::fs::read("../samples/mixed.email").unwrap())
+        .send()
+        .await
+        .expect("import request failed")
+        .json()
+        .await
+        .expect("invalid import response");
+    let mixed_url = format!("http://127.0.0.1:{http_port}/api/message/{}", imported[0]);
+    let body = client
+        .get(format!("{mixed_url}/body"))
+        .send()
+        .await
+        .expect("body request failed")
+        .text()
+        .await
+        .unwrap();
+    assert!(body.contains("/attachment/1\""));
+    assert!(body.contains("Kind regards"));
+    let amp = client
+        .get(format!("{mixed_url}/amp"))
+        .send()
+        .await
+        .expect("amp request failed")
+        .text()
+        .await
+        .unwrap();
+    assert!(amp.contains("<html amp4email>"));
+
+    // remote content is blocked unless requested, tracking pixels are detected
+    let imported: Vec<String> = client
+        .post(format!("http://127.0.0.1:{http_port}/api/messages"))
+        .body(std::fs::read("../samples/newsletter.email").unwrap())
+        .send()
+        .await
+        .expect("import request failed")
+        .json()
+        .await
+        .expect("invalid import response");
+    let newsletter_url = format!("http://127.0.0.1:{http_port}/api/message/{}", imported[0]);
+    let get_body = |query: &str| {
+        let request = client.get(format!("{newsletter_url}/body{query}"));
+        async move {
+            request
+                .send()
+                .await
+                .expect("body request failed")
+                .text()
+                .await
+                .unwrap()
+        }
+    };
+    let blocked = get_body("").await;
+    assert!(blocked.contains("<img data-remote-src=\"https://cdn.example.org/collection.jpg\""));
+    assert!(!blocked.contains("https://cdn.example.org/hero.jpg"));
+    assert!(blocked.contains("<a href=\"https://example.org/spring\">"));
+    assert!(
+        get_body("?remote=1")
+            .await
+            .contains("<img src=\"https://cdn.example.org/collection.jpg\"")
+    );
+    let remote: serde_json::Value = client
+        .get(format!("{newsletter_url}/remote"))
+        .send()
+        .await
+        .expect("remote request failed")
+        .json()
+        .await
+        .expect("invalid remote response");
+    // bodies are sandboxed, only the attachments of the message can be loaded
+    let response = client
+        .get(format!(
+            "{newsletter_url}/body?sanitize=1&scheme=partial_invert"
+        ))
+        .send()
+        .await
+        .expect("body request failed");
+    let policy = response.headers()[reqwest::header::CONTENT_SECURITY_POLICY]
+        .to_str()
+        .unwrap()
+        .to_owned();
+    assert!(policy.contains(&format!(
+        "img-src http://127.0.0.1:{http_port}/api/message/{}/attachment/",
+        imported[0]
+    )));
+    assert!(policy.contains("sandbox allow-popups"));
+    // behind a reverse proxy, the attachments are loaded from the forwarded origin
+    let forwarded = client
+        .get(format!("{newsletter_url}/body"))
+        .header("x-forwarded-host", "mail.example.com")
+        .header("x-forwarded-proto", "https")
+        .send()
+        .await
+        .expect("body request failed");
+    let forwarded = forwarded.headers()[reqwest::header::CONTENT_SECURITY_POLICY]
+        .to_str()
+        .unwrap();
+    assert!(forwarded.contains(&format!(
+        "img-src https://mail.example.com/api/message/{}/attachment/ data:;",
+        imported[0]
+    )));
+    assert!(!forwarded.contains("127.0.0.1"));
+    assert!(!policy.contains("allow-scripts"));
+    let sanitized = response.text().await.unwrap();
+    assert!(!sanitized.contains("<script"));
+    assert!(sanitized.contains("<base target=\"_blank\">"));
+    assert!(sanitized.contains("html { background: #121212; color: #e8e8e8; }"));
+
+    let kinds = remote
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|resource| resource["kind"].as_str().unwrap())
+        .collect::<Vec<_>>();
+    assert_eq!(
+        kinds,
+        vec![
+            "font",
+            "image",
+            "other",
+            "image",
+            "tracking_pixel",
+            "tracking_pixel"
+        ]
+    );
+    assert_eq!(remote[5]["tracker"], "google-analytics.com");
+
+    // calendar invitations are parsed
+    let imported: Vec<String> = client
+        .post(format!("http://127.0.0.1:{http_port}/api/messages"))
+        .body(std::fs::read("../samples/invite.email").unwrap())
+        .send()
+        .await
+        .expect("import request failed")
+        .json()
+        .await
+        .expect("invalid import response");
+    let calendar: serde_json::Value = client
+        .get(format!(
+            "http://127.0.0.1:{http_port}/api/message/{}/calendar",
+            imported[0]
+        ))
+        .send()
+        .await
+        .expect("calendar request failed")
+        .json()
+        .await
+        .expect("invalid calendar response");
+    assert_eq!(calendar[0]["method"], "REQUEST");
+    assert_eq!(
+        calendar[0]["events"][0]["start"]["timezone"],
+        "Europe/Amsterdam"
+    );
+
+    // configure simulated latency and verify the rules are stored
+    let latency = serde_json::json!({
+        "rules": [{ "phase": "rcpt", "delay": { "millis": 100 }, "recipient": "*@slow.test" }]
+    });
+    let stored: serde_json::Value = client
+        .put(format!("http://127.0.0.1:{http_port}/api/latency"))
+        .json(&latency)
+        .send()
+        .await
+        .expect("latency request failed")
+        .json()
+        .await
+        .expect("invalid latency response");
+
+    assert_eq!(stored, latency);
+
+    // the latency rules are part of the runtime configuration
+    let mut config: serde_json::Value = client
+        .get(format!("http://127.0.0.1:{http_port}/api/config"))
+        .send()
+        .await
+        .expect("config request failed")
+        .json()
+        .await
+        .expect("invalid config response");
+
+    assert_eq!(config["latency"], latency);
+
+    // invalid settings are rejected
+    let mut invalid = config.clone();
+    invalid["bounce"]["relay"] = serde_json::json!("localhost");
+    let response = client
+        .put(format!("http://127.0.0.1:{http_port}/api/config"))
+        .json(&invalid)
+        .send()
+        .await
+        .expect("config update failed");
+    assert_eq!(response.status(), reqwest::StatusCode::BAD_REQUEST);
+
+    // reject recipients, new SMTP sessions use the updated configuration
+    config["rejected_recipients"] = serde_json::json!(["*@rejected.test"]);
+    config["latency"] = serde_json::json!({ "rules": [] });
+    client
+        .put(format!("http://127.0.0.1:{http_port}/api/config"))
+        .json(&config)
+        .send()
+        .await
+        .expect("config update failed")
+        .error_for_status()
+        .expect("config update rejected");
+
+    let smtp_port: u16 = parse_env_var("SMTP_PORT", 1025);
+    let rejected = Message::builder()
+        .from("sender@example.com".parse().unwrap())
+        .to("nobody@rejected.test".parse().unwrap())
+        .subject("Rejected")
+        .body(String::from("Never delivered"))
+        .unwrap();
+    let result = AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous("127.0.0.1")
+        .port(smtp_port)
+        .build()
+        .send(rejected)
+        .await;
+
+    assert!(result.is_err());
+
     // stop the server
     join.abort();
 }
@@ -1,41 +1,90 @@
 use axum::{
     Extension, Json, Router,
-    body::Body,
+    body::{Body, Bytes},
     extract::{
-        Path, WebSocketUpgrade,
+        DefaultBodyLimit, Path, Query, WebSocketUpgrade,
         ws::{self, WebSocket},
     },
-    http::{StatusCode, Uri, header},
+    http::{HeaderMap, StatusCode, Uri, header},
     response::{Html, IntoResponse, Response},
     routing::{get, post},
 };
-use mailcrab::{Action, Error, MailMessage, MailMessageMetadata, Result as AppResult};
-use serde::Serialize;
+use futures_util::future::join_all;
+use mail_parser::MessageParser;
+use mailcrab::{
+    Action, AuthenticationReport, Calendar, ColorScheme, CompatibilityIssue, DkimResult, Error,
+    FrameOptions, Latency, Link, MailMessage, MailMessageMetadata, MimePart, Reachability,
+    RemoteResource, Result as AppResult, SpamReport, block_remote_content, is_internal_address,
+    prepare_frame,
+};
+use serde::{Deserialize, Serialize};
 use std::{
+    collections::HashMap,
     ffi::OsStr,
+    io::{Cursor, Read, Write},
     net::{IpAddr, SocketAddr},
     sync::Arc,
 };
-use tokio::net::TcpListener;
+use tokio::{net::TcpListener, sync::broadcast::error::RecvError, time::Duration};
 use tokio_util::sync::CancellationToken;
 use tower_http::trace::{DefaultMakeSpan, TraceLayer};
 use tracing::{error, info, warn};
 use uuid::Uuid;
 
-use crate::{AppState, Asset, VERSION};
+use crate::{
+    AppState, Asset, VERSION,
+    config::RuntimeConfig,
+    mbox::{append_message, split_messages},
+};
 
 #[derive(Debug, Serialize)]
 struct VersionInfo {
     version_be: String,
 }
 
-/// send mail message metadata to websocket clients when broadcaster by the SMTP server
+/// send the metadata of a new message to a websocket client, returns false when the
+/// connection should be closed
+async fn notify(socket: &mut WebSocket, received: Result<MailMessage, RecvError>) -> bool {
+    match received {
+        Ok(message) => {
+            let metadata: MailMessageMetadata = message.into();
+            match serde_json::to_string(&metadata) {
+                Ok(json) => {
+                    if socket.send(ws::Message::Text(json.into())).await.is_err() {
+                        info!("WS client disconnected");
+                        return false;
+                    }
+                }
+                Err(e) => {
+                    error!("could not convert message to json {:?}", e);
+                }
+            }
+
+            true
+        }
+        // a slow client misses messages, e.g. during a large import, it can still reload them
+        Err(RecvError::Lagged(skipped)) => {
+            warn!("WS client skipped {skipped} messages");
+
+            true
+        }
+        Err(e) => {
+            error!("event pipeline error {:?}", e);
+
+            false
+        }
+    }
+}
+
+/// send mail message metadata to websocket clients when broadcasted by the SMTP server or
+/// imported
 async fn ws_handler(
     ws: WebSocketUpgrade,
     Extension(state): Extension<Arc<AppState>>,
 ) -> impl IntoResponse {
     ws.on_upgrade(|mut socket: WebSocket| async move {
         let mut receive = state.rx.resubscribe();
+        let mut imported = state.imported.subscribe();
         let mut active = true;
         let mut ping_interval = tokio::time::interval(tokio::time::Duration::from_secs(30));
 
@@ -48,26 +97,10 @@ async fn ws_handler(
                     }
                 },
                 internal_received = receive.recv() => {
-                    match internal_received {
-                        Ok(message) => {
-                            let metadata: MailMessageMetadata = message.into();
-                            match serde_json::to_string(&metadata) {
-                                Ok(json) => {
-                                    if socket.send(ws::Message::Text(json.into())).await.is_err() {
-                                        info!("WS client disconnected");
-                                        active = false;
-                                    }
-                                },
-                                Err(e) => {
-                                    error!("could not convert message to json {:?}", e);
-                                }
-                            }
-                        },
-                        Err(e) => {
-                            error!("event pipeline error {:?}", e);
-                            active = false;
-                        }
-                    }
+                    active = notify(&mut socket, internal_received).await;
+                },
+                imported_received = imported.recv() => {
+                    active = notify(&mut socket, imported_received).await;
                 },
                 socket_received = socket.recv() => {
                     match socket_received {
@@ -110,21 +143,49 @@ async fn ws_handler(
     })
 }
 
+/// filters for the message list and exports
+#[derive(Debug, Default, Deserialize)]
+struct MessageFilter {
+    /// case insensitive search in the subject, the sender and the recipients
+    search: Option<String>,
+    /// only messages received at or after this unix timestamp
+    since: Option<i64>,
+    /// only messages received before this unix timestamp
+    until: Option<i64>,
+}
+
+impl MessageFilter {
+    fn matches(&self, message: &MailMessage) -> bool {
+        self.search
+            .as_deref()
+            .is_none_or(|search| message.contains(search))
+            && self.since.is_none_or(|since| message.time >= since)
+            && self.until.is_none_or(|until| message.time < until)
+    }
+}
+
+/// the stored messages matching the filter, oldest first
+fn filtered_messages(state: &AppState, filter: &MessageFilter) -> Option<Vec<MailMessage>> {
+    let storage = state.storage.read().ok()?;
+    let mut messages = storage
+        .values()
+        .filter(|message| filter.matches(message))
+        .cloned()
+        .collect::<Vec<MailMessage>>();
+
+    messages.sort_by_key(|m| m.time);
+
+    Some(messages)
+}
+
 /// return metadata of all currently stored messages
 async fn messages_handler(
+    Query(filter): Query<MessageFilter>,
     Extension(state): Extension<Arc<AppState>>,
 ) -> Result<Json<Vec<MailMessageMetadata>>, StatusCode> {
-    if let Ok(storage) = state.storage.read() {
-        let mut messages = storage
-            .values()
-            .map(|message| message.clone().into())
-            .collect::<Vec<MailMessageMetadata>>();
-
-        messages.sort_by_key(|m| m.time);
-
-        Ok(Json(messages))
-    } else {
-        Err(StatusCode::INTERNAL_SERVER_ERROR)
+    match filtered_messages(&state, &filter) {
+        Some(messages) => Ok(Json(messages.into_iter().map(Into::into).collect())),
+        None => Err(StatusCode::INTERNAL_SERVER_ERROR),
     }
 }
 
@@ -143,14 +204,254 @@ async fn message_handler(
     }
 }
 
-/// return message body (html/text)
+#[derive(Debug, Default, Deserialize)]
+struct BodyQuery {
+    /// `1` or `true` to load remote images, style sheets and fonts
+    remote: Option<String>,
+    /// `1` or `true` to remove scripts, frames, forms and event handlers
+    sanitize: Option<String>,
+    /// show the body in the dark mode of a mail client
+    scheme: Option<ColorScheme>,
+}
+
+fn is_enabled(value: &Option<String>) -> bool {
+    matches!(value.as_deref(), Some("1" | "true"))
+}
+
+impl BodyQuery {
+    fn options(&self) -> FrameOptions {
+        FrameOptions {
+            remote: is_enabled(&self.remote),
+            sanitize: is_enabled(&self.sanitize),
+            scheme: self.scheme.unwrap_or_default(),
+        }
+    }
+}
+
+/// the Content-Security-Policy of a body, only the attachments of the message, to which
+/// `cid:` links are rewritten, can be loaded unless remote content is requested, and the
+/// body is sandboxed without scripts and without access to the origin of the web interface
+fn body_policy(headers: &HeaderMap, url: &str, remote: bool) -> String {
+    // the first value of a header, a reverse proxy can append to a forwarded header
+    let first = |name: &str| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .and_then(|value| value.split(',').next())
+            .map(str::trim)
+    };
+    // the origin as seen by the browser, behind a reverse proxy the host header can differ
+    let host = first("x-forwarded-host")
+        .or_else(|| first(header::HOST.as_str()))
+        .filter(|host| {
+            !host.is_empty()
+                && host
+                    .chars()
+                    .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '-' | ':' | '[' | ']'))
+        });
+    let schemes = match first("x-forwarded-proto") {
+        Some(scheme @ ("http" | "https")) => vec![scheme],
+        _ => vec!["http", "https"],
+    };
+    let attachments = match host {
+        Some(host) => schemes
+            .iter()
+            .map(|scheme| format!("{scheme}://{host}{url}/attachment/"))
+            .collect::<Vec<String>>()
+            .join(" "),
+        None => "'self'".to_owned(),
+    };
+    let remote = if remote { " http: https:" } else { "" };
+
+    format!(
+        "default-src 'none'; img-src {attachments} data:{remote}; media-src {attachments}{remote}; \
+        style-src 'unsafe-inline'{remote}; font-src data:{remote}; base-uri 'none'; \
+        form-action 'none'; frame-ancestors 'self'; \
+        sandbox allow-popups allow-popups-to-escape-sandbox"
+    )
+}
+
+fn body_response(html: String, policy: String) -> Response {
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/html; charset=utf-8")
+        .header(header::CONTENT_SECURITY_POLICY, policy)
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .body(Body::from(html))
+        .unwrap()
+}
+
+/// return message body (html/text), remote content is blocked unless `remote=1` is set
 async fn message_body_handler(
+    Path(id): Path<Uuid>,
+    Query(query): Query<BodyQuery>,
+    headers: HeaderMap,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Response, StatusCode> {
+    let storage = state
+        .storage
+        .read()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let message = storage.get(&id).ok_or(StatusCode::NOT_FOUND)?;
+    let options = query.options();
+    let url = format!("{}/api/message/{id}", state.prefix.trim_end_matches('/'));
+
+    Ok(body_response(
+        prepare_frame(&message.render(&state.prefix), options),
+        body_policy(&headers, &url, options.remote),
+    ))
+}
+
+/// return the remote resources in the message body, including tracking pixels
+async fn remote_content_handler(
+    Path(id): Path<Uuid>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Json<Vec<RemoteResource>>, StatusCode> {
+    let storage = state
+        .storage
+        .read()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let message = storage.get(&id).ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(Json(
+        block_remote_content(&message.render(&state.prefix)).resources,
+    ))
+}
+
+/// scripts of an AMP for Email body are limited to the AMP runtime
+const AMP_POLICY: &str = "default-src 'none'; script-src https://cdn.ampproject.org/; \
+    img-src https: data:; style-src 'unsafe-inline'; font-src https: data:; connect-src https:; \
+    base-uri 'none'; frame-ancestors 'self'; \
+    sandbox allow-scripts allow-popups allow-popups-to-escape-sandbox";
+
+/// return the AMP for Email body of a message
+async fn message_amp_handler(
+    Path(id): Path<Uuid>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Response, StatusCode> {
+    let storage = state
+        .storage
+        .read()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    match storage.get(&id).map(MailMessage::amp) {
+        Some(amp) if !amp.is_empty() => Ok(body_response(amp.to_owned(), AMP_POLICY.to_owned())),
+        _ => Err(StatusCode::NOT_FOUND),
+    }
+}
+
+/// the indices of nested embedded messages, separated by dots, e.g. `0.1`
+fn embedded_path(path: &str) -> Result<Vec<usize>, StatusCode> {
+    path.split('.')
+        .map(|index| index.parse().map_err(|_| StatusCode::NOT_FOUND))
+        .collect()
+}
+
+/// return the body of a forwarded or attached message, like the message body
+async fn embedded_body_handler(
+    Path((id, path)): Path<(Uuid, String)>,
+    Query(query): Query<BodyQuery>,
+    headers: HeaderMap,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Response, StatusCode> {
+    let indices = embedded_path(&path)?;
+    let storage = state
+        .storage
+        .read()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let message = storage
+        .get(&id)
+        .and_then(|message| message.embedded_message(&indices))
+        .ok_or(StatusCode::NOT_FOUND)?;
+    let url = format!(
+        "{}/api/message/{id}/embedded/{path}",
+        state.prefix.trim_end_matches('/')
+    );
+
+    let options = query.options();
+
+    Ok(body_response(
+        prepare_frame(&message.render(&url), options),
+        body_policy(&headers, &url, options.remote),
+    ))
+}
+
+/// return the calendar invitations of a message
+async fn calendar_handler(
+    Path(id): Path<Uuid>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Json<Vec<Calendar>>, StatusCode> {
+    if let Ok(storage) = state.storage.read() {
+        match storage.get(&id) {
+            Some(message) => Ok(Json(message.calendar.clone())),
+            _ => Err(StatusCode::NOT_FOUND),
+        }
+    } else {
+        Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+/// return the MIME structure of the raw message
+async fn parts_handler(
+    Path(id): Path<Uuid>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Json<MimePart>, StatusCode> {
+    let storage = state
+        .storage
+        .read()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let message = storage.get(&id).ok_or(StatusCode::NOT_FOUND)?;
+
+    message
+        .mime_structure()
+        .map(Json)
+        .ok_or(StatusCode::UNPROCESSABLE_ENTITY)
+}
+
+/// return the decoded content of a MIME part, by its id, e.g. `0.1`
+async fn part_handler(
+    Path((id, part)): Path<(Uuid, String)>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Response, StatusCode> {
+    let storage = state
+        .storage
+        .read()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let message = storage.get(&id).ok_or(StatusCode::NOT_FOUND)?;
+    let (mime, bytes) = message.part_content(&part).ok_or(StatusCode::NOT_FOUND)?;
+    let len = bytes.len();
+
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, mime)
+        .header(header::CONTENT_LENGTH, len)
+        .header(header::CONTENT_SECURITY_POLICY, CONTENT_POLICY)
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
+        .body(Body::from(bytes))
+        .unwrap())
+}
+
+/// return HTML features of a message that are not supported by all major email clients
+async fn compatibility_handler(
+    Path(id): Path<Uuid>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Json<Vec<CompatibilityIssue>>, StatusCode> {
+    if let Ok(storage) = state.storage.read() {
+        match storage.get(&id) {
+            Some(message) => Ok(Json(message.compatibility_report())),
+            _ => Err(StatusCode::NOT_FOUND),
+        }
+    } else {
+        Err(StatusCode::INTERNAL_SERVER_ERROR)
+    }
+}
+
+/// return the spam score of a message and the rules that contributed to it
+async fn spam_handler(
     Path(id): Path<Uuid>,
     Extension(state): Extension<Arc<AppState>>,
-) -> Result<Html<String>, StatusCode> {
+) -> Result<Json<SpamReport>, StatusCode> {
     if let Ok(storage) = state.storage.read() {
         match storage.get(&id) {
-            Some(message) => Ok(Html(message.render(&state.prefix))),
+            Some(message) => Ok(Json(message.spam_report())),
             _ => Err(StatusCode::NOT_FOUND),
         }
     } else {
@@ -158,6 +459,169 @@ async fn message_body_handler(
     }
 }
 
+/// verify the DKIM signatures of a message, using the configured keys and DNS records
+async fn dkim_handler(
+    Path(id): Path<Uuid>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Json<Vec<DkimResult>>, (StatusCode, String)> {
+    let message = state
+        .storage
+        .read()
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, String::new()))?
+        .get(&id)
+        .cloned()
+        .ok_or((StatusCode::NOT_FOUND, String::new()))?;
+
+    let records = state
+        .dns_records()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(message.dkim_report(&records)))
+}
+
+/// check SPF, DKIM, DMARC and ARC of a message, using the configured keys and DNS records
+async fn authentication_handler(
+    Path(id): Path<Uuid>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Json<AuthenticationReport>, (StatusCode, String)> {
+    let message = state
+        .storage
+        .read()
+        .map_err(|_| (StatusCode::INTERNAL_SERVER_ERROR, String::new()))?
+        .get(&id)
+        .cloned()
+        .ok_or((StatusCode::NOT_FOUND, String::new()))?;
+
+    let records = state
+        .dns_records()
+        .map_err(|e| (StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))?;
+
+    Ok(Json(message.authentication_report(&records)))
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct LinksQuery {
+    /// request each link to check whether it is reachable
+    #[serde(default)]
+    check: bool,
+}
+
+/// timeout of a request to check whether a link is reachable
+const LINK_CHECK_TIMEOUT: Duration = Duration::from_secs(5);
+
+/// the addresses of the host of a link, links to internal addresses are not requested
+async fn resolve_link(url: &reqwest::Url) -> Result<Vec<SocketAddr>, Reachability> {
+    let (Some(host), Some(port)) = (url.host_str(), url.port_or_known_default()) else {
+        return Err(Reachability::Error("invalid url".to_owned()));
+    };
+
+    let addresses = match host
+        .trim_start_matches('[')
+        .trim_end_matches(']')
+        .parse::<IpAddr>()
+    {
+        Ok(ip) => vec![SocketAddr::new(ip, port)],
+        Err(_) => {
+            match tokio::time::timeout(LINK_CHECK_TIMEOUT, tokio::net::lookup_host((host, port)))
+                .await
+            {
+                Ok(Ok(addresses)) => addresses.collect(),
+                Ok(Err(_)) => return Err(Reachability::Error("host not found".to_owned())),
+                Err(_) => return Err(Reachability::Error("timeout".to_owned())),
+            }
+        }
+    };
+
+    if addresses.is_empty() {
+        return Err(Reachability::Error("host not found".to_owned()));
+    }
+
+    if addresses
+        .iter()
+        .any(|address| is_internal_address(address.ip()))
+    {
+        return Err(Reachability::Error("internal address".to_owned()));
+    }
+
+    Ok(addresses)
+}
+
+/// request the target of a link, redirects are not followed and the request only connects to
+/// the addresses that were checked
+async fn check_link(url: &str) -> Reachability {
+    let Ok(parsed) = reqwest::Url::parse(url) else {
+        return Reachability::Error("invalid url".to_owned());
+    };
+    let addresses = match resolve_link(&parsed).await {
+        Ok(addresses) => addresses,
+        Err(reachability) => return reachability,
+    };
+    let host = parsed.host_str().unwrap_or_default();
+
+    let client = match reqwest::Client::builder()
+        .timeout(LINK_CHECK_TIMEOUT)
+        .redirect(reqwest::redirect::Policy::none())
+        .resolve_to_addrs(host, &addresses)
+        .build()
+    {
+        Ok(client) => client,
+        Err(e) => return Reachability::Error(e.to_string()),
+    };
+
+    match client.head(url).send().await {
+        Ok(response) => Reachability::Status(response.status().as_u16()),
+        Err(e) if e.is_timeout() => Reachability::Error("timeout".to_owned()),
+        Err(e) if e.is_connect() => Reachability::Error("connection failed".to_owned()),
+        Err(e) => Reachability::Error(e.to_string()),
+    }
+}
+
+/// request the target of each link, failures (e.g. timeouts when offline) are reported per link
+async fn check_links(links: &mut [Link]) {
+    let mut urls = links
+        .iter()
+        .filter(|link| link.is_checkable())
+        .map(|link| link.url.clone())
+        .collect::<Vec<String>>();
+    urls.sort();
+    urls.dedup();
+
+    let results = join_all(urls.into_iter().map(|url| async move {
+        let reachability = check_link(&url).await;
+
+        (url, reachability)
+    }))
+    .await
+    .into_iter()
+    .collect::<HashMap<String, Reachability>>();
+
+    for link in links {
+        link.reachability = results.get(&link.url).cloned();
+    }
+}
+
+/// return the links and images in a message, optionally checking whether they are reachable
+async fn links_handler(
+    Path(id): Path<Uuid>,
+    Query(query): Query<LinksQuery>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Json<Vec<Link>>, StatusCode> {
+    let mut links = {
+        let storage = state
+            .storage
+            .read()
+            .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+
+        storage.get(&id).ok_or(StatusCode::NOT_FOUND)?.link_report()
+    };
+
+    if query.check {
+        check_links(&mut links).await;
+    }
+
+    Ok(Json(links))
+}
+
 /// delete a message
 async fn message_delete_handler(
     Path(id): Path<Uuid>,
@@ -199,9 +663,86 @@ async fn version_handler() -> Result<Json<VersionInfo>, StatusCode> {
     Ok(Json(vi))
 }
 
+/// return the simulated SMTP latency rules
+async fn latency_handler(
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Json<Latency>, StatusCode> {
+    match state.smtp_config.read() {
+        Ok(config) => Ok(Json(config.latency.clone())),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// replace the simulated SMTP latency rules, applied to all subsequent SMTP responses
+async fn latency_update_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    Json(latency): Json<Latency>,
+) -> Result<Json<Latency>, StatusCode> {
+    let mut config = state
+        .runtime_config()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    config.smtp.latency = latency.clone();
+
+    match state.update_runtime_config(config) {
+        Ok(()) => {
+            info!("latency rules updated: {:?}", latency.rules);
+
+            Ok(Json(latency))
+        }
+        Err(e) => {
+            error!("{e}");
+
+            Err(StatusCode::INTERNAL_SERVER_ERROR)
+        }
+    }
+}
+
+/// return the runtime configuration
+async fn config_handler(
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Json<RuntimeConfig>, StatusCode> {
+    match state.runtime_config() {
+        Ok(config) => Ok(Json(config)),
+        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    }
+}
+
+/// replace the runtime configuration, applied to all new SMTP sessions, invalid settings are
+/// rejected with a bad request
+async fn config_update_handler(
+    Extension(state): Extension<Arc<AppState>>,
+    Json(config): Json<RuntimeConfig>,
+) -> Result<Json<RuntimeConfig>, (StatusCode, String)> {
+    config
+        .validate()
+        .map_err(|e| (StatusCode::BAD_REQUEST, e.to_string()))?;
+
+    match state.update_runtime_config(config.clone()) {
+        Ok(()) => Ok(Json(config)),
+        Err(e) => {
+            error!("{e}");
+
+            Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()))
+        }
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct AttachmentQuery {
+    /// `1` or `true` to show the attachment in the browser instead of downloading it
+    inline: Option<String>,
+}
+
+impl AttachmentQuery {
+    fn is_inline(&self) -> bool {
+        matches!(self.inline.as_deref(), Some("1" | "true"))
+    }
+}
+
 /// return raw attachment by index
 async fn attachment_handler(
     Path((id, index)): Path<(Uuid, usize)>,
+    Query(query): Query<AttachmentQuery>,
     Extension(state): Extension<Arc<AppState>>,
 ) -> Result<Response, StatusCode> {
     let storage = state
@@ -212,17 +753,80 @@ async fn attachment_handler(
     let (filename, mime, bytes) = message
         .attachment_content(index)
         .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(attachment_response(
+        filename,
+        mime,
+        bytes,
+        query.is_inline(),
+    ))
+}
+
+/// return an attachment of a forwarded or attached message
+async fn embedded_attachment_handler(
+    Path((id, path, index)): Path<(Uuid, String, usize)>,
+    Query(query): Query<AttachmentQuery>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Response, StatusCode> {
+    let indices = embedded_path(&path)?;
+    let storage = state
+        .storage
+        .read()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let (filename, mime, bytes) = storage
+        .get(&id)
+        .and_then(|message| message.embedded_message(&indices))
+        .and_then(|message| message.attachment_content(index))
+        .ok_or(StatusCode::NOT_FOUND)?;
+
+    Ok(attachment_response(
+        filename,
+        mime,
+        bytes,
+        query.is_inline(),
+    ))
+}
+
+/// content types that are shown as plain text when an attachment is shown inline, so that the
+/// browser does not download them, or render HTML from an attachment
+fn is_text(mime: &str) -> bool {
+    let mime = mime.to_ascii_lowercase();
+
+    mime.starts_with("text/")
+        || mime.starts_with("message/")
+        || mime.ends_with("/json")
+        || mime.ends_with("+json")
+        || mime.ends_with("/xml")
+        || mime.ends_with("+xml")
+}
+
+/// attachments and parts are never rendered as a document with the origin of the web interface
+const CONTENT_POLICY: &str = "sandbox; default-src 'none'";
+
+fn attachment_response(filename: String, mime: String, bytes: Vec<u8>, inline: bool) -> Response {
+    // control characters, e.g. a decoded line break, are not allowed in a header value
+    let filename = filename
+        .chars()
+        .filter(|c| !c.is_control())
+        .collect::<String>();
     let disposition = format!(
-        "attachment; filename=\"{}\"",
+        "{}; filename=\"{}\"",
+        if inline { "inline" } else { "attachment" },
         filename.replace('\\', "\\\\").replace('"', "\\\"")
     );
+    let mime = match inline && is_text(&mime) {
+        true => "text/plain; charset=utf-8".to_owned(),
+        false => mime,
+    };
     let len = bytes.len();
-    Ok(Response::builder()
+    Response::builder()
         .header(header::CONTENT_TYPE, mime)
         .header(header::CONTENT_LENGTH, len)
         .header(header::CONTENT_DISPOSITION, disposition)
+        .header(header::CONTENT_SECURITY_POLICY, CONTENT_POLICY)
+        .header(header::X_CONTENT_TYPE_OPTIONS, "nosniff")
         .body(Body::from(bytes))
-        .unwrap())
+        .unwrap()
 }
 
 /// return the raw message (plain/text)
@@ -244,6 +848,226 @@ async fn message_raw_handler(
         .unwrap())
 }
 
+/// return the raw message as a downloadable .eml file
+async fn message_eml_handler(
+    Path(id): Path<Uuid>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Response, StatusCode> {
+    let storage = state
+        .storage
+        .read()
+        .map_err(|_| StatusCode::INTERNAL_SERVER_ERROR)?;
+    let message = storage.get(&id).ok_or(StatusCode::NOT_FOUND)?;
+    let bytes = message.raw_bytes().unwrap_or_default();
+    let len = bytes.len();
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, "message/rfc822")
+        .header(header::CONTENT_LENGTH, len)
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{id}.eml\""),
+        )
+        .body(Body::from(bytes))
+        .unwrap())
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize)]
+#[serde(rename_all = "lowercase")]
+enum ExportFormat {
+    #[default]
+    Mbox,
+    Zip,
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct ExportQuery {
+    #[serde(default)]
+    format: ExportFormat,
+}
+
+/// a zip archive with an .eml file per message
+fn zip_messages(messages: &[MailMessage]) -> zip::result::ZipResult<Vec<u8>> {
+    let mut archive = zip::ZipWriter::new(Cursor::new(Vec::new()));
+    let options = zip::write::SimpleFileOptions::default();
+
+    for message in messages {
+        archive.start_file(format!("{}.eml", message.id), options)?;
+        archive.write_all(&message.raw_bytes().unwrap_or_default())?;
+    }
+
+    Ok(archive.finish()?.into_inner())
+}
+
+/// export all messages matching the filter as an mbox file or zip archive
+async fn export_handler(
+    Query(query): Query<ExportQuery>,
+    Query(filter): Query<MessageFilter>,
+    Extension(state): Extension<Arc<AppState>>,
+) -> Result<Response, StatusCode> {
+    let messages = filtered_messages(&state, &filter).ok_or(StatusCode::INTERNAL_SERVER_ERROR)?;
+
+    let (content_type, filename, bytes) = match query.format {
+        ExportFormat::Mbox => {
+            let mut mbox = Vec::new();
+            for message in &messages {
+                append_message(
+                    &mut mbox,
+                    &message.envelope_from,
+                    message.time,
+                    &message.raw_bytes().unwrap_or_default(),
+                );
+            }
+
+            ("application/mbox", "mailcrab.mbox", mbox)
+        }
+        ExportFormat::Zip => {
+            let archive = zip_messages(&messages).map_err(|e| {
+                error!("could not create zip archive {e}");
+
+                StatusCode::INTERNAL_SERVER_ERROR
+            })?;
+
+            ("application/zip", "mailcrab.zip", archive)
+        }
+    };
+
+    let len = bytes.len();
+    Ok(Response::builder()
+        .header(header::CONTENT_TYPE, content_type)
+        .header(header::CONTENT_LENGTH, len)
+        .header(
+            header::CONTENT_DISPOSITION,
+            format!("attachment; filename=\"{filename}\""),
+        )
+        .body(Body::from(bytes))
+        .unwrap())
+}
+
+/// the maximum size of an upload to import, e.g. an mbox file or zip archive
+const MAX_IMPORT_SIZE: usize = 100 * 1024 * 1024;
+
+/// the maximum total size of the messages in an imported zip archive, when decompressed
+pub(crate) const MAX_DECOMPRESSED_IMPORT_SIZE: u64 = 256 * 1024 * 1024;
+
+/// envelope data for imported messages
+#[derive(Debug, Default, Deserialize)]
+struct ImportQuery {
+    /// envelope sender, defaults to the sender in the mbox separator line
+    from: Option<String>,
+    /// comma separated envelope recipients
+    to: Option<String>,
+}
+
+/// split an upload in messages, it is either a zip archive, an mbox file or a single message,
+/// returns (envelope sender, message) pairs, a zip archive can not decompress to more than
+/// `MAX_DECOMPRESSED_IMPORT_SIZE` bytes
+fn split_upload(upload: &[u8]) -> AppResult<Vec<(Option<String>, Vec<u8>)>> {
+    if upload.starts_with(b"PK\x03\x04") {
+        let mut archive = zip::ZipArchive::new(Cursor::new(upload))
+            .map_err(|e| Error::WebServer(format!("invalid zip archive {e}")))?;
+
+        let mut names = archive
+            .file_names()
+            .filter(|name| name.to_lowercase().ends_with(".eml"))
+            .map(ToOwned::to_owned)
+            .collect::<Vec<String>>();
+        names.sort();
+
+        let mut messages = Vec::new();
+        let mut remaining = MAX_DECOMPRESSED_IMPORT_SIZE;
+        for name in names {
+            let file = archive
+                .by_name(&name)
+                .map_err(|e| Error::WebServer(format!("invalid zip archive {e}")))?;
+            let mut raw = Vec::new();
+            file.take(remaining + 1).read_to_end(&mut raw)?;
+
+            remaining = remaining.checked_sub(raw.len() as u64).ok_or_else(|| {
+                Error::WebServer("the zip archive is too large when decompressed".to_owned())
+            })?;
+            messages.push((None, raw));
+        }
+
+        return Ok(messages);
+    }
+
+    if upload.starts_with(b"From ") {
+        return Ok(split_messages(upload)
+            .into_iter()
+            .map(|message| (message.envelope_from, message.raw))
+            .collect());
+    }
+
+    Ok(vec![(None, upload.to_vec())])
+}
+
+/// import messages from a raw message, mbox file or zip archive, they are stored directly,
+/// the broadcast queue could drop them when more messages are imported than it can hold
+/// and websocket clients are notified separately, so the storage task does not store them again
+async fn import_handler(
+    Query(query): Query<ImportQuery>,
+    Extension(state): Extension<Arc<AppState>>,
+    body: Bytes,
+) -> Result<Json<Vec<Uuid>>, (StatusCode, String)> {
+    let bad_request = |e: Error| (StatusCode::BAD_REQUEST, e.to_string());
+
+    let uploads = split_upload(&body).map_err(bad_request)?;
+    let recipients = query
+        .to
+        .unwrap_or_default()
+        .split(',')
+        .map(|recipient| recipient.trim().to_owned())
+        .filter(|recipient| !recipient.is_empty())
+        .collect::<Vec<String>>();
+
+    // parse all messages before broadcasting, an invalid upload imports nothing
+    let parser = MessageParser::default();
+    let mut messages = Vec::new();
+    for (index, (envelope_from, raw)) in uploads.iter().enumerate() {
+        let parsed = parser.parse(raw).ok_or_else(|| {
+            bad_request(Error::WebServer(format!(
+                "failed to parse message {}",
+                index + 1
+            )))
+        })?;
+
+        let mut message: MailMessage = parsed.try_into().map_err(bad_request)?;
+        message.envelope_from = query
+            .from
+            .clone()
+            .or_else(|| envelope_from.clone())
+            .unwrap_or_default();
+        message.envelope_recipients = recipients.clone();
+        message.unwrap_smime(&state.smime_keys);
+        message.unwrap_openpgp(&state.openpgp_keys);
+        messages.push(message);
+    }
+
+    let ids = messages
+        .iter()
+        .map(|message| message.id)
+        .collect::<Vec<Uuid>>();
+    match state.storage.write() {
+        Ok(mut storage) => {
+            storage.extend(messages.iter().map(|message| (message.id, message.clone())))
+        }
+        Err(e) => {
+            error!("could not store imported messages {e}");
+
+            return Err((StatusCode::INTERNAL_SERVER_ERROR, e.to_string()));
+        }
+    }
+
+    // there are no receivers when no websocket clients are connected
+    for message in messages {
+        let _ = state.imported.send(message);
+    }
+
+    info!("imported {} messages", ids.len());
+
+    Ok(Json(ids))
+}
+
 async fn not_found() -> Response {
     Response::builder()
         .status(StatusCode::NOT_FOUND)
@@ -287,9 +1111,27 @@ pub async fn web_server(
 ) -> AppResult<()> {
     let mut router = Router::new()
         .route("/ws", get(ws_handler))
-        .route("/api/messages", get(messages_handler))
+        .route(
+            "/api/messages",
+            get(messages_handler)
+                .post(import_handler)
+                .layer(DefaultBodyLimit::max(MAX_IMPORT_SIZE)),
+        )
         .route("/api/message/{id}", get(message_handler))
         .route("/api/message/{id}/body", get(message_body_handler))
+        .route("/api/message/{id}/amp", get(message_amp_handler))
+        .route("/api/message/{id}/remote", get(remote_content_handler))
+        .route(
+            "/api/message/{id}/compatibility",
+            get(compatibility_handler),
+        )
+        .route("/api/message/{id}/links", get(links_handler))
+        .route("/api/message/{id}/spam", get(spam_handler))
+        .route("/api/message/{id}/dkim", get(dkim_handler))
+        .route(
+            "/api/message/{id}/authentication",
+            get(authentication_handler),
+        )
         .route("/api/delete/{id}", post(message_delete_handler))
         .route("/api/delete-all", post(message_delete_all_handler))
         .route("/api/version", get(version_handler))
@@ -297,7 +1139,28 @@ pub async fn web_server(
             "/api/message/{id}/attachment/{index}",
             get(attachment_handler),
         )
+        .route(
+            "/api/message/{id}/embedded/{path}/body",
+            get(embedded_body_handler),
+        )
+        .route(
+            "/api/message/{id}/embedded/{path}/attachment/{index}",
+            get(embedded_attachment_handler),
+        )
+        .route("/api/message/{id}/calendar", get(calendar_handler))
+        .route("/api/message/{id}/parts", get(parts_handler))
+        .route("/api/message/{id}/parts/{part}", get(part_handler))
         .route("/api/message/{id}/raw", get(message_raw_handler))
+        .route("/api/message/{id}/eml", get(message_eml_handler))
+        .route("/api/export", get(export_handler))
+        .route(
+            "/api/latency",
+            get(latency_handler).put(latency_update_handler),
+        )
+        .route(
+            "/api/config",
+            get(config_handler).put(config_update_handler),
+        )
         .nest_service("/static", get(static_handler))
         .layer(
             TraceLayer::new_for_http()
@@ -326,3 +1189,22 @@ pub async fn web_server(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn internal_links() {
+        for url in [
+            "http://localhost:1/",
+            "http://[::ffff:127.0.0.1]:1/",
+            "http://[::ffff:10.0.0.1]:1/",
+        ] {
+            assert_eq!(
+                check_link(url).await,
+                Reachability::Error("internal address".to_owned())
+            );
+        }
+    }
+}
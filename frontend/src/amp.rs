@@ -0,0 +1,29 @@
+use crate::{api::get_api_path, message_header::MessageHeader, types::MailMessage};
+use yew::{Html, Properties, function_component, html};
+
+#[derive(Properties, Eq, PartialEq)]
+pub struct AmpProps {
+    pub message: MailMessage,
+}
+
+/// the AMP for Email version of the message, as rendered by clients that support it
+#[function_component(Amp)]
+pub fn view(props: &AmpProps) -> Html {
+    let message = &props.message;
+
+    if message.id.is_empty() {
+        return html! {};
+    }
+
+    html! {
+      <>
+        <MessageHeader message={message.clone()} />
+        <div class="body">
+          <iframe
+            sandbox="allow-scripts allow-popups allow-popups-to-escape-sandbox"
+            src={get_api_path(&format!("message/{}/amp", message.id))}
+          ></iframe>
+        </div>
+      </>
+    }
+}
@@ -1,6 +1,9 @@
 use gloo_net::http::Request;
 
-use crate::types::{MailMessage, MailMessageMetadata};
+use crate::types::{
+    AuthenticationReport, CompatibilityIssue, Link, MailMessage, MailMessageMetadata, MimePart,
+    RemoteResource, SpamReport,
+};
 
 pub fn get_api_path(path: &str) -> String {
     let mut pathname = web_sys::window()
@@ -55,3 +58,62 @@ pub async fn fetch_raw(id: &str) -> String {
         .await
         .unwrap_or_else(|e| format!("Failed to read raw message: {e}"))
 }
+
+pub async fn fetch_compatibility(id: &str) -> Vec<CompatibilityIssue> {
+    let url = get_api_path(&format!("message/{}/compatibility", id));
+
+    match Request::get(&url).send().await {
+        Ok(response) => response.json().await.unwrap_or_default(),
+        Err(_) => Vec::new(),
+    }
+}
+
+pub async fn fetch_links(id: &str, check: bool) -> Vec<Link> {
+    let url = get_api_path(&format!("message/{}/links?check={}", id, check));
+
+    match Request::get(&url).send().await {
+        Ok(response) => response.json().await.unwrap_or_default(),
+        Err(_) => Vec::new(),
+    }
+}
+
+pub async fn fetch_spam_report(id: &str) -> Option<SpamReport> {
+    let url = get_api_path(&format!("message/{}/spam", id));
+
+    Request::get(&url).send().await.ok()?.json().await.ok()
+}
+
+pub async fn fetch_authentication(id: &str) -> Option<AuthenticationReport> {
+    let url = get_api_path(&format!("message/{}/authentication", id));
+
+    Request::get(&url).send().await.ok()?.json().await.ok()
+}
+
+pub async fn fetch_remote(id: &str) -> Vec<RemoteResource> {
+    let url = get_api_path(&format!("message/{}/remote", id));
+
+    match Request::get(&url).send().await {
+        Ok(response) => response.json().await.unwrap_or_default(),
+        Err(_) => Vec::new(),
+    }
+}
+
+pub async fn fetch_parts(id: &str) -> Option<MimePart> {
+    let url = get_api_path(&format!("message/{}/parts", id));
+
+    Request::get(&url).send().await.ok()?.json().await.ok()
+}
+
+pub async fn fetch_part_text(id: &str, part: &str) -> String {
+    let url = get_api_path(&format!("message/{}/parts/{}", id, part));
+
+    let response = match Request::get(&url).send().await {
+        Ok(r) => r,
+        Err(e) => return format!("Failed to load part: {e}"),
+    };
+
+    response
+        .text()
+        .await
+        .unwrap_or_else(|e| format!("Failed to read part: {e}"))
+}
@@ -0,0 +1,106 @@
+use crate::{api::fetch_authentication, types::AuthenticationReport};
+use wasm_bindgen_futures::spawn_local;
+use yew::{Html, Properties, UseStateHandle, function_component, html, use_effect_with, use_state};
+
+#[derive(Properties, Eq, PartialEq)]
+pub struct AuthenticationProps {
+    pub id: String,
+}
+
+/// a result cell, failures are highlighted
+fn result(result: &str) -> Html {
+    html! {
+      <td class={format!("auth-result {}", result)}>{result}</td>
+    }
+}
+
+#[function_component(Authentication)]
+pub fn view(props: &AuthenticationProps) -> Html {
+    let report: UseStateHandle<Option<AuthenticationReport>> = use_state(|| None);
+
+    {
+        let report = report.clone();
+        use_effect_with(props.id.clone(), move |id| {
+            let id = id.clone();
+            report.set(None);
+            spawn_local(async move {
+                report.set(fetch_authentication(&id).await);
+            });
+            || ()
+        });
+    }
+
+    let Some(report) = &*report else {
+        return html! {};
+    };
+
+    html! {
+      <>
+        <pre class="auth-summary">{format!("Authentication-Results: {}", report.summary)}</pre>
+        <table class="report">
+          <thead>
+            <tr>
+              <th>{"Check"}</th>
+              <th>{"Result"}</th>
+              <th>{"Domain"}</th>
+              <th>{"Details"}</th>
+            </tr>
+          </thead>
+          <tbody>
+            <tr>
+              <td>{"SPF"}</td>
+              {result(&report.spf.result)}
+              <td><code>{&report.spf.domain}</code></td>
+              <td>{report.spf.reason.clone().unwrap_or_default()}</td>
+            </tr>
+            {report.dkim.iter().map(|signature| html! {
+              <tr>
+                <td>{"DKIM"}</td>
+                {result(&signature.result)}
+                <td><code>{&signature.domain}</code></td>
+                <td>
+                  {format!(
+                    "selector {}, {} ({})",
+                    signature.selector, signature.algorithm, signature.canonicalization
+                  )}
+                  if let Some(reason) = &signature.reason {
+                    <br />{reason}
+                  }
+                </td>
+              </tr>
+            }).collect::<Html>()}
+            if report.dkim.is_empty() {
+              <tr>
+                <td>{"DKIM"}</td>
+                {result("none")}
+                <td></td>
+                <td>{"No DKIM signatures"}</td>
+              </tr>
+            }
+            <tr>
+              <td>{"DMARC"}</td>
+              {result(&report.dmarc.result)}
+              <td><code>{&report.dmarc.domain}</code></td>
+              <td>
+                if let Some(policy) = &report.dmarc.policy {
+                  {format!("policy {}, ", policy)}
+                }
+                {report.dmarc.reason.clone().unwrap_or_default()}
+              </td>
+            </tr>
+            <tr>
+              <td>{"ARC"}</td>
+              {result(&report.arc.result)}
+              <td></td>
+              <td>
+                {format!("{} ARC sets", report.arc.instances)}
+                if let Some(reason) = &report.arc.reason {
+                  <br />{reason}
+                }
+              </td>
+            </tr>
+          </tbody>
+        </table>
+      </>
+    }
+}
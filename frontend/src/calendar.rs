@@ -0,0 +1,112 @@
+use crate::types::{Calendar, CalendarEvent, CalendarParticipant, CalendarTime};
+use yew::{Html, Properties, function_component, html};
+
+#[derive(Properties, Eq, PartialEq)]
+pub struct InvitationProps {
+    pub calendar: Calendar,
+}
+
+fn participant(participant: &CalendarParticipant) -> Html {
+    html! {
+      <span class="user">
+        <span class="name">{participant.name.clone().unwrap_or_default()}</span>
+        <span class="email">{&participant.email}</span>
+        if let Some(status) = &participant.status {
+          <span class={format!("status {}", status.to_lowercase())}>
+            {status.to_lowercase().replace('-', " ")}
+          </span>
+        }
+        if participant.role.as_deref() == Some("OPT-PARTICIPANT") {
+          <span class="status">{"optional"}</span>
+        }
+      </span>
+    }
+}
+
+fn time(time: &CalendarTime) -> String {
+    match &time.timezone {
+        Some(timezone) => format!("{} ({timezone})", time.display),
+        None => time.display.clone(),
+    }
+}
+
+/// start and end, the end date is left out for events that end on the day they start
+fn when(event: &CalendarEvent) -> Option<String> {
+    let start = event.start.as_ref()?;
+    let same_day = |end: &CalendarTime| {
+        !end.date_only
+            && end.timezone == start.timezone
+            && end.display.get(..10) == start.display.get(..10)
+    };
+
+    Some(match (&event.end, &event.duration) {
+        (Some(end), _) if same_day(end) => {
+            let end = end.display.get(11..).unwrap_or_default();
+            match &start.timezone {
+                Some(timezone) => format!("{} - {end} ({timezone})", start.display),
+                None => format!("{} - {end}", start.display),
+            }
+        }
+        (Some(end), _) => format!("{} - {}", time(start), time(end)),
+        (None, Some(duration)) => format!("{}, duration {duration}", time(start)),
+        (None, None) => time(start),
+    })
+}
+
+fn event(method: Option<&str>, event: &CalendarEvent) -> Html {
+    let rows = [
+        ("When", when(event)),
+        ("Repeats", event.recurrence.clone()),
+        ("Location", event.location.clone()),
+        ("Status", event.status.clone()),
+    ];
+
+    html! {
+      <div class="invitation">
+        <div class="title">
+          {event.summary.clone().unwrap_or_default()}
+          if let Some(method) = method {
+            <span class={format!("method {}", method.to_lowercase())}>{method.to_lowercase()}</span>
+          }
+        </div>
+        <table>
+          <tbody>
+            {rows.into_iter().filter_map(|(label, value)| value.map(|value| html! {
+              <tr>
+                <th>{label}</th>
+                <td>{value}</td>
+              </tr>
+            })).collect::<Html>()}
+            if let Some(organizer) = &event.organizer {
+              <tr>
+                <th>{"Organizer"}</th>
+                <td>{participant(organizer)}</td>
+              </tr>
+            }
+            if !event.attendees.is_empty() {
+              <tr>
+                <th>{"Attendees"}</th>
+                <td>{event.attendees.iter().map(participant).collect::<Html>()}</td>
+              </tr>
+            }
+          </tbody>
+        </table>
+        if let Some(description) = &event.description {
+          <pre>{description}</pre>
+        }
+      </div>
+    }
+}
+
+/// the events of a calendar invitation, as shown by mail clients
+#[function_component(Invitation)]
+pub fn view(props: &InvitationProps) -> Html {
+    let method = props.calendar.method.as_deref();
+
+    props
+        .calendar
+        .events
+        .iter()
+        .map(|e| event(method, e))
+        .collect()
+}
@@ -0,0 +1,63 @@
+use crate::{api::fetch_compatibility, types::CompatibilityIssue};
+use wasm_bindgen_futures::spawn_local;
+use yew::{Html, Properties, UseStateHandle, function_component, html, use_effect_with, use_state};
+
+#[derive(Properties, Eq, PartialEq)]
+pub struct CompatibilityProps {
+    pub id: String,
+}
+
+/// label of a feature, elements are shown as tags
+fn feature_label(issue: &CompatibilityIssue) -> String {
+    match issue.kind.as_str() {
+        "element" => format!("<{}>", issue.feature),
+        "attribute" => format!("{} attribute", issue.feature),
+        _ => issue.feature.clone(),
+    }
+}
+
+#[function_component(Compatibility)]
+pub fn view(props: &CompatibilityProps) -> Html {
+    let issues: UseStateHandle<Option<Vec<CompatibilityIssue>>> = use_state(|| None);
+
+    {
+        let issues = issues.clone();
+        use_effect_with(props.id.clone(), move |id| {
+            let id = id.clone();
+            issues.set(None);
+            spawn_local(async move {
+                issues.set(Some(fetch_compatibility(&id).await));
+            });
+            || ()
+        });
+    }
+
+    match &*issues {
+        None => html! {},
+        Some(issues) if issues.is_empty() => html! {
+          <p class="report-empty">{"No compatibility issues found"}</p>
+        },
+        Some(issues) => html! {
+          <table class="report">
+            <thead>
+              <tr>
+                <th>{"Feature"}</th>
+                <th>{"Location"}</th>
+                <th>{"Not supported in"}</th>
+              </tr>
+            </thead>
+            <tbody>
+              {issues.iter().map(|issue| {
+                html! {
+                  <tr>
+                    <td><code>{feature_label(issue)}</code></td>
+                    <td>{format!("line {}, column {}", issue.line, issue.column)}</td>
+                    <td>{issue.clients.join(", ")}</td>
+                  </tr>
+                }
+              }).collect::<Html>()}
+            </tbody>
+          </table>
+        },
+    }
+}
@@ -0,0 +1,97 @@
+use crate::{
+    api::get_api_path,
+    types::{Address, EmbeddedMessage},
+};
+use yew::{Html, Properties, function_component, html};
+
+#[derive(Properties, Eq, PartialEq)]
+pub struct EmbeddedProps {
+    pub id: String,
+    /// the indices of the messages that embed these messages, separated by dots
+    pub path: String,
+    pub messages: Vec<EmbeddedMessage>,
+}
+
+fn address(address: &Address) -> Html {
+    html! {
+      <span class="user">
+        <span class="name">
+          {address.name.clone().unwrap_or_default()}
+        </span>
+        <span class="email">
+          {address.email.clone().unwrap_or_default()}
+        </span>
+      </span>
+    }
+}
+
+/// forwarded and attached messages, expanded on click, with their own embedded messages
+#[function_component(Embedded)]
+pub fn view(props: &EmbeddedProps) -> Html {
+    props
+        .messages
+        .iter()
+        .enumerate()
+        .map(|(index, message)| {
+            let path = match props.path.is_empty() {
+                true => index.to_string(),
+                false => format!("{}.{index}", props.path),
+            };
+            let url = format!("message/{}/embedded/{path}", props.id);
+
+            html! {
+              <details class="embedded">
+                <summary>
+                  {&message.subject}
+                  <span class="size">{&message.size}</span>
+                </summary>
+                <table>
+                  <tbody>
+                    if let Some(from) = &message.from {
+                      <tr>
+                        <th>{"From"}</th>
+                        <td>{address(from)}</td>
+                      </tr>
+                    }
+                    <tr>
+                      <th>{"To"}</th>
+                      <td>{message.to.iter().map(address).collect::<Html>()}</td>
+                    </tr>
+                    if let Some(date) = &message.date {
+                      <tr>
+                        <th>{"Date"}</th>
+                        <td>{date}</td>
+                      </tr>
+                    }
+                  </tbody>
+                </table>
+                if message.html.is_empty() {
+                  <pre>{&message.text}</pre>
+                } else {
+                  <iframe
+                    sandbox="allow-popups allow-popups-to-escape-sandbox"
+                    src={get_api_path(&format!("{url}/body"))}
+                  ></iframe>
+                }
+                <div class="actions">
+                  {message.attachments.iter().enumerate().map(|(index, a)| html! {
+                    <a
+                      href={get_api_path(&format!("{url}/attachment/{index}"))}
+                      download={a.filename.clone()}
+                      class={a.mime.replace('/', "-")}
+                    >
+                      {&a.filename}
+                      <span class="size">{&a.size}</span>
+                    </a>
+                  }).collect::<Html>()}
+                </div>
+                <Embedded
+                  id={props.id.clone()}
+                  path={path.clone()}
+                  messages={message.embedded.clone()}
+                />
+              </details>
+            }
+        })
+        .collect()
+}
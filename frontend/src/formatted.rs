@@ -1,62 +1,178 @@
-use crate::{api::get_api_path, message_header::MessageHeader, types::MailMessage};
-use wasm_bindgen::JsCast;
-use web_sys::{Event, HtmlIFrameElement, HtmlLinkElement};
-use yew::{Html, Properties, function_component, html};
+use crate::{
+    api::get_api_path, message_header::MessageHeader, remote::RemoteContent, types::MailMessage,
+};
+use yew::{Callback, Html, Properties, function_component, html, use_effect_with, use_state};
 
 #[derive(Properties, Eq, PartialEq)]
 pub struct FormattedProps {
     pub message: MailMessage,
 }
 
-fn try_set_font(e: &Event) -> Option<()> {
-    let target = e.target()?;
-    let element = target.dyn_ref::<HtmlIFrameElement>()?;
+/// widths of the body preview
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Device {
+    Mobile,
+    Tablet,
+    Desktop,
+}
+
+impl Device {
+    const ALL: [Device; 3] = [Device::Mobile, Device::Tablet, Device::Desktop];
 
-    element
-        .content_document()?
-        .body()?
-        .style()
-        .set_css_text("font-family:sans-serif;line-height:1.5");
+    fn label(self) -> &'static str {
+        match self {
+            Device::Mobile => "Mobile",
+            Device::Tablet => "Tablet",
+            Device::Desktop => "Desktop",
+        }
+    }
 
-    Some(())
+    fn class(self) -> &'static str {
+        match self {
+            Device::Mobile => "mobile",
+            Device::Tablet => "tablet",
+            Device::Desktop => "desktop",
+        }
+    }
 }
 
-fn try_set_link_targets(e: &Event) -> Option<()> {
-    let target = e.target()?;
-    let element = target.dyn_ref::<HtmlIFrameElement>()?;
+/// the ways mail clients show a message in dark mode
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+enum Scheme {
+    /// no dark mode at all
+    Light,
+    /// only `prefers-color-scheme: dark` styles apply, like Apple Mail
+    Dark,
+    /// light backgrounds and dark text are inverted, like Outlook.com
+    PartialInvert,
+    /// all colors are inverted, except for images, like the Gmail apps
+    FullInvert,
+}
 
-    let links = element.content_document()?.query_selector_all("a").ok()?;
+impl Scheme {
+    const ALL: [Scheme; 4] = [
+        Scheme::Light,
+        Scheme::Dark,
+        Scheme::PartialInvert,
+        Scheme::FullInvert,
+    ];
 
-    for i in 0..links.length() {
-        if let Some(l) = links.get(i) {
-            l.unchecked_into::<HtmlLinkElement>().set_target("_blank")
+    fn label(self) -> &'static str {
+        match self {
+            Scheme::Light => "Light",
+            Scheme::Dark => "Dark",
+            Scheme::PartialInvert => "Partial inversion",
+            Scheme::FullInvert => "Full inversion",
         }
     }
 
-    Some(())
+    fn title(self) -> &'static str {
+        match self {
+            Scheme::Light => "No dark mode",
+            Scheme::Dark => "Apply prefers-color-scheme: dark styles, like Apple Mail",
+            Scheme::PartialInvert => "Invert light backgrounds and dark text, like Outlook.com",
+            Scheme::FullInvert => "Invert all colors except images, like the Gmail apps",
+        }
+    }
+
+    /// the `scheme` parameter of the body
+    fn query(self) -> Option<&'static str> {
+        match self {
+            Scheme::Light => None,
+            Scheme::Dark => Some("dark"),
+            Scheme::PartialInvert => Some("partial_invert"),
+            Scheme::FullInvert => Some("full_invert"),
+        }
+    }
 }
 
 #[function_component(Formatted)]
 pub fn view(props: &FormattedProps) -> Html {
+    let device = use_state(|| Device::Desktop);
+    let scheme = use_state(|| Scheme::Light);
+    let remote = use_state(|| false);
+    let sanitize = use_state(|| false);
     let message = &props.message;
     let mut body_src = get_api_path("message/");
     body_src.push_str(message.id.as_str());
     body_src.push_str("/body");
 
-    let onload = |e: Event| {
-        try_set_font(&e);
-        try_set_link_targets(&e);
-    };
+    let parameters = [
+        remote.then_some("remote=1".to_owned()),
+        sanitize.then_some("sanitize=1".to_owned()),
+        scheme.query().map(|scheme| format!("scheme={scheme}")),
+    ];
+    let query = parameters.into_iter().flatten().collect::<Vec<_>>();
+    if !query.is_empty() {
+        body_src.push('?');
+        body_src.push_str(&query.join("&"));
+    }
+
+    // remote content is blocked again when another message is opened
+    {
+        let remote = remote.clone();
+        use_effect_with(message.id.clone(), move |_| {
+            remote.set(false);
+            || ()
+        });
+    }
 
     if message.id.is_empty() {
         return html! {};
     }
 
+    let set_device = |d: Device| {
+        let device = device.clone();
+        Callback::from(move |_| device.set(d))
+    };
+    let set_scheme = |s: Scheme| {
+        let scheme = scheme.clone();
+        Callback::from(move |_| scheme.set(s))
+    };
+    let toggle_remote = {
+        let remote = remote.clone();
+        Callback::from(move |_| remote.set(!*remote))
+    };
+    let toggle_sanitize = {
+        let sanitize = sanitize.clone();
+        Callback::from(move |_| sanitize.set(!*sanitize))
+    };
+    let active = |is_active: bool| if is_active { "active" } else { "" };
+
     html! {
       <>
         <MessageHeader message={message.clone()} />
-        <div class="body">
-          <iframe onload={onload} src={body_src}></iframe>
+        <div class="preview-toolbar">
+          <div class="group">
+            {Device::ALL.into_iter().map(|d| html! {
+              <button class={active(*device == d)} onclick={set_device(d)}>{d.label()}</button>
+            }).collect::<Html>()}
+          </div>
+          <div class="group">
+            {Scheme::ALL.into_iter().map(|s| html! {
+              <button class={active(*scheme == s)} title={s.title()} onclick={set_scheme(s)}>
+                {s.label()}
+              </button>
+            }).collect::<Html>()}
+          </div>
+          <div class="group">
+            <button
+              class={active(*sanitize)}
+              title="Remove scripts, frames, forms and event handlers"
+              onclick={toggle_sanitize}
+            >
+              {"Sanitize"}
+            </button>
+          </div>
+        </div>
+        <RemoteContent id={message.id.clone()} loaded={*remote} toggle={toggle_remote} />
+        <div class={format!("body {}", device.class())}>
+          // scripts never run, and the body has no access to the web interface
+          <iframe
+            class={if *scheme == Scheme::Light { "" } else { "simulated" }}
+            sandbox="allow-popups allow-popups-to-escape-sandbox"
+            src={body_src}
+          ></iframe>
         </div>
       </>
     }
@@ -0,0 +1,127 @@
+use crate::{
+    api::fetch_links,
+    types::{Link, Reachability},
+};
+use wasm_bindgen_futures::spawn_local;
+use yew::{
+    Callback, Html, Properties, UseStateHandle, function_component, html, use_effect_with,
+    use_state,
+};
+
+#[derive(Properties, Eq, PartialEq)]
+pub struct LinksProps {
+    pub id: String,
+}
+
+fn issue_label(issue: &str) -> &'static str {
+    match issue {
+        "insecure_http" => "Uses http instead of https",
+        "localhost" => "Links to localhost",
+        "private_address" => "Links to a private IP address",
+        "malformed" => "Malformed URL",
+        "missing_alt" => "Image without alt text",
+        "missing_attachment" => "No attachment with this content id",
+        _ => "Unknown issue",
+    }
+}
+
+fn reachability_label(link: &Link) -> String {
+    match &link.reachability {
+        Some(Reachability {
+            status: Some(status),
+            ..
+        }) => status.to_string(),
+        Some(Reachability {
+            error: Some(error), ..
+        }) => error.clone(),
+        _ => String::new(),
+    }
+}
+
+#[function_component(Links)]
+pub fn view(props: &LinksProps) -> Html {
+    let links: UseStateHandle<Option<Vec<Link>>> = use_state(|| None);
+    let checking: UseStateHandle<bool> = use_state(|| false);
+
+    {
+        let links = links.clone();
+        use_effect_with(props.id.clone(), move |id| {
+            let id = id.clone();
+            links.set(None);
+            spawn_local(async move {
+                links.set(Some(fetch_links(&id, false).await));
+            });
+            || ()
+        });
+    }
+
+    let check = {
+        let id = props.id.clone();
+        let links = links.clone();
+        let checking = checking.clone();
+
+        Callback::from(move |_| {
+            let id = id.clone();
+            let links = links.clone();
+            let checking = checking.clone();
+            checking.set(true);
+            spawn_local(async move {
+                links.set(Some(fetch_links(&id, true).await));
+                checking.set(false);
+            });
+        })
+    };
+
+    let Some(links) = &*links else {
+        return html! {};
+    };
+
+    if links.is_empty() {
+        return html! {
+          <p class="report-empty">{"No links or images found"}</p>
+        };
+    }
+
+    html! {
+      <>
+        <div class="actions">
+          <button class="check-links" onclick={check} disabled={*checking}>
+            {if *checking { "Checking..." } else { "Check reachability" }}
+          </button>
+        </div>
+        <table class="report">
+          <thead>
+            <tr>
+              <th>{"URL"}</th>
+              <th>{"Location"}</th>
+              <th>{"Issues"}</th>
+              <th>{"Status"}</th>
+            </tr>
+          </thead>
+          <tbody>
+            {links.iter().map(|link| {
+              let location = match (&link.element, &link.attribute) {
+                (Some(element), Some(attribute)) => format!(
+                  "<{element} {attribute}> at line {}, column {}", link.line, link.column
+                ),
+                _ => format!("text at line {}, column {}", link.line, link.column),
+              };
+
+              html! {
+                <tr class={if link.issues.is_empty() { "" } else { "has-issues" }}>
+                  <td class="url"><code>{&link.url}</code></td>
+                  <td>{location}</td>
+                  <td>
+                    {link.issues.iter().map(|issue| html! {
+                      <div>{issue_label(issue)}</div>
+                    }).collect::<Html>()}
+                  </td>
+                  <td>{reachability_label(link)}</td>
+                </tr>
+              }
+            }).collect::<Html>()}
+          </tbody>
+        </table>
+      </>
+    }
+}
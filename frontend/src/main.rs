@@ -1,12 +1,21 @@
 use overview::Overview;
 
+mod amp;
 mod api;
+mod authentication;
+mod calendar;
+mod compatibility;
 mod dark_mode;
+mod embedded;
 mod formatted;
+mod links;
 mod list;
 mod message_header;
 mod overview;
+mod parts;
 mod plaintext;
+mod remote;
+mod spam;
 mod types;
 mod view;
 mod websocket;
@@ -1,19 +1,177 @@
-use crate::{api::get_api_path, dark_mode::toggle_body_invert, types::MailMessage};
-use yew::{Callback, Html, Properties, function_component, html, html_nested};
+use crate::{
+    api::get_api_path,
+    calendar::Invitation,
+    dark_mode::toggle_body_invert,
+    embedded::Embedded,
+    types::{Attachment, MailMessage, OpenPgpReport, SmimeReport},
+};
+use web_sys::MouseEvent;
+use yew::{
+    Callback, Html, Properties, UseStateHandle, function_component, html, html_nested,
+    use_effect_with, use_state,
+};
 
 #[derive(Properties, Eq, PartialEq)]
 pub struct MessageHeaderProps {
     pub message: MailMessage,
 }
 
+/// the decryption and signature status of an S/MIME message
+fn smime_status(smime: &SmimeReport) -> Html {
+    html! {
+      <td class="smime">
+        if smime.encrypted && smime.decrypted {
+          <div>{"Encrypted, decrypted with a configured key"}</div>
+        } else if smime.encrypted {
+          <div class="invalid">{"Encrypted, could not be decrypted"}</div>
+        }
+        {smime.signatures.iter().map(|signature| html! {
+          <div class={if signature.result == "pass" { "" } else { "invalid" }}>
+            {format!("Signature {}", signature.result)}
+            if signature.trusted {
+              {" (trusted)"}
+            }
+            if let Some(signer) = &signature.signer {
+              {format!(", signed by {signer}")}
+            }
+            if let Some(reason) = &signature.reason {
+              {format!(": {reason}")}
+            }
+          </div>
+        }).collect::<Html>()}
+        if let Some(reason) = &smime.reason {
+          <div class="invalid">{reason}</div>
+        }
+      </td>
+    }
+}
+
+/// the decryption and signature status of an OpenPGP/MIME message
+fn openpgp_status(openpgp: &OpenPgpReport) -> Html {
+    html! {
+      <td class="openpgp">
+        if openpgp.encrypted && openpgp.decrypted {
+          <div>{"Encrypted, decrypted with a configured key"}</div>
+        } else if openpgp.encrypted {
+          <div class="invalid">{"Encrypted, could not be decrypted"}</div>
+        }
+        {openpgp.signatures.iter().map(|signature| html! {
+          <div class={if signature.result == "pass" { "" } else { "invalid" }}>
+            {format!("Signature {}", signature.result)}
+            if let Some(signer) = &signature.signer {
+              {format!(", signed by {signer}")}
+            }
+            if let Some(key_id) = &signature.key_id {
+              {format!(" (key {key_id})")}
+            }
+            if let Some(reason) = &signature.reason {
+              {format!(": {reason}")}
+            }
+          </div>
+        }).collect::<Html>()}
+        if let Some(reason) = &openpgp.reason {
+          <div class="invalid">{reason}</div>
+        }
+      </td>
+    }
+}
+
+/// how an attachment can be shown in the preview pane
+#[derive(Clone, Copy, PartialEq)]
+enum Preview {
+    Image,
+    Pdf,
+    Text,
+    /// a forwarded or attached message, by its index in the embedded messages
+    Message(usize),
+}
+
+fn preview(attachments: &[Attachment], index: usize) -> Option<Preview> {
+    let mime = attachments.get(index)?.mime.to_lowercase();
+
+    if mime == "message/rfc822" {
+        let embedded = attachments[..index]
+            .iter()
+            .filter(|attachment| attachment.mime.eq_ignore_ascii_case("message/rfc822"))
+            .count();
+
+        return Some(Preview::Message(embedded));
+    }
+
+    if mime.starts_with("image/") {
+        Some(Preview::Image)
+    } else if mime == "application/pdf" {
+        Some(Preview::Pdf)
+    } else if mime.starts_with("text/")
+        || mime.ends_with("/json")
+        || mime.ends_with("+json")
+        || mime.ends_with("/xml")
+    {
+        Some(Preview::Text)
+    } else {
+        None
+    }
+}
+
+/// the selected attachment, shown inline
+fn preview_pane(message: &MailMessage, index: usize, close: Callback<()>) -> Html {
+    let (Some(attachment), Some(kind)) = (
+        message.attachments.get(index),
+        preview(&message.attachments, index),
+    ) else {
+        return html! {};
+    };
+    let src = get_api_path(&format!(
+        "message/{}/attachment/{}?inline=1",
+        message.id, index
+    ));
+
+    html! {
+      <div class="preview">
+        <div class="preview-title">
+          {&attachment.filename}
+          <button onclick={move |_| close.emit(())}>{"Close"}</button>
+        </div>
+        {match kind {
+          Preview::Image => html! { <img src={src} alt={attachment.filename.clone()} /> },
+          Preview::Pdf | Preview::Text => html! { <iframe src={src}></iframe> },
+          Preview::Message(embedded) => html! {
+            <iframe
+              sandbox="allow-popups allow-popups-to-escape-sandbox"
+              src={get_api_path(&format!("message/{}/embedded/{embedded}/body", message.id))}
+            ></iframe>
+          },
+        }}
+      </div>
+    }
+}
+
 #[function_component(MessageHeader)]
 pub fn view(props: &MessageHeaderProps) -> Html {
     let message = &props.message;
+    let selected: UseStateHandle<Option<usize>> = use_state(|| None);
+
+    {
+        let selected = selected.clone();
+        use_effect_with(message.id.clone(), move |_| {
+            selected.set(None);
+            || ()
+        });
+    }
 
     if message.id.is_empty() {
         return html! {};
     }
 
+    let select = |index: usize| -> Callback<MouseEvent> {
+        let selected = selected.clone();
+        Callback::from(move |_| selected.set(Some(index)))
+    };
+    let close = {
+        let selected = selected.clone();
+        Callback::from(move |_| selected.set(None))
+    };
+
     html! {
       <>
         <table>
@@ -66,20 +224,45 @@ pub fn view(props: &MessageHeaderProps) -> Html {
                 </span>
               </td>
             </tr>
+            if let Some(peer_ip) = &message.peer_ip {
+              <tr>
+                <th>{"Client"}</th>
+                <td>{peer_ip}</td>
+              </tr>
+            }
+            if let Some(smime) = &message.smime {
+              <tr>
+                <th>{"S/MIME"}</th>
+                {smime_status(smime)}
+              </tr>
+            }
+            if let Some(openpgp) = &message.openpgp {
+              <tr>
+                <th>{"OpenPGP"}</th>
+                {openpgp_status(openpgp)}
+              </tr>
+            }
           </tbody>
         </table>
         <div class="actions">
           {message.attachments.iter().enumerate().map(|(index, a)| {
             let url = get_api_path(&format!("message/{}/attachment/{}", message.id, index));
             html! {
-              <a
-                href={url}
-                download={a.filename.clone()}
-                class={a.mime.replace('/', "-")}
-              >
-                {&a.filename}
-                <span class="size">{&a.size}</span>
-              </a>
+              <>
+                <a
+                  href={url}
+                  download={a.filename.clone()}
+                  class={a.mime.replace('/', "-")}
+                >
+                  {&a.filename}
+                  <span class="size">{&a.size}</span>
+                </a>
+                if preview(&message.attachments, index).is_some() {
+                  <button class="preview-attachment" onclick={select(index)}>
+                    {"Preview"}
+                  </button>
+                }
+              </>
             }
           }).collect::<Html>()}
           <button class="invert-body" onclick={Callback::from(|_| {
@@ -88,6 +271,27 @@ pub fn view(props: &MessageHeaderProps) -> Html {
             {"Invert body"}
           </button>
         </div>
+        <div class="thumbnails">
+          {message.attachments.iter().enumerate().filter(|(index, _)| {
+            preview(&message.attachments, *index) == Some(Preview::Image)
+          }).map(|(index, a)| html! {
+            <img
+              src={get_api_path(&format!("message/{}/attachment/{}?inline=1", message.id, index))}
+              alt={a.filename.clone()}
+              title={a.filename.clone()}
+              onclick={select(index)}
+            />
+          }).collect::<Html>()}
+        </div>
+        if let Some(index) = *selected {
+          {preview_pane(message, index, close)}
+        }
+        {message.calendar.iter().map(|calendar| html! {
+          <Invitation calendar={calendar.clone()} />
+        }).collect::<Html>()}
+        if !message.embedded.is_empty() {
+          <Embedded id={message.id.clone()} path="" messages={message.embedded.clone()} />
+        }
       </>
     }
 }
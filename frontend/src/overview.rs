@@ -5,7 +5,7 @@ use web_sys::NotificationOptions;
 use yew::prelude::*;
 
 use crate::{
-    api::fetch_messages_metadata,
+    api::{fetch_messages_metadata, get_api_path},
     dark_mode::{init_dark_mode, toggle_dark_mode},
     list::MessageList,
     types::{Action, MailMessageMetadata},
@@ -26,8 +26,14 @@ pub enum Msg {
 #[derive(Clone, PartialEq, Eq)]
 pub enum Tab {
     Formatted,
+    Amp,
     Text,
     Headers,
+    Compatibility,
+    Links,
+    Spam,
+    Authentication,
+    Structure,
     Raw,
 }
 
@@ -150,6 +156,12 @@ impl Component for Overview {
               <h1>{"Mail"}<span>{"Crab"}</span></h1>
               <div>
                 if !self.messages.is_empty() {
+                  <a class="button export" href={get_api_path("export?format=mbox")} download="">
+                    {"Export mbox"}
+                  </a>
+                  <a class="button export" href={get_api_path("export?format=zip")} download="">
+                    {"Export zip"}
+                  </a>
                   <button onclick={link.callback(|_| Msg::RemoveAll)}>
                     {"Remove all"}<span>{"("}{self.messages.len()}{")"}</span>
                   </button>
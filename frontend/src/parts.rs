@@ -0,0 +1,155 @@
+use crate::{
+    api::{fetch_part_text, fetch_parts, get_api_path},
+    types::MimePart,
+};
+use wasm_bindgen_futures::spawn_local;
+use yew::{
+    Callback, Html, Properties, UseStateHandle, function_component, html, use_effect_with,
+    use_state,
+};
+
+#[derive(Properties, Eq, PartialEq)]
+pub struct PartsProps {
+    pub id: String,
+}
+
+/// parts with content that can be shown as text
+fn is_text(part: &MimePart) -> bool {
+    ["text/", "message/", "multipart/"]
+        .iter()
+        .any(|prefix| part.content_type.starts_with(prefix))
+}
+
+fn tree(part: &MimePart, selected: &Option<MimePart>, select: &Callback<MimePart>) -> Html {
+    let onclick = {
+        let select = select.clone();
+        let part = part.clone();
+        move |_| select.emit(part.clone())
+    };
+    let class = match selected {
+        Some(selected) if selected.id == part.id => "active",
+        _ => "",
+    };
+
+    html! {
+      <li>
+        <button class={class} onclick={onclick}>
+          <code>{&part.content_type}</code>
+          if let Some(filename) = &part.filename {
+            <span class="filename">{filename}</span>
+          }
+          <span class="size">{format!("{} bytes", part.size)}</span>
+        </button>
+        if !part.parts.is_empty() {
+          <ul>
+            {part.parts.iter().map(|child| tree(child, selected, select)).collect::<Html>()}
+          </ul>
+        }
+      </li>
+    }
+}
+
+fn details(part: &MimePart) -> Html {
+    let rows = [
+        ("Part", Some(part.id.clone())),
+        ("Content type", Some(part.content_type.clone())),
+        ("Charset", part.charset.clone()),
+        ("Transfer encoding", part.transfer_encoding.clone()),
+        ("Disposition", part.disposition.clone()),
+        ("Filename", part.filename.clone()),
+        ("Content ID", part.content_id.clone()),
+        ("Size", Some(format!("{} bytes", part.size))),
+        (
+            "Offsets",
+            part.offsets.as_ref().map(|offsets| {
+                format!(
+                    "header {}, body {}, end {}",
+                    offsets.header, offsets.body, offsets.end
+                )
+            }),
+        ),
+    ];
+
+    html! {
+      <table>
+        <tbody>
+          {rows.into_iter().filter_map(|(label, value)| value.map(|value| html! {
+            <tr>
+              <th>{label}</th>
+              <td>{value}</td>
+            </tr>
+          })).collect::<Html>()}
+        </tbody>
+      </table>
+    }
+}
+
+/// the MIME structure of the raw message, clicking a part shows its decoded content
+#[function_component(Parts)]
+pub fn view(props: &PartsProps) -> Html {
+    let structure: UseStateHandle<Option<MimePart>> = use_state(|| None);
+    let selected: UseStateHandle<Option<MimePart>> = use_state(|| None);
+    let content: UseStateHandle<Option<String>> = use_state(|| None);
+
+    {
+        let structure = structure.clone();
+        let selected = selected.clone();
+        use_effect_with(props.id.clone(), move |id| {
+            let id = id.clone();
+            structure.set(None);
+            selected.set(None);
+            spawn_local(async move {
+                structure.set(fetch_parts(&id).await);
+            });
+            || ()
+        });
+    }
+
+    {
+        let content = content.clone();
+        let id = props.id.clone();
+        use_effect_with((*selected).clone(), move |part| {
+            content.set(None);
+            if let Some(part) = part.clone().filter(is_text) {
+                spawn_local(async move {
+                    content.set(Some(fetch_part_text(&id, &part.id).await));
+                });
+            }
+            || ()
+        });
+    }
+
+    let Some(root) = &*structure else {
+        return html! {};
+    };
+
+    let select = {
+        let selected = selected.clone();
+        Callback::from(move |part| selected.set(Some(part)))
+    };
+
+    html! {
+      <div class="parts">
+        <ul class="tree">
+          {tree(root, &selected, &select)}
+        </ul>
+        if let Some(part) = &*selected {
+          <div class="part">
+            {details(part)}
+            if is_text(part) {
+              <pre>{(*content).clone().unwrap_or_default()}</pre>
+            } else if part.content_type.starts_with("image/") {
+              <img src={get_api_path(&format!("message/{}/parts/{}", props.id, part.id))} />
+            } else {
+              <a
+                href={get_api_path(&format!("message/{}/parts/{}", props.id, part.id))}
+                download={part.filename.clone().unwrap_or_default()}
+              >
+                {"Download"}
+              </a>
+            }
+          </div>
+        }
+      </div>
+    }
+}
@@ -0,0 +1,83 @@
+use crate::{api::fetch_remote, types::RemoteResource};
+use wasm_bindgen_futures::spawn_local;
+use yew::{
+    Callback, Html, MouseEvent, Properties, UseStateHandle, function_component, html,
+    use_effect_with, use_state,
+};
+
+#[derive(Properties, PartialEq)]
+pub struct RemoteProps {
+    pub id: String,
+    /// whether remote content is loaded in the body
+    pub loaded: bool,
+    pub toggle: Callback<()>,
+}
+
+fn kind_label(kind: &str) -> &'static str {
+    match kind {
+        "image" => "Image",
+        "tracking_pixel" => "Tracking pixel",
+        "stylesheet" => "Style sheet",
+        "font" => "Font",
+        _ => "Other",
+    }
+}
+
+/// the remote resources of the body, with a toggle to load them
+#[function_component(RemoteContent)]
+pub fn view(props: &RemoteProps) -> Html {
+    let resources: UseStateHandle<Vec<RemoteResource>> = use_state(Vec::new);
+
+    {
+        let resources = resources.clone();
+        use_effect_with(props.id.clone(), move |id| {
+            let id = id.clone();
+            resources.set(Vec::new());
+            spawn_local(async move {
+                resources.set(fetch_remote(&id).await);
+            });
+            || ()
+        });
+    }
+
+    if resources.is_empty() {
+        return html! {};
+    }
+
+    let pixels = resources
+        .iter()
+        .filter(|resource| resource.kind == "tracking_pixel")
+        .count();
+    // the button is part of the summary, clicking it should not open the details
+    let toggle = props.toggle.reform(|e: MouseEvent| e.prevent_default());
+
+    html! {
+      <details class={if props.loaded { "remote-content loaded" } else { "remote-content" }}>
+        <summary>
+          if props.loaded {
+            {format!("{} remote resources loaded", resources.len())}
+          } else {
+            {format!("{} remote resources blocked", resources.len())}
+          }
+          if pixels > 0 {
+            <span class="trackers">{format!("{pixels} tracking pixels")}</span>
+          }
+          <button onclick={toggle}>
+            {if props.loaded { "Block remote content" } else { "Load remote content" }}
+          </button>
+        </summary>
+        <table>
+          <tbody>
+            {resources.iter().map(|resource| html! {
+              <tr class={resource.kind.clone()}>
+                <th>{kind_label(&resource.kind)}</th>
+                <td><code>{&resource.element}</code></td>
+                <td class="url">{&resource.url}</td>
+                <td>{resource.tracker.clone().unwrap_or_default()}</td>
+              </tr>
+            }).collect::<Html>()}
+          </tbody>
+        </table>
+      </details>
+    }
+}
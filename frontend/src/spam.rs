@@ -0,0 +1,65 @@
+use crate::{api::fetch_spam_report, types::SpamReport};
+use wasm_bindgen_futures::spawn_local;
+use yew::{Html, Properties, UseStateHandle, function_component, html, use_effect_with, use_state};
+
+#[derive(Properties, Eq, PartialEq)]
+pub struct SpamProps {
+    pub id: String,
+}
+
+#[function_component(Spam)]
+pub fn view(props: &SpamProps) -> Html {
+    let report: UseStateHandle<Option<SpamReport>> = use_state(|| None);
+
+    {
+        let report = report.clone();
+        use_effect_with(props.id.clone(), move |id| {
+            let id = id.clone();
+            report.set(None);
+            spawn_local(async move {
+                report.set(fetch_spam_report(&id).await);
+            });
+            || ()
+        });
+    }
+
+    let Some(report) = &*report else {
+        return html! {};
+    };
+
+    let class = if report.score >= report.threshold {
+        "spam-score spam"
+    } else {
+        "spam-score"
+    };
+
+    html! {
+      <>
+        <p class={class}>
+          {format!("Score {:.1} (threshold {:.1})", report.score, report.threshold)}
+        </p>
+        if report.rules.is_empty() {
+          <p class="report-empty">{"No rules triggered"}</p>
+        } else {
+          <table class="report">
+            <thead>
+              <tr>
+                <th>{"Rule"}</th>
+                <th>{"Score"}</th>
+                <th>{"Description"}</th>
+              </tr>
+            </thead>
+            <tbody>
+              {report.rules.iter().map(|rule| html! {
+                <tr>
+                  <td><code>{&rule.name}</code></td>
+                  <td>{format!("{:.1}", rule.score)}</td>
+                  <td>{&rule.description}</td>
+                </tr>
+              }).collect::<Html>()}
+            </tbody>
+          </table>
+        }
+      </>
+    }
+}
@@ -27,6 +27,7 @@ pub struct MailMessageMetadata {
     pub attachments: Vec<AttachmentMetadata>,
     pub envelope_from: String,
     pub envelope_recipients: Vec<String>,
+    pub peer_ip: Option<String>,
 }
 
 #[derive(Clone, PartialEq, Eq, Deserialize)]
@@ -49,10 +50,222 @@ pub struct MailMessage {
     pub opened: bool,
     pub text: String,
     pub html: String,
+    pub amp: String,
     pub attachments: Vec<Attachment>,
     pub headers: HashMap<String, String>,
     pub envelope_from: String,
     pub envelope_recipients: Vec<String>,
+    pub peer_ip: Option<String>,
+    pub smime: Option<SmimeReport>,
+    pub openpgp: Option<OpenPgpReport>,
+    pub embedded: Vec<EmbeddedMessage>,
+    pub calendar: Vec<Calendar>,
+}
+
+/// a forwarded or attached message
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+pub struct EmbeddedMessage {
+    pub from: Option<Address>,
+    pub to: Vec<Address>,
+    pub subject: String,
+    pub date: Option<String>,
+    pub size: String,
+    pub text: String,
+    pub html: String,
+    pub attachments: Vec<Attachment>,
+    pub embedded: Vec<EmbeddedMessage>,
+}
+
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+pub struct SmimeSignature {
+    /// pass, fail or permerror
+    pub result: String,
+    pub signer: Option<String>,
+    /// the signer certificate is, or is issued by, a configured certificate
+    pub trusted: bool,
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+pub struct SmimeReport {
+    pub encrypted: bool,
+    pub decrypted: bool,
+    pub signatures: Vec<SmimeSignature>,
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+pub struct OpenPgpSignature {
+    /// pass, fail or permerror
+    pub result: String,
+    /// the user ID of the signer key
+    pub signer: Option<String>,
+    pub key_id: Option<String>,
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+pub struct OpenPgpReport {
+    pub encrypted: bool,
+    pub decrypted: bool,
+    pub signatures: Vec<OpenPgpSignature>,
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+pub struct CompatibilityIssue {
+    pub kind: String,
+    pub feature: String,
+    pub line: usize,
+    pub column: usize,
+    pub clients: Vec<String>,
+}
+
+/// either the HTTP status of a link target, or the error when requesting it
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+pub struct Reachability {
+    pub status: Option<u16>,
+    pub error: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+pub struct Link {
+    pub url: String,
+    pub source: String,
+    pub element: Option<String>,
+    pub attribute: Option<String>,
+    pub line: usize,
+    pub column: usize,
+    pub issues: Vec<String>,
+    pub reachability: Option<Reachability>,
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+pub struct SpamRule {
+    pub name: String,
+    pub score: f32,
+    pub description: String,
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+pub struct SpamReport {
+    pub score: f32,
+    pub threshold: f32,
+    pub rules: Vec<SpamRule>,
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+pub struct DkimResult {
+    /// pass, fail, neutral, temperror or permerror
+    pub result: String,
+    pub domain: String,
+    pub selector: String,
+    pub algorithm: String,
+    pub canonicalization: String,
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+pub struct SpfResult {
+    pub result: String,
+    pub domain: String,
+    pub ip: Option<String>,
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+pub struct DmarcResult {
+    pub result: String,
+    pub domain: String,
+    pub policy: Option<String>,
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+pub struct ArcResult {
+    pub result: String,
+    pub instances: usize,
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+pub struct AuthenticationReport {
+    pub spf: SpfResult,
+    pub dkim: Vec<DkimResult>,
+    pub dmarc: DmarcResult,
+    pub arc: ArcResult,
+    /// the results as an Authentication-Results header value
+    pub summary: String,
+}
+
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+pub struct CalendarParticipant {
+    pub name: Option<String>,
+    pub email: String,
+    pub role: Option<String>,
+    pub status: Option<String>,
+    pub rsvp: bool,
+}
+
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+pub struct CalendarTime {
+    pub value: String,
+    pub timezone: Option<String>,
+    pub date_only: bool,
+    pub display: String,
+}
+
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+pub struct CalendarEvent {
+    pub uid: Option<String>,
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    pub status: Option<String>,
+    pub sequence: Option<u32>,
+    pub organizer: Option<CalendarParticipant>,
+    pub attendees: Vec<CalendarParticipant>,
+    pub start: Option<CalendarTime>,
+    pub end: Option<CalendarTime>,
+    pub duration: Option<String>,
+    pub recurrence: Option<String>,
+}
+
+/// a calendar invitation
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+pub struct Calendar {
+    pub method: Option<String>,
+    pub events: Vec<CalendarEvent>,
+}
+
+#[derive(Clone, PartialEq, Eq, Deserialize)]
+pub struct RemoteResource {
+    pub url: String,
+    pub kind: String,
+    pub element: String,
+    pub tracker: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+pub struct PartOffsets {
+    pub header: usize,
+    pub body: usize,
+    pub end: usize,
+}
+
+#[derive(Clone, PartialEq, Deserialize)]
+pub struct MimePart {
+    /// indices of the parts that lead to this part, separated by dots
+    pub id: String,
+    pub content_type: String,
+    pub charset: Option<String>,
+    pub transfer_encoding: Option<String>,
+    pub disposition: Option<String>,
+    pub filename: Option<String>,
+    pub content_id: Option<String>,
+    pub size: usize,
+    pub offsets: Option<PartOffsets>,
+    pub parts: Vec<MimePart>,
 }
 
 #[derive(Serialize, Debug)]
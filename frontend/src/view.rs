@@ -1,8 +1,14 @@
 use crate::{
-    api::{fetch_message, fetch_raw},
+    amp::Amp,
+    api::{fetch_message, fetch_raw, get_api_path},
+    authentication::Authentication,
+    compatibility::Compatibility,
     formatted::Formatted,
+    links::Links,
     overview::Tab,
+    parts::Parts,
     plaintext::Plaintext,
+    spam::Spam,
     types::{MailMessage, MailMessageMetadata},
 };
 use wasm_bindgen_futures::spawn_local;
@@ -37,12 +43,20 @@ pub fn view(props: &ViewMessageProps) -> Html {
         raw_content_reset.set(None);
         spawn_local(async move {
             let message = fetch_message(&message_id).await;
-            if message.html.is_empty() && current_tab == Tab::Formatted {
+            if message.html.is_empty()
+                && (current_tab == Tab::Formatted || current_tab == Tab::Compatibility)
+            {
                 set_tab.emit(Tab::Text)
             }
             if message.text.is_empty() && current_tab == Tab::Text {
                 set_tab.emit(Tab::Formatted);
             }
+            if message.amp.is_empty() && current_tab == Tab::Amp {
+                set_tab.emit(match message.html.is_empty() {
+                    true => Tab::Text,
+                    false => Tab::Formatted,
+                });
+            }
             inner_message.set(message);
         });
         || ()
@@ -69,7 +83,22 @@ pub fn view(props: &ViewMessageProps) -> Html {
         return html! {};
     }
 
-    let mut tabs = vec![("Raw", Tab::Raw), ("Headers", Tab::Headers)];
+    let mut tabs = vec![
+        ("Raw", Tab::Raw),
+        ("Structure", Tab::Structure),
+        ("Headers", Tab::Headers),
+        ("Authentication", Tab::Authentication),
+        ("Spam", Tab::Spam),
+        ("Links", Tab::Links),
+    ];
+
+    if !message.html.is_empty() {
+        tabs.push(("Compatibility", Tab::Compatibility));
+    }
+
+    if !message.amp.is_empty() {
+        tabs.push(("AMP", Tab::Amp));
+    }
 
     if !message.text.is_empty() && !message.html.is_empty() {
         tabs.push(("Plain", Tab::Text));
@@ -112,6 +141,11 @@ pub fn view(props: &ViewMessageProps) -> Html {
       <div class="view-inner">
         <ul class="tabs">
           {tabs}
+          <li class="download">
+            <a href={get_api_path(&format!("message/{}/eml", message.id))} download="">
+              {"Download"}
+            </a>
+          </li>
           <li class="delete">
             <button onclick={props.remove.clone()}>
               {"Delete"}
@@ -121,6 +155,8 @@ pub fn view(props: &ViewMessageProps) -> Html {
         <div class="tab-content">
           if props.active_tab == Tab::Formatted {
             <Formatted message={(*message).clone()} />
+          } else if props.active_tab == Tab::Amp {
+            <Amp message={(*message).clone()} />
           } else if props.active_tab == Tab::Text {
             <Plaintext message={(*message).clone()} />
           } else if props.active_tab == Tab::Headers {
@@ -136,6 +172,16 @@ pub fn view(props: &ViewMessageProps) -> Html {
                 }).collect::<Html>()}
               </tbody>
             </table>
+          } else if props.active_tab == Tab::Compatibility {
+            <Compatibility id={message.id.clone()} />
+          } else if props.active_tab == Tab::Links {
+            <Links id={message.id.clone()} />
+          } else if props.active_tab == Tab::Spam {
+            <Spam id={message.id.clone()} />
+          } else if props.active_tab == Tab::Authentication {
+            <Authentication id={message.id.clone()} />
+          } else if props.active_tab == Tab::Structure {
+            <Parts id={message.id.clone()} />
           } else if props.active_tab == Tab::Raw {
             <pre>{(*raw_content).clone().unwrap_or_default()}</pre>
           }
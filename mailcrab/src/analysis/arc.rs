@@ -0,0 +1,363 @@
+//! Authenticated Received Chain (RFC 8617) validation, keys are looked up in local records
+
+use serde::Serialize;
+use std::collections::{BTreeMap, HashMap};
+
+use super::{
+    authentication::AuthResult,
+    dkim::{
+        Algorithm, Canonicalization, Failure, HeaderField, canonicalize_header, check_signature,
+        fail, normalize_line_endings, parse_tags, perm_error, public_key, signature_data,
+        split_message, verify_message_signature,
+    },
+    dns::DnsRecords,
+};
+
+/// the maximum number of ARC sets
+const MAX_INSTANCES: usize = 50;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct ArcResult {
+    pub result: AuthResult,
+    /// the number of ARC sets, added by each intermediary that handled the message
+    pub instances: usize,
+    /// why the chain did not validate
+    pub reason: Option<String>,
+}
+
+/// the header fields of an ARC set, added by a single intermediary
+#[derive(Default)]
+struct ArcSet<'a> {
+    results: Option<&'a HeaderField<'a>>,
+    signature: Option<&'a HeaderField<'a>>,
+    seal: Option<&'a HeaderField<'a>>,
+}
+
+fn field_tags(field: &HeaderField) -> HashMap<String, String> {
+    let value = String::from_utf8_lossy(field.raw);
+
+    parse_tags(
+        value
+            .split_once(':')
+            .map(|(_, value)| value)
+            .unwrap_or_default(),
+    )
+}
+
+/// the ARC sets of a message by instance, fails on duplicate or invalid instances
+fn arc_sets<'a>(fields: &'a [HeaderField<'a>]) -> Result<BTreeMap<usize, ArcSet<'a>>, Failure> {
+    let mut sets: BTreeMap<usize, ArcSet> = BTreeMap::new();
+
+    for field in fields {
+        let value = String::from_utf8_lossy(field.raw);
+        let value = value
+            .split_once(':')
+            .map(|(_, value)| value)
+            .unwrap_or_default();
+        // the instance is the first tag, also in ARC-Authentication-Results
+        let instance = value
+            .split(';')
+            .next()
+            .and_then(|tag| tag.split_once('='))
+            .filter(|(name, _)| name.trim() == "i")
+            .and_then(|(_, instance)| instance.trim().parse::<usize>().ok());
+
+        let slot: for<'s> fn(&'s mut ArcSet<'a>) -> &'s mut Option<&'a HeaderField<'a>> =
+            match field.name.as_str() {
+                "arc-authentication-results" => |set: &mut ArcSet<'a>| &mut set.results,
+                "arc-message-signature" => |set: &mut ArcSet<'a>| &mut set.signature,
+                "arc-seal" => |set: &mut ArcSet<'a>| &mut set.seal,
+                _ => continue,
+            };
+
+        let Some(instance) = instance.filter(|i| (1..=MAX_INSTANCES).contains(i)) else {
+            return Err(fail(format!("invalid instance in {}", field.name)));
+        };
+
+        let slot = slot(sets.entry(instance).or_default());
+        if slot.replace(field).is_some() {
+            return Err(fail(format!("duplicate {} for i={instance}", field.name)));
+        }
+    }
+
+    Ok(sets)
+}
+
+/// validate the seal of an instance, it signs all ARC sets up to and including the instance
+fn verify_seal(
+    sets: &BTreeMap<usize, ArcSet>,
+    instance: usize,
+    dns: &DnsRecords,
+) -> Result<(), Failure> {
+    let seal = sets[&instance]
+        .seal
+        .ok_or_else(|| fail("missing ARC-Seal"))?;
+    let tags = field_tags(seal);
+
+    for tag in ["a", "b", "d", "s"] {
+        if !tags.contains_key(tag) {
+            return Err(perm_error(format!("missing required tag {tag}=")));
+        }
+    }
+
+    let algorithm = Algorithm::parse(&tags["a"])
+        .ok_or_else(|| perm_error(format!("unsupported algorithm {}", tags["a"])))?;
+    let key = public_key(&tags, algorithm, dns)?;
+
+    let mut data = Vec::new();
+    for (i, set) in sets.range(..=instance) {
+        for field in [set.results, set.signature] {
+            let field = field.ok_or_else(|| fail(format!("incomplete ARC set i={i}")))?;
+            data.extend(canonicalize_header(field.raw, Canonicalization::Relaxed));
+        }
+
+        let seal = set
+            .seal
+            .ok_or_else(|| fail(format!("incomplete ARC set i={i}")))?;
+        match *i == instance {
+            true => data.extend(signature_data(seal.raw, Canonicalization::Relaxed)),
+            false => data.extend(canonicalize_header(seal.raw, Canonicalization::Relaxed)),
+        }
+    }
+
+    check_signature(&tags, algorithm, &key, &data)
+}
+
+fn validate(fields: &[HeaderField], body: &[u8], dns: &DnsRecords) -> Result<usize, Failure> {
+    let sets = arc_sets(fields)?;
+    let instances = sets.len();
+
+    // instances should be numbered 1 to n
+    if sets.keys().last().is_some_and(|last| *last != instances) {
+        return Err(fail("the ARC sets are not numbered consecutively"));
+    }
+
+    for (instance, set) in &sets {
+        let seal = set
+            .seal
+            .filter(|_| set.results.is_some() && set.signature.is_some())
+            .ok_or_else(|| fail(format!("incomplete ARC set i={instance}")))?;
+
+        let expected = if *instance == 1 { "none" } else { "pass" };
+        let validation = field_tags(seal).get("cv").cloned().unwrap_or_default();
+
+        if validation != expected {
+            return Err(fail(format!(
+                "ARC-Seal i={instance} has cv={validation}, expected cv={expected}"
+            )));
+        }
+    }
+
+    // only the most recent message signature is validated
+    if let Some(set) = sets.get(&instances) {
+        let signature = set
+            .signature
+            .ok_or_else(|| fail("missing ARC-Message-Signature"))?;
+        let tags = field_tags(signature);
+
+        for tag in ["a", "b", "bh", "d", "h", "s"] {
+            if !tags.contains_key(tag) {
+                return Err(perm_error(format!(
+                    "ARC-Message-Signature i={instances}: missing required tag {tag}="
+                )));
+            }
+        }
+
+        verify_message_signature(&tags, signature.raw, fields, body, dns).map_err(
+            |(result, reason)| {
+                (
+                    result,
+                    format!("ARC-Message-Signature i={instances}: {reason}"),
+                )
+            },
+        )?;
+    }
+
+    for instance in (1..=instances).rev() {
+        verify_seal(&sets, instance, dns)
+            .map_err(|(result, reason)| (result, format!("ARC-Seal i={instance}: {reason}")))?;
+    }
+
+    Ok(instances)
+}
+
+/// validate the ARC chain of a raw message
+pub fn verify_arc(raw: &[u8], dns: &DnsRecords) -> ArcResult {
+    let raw = normalize_line_endings(raw);
+    let (fields, body) = split_message(&raw);
+
+    match validate(&fields, body, dns) {
+        Ok(0) => ArcResult {
+            result: AuthResult::None,
+            instances: 0,
+            reason: None,
+        },
+        Ok(instances) => ArcResult {
+            result: AuthResult::Pass,
+            instances,
+            reason: None,
+        },
+        Err((result, reason)) => ArcResult {
+            // chain validation only passes or fails
+            result: match result {
+                AuthResult::PermError => AuthResult::Fail,
+                result => result,
+            },
+            instances: arc_sets(&fields).map(|sets| sets.len()).unwrap_or_default(),
+            reason: Some(reason),
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use base64ct::{Base64, Encoding};
+    use ring::{
+        digest,
+        signature::{Ed25519KeyPair, KeyPair},
+    };
+
+    const MESSAGE: &str = "From: Joe SixPack <joe@football.example.com>\r
+To: Suzie Q <suzie@shopping.example.net>\r
+Subject: Is dinner ready?\r
+Date: Fri, 11 Jul 2003 21:00:37 -0700 (PDT)\r
+Message-ID: <20030712040037.46341.5F8J@football.example.com>\r
+\r
+Hi.\r
+\r
+We lost the game.  Are you hungry yet?\r
+\r
+Joe.\r
+";
+
+    fn key_pair() -> Ed25519KeyPair {
+        Ed25519KeyPair::from_seed_unchecked(&[7; 32]).unwrap()
+    }
+
+    fn dns() -> DnsRecords {
+        let mut dns = DnsRecords::default();
+        dns.add_txt(
+            "arc._domainkey.lists.example.org",
+            format!(
+                "v=DKIM1; k=ed25519; p={}",
+                Base64::encode_string(key_pair().public_key().as_ref())
+            ),
+        );
+        dns
+    }
+
+    /// replace the empty b= tag of the header field starting with prefix by a signature
+    fn sign(message: &str, prefix: &str, data: Vec<u8>) -> String {
+        let hash = digest::digest(&digest::SHA256, &data);
+        let signature = Base64::encode_string(key_pair().sign(hash.as_ref()).as_ref());
+
+        message
+            .split('\n')
+            .map(
+                |line| match line.starts_with(prefix) && line.ends_with("b=\r") {
+                    true => format!("{}{signature}\r", line.trim_end_matches('\r')),
+                    false => line.to_owned(),
+                },
+            )
+            .collect::<Vec<String>>()
+            .join("\n")
+    }
+
+    /// add an ARC set to a message, as an intermediary would
+    fn add_arc_set(message: &str, instance: usize) -> String {
+        let validation = if instance == 1 { "none" } else { "pass" };
+        let message = format!(
+            "ARC-Seal: i={instance}; a=ed25519-sha256; cv={validation}; d=lists.example.org; s=arc; b=\r\n\
+             ARC-Message-Signature: i={instance}; a=ed25519-sha256; c=relaxed/relaxed;\r\n \
+             d=lists.example.org; s=arc; h=from:to:subject;\r\n \
+             bh=2jUSOH9NhtVGCQWNr9BrIAPreKQjO6Sn7XIkfJVOzv8=; b=\r\n\
+             ARC-Authentication-Results: i={instance}; lists.example.org; spf=pass\r\n{message}"
+        );
+
+        let raw = message.as_bytes().to_vec();
+        let (fields, _) = split_message(&raw);
+        let signature = fields
+            .iter()
+            .find(|field| field.name == "arc-message-signature")
+            .unwrap();
+        let data = super::super::dkim::signed_data(
+            &field_tags(signature),
+            signature.raw,
+            &fields,
+            Canonicalization::Relaxed,
+        );
+        let message = sign(
+            &message,
+            " bh=2jUSOH9NhtVGCQWNr9BrIAPreKQjO6Sn7XIkfJVOzv8=; b=",
+            data,
+        );
+
+        let raw = message.as_bytes().to_vec();
+        let (fields, _) = split_message(&raw);
+        let sets = arc_sets(&fields).unwrap();
+        let mut data = Vec::new();
+        for (i, set) in &sets {
+            data.extend(canonicalize_header(
+                set.results.unwrap().raw,
+                Canonicalization::Relaxed,
+            ));
+            data.extend(canonicalize_header(
+                set.signature.unwrap().raw,
+                Canonicalization::Relaxed,
+            ));
+            match *i == instance {
+                true => data.extend(signature_data(
+                    set.seal.unwrap().raw,
+                    Canonicalization::Relaxed,
+                )),
+                false => data.extend(canonicalize_header(
+                    set.seal.unwrap().raw,
+                    Canonicalization::Relaxed,
+                )),
+            }
+        }
+
+        sign(&message, &format!("ARC-Seal: i={instance};"), data)
+    }
+
+    fn result(message: &str) -> (AuthResult, usize, Option<String>) {
+        let result = verify_arc(message.as_bytes(), &dns());
+
+        (result.result, result.instances, result.reason)
+    }
+
+    #[test]
+    fn chain() {
+        assert_eq!(result(MESSAGE), (AuthResult::None, 0, None));
+
+        let once = add_arc_set(MESSAGE, 1);
+        assert_eq!(result(&once), (AuthResult::Pass, 1, None));
+
+        let twice = add_arc_set(&once, 2);
+        assert_eq!(result(&twice), (AuthResult::Pass, 2, None));
+
+        let (result_code, instances, reason) =
+            result(&twice.replace("lost the game", "won the game"));
+        assert_eq!((result_code, instances), (AuthResult::Fail, 2));
+        assert!(
+            reason
+                .unwrap()
+                .starts_with("ARC-Message-Signature i=2: body hash mismatch")
+        );
+
+        // an intermediary changed the results of an earlier intermediary
+        let changed = twice.replacen("spf=pass\r\nFrom", "spf=fail\r\nFrom", 1);
+        let (result_code, _, reason) = result(&changed);
+        assert_eq!(result_code, AuthResult::Fail);
+        assert!(
+            reason
+                .unwrap()
+                .starts_with("ARC-Seal i=2: signature mismatch")
+        );
+
+        let (result_code, _, reason) = result(&twice.replace("i=1; lists", "i=3; lists"));
+        assert_eq!(result_code, AuthResult::Fail);
+        assert!(reason.is_some());
+    }
+}
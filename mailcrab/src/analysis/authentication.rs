@@ -0,0 +1,174 @@
+//! Combined SPF, DKIM, DMARC and ARC results, summarized like an Authentication-Results header
+
+use serde::Serialize;
+use std::{fmt, net::IpAddr};
+
+use super::{
+    arc::{ArcResult, verify_arc},
+    dkim::{DkimResult, verify_dkim},
+    dmarc::{DmarcResult, check_dmarc},
+    dns::DnsRecords,
+    spf::{SpfResult, check_spf},
+};
+
+/// the authentication service identifier used in the summary
+const AUTHSERV_ID: &str = "mailcrab";
+
+/// result of an authentication check, as used in Authentication-Results headers (RFC 8601)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AuthResult {
+    Pass,
+    Fail,
+    SoftFail,
+    Neutral,
+    None,
+    TempError,
+    PermError,
+}
+
+impl fmt::Display for AuthResult {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let result = match self {
+            AuthResult::Pass => "pass",
+            AuthResult::Fail => "fail",
+            AuthResult::SoftFail => "softfail",
+            AuthResult::Neutral => "neutral",
+            AuthResult::None => "none",
+            AuthResult::TempError => "temperror",
+            AuthResult::PermError => "permerror",
+        };
+
+        f.write_str(result)
+    }
+}
+
+/// the SMTP session and message details that are authenticated
+pub struct AuthenticationInput<'a> {
+    pub raw: &'a [u8],
+    pub peer_ip: Option<IpAddr>,
+    pub envelope_from: &'a str,
+    /// the address of the From header
+    pub from: &'a str,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AuthenticationReport {
+    pub spf: SpfResult,
+    pub dkim: Vec<DkimResult>,
+    pub dmarc: DmarcResult,
+    pub arc: ArcResult,
+    /// the results as the value of an Authentication-Results header
+    pub summary: String,
+}
+
+/// format the results like an Authentication-Results header value
+fn summary(spf: &SpfResult, dkim: &[DkimResult], dmarc: &DmarcResult, arc: &ArcResult) -> String {
+    let mut results = vec![AUTHSERV_ID.to_owned()];
+
+    if dkim.is_empty() {
+        results.push("dkim=none".to_owned());
+    }
+
+    for signature in dkim {
+        let mut result = format!(
+            "dkim={} header.d={} header.s={}",
+            signature.result, signature.domain, signature.selector
+        );
+        if let Some(reason) = &signature.reason {
+            result.push_str(&format!(" ({reason})"));
+        }
+        results.push(result);
+    }
+
+    let mut result = format!("spf={}", spf.result);
+    if let Some(reason) = &spf.reason {
+        result.push_str(&format!(" ({reason})"));
+    }
+    if !spf.domain.is_empty() {
+        result.push_str(&format!(" smtp.mailfrom={}", spf.domain));
+    }
+    results.push(result);
+
+    let mut result = format!("dmarc={}", dmarc.result);
+    if let Some(policy) = &dmarc.policy {
+        result.push_str(&format!(" (p={policy})"));
+    }
+    if !dmarc.domain.is_empty() {
+        result.push_str(&format!(" header.from={}", dmarc.domain));
+    }
+    results.push(result);
+
+    let mut result = format!("arc={}", arc.result);
+    if let Some(reason) = &arc.reason {
+        result.push_str(&format!(" ({reason})"));
+    }
+    results.push(result);
+
+    results.join(";\n  ")
+}
+
+/// check SPF, DKIM, DMARC and ARC of a message, now is the current unix timestamp
+pub fn authenticate(
+    input: &AuthenticationInput,
+    dns: &DnsRecords,
+    now: i64,
+) -> AuthenticationReport {
+    let spf = check_spf(input.peer_ip, input.envelope_from, dns);
+    let dkim = verify_dkim(input.raw, dns, now);
+    let dmarc = check_dmarc(input.from, &spf, &dkim, dns);
+    let arc = verify_arc(input.raw, dns);
+    let summary = summary(&spf, &dkim, &dmarc, &arc);
+
+    AuthenticationReport {
+        spf,
+        dkim,
+        dmarc,
+        arc,
+        summary,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report() {
+        let mut dns = DnsRecords::default();
+        dns.add_zone(
+            "$ORIGIN example.com.\n\
+             @      TXT \"v=spf1 ip4:192.0.2.0/24 -all\"\n\
+             _dmarc TXT \"v=DMARC1; p=quarantine\"\n",
+        )
+        .unwrap();
+
+        let raw = b"From: Joe <joe@example.com>\r\nSubject: test\r\n\r\nHi\r\n";
+        let input = AuthenticationInput {
+            raw,
+            peer_ip: Some("192.0.2.1".parse().unwrap()),
+            envelope_from: "bounces@example.com",
+            from: "joe@example.com",
+        };
+
+        let report = authenticate(&input, &dns, 0);
+        assert_eq!(report.spf.result, AuthResult::Pass);
+        assert_eq!(report.dmarc.result, AuthResult::Pass);
+        assert_eq!(report.arc.result, AuthResult::None);
+        assert_eq!(
+            report.summary,
+            "mailcrab;\n  dkim=none;\n  \
+             spf=pass (192.0.2.1 matched ip4:192.0.2.0/24 of example.com) smtp.mailfrom=example.com;\n  \
+             dmarc=pass (p=quarantine) header.from=example.com;\n  \
+             arc=none"
+        );
+
+        let input = AuthenticationInput {
+            peer_ip: Some("203.0.113.1".parse().unwrap()),
+            ..input
+        };
+        let report = authenticate(&input, &dns, 0);
+        assert_eq!(report.spf.result, AuthResult::Fail);
+        assert_eq!(report.dmarc.result, AuthResult::Fail);
+    }
+}
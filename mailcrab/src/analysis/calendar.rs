@@ -0,0 +1,356 @@
+//! Calendar invitations (RFC 5545 iCalendar, with RFC 5546 methods), the events and their
+//! participants
+
+use serde::Serialize;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CalendarParticipant {
+    /// the common name (CN) parameter
+    pub name: Option<String>,
+    pub email: String,
+    /// e.g. REQ-PARTICIPANT or OPT-PARTICIPANT
+    pub role: Option<String>,
+    /// participation status, e.g. NEEDS-ACTION or ACCEPTED
+    pub status: Option<String>,
+    pub rsvp: bool,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CalendarTime {
+    /// the value as found in the calendar, e.g. `20230612T140000`
+    pub value: String,
+    /// the TZID parameter, or UTC for times ending in `Z`, none for floating times and dates
+    pub timezone: Option<String>,
+    /// an all-day date without a time
+    pub date_only: bool,
+    /// formatted as `2023-06-12 14:00`
+    pub display: String,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct CalendarEvent {
+    pub uid: Option<String>,
+    pub summary: Option<String>,
+    pub description: Option<String>,
+    pub location: Option<String>,
+    pub status: Option<String>,
+    pub sequence: Option<u32>,
+    pub organizer: Option<CalendarParticipant>,
+    pub attendees: Vec<CalendarParticipant>,
+    pub start: Option<CalendarTime>,
+    pub end: Option<CalendarTime>,
+    /// the DURATION of events without an end
+    pub duration: Option<String>,
+    /// the RRULE, e.g. `FREQ=WEEKLY;BYDAY=MO`
+    pub recurrence: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct Calendar {
+    /// the scheduling method, e.g. REQUEST, REPLY or CANCEL
+    pub method: Option<String>,
+    pub events: Vec<CalendarEvent>,
+}
+
+/// a content line: name, parameters and value
+struct Property<'x> {
+    name: String,
+    params: Vec<(String, String)>,
+    value: &'x str,
+}
+
+impl Property<'_> {
+    fn param(&self, name: &str) -> Option<&str> {
+        self.params
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+/// join folded lines, a line starting with a space or tab continues the previous line
+fn unfold(text: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+
+    for line in text.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(continuation), Some(last)) => last.push_str(continuation),
+            _ => lines.push(line.to_owned()),
+        }
+    }
+
+    lines
+}
+
+/// split a content line, parameter values can be quoted and contain `:` and `;`
+fn property(line: &str) -> Option<Property<'_>> {
+    let mut in_quotes = false;
+    let mut separators = Vec::new();
+    let mut value_start = None;
+
+    for (index, ch) in line.char_indices() {
+        match ch {
+            '"' => in_quotes = !in_quotes,
+            ';' if !in_quotes => separators.push(index),
+            ':' if !in_quotes => {
+                value_start = Some(index);
+                break;
+            }
+            _ => (),
+        }
+    }
+
+    let value_start = value_start?;
+    let name_end = separators.first().copied().unwrap_or(value_start);
+    let mut bounds = separators;
+    bounds.push(value_start);
+
+    let params = bounds
+        .windows(2)
+        .filter_map(|window| {
+            let (key, value) = line[window[0] + 1..window[1]].split_once('=')?;
+            Some((key.to_owned(), value.trim_matches('"').to_owned()))
+        })
+        .collect();
+
+    Some(Property {
+        name: line[..name_end].to_ascii_uppercase(),
+        params,
+        value: &line[value_start + 1..],
+    })
+}
+
+/// unescape a TEXT value
+fn text(value: &str) -> String {
+    let mut result = String::with_capacity(value.len());
+    let mut chars = value.chars();
+
+    while let Some(ch) = chars.next() {
+        if ch != '\\' {
+            result.push(ch);
+            continue;
+        }
+
+        match chars.next() {
+            Some('n' | 'N') => result.push('\n'),
+            Some(escaped) => result.push(escaped),
+            None => (),
+        }
+    }
+
+    result
+}
+
+fn participant(property: &Property) -> CalendarParticipant {
+    let value = property.value;
+    let email = match value.get(..7) {
+        Some(scheme) if scheme.eq_ignore_ascii_case("mailto:") => &value[7..],
+        _ => value,
+    };
+
+    CalendarParticipant {
+        name: property.param("CN").map(str::to_owned),
+        email: email.to_owned(),
+        role: property.param("ROLE").map(str::to_owned),
+        status: property.param("PARTSTAT").map(str::to_owned),
+        rsvp: property
+            .param("RSVP")
+            .is_some_and(|rsvp| rsvp.eq_ignore_ascii_case("true")),
+    }
+}
+
+/// a DATE (`20230612`) or DATE-TIME (`20230612T140000`, optionally ending in `Z`)
+fn time(property: &Property) -> Option<CalendarTime> {
+    let value = property.value.trim();
+    let digits = |range: std::ops::Range<usize>| {
+        value
+            .get(range)
+            .filter(|part| part.bytes().all(|b| b.is_ascii_digit()))
+    };
+    let date = format!("{}-{}-{}", digits(0..4)?, digits(4..6)?, digits(6..8)?);
+
+    if value.len() == 8 {
+        return Some(CalendarTime {
+            value: value.to_owned(),
+            timezone: None,
+            date_only: true,
+            display: date,
+        });
+    }
+
+    if value.get(8..9) != Some("T") {
+        return None;
+    }
+
+    let utc = value.ends_with('Z');
+
+    Some(CalendarTime {
+        value: value.to_owned(),
+        timezone: match utc {
+            true => Some("UTC".to_owned()),
+            false => property.param("TZID").map(str::to_owned),
+        },
+        date_only: false,
+        display: format!("{date} {}:{}", digits(9..11)?, digits(11..13)?),
+    })
+}
+
+/// parse a calendar object, none if the text does not contain one
+pub fn parse_calendar(calendar: &str) -> Option<Calendar> {
+    let mut result: Option<Calendar> = None;
+    let mut components: Vec<String> = Vec::new();
+    let mut event: Option<CalendarEvent> = None;
+
+    for line in unfold(calendar) {
+        let Some(property) = property(&line) else {
+            continue;
+        };
+        let value = property.value;
+
+        match property.name.as_str() {
+            "BEGIN" => {
+                let component = value.trim().to_ascii_uppercase();
+                match (component.as_str(), components.as_slice()) {
+                    ("VCALENDAR", []) => result = Some(Calendar::default()),
+                    ("VEVENT", [parent]) if parent == "VCALENDAR" => {
+                        event = Some(CalendarEvent::default())
+                    }
+                    _ => (),
+                }
+                components.push(component);
+                continue;
+            }
+            "END" => {
+                if components.pop().as_deref() == Some("VEVENT")
+                    && let (Some(calendar), Some(event)) = (result.as_mut(), event.take())
+                {
+                    calendar.events.push(event);
+                }
+                continue;
+            }
+            _ => (),
+        }
+
+        match (components.last().map(String::as_str), &mut event) {
+            (Some("VCALENDAR"), _) if property.name == "METHOD" => {
+                if let Some(calendar) = result.as_mut() {
+                    calendar.method = Some(value.trim().to_ascii_uppercase());
+                }
+            }
+            (Some("VEVENT"), Some(event)) => match property.name.as_str() {
+                "UID" => event.uid = Some(text(value)),
+                "SUMMARY" => event.summary = Some(text(value)),
+                "DESCRIPTION" => event.description = Some(text(value)),
+                "LOCATION" => event.location = Some(text(value)),
+                "STATUS" => event.status = Some(value.trim().to_ascii_uppercase()),
+                "SEQUENCE" => event.sequence = value.trim().parse().ok(),
+                "ORGANIZER" => event.organizer = Some(participant(&property)),
+                "ATTENDEE" => event.attendees.push(participant(&property)),
+                "DTSTART" => event.start = time(&property),
+                "DTEND" => event.end = time(&property),
+                "DURATION" => event.duration = Some(value.trim().to_owned()),
+                "RRULE" => event.recurrence = Some(value.trim().to_owned()),
+                _ => (),
+            },
+            _ => (),
+        }
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const INVITE: &str = "BEGIN:VCALENDAR\r\n\
+        PRODID:-//Example//Calendar//EN\r\n\
+        VERSION:2.0\r\n\
+        METHOD:REQUEST\r\n\
+        BEGIN:VTIMEZONE\r\n\
+        TZID:Europe/Amsterdam\r\n\
+        BEGIN:STANDARD\r\n\
+        DTSTART:19701025T030000\r\n\
+        TZOFFSETFROM:+0200\r\n\
+        TZOFFSETTO:+0100\r\n\
+        END:STANDARD\r\n\
+        END:VTIMEZONE\r\n\
+        BEGIN:VEVENT\r\n\
+        UID:standup-42@example.com\r\n\
+        SEQUENCE:1\r\n\
+        SUMMARY:Weekly standup\\, team A\r\n\
+        DESCRIPTION:Agenda:\\n- updates\\n- blockers that are described in a line that\r\n \
+        \x20is folded\r\n\
+        LOCATION:Room 3\r\n\
+        DTSTART;TZID=Europe/Amsterdam:20230612T093000\r\n\
+        DTEND;TZID=Europe/Amsterdam:20230612T094500\r\n\
+        RRULE:FREQ=WEEKLY;BYDAY=MO;COUNT=10\r\n\
+        ORGANIZER;CN=\"Leannon, Fernando\":mailto:grayson@example.com\r\n\
+        ATTENDEE;CN=Jacklyn Ebert;ROLE=REQ-PARTICIPANT;PARTSTAT=NEEDS-ACTION;RSVP=TRUE:\r\n \
+        mailto:joelle@example.org\r\n\
+        ATTENDEE;PARTSTAT=ACCEPTED:MAILTO:daisy@example.net\r\n\
+        BEGIN:VALARM\r\n\
+        DESCRIPTION:Reminder\r\n\
+        TRIGGER:-PT15M\r\n\
+        END:VALARM\r\n\
+        END:VEVENT\r\n\
+        END:VCALENDAR\r\n";
+
+    #[test]
+    fn invitation() {
+        let calendar = parse_calendar(INVITE).unwrap();
+        assert_eq!(calendar.method.as_deref(), Some("REQUEST"));
+        assert_eq!(calendar.events.len(), 1);
+
+        let event = &calendar.events[0];
+        assert_eq!(event.summary.as_deref(), Some("Weekly standup, team A"));
+        assert_eq!(
+            event.description.as_deref(),
+            Some("Agenda:\n- updates\n- blockers that are described in a line that is folded")
+        );
+        assert_eq!(event.sequence, Some(1));
+        assert_eq!(
+            event.recurrence.as_deref(),
+            Some("FREQ=WEEKLY;BYDAY=MO;COUNT=10")
+        );
+
+        let start = event.start.as_ref().unwrap();
+        assert_eq!(start.timezone.as_deref(), Some("Europe/Amsterdam"));
+        assert_eq!(start.display, "2023-06-12 09:30");
+        assert_eq!(event.end.as_ref().unwrap().display, "2023-06-12 09:45");
+
+        let organizer = event.organizer.as_ref().unwrap();
+        assert_eq!(organizer.name.as_deref(), Some("Leannon, Fernando"));
+        assert_eq!(organizer.email, "grayson@example.com");
+
+        assert_eq!(event.attendees.len(), 2);
+        assert_eq!(event.attendees[0].email, "joelle@example.org");
+        assert_eq!(event.attendees[0].role.as_deref(), Some("REQ-PARTICIPANT"));
+        assert!(event.attendees[0].rsvp);
+        assert_eq!(event.attendees[1].email, "daisy@example.net");
+        assert_eq!(event.attendees[1].status.as_deref(), Some("ACCEPTED"));
+    }
+
+    #[test]
+    fn times() {
+        let calendar = parse_calendar(
+            "BEGIN:VCALENDAR\nBEGIN:VEVENT\nDTSTART;VALUE=DATE:20230612\n\
+             DTEND:20230612T150000Z\nEND:VEVENT\nEND:VCALENDAR\n",
+        )
+        .unwrap();
+        let event = &calendar.events[0];
+
+        let start = event.start.as_ref().unwrap();
+        assert!(start.date_only);
+        assert_eq!(start.display, "2023-06-12");
+
+        let end = event.end.as_ref().unwrap();
+        assert_eq!(end.timezone.as_deref(), Some("UTC"));
+        assert_eq!(end.display, "2023-06-12 15:00");
+    }
+
+    #[test]
+    fn not_a_calendar() {
+        assert!(parse_calendar("BEGIN:VCARD\nFN:Daisy\nEND:VCARD\n").is_none());
+    }
+}
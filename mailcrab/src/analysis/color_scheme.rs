@@ -0,0 +1,364 @@
+//! Dark mode as mail clients apply it, by rewriting the styles of the HTML body
+
+use serde::Deserialize;
+
+use super::{
+    css::{CssItem, parse_declarations, parse_stylesheet},
+    html::{Token, apply_edits, insert_in_head, tokenize},
+};
+
+/// the ways mail clients show a message in dark mode
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ColorScheme {
+    /// no dark mode at all
+    #[default]
+    Light,
+    /// only `prefers-color-scheme: dark` styles apply, like Apple Mail
+    Dark,
+    /// light backgrounds and dark text are inverted, like Outlook.com
+    PartialInvert,
+    /// all colors are inverted, except for images, like the Gmail apps
+    FullInvert,
+}
+
+const DARK: &str = "<style>:root { color-scheme: dark; }</style>";
+
+const PARTIAL_INVERT: &str = "<style>html { background: #121212; color: #e8e8e8; }</style>";
+
+const FULL_INVERT: &str = "<style>html { background: white; filter: invert(1) hue-rotate(180deg); } \
+    img, picture, video, [style*=\"background-image\"] { filter: invert(1) hue-rotate(180deg); }</style>";
+
+/// named colors that are common in emails
+const NAMED_COLORS: [(&str, [u8; 3]); 14] = [
+    ("white", [255, 255, 255]),
+    ("snow", [255, 250, 250]),
+    ("ghostwhite", [248, 248, 255]),
+    ("whitesmoke", [245, 245, 245]),
+    ("ivory", [255, 255, 240]),
+    ("gainsboro", [220, 220, 220]),
+    ("lightgray", [211, 211, 211]),
+    ("lightgrey", [211, 211, 211]),
+    ("silver", [192, 192, 192]),
+    ("gray", [128, 128, 128]),
+    ("grey", [128, 128, 128]),
+    ("dimgray", [105, 105, 105]),
+    ("dimgrey", [105, 105, 105]),
+    ("black", [0, 0, 0]),
+];
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Color {
+    r: f64,
+    g: f64,
+    b: f64,
+    a: f64,
+}
+
+impl Color {
+    fn parse(value: &str) -> Option<Color> {
+        let value = value.trim().to_ascii_lowercase();
+
+        if let Some(hex) = value.strip_prefix('#') {
+            let digits = match hex.len() {
+                3 | 4 => hex.chars().flat_map(|c| [c, c]).collect::<String>(),
+                6 | 8 => hex.to_owned(),
+                _ => return None,
+            };
+            let channel = |index: usize| {
+                digits
+                    .get(index * 2..index * 2 + 2)
+                    .and_then(|digits| u8::from_str_radix(digits, 16).ok())
+                    .map(f64::from)
+            };
+
+            return Some(Color {
+                r: channel(0)?,
+                g: channel(1)?,
+                b: channel(2)?,
+                a: channel(3).map_or(1.0, |a| a / 255.0),
+            });
+        }
+
+        if let Some(arguments) = value
+            .strip_prefix("rgba(")
+            .or_else(|| value.strip_prefix("rgb("))
+        {
+            let mut numbers = arguments
+                .trim_end_matches(')')
+                .split([',', ' ', '/'])
+                .filter(|number| !number.is_empty())
+                .map(|number| match number.strip_suffix('%') {
+                    Some(percentage) => percentage.parse::<f64>().ok().map(|p| p / 100.0),
+                    None => number.parse::<f64>().ok(),
+                });
+
+            return Some(Color {
+                r: numbers.next()??,
+                g: numbers.next()??,
+                b: numbers.next()??,
+                a: numbers.next().flatten().unwrap_or(1.0),
+            });
+        }
+
+        NAMED_COLORS
+            .iter()
+            .find(|(name, _)| *name == value)
+            .map(|(_, [r, g, b])| Color {
+                r: f64::from(*r),
+                g: f64::from(*g),
+                b: f64::from(*b),
+                a: 1.0,
+            })
+    }
+
+    /// relative lightness, between 0 and 1
+    fn lightness(&self) -> f64 {
+        (self.r.max(self.g).max(self.b) + self.r.min(self.g).min(self.b)) / 510.0
+    }
+
+    fn is_light(&self) -> bool {
+        self.a > 0.0 && self.lightness() > 0.5
+    }
+
+    fn is_dark(&self) -> bool {
+        self.a > 0.0 && self.lightness() < 0.5
+    }
+
+    /// invert the lightness while keeping the hue
+    fn invert(&self) -> String {
+        let shift = 255.0 - self.r.max(self.g).max(self.b) - self.r.min(self.g).min(self.b);
+        let channel = |c: f64| (c + shift).clamp(0.0, 255.0).round() as u8;
+        let (r, g, b) = (channel(self.r), channel(self.g), channel(self.b));
+
+        match self.a < 1.0 {
+            true => format!("rgba({r}, {g}, {b}, {})", self.a),
+            false => format!("#{r:02x}{g:02x}{b:02x}"),
+        }
+    }
+}
+
+/// find the colors in a CSS value, with their byte ranges
+fn colors(value: &str) -> Vec<(usize, usize, Color)> {
+    let bytes = value.as_bytes();
+    let mut found = Vec::new();
+    let mut position = 0;
+
+    while position < bytes.len() {
+        let rest = &value[position..];
+        let end = if bytes[position] == b'#' {
+            position
+                + 1
+                + rest[1..]
+                    .find(|c: char| !c.is_ascii_hexdigit())
+                    .unwrap_or(rest.len() - 1)
+        } else if bytes[position].is_ascii_alphabetic() {
+            let word = rest
+                .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+                .unwrap_or(rest.len());
+            // functions like rgb() and url() are skipped as a whole
+            match rest[word..].starts_with('(') {
+                true => rest.find(')').map_or(value.len(), |end| position + end + 1),
+                false => position + word,
+            }
+        } else {
+            position += rest.chars().next().map_or(1, char::len_utf8);
+            continue;
+        };
+
+        if let Some(color) = Color::parse(&value[position..end]) {
+            found.push((position, end, color));
+        }
+        position = end;
+    }
+
+    found
+}
+
+/// invert dark text and light backgrounds in declarations, `offset` is the position of the
+/// CSS in the HTML body
+fn invert_css(
+    css: &str,
+    items: Vec<CssItem>,
+    offset: usize,
+    edits: &mut Vec<(usize, usize, String)>,
+) {
+    for item in items {
+        let CssItem::Declaration {
+            property,
+            offset: start,
+            ..
+        } = item
+        else {
+            continue;
+        };
+
+        let invert: fn(&Color) -> bool = match property.as_str() {
+            "color" => Color::is_dark,
+            "background" | "background-color" => Color::is_light,
+            _ => continue,
+        };
+
+        let Some(colon) = css[start..].find(':') else {
+            continue;
+        };
+        let value_start = start + colon + 1;
+        let value_end = css[value_start..]
+            .find([';', '}'])
+            .map_or(css.len(), |end| value_start + end);
+
+        for (from, to, color) in colors(&css[value_start..value_end]) {
+            if invert(&color) {
+                edits.push((
+                    offset + value_start + from,
+                    offset + value_start + to,
+                    color.invert(),
+                ));
+            }
+        }
+    }
+}
+
+/// invert dark text and light backgrounds in style sheets, style attributes and the legacy
+/// color attributes, like Outlook.com does
+fn invert_partially(html: &str) -> String {
+    let mut edits = Vec::new();
+
+    for token in tokenize(html) {
+        match token {
+            Token::Style { css, offset } => {
+                invert_css(&css, parse_stylesheet(&css), offset, &mut edits);
+            }
+            Token::StartTag {
+                name, attributes, ..
+            } => {
+                for attribute in attributes {
+                    let invert: fn(&Color) -> bool = match (name.as_str(), attribute.name.as_str())
+                    {
+                        (_, "style") => {
+                            let items = parse_declarations(&attribute.value);
+                            invert_css(&attribute.value, items, attribute.value_offset, &mut edits);
+                            continue;
+                        }
+                        (_, "bgcolor") => Color::is_light,
+                        ("font", "color") | ("body", "text") => Color::is_dark,
+                        _ => continue,
+                    };
+
+                    if let Some(color) = Color::parse(&attribute.value).filter(invert) {
+                        edits.push((
+                            attribute.value_offset,
+                            attribute.value_offset + attribute.value.len(),
+                            color.invert(),
+                        ));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    insert_in_head(&apply_edits(html, edits), PARTIAL_INVERT)
+}
+
+/// make `prefers-color-scheme` media queries in style sheets match the dark color scheme
+fn emulate_dark_media(html: &str) -> String {
+    let mut edits = Vec::new();
+
+    for token in tokenize(html) {
+        let Token::Style { css, offset } = token else {
+            continue;
+        };
+        let lower = css.to_ascii_lowercase();
+
+        for (index, feature) in lower.match_indices("prefers-color-scheme") {
+            let rest = &lower[index + feature.len()..];
+            let value = rest
+                .trim_start()
+                .strip_prefix(':')
+                .unwrap_or(rest)
+                .trim_start();
+            let replacement = if value.starts_with("dark") {
+                "min-width: 0px"
+            } else if value.starts_with("light") {
+                "max-width: 0px"
+            } else {
+                continue;
+            };
+            let end = lower.len() - value.len() + if value.starts_with("dark") { 4 } else { 5 };
+
+            edits.push((offset + index, offset + end, replacement.to_owned()));
+        }
+    }
+
+    insert_in_head(&apply_edits(html, edits), DARK)
+}
+
+/// show the HTML body as a mail client does in the given color scheme
+pub fn apply_color_scheme(html: &str, scheme: ColorScheme) -> String {
+    match scheme {
+        ColorScheme::Light => html.to_owned(),
+        ColorScheme::Dark => emulate_dark_media(html),
+        ColorScheme::PartialInvert => invert_partially(html),
+        ColorScheme::FullInvert => insert_in_head(html, FULL_INVERT),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse() {
+        assert_eq!(
+            Color::parse("#FFF").map(|c| c.invert()),
+            Some("#000000".to_owned())
+        );
+        assert_eq!(
+            Color::parse("rgba(255, 255, 255, 0.5)").map(|c| c.invert()),
+            Some("rgba(0, 0, 0, 0.5)".to_owned())
+        );
+        // the hue is kept, only the lightness is inverted
+        assert_eq!(
+            Color::parse("#ffcccc").map(|c| c.invert()),
+            Some("#330000".to_owned())
+        );
+        assert_eq!(Color::parse("rgb(0 0 0 / 50%)").map(|c| c.a), Some(0.5));
+        assert_eq!(
+            Color::parse("Gray").map(|c| c.lightness()),
+            Some(128.0 / 255.0)
+        );
+        assert_eq!(Color::parse("transparent"), None);
+    }
+
+    #[test]
+    fn dark() {
+        let html = "<html><head><style>p { color: black }\
+            @media (prefers-color-scheme:Dark) { p { color: white } }\
+            @media (prefers-color-scheme: light) { p { color: red } }</style></head></html>";
+
+        assert_eq!(
+            apply_color_scheme(html, ColorScheme::Dark),
+            "<html><head><style>:root { color-scheme: dark; }</style><style>p { color: black }\
+            @media (min-width: 0px) { p { color: white } }\
+            @media (max-width: 0px) { p { color: red } }</style></head></html>"
+        );
+    }
+
+    #[test]
+    fn partial() {
+        let html = "<style>body { background: #fff url(#a) no-repeat; color: #333 !important }\
+            .button { background-color: #0066cc; color: white }</style>\
+            <body bgcolor=\"#FFFFFF\" text=\"black\"><div style=\"background-color: rgb(250, 250, 250); color: #777\">\
+            <font color=\"#000\">x</font><p style=\"background: ä #fff\">ü</p></div></body>";
+
+        assert_eq!(
+            apply_color_scheme(html, ColorScheme::PartialInvert),
+            "<style>html { background: #121212; color: #e8e8e8; }</style>\
+            <style>body { background: #000000 url(#a) no-repeat; color: #cccccc !important }\
+            .button { background-color: #0066cc; color: white }</style>\
+            <body bgcolor=\"#000000\" text=\"#ffffff\"><div style=\"background-color: #050505; color: #888888\">\
+            <font color=\"#ffffff\">x</font><p style=\"background: ä #000000\">ü</p></div></body>"
+        );
+    }
+}
@@ -0,0 +1,316 @@
+//! Email client compatibility of HTML, based on (a bundled subset of) the caniemail.com data
+
+use serde::Serialize;
+
+use super::{
+    css::{CssItem, parse_declarations, parse_stylesheet},
+    html::{Token, location, tokenize},
+};
+
+/// email clients for which support is reported
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum Client {
+    #[serde(rename = "Outlook (Windows)")]
+    OutlookWindows,
+    #[serde(rename = "Outlook (macOS)")]
+    OutlookMac,
+    #[serde(rename = "Outlook.com")]
+    OutlookCom,
+    #[serde(rename = "Gmail")]
+    Gmail,
+    #[serde(rename = "Apple Mail")]
+    AppleMail,
+    #[serde(rename = "Yahoo Mail")]
+    Yahoo,
+    #[serde(rename = "Samsung Email")]
+    SamsungEmail,
+    #[serde(rename = "Thunderbird")]
+    Thunderbird,
+}
+
+use Client::*;
+
+const ALL_CLIENTS: &[Client] = &[
+    OutlookWindows,
+    OutlookMac,
+    OutlookCom,
+    Gmail,
+    AppleMail,
+    Yahoo,
+    SamsungEmail,
+    Thunderbird,
+];
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum FeatureKind {
+    /// a CSS property, optionally with a specific value, e.g. "display: flex"
+    CssProperty,
+    /// a CSS function used in a value, e.g. "var()"
+    CssFunction,
+    CssAtRule,
+    Element,
+    Attribute,
+}
+
+/// a feature and the clients that do not (fully) support it
+struct Feature {
+    kind: FeatureKind,
+    name: &'static str,
+    clients: &'static [Client],
+}
+
+const fn feature(kind: FeatureKind, name: &'static str, clients: &'static [Client]) -> Feature {
+    Feature {
+        kind,
+        name,
+        clients,
+    }
+}
+
+use FeatureKind::*;
+
+const FEATURES: &[Feature] = &[
+    feature(
+        CssProperty,
+        "display: flex",
+        &[OutlookWindows, OutlookCom, Yahoo],
+    ),
+    feature(
+        CssProperty,
+        "display: grid",
+        &[OutlookWindows, OutlookCom, Gmail, Yahoo],
+    ),
+    feature(
+        CssProperty,
+        "grid-template-columns",
+        &[OutlookWindows, OutlookCom, Gmail, Yahoo],
+    ),
+    feature(
+        CssProperty,
+        "position",
+        &[OutlookWindows, OutlookCom, Gmail, Yahoo],
+    ),
+    feature(CssProperty, "float", &[OutlookWindows]),
+    feature(CssProperty, "background-image", &[OutlookWindows]),
+    feature(
+        CssProperty,
+        "background-size",
+        &[OutlookWindows, OutlookCom],
+    ),
+    feature(CssProperty, "border-radius", &[OutlookWindows]),
+    feature(
+        CssProperty,
+        "box-shadow",
+        &[OutlookWindows, OutlookCom, Gmail, Yahoo],
+    ),
+    feature(CssProperty, "text-shadow", &[OutlookWindows]),
+    feature(CssProperty, "max-width", &[OutlookWindows]),
+    feature(CssProperty, "min-height", &[OutlookWindows, OutlookCom]),
+    feature(CssProperty, "opacity", &[OutlookWindows]),
+    feature(
+        CssProperty,
+        "object-fit",
+        &[OutlookWindows, OutlookCom, Gmail, Yahoo],
+    ),
+    feature(
+        CssProperty,
+        "transform",
+        &[OutlookWindows, OutlookCom, Gmail],
+    ),
+    feature(
+        CssProperty,
+        "animation",
+        &[OutlookWindows, OutlookCom, Gmail, Yahoo],
+    ),
+    feature(
+        CssProperty,
+        "transition",
+        &[OutlookWindows, OutlookCom, Gmail, Yahoo],
+    ),
+    feature(
+        CssFunction,
+        "var()",
+        &[OutlookWindows, OutlookCom, Gmail, Yahoo],
+    ),
+    feature(CssFunction, "calc()", &[OutlookWindows, Gmail]),
+    feature(CssAtRule, "@media", &[OutlookWindows]),
+    feature(
+        CssAtRule,
+        "@font-face",
+        &[OutlookWindows, OutlookCom, Gmail, Yahoo],
+    ),
+    feature(
+        CssAtRule,
+        "@import",
+        &[OutlookWindows, OutlookCom, Gmail, Yahoo],
+    ),
+    feature(
+        CssAtRule,
+        "@supports",
+        &[OutlookWindows, OutlookCom, Gmail, Yahoo],
+    ),
+    feature(
+        CssAtRule,
+        "@keyframes",
+        &[OutlookWindows, OutlookCom, Gmail, Yahoo],
+    ),
+    feature(Element, "script", ALL_CLIENTS),
+    feature(Element, "iframe", ALL_CLIENTS),
+    feature(Element, "object", ALL_CLIENTS),
+    feature(Element, "embed", ALL_CLIENTS),
+    feature(
+        Element,
+        "video",
+        &[OutlookWindows, OutlookCom, Gmail, Yahoo],
+    ),
+    feature(
+        Element,
+        "audio",
+        &[OutlookWindows, OutlookCom, Gmail, Yahoo],
+    ),
+    feature(Element, "svg", &[OutlookWindows, OutlookCom, Gmail, Yahoo]),
+    feature(Element, "form", &[OutlookWindows, OutlookCom, Gmail, Yahoo]),
+    feature(
+        Element,
+        "input",
+        &[OutlookWindows, OutlookCom, Gmail, Yahoo],
+    ),
+    feature(Element, "picture", &[OutlookWindows, OutlookCom, Gmail]),
+    feature(Element, "link", &[OutlookWindows, OutlookCom, Gmail, Yahoo]),
+    feature(Element, "base", &[OutlookCom, Gmail, Yahoo]),
+    feature(Attribute, "srcset", &[OutlookWindows, OutlookCom, Gmail]),
+    feature(Attribute, "loading", &[OutlookWindows, OutlookCom]),
+    feature(Attribute, "hidden", &[OutlookWindows, Yahoo]),
+    feature(Attribute, "on*", ALL_CLIENTS),
+];
+
+/// usage of a feature that is not supported by all clients
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct CompatibilityIssue {
+    pub kind: FeatureKind,
+    pub feature: &'static str,
+    /// one based location in the HTML body
+    pub line: usize,
+    pub column: usize,
+    pub clients: &'static [Client],
+}
+
+fn lookup(kind: FeatureKind, name: &str) -> Option<&'static Feature> {
+    FEATURES
+        .iter()
+        .find(|feature| feature.kind == kind && feature.name == name)
+}
+
+/// find CSS features in style sheets or style attributes, offsets are relative to the HTML
+fn css_features(items: Vec<CssItem>, base: usize, found: &mut Vec<(&'static Feature, usize)>) {
+    for item in items {
+        match item {
+            CssItem::AtRule { name, offset } => {
+                found.extend(lookup(CssAtRule, &name).map(|f| (f, base + offset)));
+            }
+            CssItem::Declaration {
+                property,
+                value,
+                offset,
+            } => {
+                let with_value = format!("{property}: {value}");
+                found.extend(
+                    lookup(CssProperty, &with_value)
+                        .or_else(|| lookup(CssProperty, &property))
+                        .map(|f| (f, base + offset)),
+                );
+
+                for function in FEATURES.iter().filter(|f| f.kind == CssFunction) {
+                    let name = function.name.trim_end_matches(')');
+                    if value.contains(name) {
+                        found.push((function, base + offset));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// find HTML and CSS features that are not supported by all major email clients
+pub fn compatibility_report(html: &str) -> Vec<CompatibilityIssue> {
+    let mut found = Vec::new();
+
+    for token in tokenize(html) {
+        match token {
+            Token::StartTag {
+                name,
+                attributes,
+                offset,
+                ..
+            } => {
+                found.extend(lookup(Element, &name).map(|f| (f, offset)));
+
+                for attribute in attributes {
+                    let feature = if attribute.name.starts_with("on") && attribute.name.len() > 2 {
+                        lookup(Attribute, "on*")
+                    } else {
+                        lookup(Attribute, &attribute.name)
+                    };
+                    found.extend(feature.map(|f| (f, attribute.offset)));
+
+                    if attribute.name == "style" {
+                        css_features(
+                            parse_declarations(&attribute.value),
+                            attribute.value_offset,
+                            &mut found,
+                        );
+                    }
+                }
+            }
+            Token::Style { css, offset } => {
+                css_features(parse_stylesheet(&css), offset, &mut found);
+            }
+            Token::EndTag { .. } | Token::Text { .. } => {}
+        }
+    }
+
+    found.sort_by_key(|(_, offset)| *offset);
+
+    found
+        .into_iter()
+        .map(|(feature, offset)| {
+            let (line, column) = location(html, offset);
+
+            CompatibilityIssue {
+                kind: feature.kind,
+                feature: feature.name,
+                line,
+                column,
+                clients: feature.clients,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn report() {
+        let html = "<html><head><style>@media (max-width: 600px) { .a { display: flex } }</style></head>\n\
+                    <body><div style=\"border-radius: 4px; color: red\" onclick=\"x()\">\
+                    <video src=\"a.mp4\"></video></div></body></html>";
+        let issues = compatibility_report(html)
+            .into_iter()
+            .map(|issue| (issue.feature, issue.line, issue.column))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            issues,
+            vec![
+                ("@media", 1, 20),
+                ("display: flex", 1, 53),
+                ("border-radius", 2, 19),
+                ("on*", 2, 51),
+                ("video", 2, 65),
+            ]
+        );
+    }
+}
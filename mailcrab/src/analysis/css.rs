@@ -0,0 +1,175 @@
+//! Minimal CSS scanning, finds declarations and at-rules in style sheets and style attributes
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum CssItem {
+    Declaration {
+        /// lowercase property name
+        property: String,
+        /// lowercase value without "!important"
+        value: String,
+        offset: usize,
+    },
+    AtRule {
+        /// lowercase name including the "@"
+        name: String,
+        offset: usize,
+    },
+}
+
+/// replace comments with spaces, so offsets are preserved
+fn strip_comments(css: &str) -> String {
+    let mut stripped = String::with_capacity(css.len());
+    let mut rest = css;
+
+    while let Some(start) = rest.find("/*") {
+        stripped.push_str(&rest[..start]);
+        let end = rest[start + 2..]
+            .find("*/")
+            .map_or(rest.len(), |end| start + 2 + end + 2);
+        stripped.extend(std::iter::repeat_n(' ', end - start));
+        rest = &rest[end..];
+    }
+    stripped.push_str(rest);
+
+    stripped
+}
+
+fn declaration(css: &str, start: usize, end: usize) -> Option<CssItem> {
+    let text = &css[start..end];
+    let (property, value) = text.split_once(':')?;
+    let offset = start + (text.len() - text.trim_start().len());
+    let property = property.trim().to_ascii_lowercase();
+
+    if property.is_empty()
+        || !property
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '-')
+    {
+        return None;
+    }
+
+    let value = value.trim().to_ascii_lowercase();
+    let value = value.trim_end_matches("!important").trim_end().to_owned();
+
+    Some(CssItem::Declaration {
+        property,
+        value,
+        offset,
+    })
+}
+
+fn at_rule(css: &str, start: usize, end: usize) -> Option<CssItem> {
+    let text = &css[start..end];
+    let trimmed = text.trim_start();
+    let name = trimmed.strip_prefix('@')?;
+    let length = name
+        .find(|c: char| !(c.is_ascii_alphanumeric() || c == '-'))
+        .unwrap_or(name.len());
+
+    Some(CssItem::AtRule {
+        name: format!("@{}", name[..length].to_ascii_lowercase()),
+        offset: start + (text.len() - trimmed.len()),
+    })
+}
+
+/// find declarations and at-rules in a style sheet
+pub(crate) fn parse_stylesheet(css: &str) -> Vec<CssItem> {
+    let css = strip_comments(css);
+    let mut items = Vec::new();
+    let mut depth = 0usize;
+    let mut start = 0;
+
+    for (index, c) in css.char_indices() {
+        match c {
+            '{' => {
+                // a selector or an at-rule prelude
+                items.extend(at_rule(&css, start, index));
+                depth += 1;
+                start = index + 1;
+            }
+            ';' | '}' => {
+                if depth == 0 {
+                    // statement at-rules, like @import
+                    items.extend(at_rule(&css, start, index));
+                } else {
+                    items.extend(declaration(&css, start, index));
+                }
+
+                if c == '}' {
+                    depth = depth.saturating_sub(1);
+                }
+                start = index + 1;
+            }
+            _ => {}
+        }
+    }
+
+    items
+}
+
+/// find the declarations in a style attribute
+pub(crate) fn parse_declarations(css: &str) -> Vec<CssItem> {
+    let css = strip_comments(css);
+    let mut items = Vec::new();
+    let mut start = 0;
+
+    for (index, _) in css.match_indices(';') {
+        items.extend(declaration(&css, start, index));
+        start = index + 1;
+    }
+    items.extend(declaration(&css, start, css.len()));
+
+    items
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn stylesheet() {
+        let css = "@import url(a.css);\n/* a { x: y } */\
+                   @media (max-width: 600px) { a:hover { color: red; DISPLAY: Flex !important } }";
+        let items = parse_stylesheet(css);
+
+        assert_eq!(
+            items,
+            vec![
+                CssItem::AtRule {
+                    name: "@import".to_owned(),
+                    offset: 0,
+                },
+                CssItem::AtRule {
+                    name: "@media".to_owned(),
+                    offset: 36,
+                },
+                CssItem::Declaration {
+                    property: "color".to_owned(),
+                    value: "red".to_owned(),
+                    offset: 74,
+                },
+                CssItem::Declaration {
+                    property: "display".to_owned(),
+                    value: "flex".to_owned(),
+                    offset: 86,
+                },
+            ]
+        );
+
+        assert_eq!(
+            parse_declarations("color: red; border-radius:4px"),
+            vec![
+                CssItem::Declaration {
+                    property: "color".to_owned(),
+                    value: "red".to_owned(),
+                    offset: 0,
+                },
+                CssItem::Declaration {
+                    property: "border-radius".to_owned(),
+                    value: "4px".to_owned(),
+                    offset: 12,
+                },
+            ]
+        );
+    }
+}
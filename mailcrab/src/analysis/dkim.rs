@@ -0,0 +1,720 @@
+//! Verification of DKIM signatures (RFC 6376 and RFC 8463), keys are looked up in local records
+
+use base64ct::{Base64, Encoding};
+use chrono::DateTime;
+use ring::{digest, signature};
+use serde::Serialize;
+use std::collections::HashMap;
+
+use super::{authentication::AuthResult, dns::DnsRecords};
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DkimResult {
+    pub result: AuthResult,
+    /// signing domain (d=)
+    pub domain: String,
+    /// selector (s=)
+    pub selector: String,
+    pub algorithm: String,
+    pub canonicalization: String,
+    /// why the signature did not pass
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Canonicalization {
+    Simple,
+    Relaxed,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum Algorithm {
+    RsaSha1,
+    RsaSha256,
+    Ed25519Sha256,
+}
+
+impl Algorithm {
+    pub(super) fn parse(value: &str) -> Option<Self> {
+        match value {
+            "rsa-sha1" => Some(Algorithm::RsaSha1),
+            "rsa-sha256" => Some(Algorithm::RsaSha256),
+            "ed25519-sha256" => Some(Algorithm::Ed25519Sha256),
+            _ => None,
+        }
+    }
+
+    fn digest(self) -> &'static digest::Algorithm {
+        match self {
+            Algorithm::RsaSha1 => &digest::SHA1_FOR_LEGACY_USE_ONLY,
+            Algorithm::RsaSha256 | Algorithm::Ed25519Sha256 => &digest::SHA256,
+        }
+    }
+
+    fn key_type(self) -> &'static str {
+        match self {
+            Algorithm::RsaSha1 | Algorithm::RsaSha256 => "rsa",
+            Algorithm::Ed25519Sha256 => "ed25519",
+        }
+    }
+
+    fn hash_name(self) -> &'static str {
+        match self {
+            Algorithm::RsaSha1 => "sha1",
+            Algorithm::RsaSha256 | Algorithm::Ed25519Sha256 => "sha256",
+        }
+    }
+}
+
+/// a header field, the raw bytes include the line ending
+pub(super) struct HeaderField<'a> {
+    pub(super) name: String,
+    pub(super) raw: &'a [u8],
+}
+
+fn is_wsp(b: u8) -> bool {
+    b == b' ' || b == b'\t'
+}
+
+/// DKIM uses CRLF line endings, messages imported from files may only use LF
+pub(super) fn normalize_line_endings(raw: &[u8]) -> Vec<u8> {
+    let mut normalized = Vec::with_capacity(raw.len());
+
+    for (index, b) in raw.iter().enumerate() {
+        if *b == b'\n' && (index == 0 || raw[index - 1] != b'\r') {
+            normalized.push(b'\r');
+        }
+        normalized.push(*b);
+    }
+
+    normalized
+}
+
+/// split a message in header fields and the body
+pub(super) fn split_message(raw: &[u8]) -> (Vec<HeaderField<'_>>, &[u8]) {
+    let (header, body) = match raw.windows(4).position(|w| w == b"\r\n\r\n") {
+        Some(index) => (&raw[..index + 2], &raw[index + 4..]),
+        None => (raw, &raw[raw.len()..]),
+    };
+
+    let mut fields: Vec<HeaderField> = Vec::new();
+    let mut start = 0;
+
+    for (index, _) in header.windows(2).enumerate().filter(|(_, w)| *w == b"\r\n") {
+        let next = index + 2;
+
+        // folded lines continue the current field
+        if header.get(next).is_some_and(|b| is_wsp(*b)) {
+            continue;
+        }
+
+        let raw = &header[start..next];
+        let name = raw
+            .iter()
+            .position(|b| *b == b':')
+            .map(|colon| {
+                String::from_utf8_lossy(&raw[..colon])
+                    .trim()
+                    .to_ascii_lowercase()
+            })
+            .unwrap_or_default();
+
+        fields.push(HeaderField { name, raw });
+        start = next;
+    }
+
+    (fields, body)
+}
+
+/// replace runs of whitespace with a single space
+fn compress_whitespace(value: &[u8]) -> Vec<u8> {
+    let mut compressed = Vec::with_capacity(value.len());
+
+    for b in value {
+        if is_wsp(*b) {
+            if compressed.last() != Some(&b' ') {
+                compressed.push(b' ');
+            }
+        } else {
+            compressed.push(*b);
+        }
+    }
+
+    compressed
+}
+
+pub(super) fn canonicalize_header(raw: &[u8], canonicalization: Canonicalization) -> Vec<u8> {
+    if canonicalization == Canonicalization::Simple {
+        return raw.to_vec();
+    }
+
+    let colon = raw.iter().position(|b| *b == b':').unwrap_or(raw.len());
+    let name = String::from_utf8_lossy(&raw[..colon])
+        .trim_end()
+        .to_ascii_lowercase();
+    let value = raw[(colon + 1).min(raw.len())..]
+        .iter()
+        .copied()
+        .filter(|b| *b != b'\r' && *b != b'\n')
+        .collect::<Vec<u8>>();
+    let value = compress_whitespace(&value);
+    let value = value.trim_ascii();
+
+    let mut canonical = name.into_bytes();
+    canonical.push(b':');
+    canonical.extend_from_slice(value);
+    canonical.extend_from_slice(b"\r\n");
+
+    canonical
+}
+
+fn canonicalize_body(body: &[u8], canonicalization: Canonicalization) -> Vec<u8> {
+    let mut lines = body
+        .split(|b| *b == b'\n')
+        .map(|line| line.strip_suffix(b"\r").unwrap_or(line))
+        .map(|line| match canonicalization {
+            Canonicalization::Simple => line.to_vec(),
+            Canonicalization::Relaxed => {
+                let mut line = compress_whitespace(line);
+                if line.last() == Some(&b' ') {
+                    line.pop();
+                }
+                line
+            }
+        })
+        .collect::<Vec<Vec<u8>>>();
+
+    // ignore empty lines at the end of the body
+    while lines.last().is_some_and(Vec::is_empty) {
+        lines.pop();
+    }
+
+    if lines.is_empty() {
+        return match canonicalization {
+            Canonicalization::Simple => b"\r\n".to_vec(),
+            Canonicalization::Relaxed => Vec::new(),
+        };
+    }
+
+    let mut canonical = lines.join(&b"\r\n"[..]);
+    canonical.extend_from_slice(b"\r\n");
+
+    canonical
+}
+
+/// parse a tag list, e.g. "v=1; a=rsa-sha256; ..."
+pub(super) fn parse_tags(value: &str) -> HashMap<String, String> {
+    value
+        .split(';')
+        .filter_map(|tag| tag.split_once('='))
+        .map(|(name, value)| (name.trim().to_owned(), value.trim().to_owned()))
+        .collect()
+}
+
+/// remove folding whitespace, used for base64 values
+fn remove_whitespace(value: &str) -> String {
+    value.chars().filter(|c| !c.is_whitespace()).collect()
+}
+
+/// the signature header field with the value of the b= tag removed
+fn without_signature(raw: &[u8]) -> Vec<u8> {
+    let colon = raw.iter().position(|b| *b == b':').unwrap_or(0);
+    let mut result = raw[..=colon].to_vec();
+    let tags = &raw[colon + 1..];
+
+    let mut segments = tags.split(|b| *b == b';').peekable();
+    while let Some(segment) = segments.next() {
+        match segment.iter().position(|b| *b == b'=') {
+            Some(equals) if segment[..equals].trim_ascii() == b"b" => {
+                result.extend_from_slice(&segment[..=equals]);
+                // keep the line ending when the b= tag is the last tag
+                if segments.peek().is_none() && segment.ends_with(b"\r\n") {
+                    result.extend_from_slice(b"\r\n");
+                }
+            }
+            _ => result.extend_from_slice(segment),
+        }
+
+        if segments.peek().is_some() {
+            result.push(b';');
+        }
+    }
+
+    result
+}
+
+/// read a DER tag-length-value, returns the tag, the contents and the remaining data
+fn read_der(data: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (&tag, rest) = data.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+
+    let (length, rest) = if first < 0x80 {
+        (first as usize, rest)
+    } else {
+        let count = (first & 0x7f) as usize;
+        if count == 0 || count > 4 || rest.len() < count {
+            return None;
+        }
+        let length = rest[..count]
+            .iter()
+            .fold(0usize, |length, b| (length << 8) | *b as usize);
+
+        (length, &rest[count..])
+    };
+
+    if rest.len() < length {
+        return None;
+    }
+
+    Some((tag, &rest[..length], &rest[length..]))
+}
+
+const DER_SEQUENCE: u8 = 0x30;
+const DER_BIT_STRING: u8 = 0x03;
+
+/// the object identifier of Ed25519 keys (1.3.101.112)
+const ED25519_OID: &[u8] = &[0x06, 0x03, 0x2b, 0x65, 0x70];
+
+/// the key of a DER encoded SubjectPublicKeyInfo, and whether it is an Ed25519 key
+fn subject_public_key(der: &[u8]) -> Option<(&[u8], bool)> {
+    let (DER_SEQUENCE, info, _) = read_der(der)? else {
+        return None;
+    };
+    let (DER_SEQUENCE, algorithm, rest) = read_der(info)? else {
+        return None;
+    };
+    let (DER_BIT_STRING, key, _) = read_der(rest)? else {
+        return None;
+    };
+
+    // the first byte of a bit string is the number of unused bits
+    Some((key.get(1..)?, algorithm.starts_with(ED25519_OID)))
+}
+
+/// convert a PEM encoded public key to a DKIM key record
+pub fn dkim_record_from_pem(pem: &str) -> Option<String> {
+    let base64 = pem
+        .lines()
+        .filter(|line| !line.starts_with("-----"))
+        .collect::<String>();
+    let der = Base64::decode_vec(base64.trim()).ok()?;
+
+    match subject_public_key(&der)? {
+        (key, true) => Some(format!(
+            "v=DKIM1; k=ed25519; p={}",
+            Base64::encode_string(key)
+        )),
+        (_, false) => Some(format!("v=DKIM1; k=rsa; p={base64}")),
+    }
+}
+
+/// verify a signature with the public key from a DKIM key record
+fn verify_signature(algorithm: Algorithm, key: &[u8], data: &[u8], signature: &[u8]) -> bool {
+    match algorithm {
+        Algorithm::Ed25519Sha256 => {
+            // Ed25519 signs the hash of the data
+            let hash = digest::digest(&digest::SHA256, data);
+
+            signature::UnparsedPublicKey::new(&signature::ED25519, key)
+                .verify(hash.as_ref(), signature)
+                .is_ok()
+        }
+        Algorithm::RsaSha1 | Algorithm::RsaSha256 => {
+            // keys are a SubjectPublicKeyInfo, some publish the RSAPublicKey structure directly
+            let key = match subject_public_key(key) {
+                Some((key, false)) => key,
+                _ => key,
+            };
+            let parameters = match algorithm {
+                Algorithm::RsaSha1 => &signature::RSA_PKCS1_1024_8192_SHA1_FOR_LEGACY_USE_ONLY,
+                _ => &signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY,
+            };
+
+            signature::UnparsedPublicKey::new(parameters, key)
+                .verify(data, signature)
+                .is_ok()
+        }
+    }
+}
+
+/// the signature could not be verified, with a reason
+pub(super) type Failure = (AuthResult, String);
+
+pub(super) fn perm_error(reason: impl Into<String>) -> Failure {
+    (AuthResult::PermError, reason.into())
+}
+
+pub(super) fn fail(reason: impl Into<String>) -> Failure {
+    (AuthResult::Fail, reason.into())
+}
+
+/// the public key for a signature, from the key record at selector._domainkey.domain
+pub(super) fn public_key(
+    tags: &HashMap<String, String>,
+    algorithm: Algorithm,
+    dns: &DnsRecords,
+) -> Result<Vec<u8>, Failure> {
+    let name = format!("{}._domainkey.{}", tags["s"], tags["d"]);
+    let key_tags = dns
+        .txt(&name)
+        .iter()
+        .map(|record| parse_tags(record))
+        .find(|tags| tags.get("v").is_none_or(|version| version == "DKIM1"))
+        .ok_or_else(|| perm_error(format!("no key found for {name}")))?;
+
+    let key_type = key_tags.get("k").map(String::as_str).unwrap_or("rsa");
+    if key_type != algorithm.key_type() {
+        return Err(perm_error(format!(
+            "key type {key_type} does not match algorithm {}",
+            tags["a"]
+        )));
+    }
+
+    if let Some(hashes) = key_tags.get("h")
+        && !hashes
+            .split(':')
+            .any(|hash| hash.trim() == algorithm.hash_name())
+    {
+        return Err(perm_error(format!(
+            "hash algorithm {} is not allowed by the key",
+            algorithm.hash_name()
+        )));
+    }
+
+    let key = remove_whitespace(key_tags.get("p").map(String::as_str).unwrap_or_default());
+    if key.is_empty() {
+        return Err(perm_error(format!("key {name} is revoked")));
+    }
+
+    Base64::decode_vec(&key).map_err(|_| perm_error(format!("invalid key {name}")))
+}
+
+/// check the b= tag of a signature over the canonicalized data
+pub(super) fn check_signature(
+    tags: &HashMap<String, String>,
+    algorithm: Algorithm,
+    key: &[u8],
+    data: &[u8],
+) -> Result<(), Failure> {
+    let signature = Base64::decode_vec(&remove_whitespace(&tags["b"]))
+        .map_err(|_| perm_error("invalid signature"))?;
+
+    if !verify_signature(algorithm, key, data, &signature) {
+        return Err(fail(
+            "signature mismatch, a signed header was changed or the key does not match",
+        ));
+    }
+
+    Ok(())
+}
+
+/// the canonicalized header fields listed in the h= tag, followed by the signature header field
+/// without signature, multiple instances of a header field are used from the bottom up
+pub(super) fn signed_data(
+    tags: &HashMap<String, String>,
+    signature_field: &[u8],
+    fields: &[HeaderField],
+    canonicalization: Canonicalization,
+) -> Vec<u8> {
+    let mut used: HashMap<String, usize> = HashMap::new();
+    let mut data = Vec::new();
+
+    for name in tags["h"]
+        .split(':')
+        .map(|name| name.trim().to_ascii_lowercase())
+    {
+        let count = used.entry(name.clone()).or_default();
+        let field = fields
+            .iter()
+            .filter(|field| field.name == name)
+            .rev()
+            .nth(*count);
+        *count += 1;
+
+        if let Some(field) = field {
+            data.extend(canonicalize_header(field.raw, canonicalization));
+        }
+    }
+
+    data.extend(signature_data(signature_field, canonicalization));
+
+    data
+}
+
+/// the canonicalized signature header field, without signature and trailing line ending
+pub(super) fn signature_data(
+    signature_field: &[u8],
+    canonicalization: Canonicalization,
+) -> Vec<u8> {
+    let mut data = canonicalize_header(&without_signature(signature_field), canonicalization);
+    if data.ends_with(b"\r\n") {
+        data.truncate(data.len() - 2);
+    }
+
+    data
+}
+
+/// verify the body hash and the signature over the signed header fields,
+/// used for DKIM-Signature and ARC-Message-Signature header fields
+pub(super) fn verify_message_signature(
+    tags: &HashMap<String, String>,
+    signature_field: &[u8],
+    fields: &[HeaderField],
+    body: &[u8],
+    dns: &DnsRecords,
+) -> Result<(), Failure> {
+    let algorithm = Algorithm::parse(&tags["a"])
+        .ok_or_else(|| perm_error(format!("unsupported algorithm {}", tags["a"])))?;
+
+    let (header_canonicalization, body_canonicalization) =
+        match tags.get("c").map(String::as_str).unwrap_or("simple/simple") {
+            "simple" | "simple/simple" => (Canonicalization::Simple, Canonicalization::Simple),
+            "simple/relaxed" => (Canonicalization::Simple, Canonicalization::Relaxed),
+            "relaxed" | "relaxed/simple" => (Canonicalization::Relaxed, Canonicalization::Simple),
+            "relaxed/relaxed" => (Canonicalization::Relaxed, Canonicalization::Relaxed),
+            other => return Err(perm_error(format!("unsupported canonicalization {other}"))),
+        };
+
+    let key = public_key(tags, algorithm, dns)?;
+
+    // the body hash
+    let mut canonical_body = canonicalize_body(body, body_canonicalization);
+    if let Some(length) = tags.get("l") {
+        let length = length
+            .parse::<usize>()
+            .map_err(|_| perm_error(format!("invalid body length {length}")))?;
+        canonical_body.truncate(length);
+    }
+
+    let body_hash = digest::digest(algorithm.digest(), &canonical_body);
+    let expected_body_hash = Base64::decode_vec(&remove_whitespace(&tags["bh"]))
+        .map_err(|_| perm_error("invalid body hash"))?;
+
+    if body_hash.as_ref() != expected_body_hash.as_slice() {
+        return Err(fail(
+            "body hash mismatch, the body was changed after signing",
+        ));
+    }
+
+    let data = signed_data(tags, signature_field, fields, header_canonicalization);
+
+    check_signature(tags, algorithm, &key, &data)
+}
+
+/// verify a single DKIM signature, fields are all header fields of the message
+fn verify(
+    tags: &HashMap<String, String>,
+    signature_field: &[u8],
+    fields: &[HeaderField],
+    body: &[u8],
+    dns: &DnsRecords,
+    now: i64,
+) -> Result<(), Failure> {
+    for tag in ["v", "a", "b", "bh", "d", "h", "s"] {
+        if !tags.contains_key(tag) {
+            return Err(perm_error(format!("missing required tag {tag}=")));
+        }
+    }
+
+    if tags["v"] != "1" {
+        return Err(perm_error(format!("unsupported version {}", tags["v"])));
+    }
+
+    if !tags["h"]
+        .split(':')
+        .any(|name| name.trim().eq_ignore_ascii_case("from"))
+    {
+        return Err(perm_error("the From header is not signed"));
+    }
+
+    let domain = tags["d"].to_ascii_lowercase();
+    if let Some(identity) = tags.get("i") {
+        let identity_domain = identity
+            .rsplit('@')
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        if identity_domain != domain && !identity_domain.ends_with(&format!(".{domain}")) {
+            return Err(perm_error(format!(
+                "identity {identity} is not in the signing domain {domain}"
+            )));
+        }
+    }
+
+    if let Some(expiration) = tags.get("x") {
+        let expiration = expiration
+            .parse::<i64>()
+            .map_err(|_| perm_error(format!("invalid expiration {expiration}")))?;
+
+        if expiration < now {
+            let date = DateTime::from_timestamp(expiration, 0).unwrap_or_default();
+
+            return Err(fail(format!("signature expired at {}", date.to_rfc2822())));
+        }
+    }
+
+    verify_message_signature(tags, signature_field, fields, body, dns)
+}
+
+/// verify all DKIM signatures of a raw message, now is the current unix timestamp
+pub fn verify_dkim(raw: &[u8], dns: &DnsRecords, now: i64) -> Vec<DkimResult> {
+    let raw = normalize_line_endings(raw);
+    let (fields, body) = split_message(&raw);
+
+    fields
+        .iter()
+        .filter(|field| field.name == "dkim-signature")
+        .map(|field| {
+            let value = String::from_utf8_lossy(field.raw);
+            let value = value
+                .split_once(':')
+                .map(|(_, value)| value)
+                .unwrap_or_default();
+            let tags = parse_tags(value);
+            let tag = |name: &str| tags.get(name).cloned().unwrap_or_default();
+
+            let (result, reason) = match verify(&tags, field.raw, &fields, body, dns, now) {
+                Ok(()) => (AuthResult::Pass, None),
+                Err((result, reason)) => (result, Some(reason)),
+            };
+
+            DkimResult {
+                result,
+                domain: tag("d"),
+                selector: tag("s"),
+                algorithm: tag("a"),
+                canonicalization: tags
+                    .get("c")
+                    .cloned()
+                    .unwrap_or_else(|| "simple/simple".to_owned()),
+                reason,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the example message from RFC 8463, signed with an Ed25519 and an RSA key
+    const MESSAGE: &str = "DKIM-Signature: v=1; a=ed25519-sha256; c=relaxed/relaxed;\r
+ d=football.example.com; i=@football.example.com;\r
+ q=dns/txt; s=brisbane; t=1528637909; h=from : to :\r
+ subject : date : message-id : from : subject : date;\r
+ bh=2jUSOH9NhtVGCQWNr9BrIAPreKQjO6Sn7XIkfJVOzv8=;\r
+ b=/gCrinpcQOoIfuHNQIbq4pgh9kyIK3AQUdt9OdqQehSwhEIug4D11Bus\r
+ Fa3bT3FY5OsU7ZbnKELq+eXdp1Q1Dw==\r
+DKIM-Signature: v=1; a=rsa-sha256; c=relaxed/relaxed;\r
+ d=football.example.com; i=@football.example.com;\r
+ q=dns/txt; s=test; t=1528637909; h=from : to : subject :\r
+ date : message-id : from : subject : date;\r
+ bh=2jUSOH9NhtVGCQWNr9BrIAPreKQjO6Sn7XIkfJVOzv8=;\r
+ b=F45dVWDfMbQDGHJFlXUNB2HKfbCeLRyhDXgFpEL8GwpsRe0IeIixNTe3\r
+ DhCVlUrSjV4BwcVcOF6+FF3Zo9Rpo1tFOeS9mPYQTnGdaSGsgeefOsk2Jz\r
+ dA+L10TeYt9BgDfQNZtKdN1WO//KgIqXP7OdEFE4LjFYNcUxZQ4FADY+8=\r
+From: Joe SixPack <joe@football.example.com>\r
+To: Suzie Q <suzie@shopping.example.net>\r
+Subject: Is dinner ready?\r
+Date: Fri, 11 Jul 2003 21:00:37 -0700 (PDT)\r
+Message-ID: <20030712040037.46341.5F8J@football.example.com>\r
+\r
+Hi.\r
+\r
+We lost the game.  Are you hungry yet?\r
+\r
+Joe.\r
+";
+
+    fn dns() -> DnsRecords {
+        let mut dns = DnsRecords::default();
+        dns.add_txt(
+            "brisbane._domainkey.football.example.com",
+            "v=DKIM1; k=ed25519; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=",
+        );
+        dns.add_txt(
+            "test._domainkey.football.example.com.",
+            "v=DKIM1; k=rsa; p=MIGfMA0GCSqGSIb3DQEBAQUAA4GNADCBiQKBgQDkHlOQoBTzWRiGs5V6NpP3id\
+             Y6Wk08a5qhdR6wy5bdOKb2jLQiY/J16JYi0Qvx/byYzCNb3W91y3FutACDfzwQ/BC/e/8uBsCR+yz1Lxj+PL\
+             6lHvqMKrM3rG4hstT5QjvHO9PzoxZyVYLzBfO2EeC3Ip3G+2kryOTIKT+l/K4w3QIDAQAB",
+        );
+        dns
+    }
+
+    fn results(message: &str, dns: &DnsRecords) -> Vec<(AuthResult, Option<String>)> {
+        verify_dkim(message.as_bytes(), dns, 1528637909)
+            .into_iter()
+            .map(|result| (result.result, result.reason))
+            .collect()
+    }
+
+    #[test]
+    fn rfc8463_example() {
+        assert_eq!(
+            results(MESSAGE, &dns()),
+            vec![(AuthResult::Pass, None), (AuthResult::Pass, None)]
+        );
+
+        // line endings are normalized
+        let message = MESSAGE.replace("\r\n", "\n");
+        assert_eq!(
+            results(&message, &dns()),
+            vec![(AuthResult::Pass, None), (AuthResult::Pass, None)]
+        );
+    }
+
+    #[test]
+    fn failures() {
+        let body_changed = MESSAGE.replace("lost the game", "won the game");
+        assert!(
+            results(&body_changed, &dns())
+                .iter()
+                .all(|(result, reason)| *result == AuthResult::Fail
+                    && reason
+                        .as_deref()
+                        .unwrap_or_default()
+                        .starts_with("body hash"))
+        );
+
+        let header_changed = MESSAGE.replace("Is dinner ready?", "Is lunch ready?");
+        assert!(
+            results(&header_changed, &dns())
+                .iter()
+                .all(|(result, reason)| *result == AuthResult::Fail
+                    && reason
+                        .as_deref()
+                        .unwrap_or_default()
+                        .starts_with("signature"))
+        );
+
+        let expired = MESSAGE.replace("t=1528637909;", "t=1528637909; x=1528637000;");
+        assert_eq!(
+            results(&expired, &dns())[0],
+            (
+                AuthResult::Fail,
+                Some("signature expired at Sun, 10 Jun 2018 13:23:20 +0000".to_owned())
+            )
+        );
+
+        assert_eq!(
+            results(MESSAGE, &DnsRecords::default())[1],
+            (
+                AuthResult::PermError,
+                Some("no key found for test._domainkey.football.example.com".to_owned())
+            )
+        );
+    }
+
+    #[test]
+    fn pem_keys() {
+        let pem = "-----BEGIN PUBLIC KEY-----\n\
+                   MCowBQYDK2VwAyEA11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=\n\
+                   -----END PUBLIC KEY-----\n";
+
+        assert_eq!(
+            dkim_record_from_pem(pem).as_deref(),
+            Some("v=DKIM1; k=ed25519; p=11qYAYKxCrfVS/7TyWQHOg7hcvPapiMlrwIaaPcHURo=")
+        );
+    }
+}
@@ -0,0 +1,212 @@
+//! DMARC (RFC 7489) evaluation, based on the SPF and DKIM results
+
+use serde::Serialize;
+
+use super::{
+    authentication::AuthResult, dkim::DkimResult, dkim::parse_tags, dns::DnsRecords, spf::SpfResult,
+};
+
+/// public suffixes with two labels, the full public suffix list is not bundled
+const PUBLIC_SUFFIXES: &[&str] = &[
+    "co.uk", "org.uk", "ac.uk", "gov.uk", "com.au", "net.au", "org.au", "co.nz", "co.jp", "co.za",
+    "com.br", "com.cn", "com.mx", "co.in",
+];
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct DmarcResult {
+    pub result: AuthResult,
+    /// the domain of the From header
+    pub domain: String,
+    /// the requested policy for messages that fail: none, quarantine or reject
+    pub policy: Option<String>,
+    /// the aligned identifier, or why the check failed
+    pub reason: Option<String>,
+}
+
+/// the organizational domain, e.g. example.com for mail.example.com
+fn organizational_domain(domain: &str) -> String {
+    let labels = domain.split('.').collect::<Vec<&str>>();
+    let suffix_labels = match labels.len() >= 2
+        && PUBLIC_SUFFIXES.contains(&labels[labels.len() - 2..].join(".").as_str())
+    {
+        true => 2,
+        false => 1,
+    };
+
+    labels[labels.len().saturating_sub(suffix_labels + 1)..].join(".")
+}
+
+/// are the domains aligned, strict alignment requires the same domain
+fn aligned(domain: &str, from: &str, strict: bool) -> bool {
+    let domain = domain.to_ascii_lowercase();
+
+    match strict {
+        true => domain == from,
+        false => organizational_domain(&domain) == organizational_domain(from),
+    }
+}
+
+/// evaluate the DMARC policy of the domain of the From header address
+pub fn check_dmarc(
+    from: &str,
+    spf: &SpfResult,
+    dkim: &[DkimResult],
+    dns: &DnsRecords,
+) -> DmarcResult {
+    let domain = from
+        .rsplit_once('@')
+        .map(|(_, domain)| domain)
+        .unwrap_or_default()
+        .trim_end_matches('>')
+        .to_ascii_lowercase();
+
+    let result = |result, policy: Option<&String>, reason: String| DmarcResult {
+        result,
+        domain: domain.clone(),
+        policy: policy.cloned(),
+        reason: Some(reason),
+    };
+
+    if domain.is_empty() {
+        return result(
+            AuthResult::None,
+            None,
+            "the From header has no address".to_owned(),
+        );
+    }
+
+    // the policy of the organizational domain applies to subdomains without a record
+    let organizational = organizational_domain(&domain);
+    let record = |name: &str| {
+        dns.txt(&format!("_dmarc.{name}"))
+            .iter()
+            .find(|record| record.trim_start().starts_with("v=DMARC1"))
+            .map(|record| parse_tags(record))
+    };
+
+    let (tags, subdomain) = match record(&domain) {
+        Some(tags) => (tags, false),
+        None => match record(&organizational) {
+            Some(tags) if organizational != domain => (tags, true),
+            _ => {
+                return result(
+                    AuthResult::None,
+                    None,
+                    format!("no DMARC record for {domain}"),
+                );
+            }
+        },
+    };
+
+    let Some(policy) = tags.get("p") else {
+        return result(
+            AuthResult::PermError,
+            None,
+            "the DMARC record has no policy (p=)".to_owned(),
+        );
+    };
+    let policy = match subdomain {
+        true => tags.get("sp").unwrap_or(policy),
+        false => policy,
+    };
+
+    let strict = |tag: &str| tags.get(tag).is_some_and(|mode| mode == "s");
+
+    if spf.result == AuthResult::Pass && aligned(&spf.domain, &domain, strict("aspf")) {
+        return result(
+            AuthResult::Pass,
+            Some(policy),
+            format!("SPF passed for aligned domain {}", spf.domain),
+        );
+    }
+
+    if let Some(signature) = dkim.iter().find(|signature| {
+        signature.result == AuthResult::Pass && aligned(&signature.domain, &domain, strict("adkim"))
+    }) {
+        return result(
+            AuthResult::Pass,
+            Some(policy),
+            format!(
+                "DKIM signature of aligned domain {} passed",
+                signature.domain
+            ),
+        );
+    }
+
+    result(
+        AuthResult::Fail,
+        Some(policy),
+        format!("neither SPF nor DKIM passed for a domain aligned with {domain}"),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn spf(result: AuthResult, domain: &str) -> SpfResult {
+        SpfResult {
+            result,
+            domain: domain.to_owned(),
+            ip: None,
+            reason: None,
+        }
+    }
+
+    fn dkim(result: AuthResult, domain: &str) -> DkimResult {
+        DkimResult {
+            result,
+            domain: domain.to_owned(),
+            selector: "mail".to_owned(),
+            algorithm: "rsa-sha256".to_owned(),
+            canonicalization: "relaxed/relaxed".to_owned(),
+            reason: None,
+        }
+    }
+
+    #[test]
+    fn alignment() {
+        let mut dns = DnsRecords::default();
+        dns.add_txt(
+            "_dmarc.example.com",
+            "v=DMARC1; p=reject; sp=quarantine; aspf=s",
+        );
+        dns.add_txt("_dmarc.example.co.uk", "v=DMARC1; p=none");
+
+        assert_eq!(organizational_domain("a.b.example.co.uk"), "example.co.uk");
+        assert_eq!(organizational_domain("example.com"), "example.com");
+
+        // strict SPF alignment, relaxed DKIM alignment
+        let relaxed_spf = spf(AuthResult::Pass, "bounces.example.com");
+        let result = check_dmarc("joe@example.com", &relaxed_spf, &[], &dns);
+        assert_eq!(result.result, AuthResult::Fail);
+        assert_eq!(result.policy.as_deref(), Some("reject"));
+
+        let signatures = [
+            dkim(AuthResult::Pass, "other.example"),
+            dkim(AuthResult::Pass, "mail.example.com"),
+        ];
+        let result = check_dmarc("joe@example.com", &relaxed_spf, &signatures, &dns);
+        assert_eq!(result.result, AuthResult::Pass);
+
+        // subdomains use the sp= policy of the organizational domain
+        let result = check_dmarc(
+            "joe@news.example.com",
+            &spf(AuthResult::Pass, "news.example.com"),
+            &[],
+            &dns,
+        );
+        assert_eq!(result.result, AuthResult::Pass);
+        assert_eq!(result.policy.as_deref(), Some("quarantine"));
+
+        let failed = spf(AuthResult::Fail, "example.co.uk");
+        let result = check_dmarc("joe@shop.example.co.uk", &failed, &[], &dns);
+        assert_eq!(
+            (result.result, result.policy.as_deref()),
+            (AuthResult::Fail, Some("none"))
+        );
+
+        let result = check_dmarc("joe@other.example", &failed, &[], &dns);
+        assert_eq!(result.result, AuthResult::None);
+    }
+}
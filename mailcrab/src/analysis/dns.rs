@@ -0,0 +1,286 @@
+use std::{collections::HashMap, net::IpAddr};
+
+use crate::error::{Error, Result};
+
+/// DNS records that are used instead of network lookups, e.g. for DKIM keys and SPF
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct DnsRecords {
+    txt: HashMap<String, Vec<String>>,
+    /// A and AAAA records
+    addresses: HashMap<String, Vec<IpAddr>>,
+    /// MX records, (preference, exchange)
+    mx: HashMap<String, Vec<(u16, String)>>,
+}
+
+/// names are case insensitive and may be fully qualified
+fn normalize(name: &str) -> String {
+    name.trim().trim_end_matches('.').to_ascii_lowercase()
+}
+
+/// split a zone file line in fields, quoted strings are a single field (without the quotes)
+fn zone_fields(line: &str) -> Vec<(String, bool)> {
+    let mut fields = Vec::new();
+    let mut chars = line.chars().peekable();
+
+    while let Some(c) = chars.next() {
+        match c {
+            '"' => {
+                let mut field = String::new();
+                while let Some(c) = chars.next() {
+                    match c {
+                        '"' => break,
+                        '\\' => field.extend(chars.next()),
+                        c => field.push(c),
+                    }
+                }
+                fields.push((field, true));
+            }
+            c if c.is_whitespace() || c == '(' || c == ')' => {}
+            c => {
+                let mut field = c.to_string();
+                while let Some(c) = chars.next_if(|c| !c.is_whitespace() && !"()\"".contains(*c)) {
+                    field.push(c);
+                }
+                fields.push((field, false));
+            }
+        }
+    }
+
+    fields
+}
+
+/// the logical lines of a zone file without comments, parentheses continue a record on the next lines
+fn zone_lines(zone: &str) -> Vec<(usize, String)> {
+    let mut lines: Vec<(usize, String)> = Vec::new();
+    let mut depth = 0;
+
+    for (index, line) in zone.lines().enumerate() {
+        let continues = depth > 0;
+        let mut quoted = false;
+        let mut end = line.len();
+
+        for (position, c) in line.char_indices() {
+            match c {
+                '"' => quoted = !quoted,
+                ';' if !quoted => {
+                    end = position;
+                    break;
+                }
+                '(' if !quoted => depth += 1,
+                ')' if !quoted => depth -= 1,
+                _ => {}
+            }
+        }
+
+        match lines.last_mut() {
+            Some((_, record)) if continues => {
+                record.push(' ');
+                record.push_str(&line[..end]);
+            }
+            _ => lines.push((index + 1, line[..end].to_owned())),
+        }
+    }
+
+    lines
+}
+
+impl DnsRecords {
+    pub fn add_txt(&mut self, name: &str, value: impl Into<String>) {
+        self.txt
+            .entry(normalize(name))
+            .or_default()
+            .push(value.into());
+    }
+
+    pub fn add_address(&mut self, name: &str, address: IpAddr) {
+        self.addresses
+            .entry(normalize(name))
+            .or_default()
+            .push(address);
+    }
+
+    pub fn add_mx(&mut self, name: &str, preference: u16, exchange: &str) {
+        self.mx
+            .entry(normalize(name))
+            .or_default()
+            .push((preference, normalize(exchange)));
+    }
+
+    /// the TXT records of a name
+    pub fn txt(&self, name: &str) -> &[String] {
+        self.txt
+            .get(&normalize(name))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// the A and AAAA records of a name
+    pub fn addresses(&self, name: &str) -> &[IpAddr] {
+        self.addresses
+            .get(&normalize(name))
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    /// the mail exchanges of a name, ordered by preference
+    pub fn mx(&self, name: &str) -> Vec<&str> {
+        let mut mx = self
+            .mx
+            .get(&normalize(name))
+            .map(|mx| mx.iter().collect::<Vec<_>>())
+            .unwrap_or_default();
+        mx.sort_by_key(|(preference, _)| *preference);
+
+        mx.into_iter()
+            .map(|(_, exchange)| exchange.as_str())
+            .collect()
+    }
+
+    /// add the TXT, A, AAAA and MX records of a zone file (RFC 1035), other records are ignored
+    pub fn add_zone(&mut self, zone: &str) -> Result<()> {
+        let mut origin = String::new();
+        let mut owner = String::new();
+
+        for (number, line) in zone_lines(zone) {
+            let invalid =
+                |reason: &str| Error::Config(format!("zone file line {number}: {reason}"));
+            let continued = line.starts_with(char::is_whitespace);
+            let fields = zone_fields(&line);
+            let Some(((first, _), rest)) = fields.split_first() else {
+                continue;
+            };
+
+            // relative names are relative to the origin
+            let absolute = |name: &str| match name {
+                "@" => origin.clone(),
+                name if name.ends_with('.') || origin.is_empty() => normalize(name),
+                name => format!("{}.{origin}", normalize(name)),
+            };
+
+            match first.to_ascii_uppercase().as_str() {
+                "$ORIGIN" => {
+                    let (name, _) = rest.first().ok_or_else(|| invalid("missing origin"))?;
+                    origin = normalize(name);
+                    continue;
+                }
+                "$TTL" => continue,
+                directive if directive.starts_with('$') => {
+                    return Err(invalid(&format!("unsupported directive {directive}")));
+                }
+                _ => {}
+            }
+
+            // records on a continued line belong to the previous owner
+            let mut fields = match continued {
+                true => fields.as_slice(),
+                false => {
+                    owner = absolute(first);
+                    rest
+                }
+            };
+
+            // optional TTL and class, in any order
+            while let Some(((field, false), rest)) = fields.split_first() {
+                let upper = field.to_ascii_uppercase();
+                if field.chars().all(|c| c.is_ascii_digit())
+                    || ["IN", "CH", "HS"].contains(&upper.as_str())
+                {
+                    fields = rest;
+                } else {
+                    break;
+                }
+            }
+
+            let Some(((record_type, _), data)) = fields.split_first() else {
+                return Err(invalid("missing record type"));
+            };
+
+            match record_type.to_ascii_uppercase().as_str() {
+                "TXT" => {
+                    // a TXT record may consist of multiple strings, that are concatenated
+                    let value = data
+                        .iter()
+                        .map(|(field, _)| field.as_str())
+                        .collect::<String>();
+                    self.add_txt(&owner, value);
+                }
+                "A" | "AAAA" => {
+                    let (address, _) = data.first().ok_or_else(|| invalid("missing address"))?;
+                    let address = address
+                        .parse()
+                        .map_err(|_| invalid(&format!("invalid address {address}")))?;
+                    self.add_address(&owner, address);
+                }
+                "MX" => {
+                    let [(preference, _), (exchange, _), ..] = data else {
+                        return Err(invalid("MX records need a preference and an exchange"));
+                    };
+                    let preference = preference
+                        .parse()
+                        .map_err(|_| invalid(&format!("invalid preference {preference}")))?;
+                    self.add_mx(&owner.clone(), preference, &absolute(exchange));
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn zone_file() {
+        let zone = r#"
+$ORIGIN example.com.
+$TTL 3600
+@       IN  SOA ns.example.com. admin.example.com. (
+                2024010101 ; serial
+                3600 900 604800 300 )
+        IN  MX  20 backup
+        IN  MX  10 mail.example.com.
+        IN  TXT ( "v=spf1 ip4:192.0.2.0/24 "
+                  "include:_spf.example.net -all" )
+mail    300 IN A 192.0.2.10
+mail    AAAA 2001:db8::10
+_dmarc  TXT "v=DMARC1; p=reject; rua=mailto:dmarc@example.com" ; a comment
+"#;
+
+        let mut records = DnsRecords::default();
+        records.add_zone(zone).unwrap();
+
+        assert_eq!(
+            records.txt("example.com"),
+            ["v=spf1 ip4:192.0.2.0/24 include:_spf.example.net -all"]
+        );
+        assert_eq!(
+            records.mx("example.com"),
+            ["mail.example.com", "backup.example.com"]
+        );
+        assert_eq!(
+            records.addresses("MAIL.example.com."),
+            [
+                "192.0.2.10".parse::<IpAddr>().unwrap(),
+                "2001:db8::10".parse().unwrap()
+            ]
+        );
+        assert_eq!(
+            records.txt("_dmarc.example.com"),
+            ["v=DMARC1; p=reject; rua=mailto:dmarc@example.com"]
+        );
+
+        assert!(
+            DnsRecords::default()
+                .add_zone("mail A 192.0.2.300")
+                .is_err()
+        );
+        assert!(
+            DnsRecords::default()
+                .add_zone("$INCLUDE other.zone")
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,33 @@
+//! Prepares a rendered HTML body for the preview frame of the web interface
+
+use super::{
+    ColorScheme, apply_color_scheme, block_remote_content, html::insert_in_head, sanitize_html,
+};
+
+/// links open in a new window, the frame is sandboxed and can not navigate itself
+const FRAME_DEFAULTS: &str = "<base target=\"_blank\">\
+    <style>body { font-family: sans-serif; line-height: 1.5; }</style>";
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct FrameOptions {
+    /// load remote images, style sheets and fonts
+    pub remote: bool,
+    /// remove scripts, frames, forms and event handlers
+    pub sanitize: bool,
+    pub scheme: ColorScheme,
+}
+
+/// prepare a rendered body for the sandboxed preview frame
+pub fn prepare_frame(html: &str, options: FrameOptions) -> String {
+    let html = match options.sanitize {
+        true => sanitize_html(html),
+        false => html.to_owned(),
+    };
+    let html = match options.remote {
+        true => html,
+        false => block_remote_content(&html).html,
+    };
+    let html = apply_color_scheme(&html, options.scheme);
+
+    insert_in_head(&html, FRAME_DEFAULTS)
+}
@@ -0,0 +1,425 @@
+//! A forgiving HTML tokenizer, it only reports what the analysis needs: tags with their
+//! attributes and the contents of style elements, including their byte offsets
+
+/// an attribute of a start tag
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct Attribute {
+    /// lowercase attribute name
+    pub(crate) name: String,
+    pub(crate) value: String,
+    pub(crate) offset: usize,
+    /// offset of the value, the value is not decoded so offsets within it are preserved
+    pub(crate) value_offset: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) enum Token {
+    StartTag {
+        /// lowercase element name
+        name: String,
+        attributes: Vec<Attribute>,
+        offset: usize,
+        /// offset after the closing ">"
+        end: usize,
+    },
+    EndTag {
+        name: String,
+        offset: usize,
+        end: usize,
+    },
+    /// the contents of a style element
+    Style { css: String, offset: usize },
+    /// text between tags, with decoded character references
+    Text { text: String, offset: usize },
+}
+
+/// elements of which the content is not parsed as HTML
+const RAW_TEXT_ELEMENTS: [&str; 4] = ["style", "script", "textarea", "title"];
+
+fn is_name_char(c: u8) -> bool {
+    c.is_ascii_alphanumeric() || matches!(c, b'-' | b'_' | b':' | b'.')
+}
+
+/// find the (case insensitive) closing tag of a raw text element
+fn find_end_tag(html: &str, from: usize, name: &str) -> usize {
+    let needle = format!("</{name}");
+
+    html[from..]
+        .to_ascii_lowercase()
+        .find(&needle)
+        .map_or(html.len(), |index| from + index)
+}
+
+/// parse the attributes of a start tag, returns the attributes and the offset after the tag
+fn parse_attributes(html: &str, mut position: usize) -> (Vec<Attribute>, usize) {
+    let bytes = html.as_bytes();
+    let mut attributes = Vec::new();
+
+    loop {
+        while position < bytes.len()
+            && (bytes[position].is_ascii_whitespace() || bytes[position] == b'/')
+        {
+            position += 1;
+        }
+
+        if position >= bytes.len() {
+            return (attributes, position);
+        }
+
+        if bytes[position] == b'>' {
+            return (attributes, position + 1);
+        }
+
+        let offset = position;
+        while position < bytes.len()
+            && !bytes[position].is_ascii_whitespace()
+            && !matches!(bytes[position], b'=' | b'>' | b'/')
+        {
+            position += 1;
+        }
+        // skip invalid characters, e.g. a stray quote
+        if position == offset {
+            position += 1;
+            continue;
+        }
+        let name = html[offset..position].to_ascii_lowercase();
+
+        while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+
+        if position >= bytes.len() || bytes[position] != b'=' {
+            attributes.push(Attribute {
+                name,
+                value: String::new(),
+                offset,
+                value_offset: position,
+            });
+            continue;
+        }
+
+        position += 1;
+        while position < bytes.len() && bytes[position].is_ascii_whitespace() {
+            position += 1;
+        }
+
+        let (value_offset, value_end, next) = match bytes.get(position) {
+            Some(quote @ (b'"' | b'\'')) => {
+                let start = position + 1;
+                let end = html[start..]
+                    .find(*quote as char)
+                    .map_or(html.len(), |index| start + index);
+
+                (start, end, (end + 1).min(html.len()))
+            }
+            _ => {
+                let start = position;
+                let mut end = start;
+                while end < bytes.len() && !bytes[end].is_ascii_whitespace() && bytes[end] != b'>' {
+                    end += 1;
+                }
+
+                (start, end, end)
+            }
+        };
+
+        attributes.push(Attribute {
+            name,
+            value: html[value_offset..value_end].to_owned(),
+            offset,
+            value_offset,
+        });
+        position = next;
+    }
+}
+
+/// add a text token, unless it only contains whitespace
+fn push_text(tokens: &mut Vec<Token>, html: &str, start: usize, end: usize) {
+    let text = &html[start..end];
+
+    if !text.trim().is_empty() {
+        tokens.push(Token::Text {
+            text: decode_entities(text),
+            offset: start,
+        });
+    }
+}
+
+/// split a HTML document in tokens, comments and doctypes are skipped
+pub(crate) fn tokenize(html: &str) -> Vec<Token> {
+    let bytes = html.as_bytes();
+    let mut tokens = Vec::new();
+    let mut position = 0;
+    let mut text_start = 0;
+
+    while let Some(index) = html[position..].find('<') {
+        let offset = position + index;
+        let rest = &html[offset..];
+
+        // a "<" that does not start a tag is text
+        let tag = rest.starts_with("<!")
+            || rest.starts_with("<?")
+            || rest[1..]
+                .trim_start_matches('/')
+                .starts_with(|c: char| c.is_ascii_alphabetic());
+        if !tag {
+            position = offset + 1;
+            continue;
+        }
+
+        push_text(&mut tokens, html, text_start, offset);
+
+        if let Some(comment) = rest.strip_prefix("<!--") {
+            position = comment
+                .find("-->")
+                .map_or(html.len(), |end| offset + 4 + end + 3);
+            text_start = position;
+            continue;
+        }
+
+        if rest.starts_with("<!") || rest.starts_with("<?") {
+            position = rest.find('>').map_or(html.len(), |end| offset + end + 1);
+            text_start = position;
+            continue;
+        }
+
+        let closing = rest.starts_with("</");
+        let name_start = offset + if closing { 2 } else { 1 };
+        let mut name_end = name_start;
+        while name_end < bytes.len() && is_name_char(bytes[name_end]) {
+            name_end += 1;
+        }
+
+        let name = html[name_start..name_end].to_ascii_lowercase();
+
+        if closing {
+            position = html[name_end..]
+                .find('>')
+                .map_or(html.len(), |end| name_end + end + 1);
+            tokens.push(Token::EndTag {
+                name,
+                offset,
+                end: position,
+            });
+            text_start = position;
+            continue;
+        }
+
+        let (attributes, end) = parse_attributes(html, name_end);
+        position = end;
+
+        let raw_text = RAW_TEXT_ELEMENTS.contains(&name.as_str());
+        let is_style = name == "style";
+
+        tokens.push(Token::StartTag {
+            name: name.clone(),
+            attributes,
+            offset,
+            end,
+        });
+
+        if raw_text {
+            let end = find_end_tag(html, position, &name);
+
+            if is_style {
+                tokens.push(Token::Style {
+                    css: html[position..end].to_owned(),
+                    offset: position,
+                });
+            }
+
+            position = end;
+        }
+
+        text_start = position;
+    }
+
+    push_text(&mut tokens, html, text_start, html.len());
+
+    tokens
+}
+
+/// decode character references in an attribute value, e.g. "&amp;" in URLs
+pub(crate) fn decode_entities(value: &str) -> String {
+    let mut decoded = String::with_capacity(value.len());
+    let mut rest = value;
+
+    while let Some(start) = rest.find('&') {
+        decoded.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let reference = rest[1..]
+            .find(';')
+            .filter(|end| *end <= 10)
+            .map(|end| &rest[1..end + 1]);
+        let character = reference.and_then(|reference| match reference {
+            "amp" => Some('&'),
+            "lt" => Some('<'),
+            "gt" => Some('>'),
+            "quot" => Some('"'),
+            "apos" => Some('\''),
+            "nbsp" => Some('\u{a0}'),
+            _ => {
+                let number = reference.strip_prefix('#')?;
+                let code = match number.strip_prefix(['x', 'X']) {
+                    Some(hex) => u32::from_str_radix(hex, 16).ok()?,
+                    None => number.parse().ok()?,
+                };
+
+                char::from_u32(code)
+            }
+        });
+
+        match (reference, character) {
+            (Some(reference), Some(character)) => {
+                decoded.push(character);
+                rest = &rest[reference.len() + 2..];
+            }
+            _ => {
+                decoded.push('&');
+                rest = &rest[1..];
+            }
+        }
+    }
+    decoded.push_str(rest);
+
+    decoded
+}
+
+/// replace byte ranges of a HTML document, overlapping ranges are skipped
+pub(crate) fn apply_edits(html: &str, mut edits: Vec<(usize, usize, String)>) -> String {
+    edits.sort_by_key(|(start, ..)| *start);
+
+    let mut edited = String::with_capacity(html.len());
+    let mut position = 0;
+    for (start, end, replacement) in edits {
+        if start < position {
+            continue;
+        }
+        edited.push_str(&html[position..start]);
+        edited.push_str(&replacement);
+        position = end;
+    }
+    edited.push_str(&html[position..]);
+
+    edited
+}
+
+/// insert content at the start of the head, or of the document when it has no head,
+/// after the doctype so the document is not rendered in quirks mode
+pub(crate) fn insert_in_head(html: &str, content: &str) -> String {
+    let position = tokenize(html)
+        .into_iter()
+        .take_while(|token| !matches!(token, Token::Text { .. }))
+        .find_map(|token| match token {
+            Token::StartTag { name, end, .. } if name == "head" => Some(end),
+            _ => None,
+        })
+        .unwrap_or_else(|| {
+            let trimmed = html.trim_start();
+            match trimmed.get(..9) {
+                Some(doctype) if doctype.eq_ignore_ascii_case("<!doctype") => trimmed
+                    .find('>')
+                    .map_or(0, |end| html.len() - trimmed.len() + end + 1),
+                _ => 0,
+            }
+        });
+
+    format!("{}{content}{}", &html[..position], &html[position..])
+}
+
+/// one based line and column of a byte offset
+pub(crate) fn location(text: &str, offset: usize) -> (usize, usize) {
+    let before = &text[..offset.min(text.len())];
+    let line = before.matches('\n').count() + 1;
+    let column = before
+        .rsplit('\n')
+        .next()
+        .unwrap_or_default()
+        .chars()
+        .count()
+        + 1;
+
+    (line, column)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tags_and_styles() {
+        let html = "<!-- <video> -->\n<P class=a style='color: red'>x < y<br/>\
+                    <STYLE>p { margin: 0 }</style><img src=a.png alt></p>";
+        let tokens = tokenize(html);
+
+        assert_eq!(tokens.len(), 8);
+        assert_eq!(
+            tokens[0],
+            Token::StartTag {
+                name: "p".to_owned(),
+                attributes: vec![
+                    Attribute {
+                        name: "class".to_owned(),
+                        value: "a".to_owned(),
+                        offset: 20,
+                        value_offset: 26,
+                    },
+                    Attribute {
+                        name: "style".to_owned(),
+                        value: "color: red".to_owned(),
+                        offset: 28,
+                        value_offset: 35,
+                    },
+                ],
+                offset: 17,
+                end: 47,
+            }
+        );
+        assert_eq!(
+            tokens[1],
+            Token::Text {
+                text: "x < y".to_owned(),
+                offset: 47,
+            }
+        );
+        assert!(matches!(&tokens[2], Token::StartTag { name, .. } if name == "br"));
+        assert!(matches!(&tokens[4], Token::Style { css, .. } if css == "p { margin: 0 }"));
+        assert_eq!(
+            tokens[7],
+            Token::EndTag {
+                name: "p".to_owned(),
+                offset: html.len() - 4,
+                end: html.len(),
+            }
+        );
+        assert!(
+            matches!(&tokens[6], Token::StartTag { attributes, .. } if attributes[1].name == "alt")
+        );
+        assert_eq!(location(html, 20), (2, 4));
+        assert_eq!(decode_entities("?a=1&amp;b=&#50;&#x33;&c"), "?a=1&b=23&c");
+    }
+
+    #[test]
+    fn head() {
+        assert_eq!(
+            insert_in_head(
+                "<!DOCTYPE html><html><HEAD lang=en><title>x</title>",
+                "<base>"
+            ),
+            "<!DOCTYPE html><html><HEAD lang=en><base><title>x</title>"
+        );
+        assert_eq!(
+            insert_in_head("\n<!doctype html>\n<p>text</p><head>", "<base>"),
+            "\n<!doctype html><base>\n<p>text</p><head>"
+        );
+        assert_eq!(insert_in_head("plain text", "<base>"), "<base>plain text");
+        assert_eq!(
+            apply_edits(
+                "abcdef",
+                vec![(4, 5, "E".to_owned()), (0, 2, String::new())]
+            ),
+            "cdEf"
+        );
+    }
+}
@@ -0,0 +1,308 @@
+//! Links and images in the message bodies, and common problems with them
+
+use serde::Serialize;
+use std::net::{IpAddr, Ipv6Addr};
+use url::{Host, Url};
+
+use super::html::{Token, decode_entities, location, tokenize};
+
+/// the body a link was found in
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkSource {
+    Html,
+    Text,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum LinkIssue {
+    /// plain http instead of https
+    InsecureHttp,
+    Localhost,
+    /// a private, link-local or unspecified IP address
+    PrivateAddress,
+    Malformed,
+    /// an image without alt text
+    MissingAlt,
+    /// a cid: reference without an attachment with that content id
+    MissingAttachment,
+}
+
+/// result of an (optional) request to the link target
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Reachability {
+    /// the HTTP status code of the response
+    Status(u16),
+    Error(String),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Link {
+    pub url: String,
+    pub source: LinkSource,
+    /// element and attribute of a link in the HTML body, e.g. "img" and "src"
+    pub element: Option<String>,
+    pub attribute: Option<String>,
+    /// one based location in the body
+    pub line: usize,
+    pub column: usize,
+    pub issues: Vec<LinkIssue>,
+    pub reachability: Option<Reachability>,
+}
+
+impl Link {
+    /// can the target of the link be requested to check whether it is reachable
+    pub fn is_checkable(&self) -> bool {
+        (self.url.starts_with("http://") || self.url.starts_with("https://"))
+            && !self.issues.iter().any(|issue| {
+                matches!(
+                    issue,
+                    LinkIssue::Malformed | LinkIssue::Localhost | LinkIssue::PrivateAddress
+                )
+            })
+    }
+}
+
+/// attributes that contain a URL
+const URL_ATTRIBUTES: [&str; 2] = ["href", "src"];
+
+fn is_private(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_private() || ip.is_link_local() || ip.is_unspecified(),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_private(ip.into()),
+            None => {
+                let unique_local = (ip.segments()[0] & 0xfe00) == 0xfc00;
+                let link_local = (ip.segments()[0] & 0xffc0) == 0xfe80;
+
+                unique_local || link_local || ip.is_unspecified()
+            }
+        },
+    }
+}
+
+fn is_loopback(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => ip.is_loopback(),
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => ip.is_loopback(),
+            None => ip == Ipv6Addr::LOCALHOST,
+        },
+    }
+}
+
+/// loopback, private, link-local and unspecified addresses, including IPv4-mapped IPv6
+/// addresses, links to these are never requested
+pub fn is_internal_address(ip: IpAddr) -> bool {
+    is_loopback(ip) || is_private(ip)
+}
+
+/// problems with a URL, content ids are the ids of the attachments of the message
+fn url_issues(url: &str, content_ids: &[&str]) -> Vec<LinkIssue> {
+    if let Some(content_id) = url.strip_prefix("cid:") {
+        return match content_ids.contains(&content_id) {
+            true => Vec::new(),
+            false => vec![LinkIssue::MissingAttachment],
+        };
+    }
+
+    let Ok(parsed) = Url::parse(url) else {
+        return vec![LinkIssue::Malformed];
+    };
+
+    let mut issues = Vec::new();
+
+    match parsed.scheme() {
+        "http" => issues.push(LinkIssue::InsecureHttp),
+        "https" => {}
+        // mailto:, tel:, data: etc.
+        _ => return issues,
+    }
+
+    match parsed.host() {
+        None => issues.push(LinkIssue::Malformed),
+        Some(Host::Domain(domain)) => {
+            let domain = domain.trim_end_matches('.');
+            if domain == "localhost" || domain.ends_with(".localhost") {
+                issues.push(LinkIssue::Localhost);
+            }
+        }
+        Some(Host::Ipv4(ip)) if is_loopback(ip.into()) => issues.push(LinkIssue::Localhost),
+        Some(Host::Ipv6(ip)) if is_loopback(ip.into()) => issues.push(LinkIssue::Localhost),
+        Some(Host::Ipv4(ip)) if is_private(ip.into()) => issues.push(LinkIssue::PrivateAddress),
+        Some(Host::Ipv6(ip)) if is_private(ip.into()) => issues.push(LinkIssue::PrivateAddress),
+        Some(_) => {}
+    }
+
+    issues
+}
+
+/// URLs in a plain text body, with their byte offsets
+fn text_urls(text: &str) -> Vec<(usize, &str)> {
+    let mut urls = Vec::new();
+    let mut position = 0;
+
+    while let Some(index) = text[position..].find("http") {
+        let start = position + index;
+        let rest = &text[start..];
+
+        if !(rest.starts_with("http://") || rest.starts_with("https://")) {
+            position = start + 4;
+            continue;
+        }
+
+        let length = rest
+            .find(|c: char| c.is_whitespace() || matches!(c, '<' | '>' | '"'))
+            .unwrap_or(rest.len());
+        let url = rest[..length].trim_end_matches(['.', ',', ';', ':', '!', '?', ')', '\'']);
+
+        urls.push((start, url));
+        position = start + length;
+    }
+
+    urls
+}
+
+/// find all links and images in the HTML and text bodies of a message
+pub fn link_report(html: &str, text: &str, content_ids: &[&str]) -> Vec<Link> {
+    let mut links = Vec::new();
+
+    for token in tokenize(html) {
+        let Token::StartTag {
+            name, attributes, ..
+        } = token
+        else {
+            continue;
+        };
+
+        for attribute in &attributes {
+            if !URL_ATTRIBUTES.contains(&attribute.name.as_str()) {
+                continue;
+            }
+
+            let url = decode_entities(attribute.value.trim());
+
+            // links within the message itself
+            if url.starts_with('#') {
+                continue;
+            }
+
+            let mut issues = url_issues(&url, content_ids);
+
+            if name == "img"
+                && !attributes
+                    .iter()
+                    .any(|alt| alt.name == "alt" && !alt.value.trim().is_empty())
+            {
+                issues.push(LinkIssue::MissingAlt);
+            }
+
+            let (line, column) = location(html, attribute.value_offset);
+
+            links.push(Link {
+                url,
+                source: LinkSource::Html,
+                element: Some(name.clone()),
+                attribute: Some(attribute.name.clone()),
+                line,
+                column,
+                issues,
+                reachability: None,
+            });
+        }
+    }
+
+    for (offset, url) in text_urls(text) {
+        let (line, column) = location(text, offset);
+
+        links.push(Link {
+            url: url.to_owned(),
+            source: LinkSource::Text,
+            element: None,
+            attribute: None,
+            line,
+            column,
+            issues: url_issues(url, content_ids),
+            reachability: None,
+        });
+    }
+
+    links
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn issues() {
+        let html = "<a href=\"http://example.com/?a=1&amp;b=2\">x</a>\n\
+                    <a href='#top'>top</a><a href=\"https://localhost:8080\">dev</a>\
+                    <img src=\"cid:logo\"><img src=\"https://10.0.0.1/pixel.gif\" alt=\"pixel\">\
+                    <a href=\"https://exa mple.com\">broken</a><img src=\"cid:missing\" alt=\"x\">";
+        let text =
+            "Visit https://[::1]/admin, or (http://192.168.1.1).\nhttps://[::ffff:127.0.0.1]/";
+
+        let links = link_report(html, text, &["logo"])
+            .into_iter()
+            .map(|link| (link.url, link.line, link.column, link.issues))
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            links,
+            vec![
+                (
+                    "http://example.com/?a=1&b=2".to_owned(),
+                    1,
+                    10,
+                    vec![LinkIssue::InsecureHttp]
+                ),
+                (
+                    "https://localhost:8080".to_owned(),
+                    2,
+                    32,
+                    vec![LinkIssue::Localhost]
+                ),
+                ("cid:logo".to_owned(), 2, 73, vec![LinkIssue::MissingAlt]),
+                (
+                    "https://10.0.0.1/pixel.gif".to_owned(),
+                    2,
+                    93,
+                    vec![LinkIssue::PrivateAddress]
+                ),
+                (
+                    "https://exa mple.com".to_owned(),
+                    2,
+                    142,
+                    vec![LinkIssue::Malformed]
+                ),
+                (
+                    "cid:missing".to_owned(),
+                    2,
+                    184,
+                    vec![LinkIssue::MissingAttachment]
+                ),
+                (
+                    "https://[::1]/admin".to_owned(),
+                    1,
+                    7,
+                    vec![LinkIssue::Localhost]
+                ),
+                (
+                    "http://192.168.1.1".to_owned(),
+                    1,
+                    32,
+                    vec![LinkIssue::InsecureHttp, LinkIssue::PrivateAddress]
+                ),
+                (
+                    "https://[::ffff:127.0.0.1]/".to_owned(),
+                    2,
+                    1,
+                    vec![LinkIssue::Localhost]
+                ),
+            ]
+        );
+    }
+}
@@ -0,0 +1,41 @@
+pub use self::{
+    arc::{ArcResult, verify_arc},
+    authentication::{AuthResult, AuthenticationInput, AuthenticationReport, authenticate},
+    calendar::{Calendar, CalendarEvent, CalendarParticipant, CalendarTime, parse_calendar},
+    color_scheme::{ColorScheme, apply_color_scheme},
+    compatibility::{Client, CompatibilityIssue, FeatureKind, compatibility_report},
+    dkim::{DkimResult, dkim_record_from_pem, verify_dkim},
+    dmarc::{DmarcResult, check_dmarc},
+    dns::DnsRecords,
+    frame::{FrameOptions, prepare_frame},
+    links::{Link, LinkIssue, LinkSource, Reachability, is_internal_address, link_report},
+    openpgp::{OpenPgpKeys, OpenPgpReport, OpenPgpSignature, unwrap_openpgp},
+    parts::{MimePart, PartOffsets, mime_structure, part_content},
+    remote::{RemoteContent, RemoteKind, RemoteResource, block_remote_content},
+    sanitize::sanitize_html,
+    smime::{SmimeKeys, SmimeReport, SmimeSignature, unwrap_smime},
+    spam::{SPAM_THRESHOLD, SpamInput, SpamReport, SpamRule, spam_report},
+    spf::{SpfResult, check_spf},
+    tnef::{TnefAttachment, TnefContent, parse_tnef},
+};
+
+mod arc;
+mod authentication;
+mod calendar;
+mod color_scheme;
+mod compatibility;
+mod css;
+mod dkim;
+mod dmarc;
+mod dns;
+mod frame;
+mod html;
+mod links;
+mod openpgp;
+mod parts;
+mod remote;
+mod sanitize;
+mod smime;
+mod spam;
+mod spf;
+mod tnef;
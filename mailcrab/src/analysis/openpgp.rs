@@ -0,0 +1,1034 @@
+//! OpenPGP/MIME (RFC 3156) decryption and signature verification, with a local keyring
+
+use aes_kw::{KekAes128, KekAes192, KekAes256};
+use base64ct::{Base64, Encoding};
+use cfb_mode::cipher::{AsyncStreamCipher, BlockCipher, BlockEncryptMut, KeyInit, KeyIvInit};
+use flate2::read::{DeflateDecoder, ZlibDecoder};
+use mail_parser::{MessageParser, MimeHeaders};
+use ring::{digest, signature};
+use rsa::{BigUint, Pkcs1v15Encrypt, RsaPrivateKey, traits::PublicKeyParts};
+use serde::Serialize;
+use std::io::Read;
+use x25519_dalek::StaticSecret;
+
+use super::{
+    authentication::AuthResult,
+    dkim::{normalize_line_endings, split_message},
+    smime::{first_part, replace_content},
+};
+use crate::error::{Error, Result};
+
+/// the maximum number of nested layers, e.g. a signed message that is encrypted and compressed
+const MAX_LAYERS: usize = 5;
+
+/// the maximum size of decompressed data, larger than any message that is worth displaying
+const MAX_DECOMPRESSED_SIZE: u64 = 25 * 1024 * 1024;
+
+const ED25519_CURVE: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0xda, 0x47, 0x0f, 0x01];
+const CURVE25519: &[u8] = &[0x2b, 0x06, 0x01, 0x04, 0x01, 0x97, 0x55, 0x01, 0x05, 0x01];
+
+/// packet tags (RFC 9580 section 5)
+const PUBLIC_KEY_ENCRYPTED_SESSION_KEY: u8 = 1;
+const SIGNATURE: u8 = 2;
+const SECRET_KEY: u8 = 5;
+const PUBLIC_KEY: u8 = 6;
+const SECRET_SUBKEY: u8 = 7;
+const COMPRESSED_DATA: u8 = 8;
+const SYMMETRICALLY_ENCRYPTED_DATA: u8 = 9;
+const LITERAL_DATA: u8 = 11;
+const USER_ID: u8 = 13;
+const PUBLIC_SUBKEY: u8 = 14;
+const ENCRYPTED_PROTECTED_DATA: u8 = 18;
+const AEAD_ENCRYPTED_DATA: u8 = 20;
+
+/// public and secret keys to verify signatures and decrypt messages
+#[derive(Clone, Debug, Default)]
+pub struct OpenPgpKeys {
+    keys: Vec<Key>,
+}
+
+#[derive(Clone, Debug)]
+struct Key {
+    fingerprint: [u8; 20],
+    /// the first user ID of the certificate the (sub)key belongs to
+    user_id: Option<String>,
+    public: PublicKey,
+    secret: Option<SecretKey>,
+}
+
+#[derive(Clone, Debug)]
+enum PublicKey {
+    Rsa {
+        n: Vec<u8>,
+        e: Vec<u8>,
+    },
+    Ed25519([u8; 32]),
+    /// ECDH with Curve25519, with the parameters of the key derivation function
+    Cv25519 {
+        hash: u8,
+        cipher: u8,
+    },
+    Unsupported,
+}
+
+#[derive(Clone, Debug)]
+enum SecretKey {
+    Rsa(Box<RsaPrivateKey>),
+    Cv25519([u8; 32]),
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct OpenPgpSignature {
+    pub result: AuthResult,
+    /// the user ID of the signer key
+    pub signer: Option<String>,
+    /// the ID of the signer key, in hexadecimal
+    pub key_id: Option<String>,
+    /// why the signature did not verify
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct OpenPgpReport {
+    pub encrypted: bool,
+    /// the message was decrypted with one of the configured secret keys
+    pub decrypted: bool,
+    pub signatures: Vec<OpenPgpSignature>,
+    /// why the message could not be decrypted or unwrapped
+    pub reason: Option<String>,
+}
+
+impl Key {
+    fn key_id(&self) -> &[u8] {
+        &self.fingerprint[12..]
+    }
+}
+
+impl OpenPgpKeys {
+    /// add the keys of an ASCII armored or binary keyring, secret keys must not be protected with
+    /// a passphrase
+    pub fn add_keyring(&mut self, data: &[u8]) -> Result<()> {
+        let invalid = |reason: &str| Error::Config(format!("invalid OpenPGP keyring: {reason}"));
+        let data = dearmor(data).ok_or_else(|| invalid("no keys found"))?;
+        let packets = parse_packets(&data).ok_or_else(|| invalid("malformed packet"))?;
+        let mut primary = None;
+
+        for packet in packets {
+            match packet.tag {
+                SECRET_KEY | PUBLIC_KEY | SECRET_SUBKEY | PUBLIC_SUBKEY => {
+                    let Some(mut key) = parse_key(&packet)? else {
+                        continue;
+                    };
+                    match packet.tag {
+                        SECRET_KEY | PUBLIC_KEY => primary = Some(self.keys.len()),
+                        _ => {
+                            key.user_id = primary.and_then(|index| self.keys[index].user_id.clone())
+                        }
+                    }
+
+                    match self
+                        .keys
+                        .iter_mut()
+                        .find(|known| known.fingerprint == key.fingerprint)
+                    {
+                        // a secret key replaces its public key, e.g. when both are exported
+                        Some(known) if key.secret.is_some() => *known = key,
+                        Some(_) => {}
+                        None => self.keys.push(key),
+                    }
+                }
+                USER_ID => {
+                    if let Some(key) = primary.and_then(|index| self.keys.get_mut(index))
+                        && key.user_id.is_none()
+                    {
+                        key.user_id = Some(String::from_utf8_lossy(&packet.body).into_owned());
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        Ok(())
+    }
+
+    fn find(&self, key_id: &[u8]) -> Option<&Key> {
+        self.keys.iter().find(|key| key.key_id() == key_id)
+    }
+}
+
+struct Packet {
+    tag: u8,
+    body: Vec<u8>,
+}
+
+/// a cursor over the fields of a packet
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let (value, rest) = self.0.split_at_checked(length)?;
+        self.0 = rest;
+
+        Some(value)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    fn u16(&mut self) -> Option<usize> {
+        self.take(2)
+            .map(|bytes| u16::from_be_bytes([bytes[0], bytes[1]]) as usize)
+    }
+
+    fn u32(&mut self) -> Option<usize> {
+        self.take(4)
+            .map(|bytes| u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize)
+    }
+
+    /// a multiprecision integer, big-endian without leading zeros
+    fn mpi(&mut self) -> Option<&'a [u8]> {
+        let bits = self.u16()?;
+
+        self.take(bits.div_ceil(8))
+    }
+
+    /// a value prefixed with its length in a single octet, e.g. a curve OID
+    fn prefixed(&mut self) -> Option<&'a [u8]> {
+        let length = self.byte()?;
+
+        self.take(length as usize)
+    }
+}
+
+/// the packets of ASCII armored data (RFC 9580 section 6), binary data is returned as is
+fn dearmor(data: &[u8]) -> Option<Vec<u8>> {
+    if data.first().is_some_and(|b| b & 0x80 != 0) {
+        return Some(data.to_vec());
+    }
+
+    let text = std::str::from_utf8(data).ok()?;
+    let mut packets = Vec::new();
+    let mut found = false;
+    // whether the armor headers have ended, and the base64 encoded packets
+    let mut block: Option<(bool, String)> = None;
+
+    for line in text.lines().map(str::trim) {
+        if line.starts_with("-----BEGIN PGP ") {
+            block = Some((false, String::new()));
+        } else if line.starts_with("-----END PGP ") {
+            if let Some((_, base64)) = block.take() {
+                packets.extend(Base64::decode_vec(&base64).ok()?);
+                found = true;
+            }
+        } else if let Some((started, base64)) = &mut block {
+            match started {
+                false => *started = line.is_empty(),
+                // skip the checksum
+                true if line.starts_with('=') => {}
+                true => base64.push_str(line),
+            }
+        }
+    }
+
+    found.then_some(packets)
+}
+
+/// split data in packets, partial body lengths are joined
+fn parse_packets(data: &[u8]) -> Option<Vec<Packet>> {
+    let mut reader = Reader(data);
+    let mut packets = Vec::new();
+
+    while let Some(header) = reader.byte() {
+        if header & 0x80 == 0 {
+            return None;
+        }
+
+        // the current format has the tag in the lower six bits
+        if header & 0x40 != 0 {
+            let mut body = Vec::new();
+            loop {
+                let length = match reader.byte()? {
+                    length @ 0..192 => length as usize,
+                    length @ 192..224 => {
+                        ((length as usize - 192) << 8) + reader.byte()? as usize + 192
+                    }
+                    length @ 224..255 => {
+                        body.extend_from_slice(reader.take(1 << (length & 0x1f))?);
+                        continue;
+                    }
+                    255 => reader.u32()?,
+                };
+                body.extend_from_slice(reader.take(length)?);
+                break;
+            }
+
+            packets.push(Packet {
+                tag: header & 0x3f,
+                body,
+            });
+        } else {
+            let length = match header & 0x03 {
+                0 => reader.byte()? as usize,
+                1 => reader.u16()?,
+                2 => reader.u32()?,
+                // indeterminate length, up to the end of the data
+                _ => reader.0.len(),
+            };
+
+            packets.push(Packet {
+                tag: (header >> 2) & 0x0f,
+                body: reader.take(length)?.to_vec(),
+            });
+        }
+    }
+
+    Some(packets)
+}
+
+/// left pad a big-endian integer with zeros
+fn pad(value: &[u8], length: usize) -> Vec<u8> {
+    let mut padded = vec![0; length.saturating_sub(value.len())];
+    padded.extend_from_slice(value);
+
+    padded
+}
+
+/// parse the public key material, returns None for unsupported algorithms
+fn parse_public_key(reader: &mut Reader) -> Option<PublicKey> {
+    match reader.byte()? {
+        // RSA, RSA encrypt-only and RSA sign-only
+        1..=3 => Some(PublicKey::Rsa {
+            n: reader.mpi()?.to_vec(),
+            e: reader.mpi()?.to_vec(),
+        }),
+        // EdDSA (legacy), the point is prefixed with 0x40
+        22 => {
+            let curve = reader.prefixed()?;
+            let point = reader.mpi()?.strip_prefix(&[0x40])?;
+
+            (curve == ED25519_CURVE).then_some(PublicKey::Ed25519(point.try_into().ok()?))
+        }
+        // ECDH, followed by the KDF parameters: reserved, hash algorithm and cipher algorithm
+        18 => {
+            let curve = reader.prefixed()?;
+            let point = reader.mpi()?;
+            let kdf = reader.prefixed()?;
+
+            match (curve == CURVE25519 && point.len() == 33, kdf) {
+                (true, [1, hash, cipher]) => Some(PublicKey::Cv25519 {
+                    hash: *hash,
+                    cipher: *cipher,
+                }),
+                _ => None,
+            }
+        }
+        27 => Some(PublicKey::Ed25519(reader.take(32)?.try_into().ok()?)),
+        _ => None,
+    }
+}
+
+/// parse a version 4 (sub)key packet, returns None for keys that cannot be used
+fn parse_key(packet: &Packet) -> Result<Option<Key>> {
+    let mut reader = Reader(&packet.body);
+    // version and creation time
+    if reader.byte() != Some(4) || reader.take(4).is_none() {
+        return Ok(None);
+    }
+
+    let public = match parse_public_key(&mut reader) {
+        Some(public) => public,
+        // the length of the key material is only known for public keys
+        None if matches!(packet.tag, PUBLIC_KEY | PUBLIC_SUBKEY) => {
+            reader = Reader(&[]);
+            PublicKey::Unsupported
+        }
+        None => return Ok(None),
+    };
+
+    // the fingerprint is the SHA-1 hash of the public key packet
+    let length = packet.body.len() - reader.0.len();
+    let mut context = digest::Context::new(&digest::SHA1_FOR_LEGACY_USE_ONLY);
+    context.update(&[0x99]);
+    context.update(&(length as u16).to_be_bytes());
+    context.update(&packet.body[..length]);
+    let Ok(fingerprint) = context.finish().as_ref().try_into() else {
+        return Ok(None);
+    };
+
+    let secret = match packet.tag {
+        SECRET_KEY | SECRET_SUBKEY => parse_secret_key(&mut reader, &public)?,
+        _ => None,
+    };
+
+    Ok(Some(Key {
+        fingerprint,
+        user_id: None,
+        public,
+        secret,
+    }))
+}
+
+/// parse the unprotected secret key material that follows the public key
+fn parse_secret_key(reader: &mut Reader, public: &PublicKey) -> Result<Option<SecretKey>> {
+    let invalid = || Error::Config("invalid OpenPGP keyring: malformed secret key".to_owned());
+
+    match reader.byte().ok_or_else(invalid)? {
+        0 => {}
+        _ => {
+            return Err(Error::Config(
+                "passphrase protected OpenPGP secret keys are not supported".to_owned(),
+            ));
+        }
+    }
+
+    match public {
+        PublicKey::Rsa { n, e } => {
+            let mut integer = || reader.mpi().map(BigUint::from_bytes_be).ok_or_else(invalid);
+            let (d, p, q) = (integer()?, integer()?, integer()?);
+            let key = RsaPrivateKey::from_components(
+                BigUint::from_bytes_be(n),
+                BigUint::from_bytes_be(e),
+                d,
+                vec![p, q],
+            )
+            .map_err(|e| Error::Config(format!("invalid OpenPGP keyring: {e}")))?;
+
+            Ok(Some(SecretKey::Rsa(Box::new(key))))
+        }
+        // the scalar is stored big-endian, X25519 uses little-endian
+        PublicKey::Cv25519 { .. } => {
+            let mut scalar: [u8; 32] = pad(reader.mpi().ok_or_else(invalid)?, 32)
+                .try_into()
+                .map_err(|_| invalid())?;
+            scalar.reverse();
+
+            Ok(Some(SecretKey::Cv25519(scalar)))
+        }
+        // signing keys are only used to verify signatures
+        _ => Ok(None),
+    }
+}
+
+fn digest_algorithm(id: u8) -> Option<&'static digest::Algorithm> {
+    match id {
+        2 => Some(&digest::SHA1_FOR_LEGACY_USE_ONLY),
+        8 => Some(&digest::SHA256),
+        9 => Some(&digest::SHA384),
+        10 => Some(&digest::SHA512),
+        _ => None,
+    }
+}
+
+/// a version 4 signature
+struct Signature<'a> {
+    kind: u8,
+    algorithm: u8,
+    hash: u8,
+    /// the signed part of the packet, from the version up to the end of the hashed subpackets
+    hashed: &'a [u8],
+    issuer: Option<&'a [u8]>,
+    /// the first two octets of the signed hash
+    left: &'a [u8],
+    values: Vec<&'a [u8]>,
+}
+
+/// the type and data of signature subpackets
+fn subpackets(data: &[u8]) -> Option<Vec<(u8, &[u8])>> {
+    let mut reader = Reader(data);
+    let mut subpackets = Vec::new();
+
+    while let Some(first) = reader.byte() {
+        let length = match first {
+            0..192 => first as usize,
+            192..255 => ((first as usize - 192) << 8) + reader.byte()? as usize + 192,
+            255 => reader.u32()?,
+        };
+        let (kind, data) = reader.take(length)?.split_first()?;
+        // the most significant bit marks critical subpackets
+        subpackets.push((kind & 0x7f, data));
+    }
+
+    Some(subpackets)
+}
+
+fn parse_signature(body: &[u8]) -> Option<Signature<'_>> {
+    let mut reader = Reader(body);
+    if reader.byte()? != 4 {
+        return None;
+    }
+
+    let kind = reader.byte()?;
+    let algorithm = reader.byte()?;
+    let hash = reader.byte()?;
+    let hashed_length = reader.u16()?;
+    let hashed_subpackets = reader.take(hashed_length)?;
+    let unhashed_length = reader.u16()?;
+    let unhashed_subpackets = reader.take(unhashed_length)?;
+    let left = reader.take(2)?;
+    let values = match algorithm {
+        1 | 3 => vec![reader.mpi()?],
+        22 => vec![reader.mpi()?, reader.mpi()?],
+        27 => vec![reader.take(64)?],
+        _ => Vec::new(),
+    };
+
+    // the issuer key ID, or the key ID part of the issuer fingerprint
+    let issuer = subpackets(hashed_subpackets)?
+        .into_iter()
+        .chain(subpackets(unhashed_subpackets)?)
+        .find_map(|(kind, data)| match (kind, data) {
+            (16, data) if data.len() == 8 => Some(data),
+            (33, [4, fingerprint @ ..]) if fingerprint.len() == 20 => Some(&fingerprint[12..]),
+            _ => None,
+        });
+
+    Some(Signature {
+        kind,
+        algorithm,
+        hash,
+        hashed: &body[..6 + hashed_length],
+        issuer,
+        left,
+        values,
+    })
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02X}")).collect()
+}
+
+/// verify a signature packet over the signed content
+fn verify_signature(packet: &[u8], content: &[u8], keys: &OpenPgpKeys) -> OpenPgpSignature {
+    let signature = parse_signature(packet);
+    let issuer = signature.as_ref().and_then(|signature| signature.issuer);
+    let key = issuer.and_then(|key_id| keys.find(key_id));
+
+    let result = |result, reason: &str| OpenPgpSignature {
+        result,
+        signer: key.and_then(|key| key.user_id.clone()),
+        key_id: issuer.map(hex),
+        reason: (!reason.is_empty()).then(|| reason.to_owned()),
+    };
+
+    let Some(signature) = signature else {
+        return result(AuthResult::PermError, "unsupported signature version");
+    };
+    let Some(key) = key else {
+        return result(
+            AuthResult::PermError,
+            "the signer key is not in the keyring",
+        );
+    };
+    let Some(hash) = digest_algorithm(signature.hash) else {
+        return result(AuthResult::PermError, "unsupported digest algorithm");
+    };
+
+    // binary signatures cover the content as is, text signatures the content with CRLF line endings
+    let mut data = match signature.kind {
+        0x00 => content.to_vec(),
+        0x01 => normalize_line_endings(content),
+        _ => return result(AuthResult::PermError, "unsupported signature type"),
+    };
+    data.extend_from_slice(signature.hashed);
+    data.extend_from_slice(&[0x04, 0xff]);
+    data.extend_from_slice(&(signature.hashed.len() as u32).to_be_bytes());
+
+    let digest = digest::digest(hash, &data);
+    if digest.as_ref()[..2] != *signature.left {
+        return result(
+            AuthResult::Fail,
+            "message digest mismatch, the content was modified",
+        );
+    }
+
+    let verified = match (&key.public, signature.algorithm, &signature.values[..]) {
+        (PublicKey::Rsa { n, e }, 1 | 3, [value]) => {
+            let parameters = match signature.hash {
+                2 => &signature::RSA_PKCS1_1024_8192_SHA1_FOR_LEGACY_USE_ONLY,
+                8 => &signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY,
+                9 => &signature::RSA_PKCS1_2048_8192_SHA384,
+                _ => &signature::RSA_PKCS1_1024_8192_SHA512_FOR_LEGACY_USE_ONLY,
+            };
+
+            Some(
+                signature::RsaPublicKeyComponents { n, e }
+                    .verify(parameters, &data, &pad(value, n.len()))
+                    .is_ok(),
+            )
+        }
+        // EdDSA signs the hash, as two integers or the native signature
+        (PublicKey::Ed25519(point), 22 | 27, values) => {
+            let value = values
+                .iter()
+                .flat_map(|value| match values.len() {
+                    1 => value.to_vec(),
+                    _ => pad(value, 32),
+                })
+                .collect::<Vec<u8>>();
+
+            Some(
+                signature::UnparsedPublicKey::new(&signature::ED25519, point)
+                    .verify(digest.as_ref(), &value)
+                    .is_ok(),
+            )
+        }
+        _ => None,
+    };
+
+    match verified {
+        Some(true) => result(AuthResult::Pass, ""),
+        Some(false) => result(AuthResult::Fail, "signature mismatch"),
+        None => result(AuthResult::PermError, "unsupported signature algorithm"),
+    }
+}
+
+/// decrypt the session key with a secret key, returns the cipher algorithm and the session key
+fn decrypt_session_key(packet: &[u8], key: &Key) -> Option<(u8, Vec<u8>)> {
+    let mut reader = Reader(packet);
+    // version and recipient key ID
+    if reader.byte()? != 3 || reader.take(8).is_none() {
+        return None;
+    }
+
+    let decrypted = match (key.secret.as_ref()?, &key.public, reader.byte()?) {
+        (SecretKey::Rsa(private), _, 1 | 2) => private
+            .decrypt(Pkcs1v15Encrypt, &pad(reader.mpi()?, private.size()))
+            .ok()?,
+        // the session key is wrapped with a key derived from the shared secret (RFC 6637)
+        (SecretKey::Cv25519(scalar), PublicKey::Cv25519 { hash, cipher, .. }, 18) => {
+            let point: [u8; 32] = reader.mpi()?.strip_prefix(&[0x40])?.try_into().ok()?;
+            let wrapped = reader.prefixed()?;
+            let shared =
+                StaticSecret::from(*scalar).diffie_hellman(&x25519_dalek::PublicKey::from(point));
+
+            let mut context = digest::Context::new(digest_algorithm(*hash)?);
+            context.update(&[0, 0, 0, 1]);
+            context.update(shared.as_bytes());
+            context.update(&[CURVE25519.len() as u8]);
+            context.update(CURVE25519);
+            context.update(&[18, 3, 1, *hash, *cipher]);
+            context.update(b"Anonymous Sender    ");
+            context.update(&key.fingerprint);
+            let kek = context.finish();
+
+            let mut unwrapped = match cipher {
+                7 => KekAes128::try_from(kek.as_ref().get(..16)?)
+                    .ok()?
+                    .unwrap_vec(wrapped),
+                8 => KekAes192::try_from(kek.as_ref().get(..24)?)
+                    .ok()?
+                    .unwrap_vec(wrapped),
+                9 => KekAes256::try_from(kek.as_ref().get(..32)?)
+                    .ok()?
+                    .unwrap_vec(wrapped),
+                _ => return None,
+            }
+            .ok()?;
+
+            // PKCS #5 padding
+            let padding = *unwrapped.last()? as usize;
+            unwrapped.truncate(unwrapped.len().checked_sub(padding)?);
+            unwrapped
+        }
+        _ => return None,
+    };
+
+    // the cipher algorithm, the session key and a checksum of the session key
+    let (cipher, rest) = decrypted.split_first()?;
+    let (session_key, checksum) = rest.split_at_checked(rest.len().checked_sub(2)?)?;
+    let sum = session_key
+        .iter()
+        .fold(0u16, |sum, b| sum.wrapping_add(*b as u16));
+
+    (sum.to_be_bytes() == checksum).then(|| (*cipher, session_key.to_vec()))
+}
+
+fn decrypt_cfb<C>(key: &[u8], data: &mut [u8]) -> Option<()>
+where
+    C: BlockEncryptMut + BlockCipher + KeyInit,
+{
+    cfb_mode::Decryptor::<C>::new_from_slices(key, &[0; 16])
+        .ok()?
+        .decrypt(data);
+
+    Some(())
+}
+
+/// decrypt symmetrically encrypted and integrity protected data (version 1)
+fn decrypt_data(cipher: u8, key: &[u8], data: &[u8]) -> std::result::Result<Vec<u8>, String> {
+    let mut plain = data.to_vec();
+    let decrypted = match cipher {
+        7 => decrypt_cfb::<aes::Aes128>(key, &mut plain),
+        8 => decrypt_cfb::<aes::Aes192>(key, &mut plain),
+        9 => decrypt_cfb::<aes::Aes256>(key, &mut plain),
+        _ => return Err(format!("unsupported cipher algorithm {cipher}")),
+    };
+
+    // a random block with its last two octets repeated precedes the packets, which are followed
+    // by a modification detection code packet with the SHA-1 hash of everything before it
+    const PREFIX: usize = 16 + 2;
+    const MDC: usize = 2 + 20;
+    if decrypted.is_none() || plain.len() < PREFIX + MDC || plain[14..16] != plain[16..18] {
+        return Err("invalid session key".to_owned());
+    }
+
+    let (content, mdc) = plain.split_at(plain.len() - 20);
+    if !content.ends_with(&[0xd3, 0x14])
+        || digest::digest(&digest::SHA1_FOR_LEGACY_USE_ONLY, content).as_ref() != mdc
+    {
+        return Err("the integrity check failed, the message was modified".to_owned());
+    }
+
+    Ok(plain[PREFIX..plain.len() - MDC].to_vec())
+}
+
+/// decrypt the encrypted data with one of the configured secret keys
+fn decrypt(packets: &[Packet], keys: &OpenPgpKeys) -> std::result::Result<Vec<u8>, String> {
+    let data = packets
+        .iter()
+        .find(|packet| {
+            matches!(
+                packet.tag,
+                SYMMETRICALLY_ENCRYPTED_DATA | ENCRYPTED_PROTECTED_DATA | AEAD_ENCRYPTED_DATA
+            )
+        })
+        .ok_or("the encrypted data is missing")?;
+
+    let data = match (data.tag, data.body.split_first()) {
+        (ENCRYPTED_PROTECTED_DATA, Some((1, data))) => data,
+        (SYMMETRICALLY_ENCRYPTED_DATA, _) => {
+            return Err("messages without integrity protection are not supported".to_owned());
+        }
+        _ => return Err("AEAD encrypted messages are not supported".to_owned()),
+    };
+
+    if keys.keys.iter().all(|key| key.secret.is_none()) {
+        return Err("no secret keys are configured".to_owned());
+    }
+
+    for packet in packets
+        .iter()
+        .filter(|packet| packet.tag == PUBLIC_KEY_ENCRYPTED_SESSION_KEY)
+    {
+        // the recipient key ID is zero when the recipient is hidden
+        let recipient = packet.body.get(1..9).unwrap_or_default();
+
+        for key in keys.keys.iter().filter(|key| {
+            key.secret.is_some() && (key.key_id() == recipient || recipient == [0; 8])
+        }) {
+            if let Some((cipher, session_key)) = decrypt_session_key(&packet.body, key) {
+                return decrypt_data(cipher, &session_key, data);
+            }
+        }
+    }
+
+    Err("none of the configured secret keys can decrypt the message".to_owned())
+}
+
+/// decompress data up to `MAX_DECOMPRESSED_SIZE`, a few bytes can inflate to gigabytes
+fn decompress(body: &[u8]) -> std::result::Result<Vec<u8>, String> {
+    let (algorithm, data) = body.split_first().ok_or("invalid compressed data")?;
+    let mut content = Vec::new();
+    let limit = MAX_DECOMPRESSED_SIZE + 1;
+
+    let result = match algorithm {
+        0 => data.take(limit).read_to_end(&mut content),
+        1 => DeflateDecoder::new(data)
+            .take(limit)
+            .read_to_end(&mut content),
+        2 => ZlibDecoder::new(data).take(limit).read_to_end(&mut content),
+        _ => return Err(format!("unsupported compression algorithm {algorithm}")),
+    };
+    result.map_err(|_| "invalid compressed data")?;
+
+    if content.len() as u64 > MAX_DECOMPRESSED_SIZE {
+        return Err("the decompressed data is too large".to_owned());
+    }
+
+    Ok(content)
+}
+
+/// decrypt, decompress and verify an OpenPGP message, returns the literal data, compressed
+/// data is only accepted once it has been decrypted
+fn open_message(
+    data: &[u8],
+    keys: &OpenPgpKeys,
+    report: &mut OpenPgpReport,
+    depth: usize,
+    decrypted: bool,
+) -> std::result::Result<Vec<u8>, String> {
+    let packets = parse_packets(data)
+        .filter(|_| depth < MAX_LAYERS)
+        .ok_or("invalid OpenPGP message")?;
+
+    if packets
+        .iter()
+        .any(|packet| packet.tag == PUBLIC_KEY_ENCRYPTED_SESSION_KEY)
+    {
+        report.encrypted = true;
+        let content = decrypt(&packets, keys)?;
+        report.decrypted = true;
+
+        return open_message(&content, keys, report, depth + 1, true);
+    }
+
+    if let Some(compressed) = packets.iter().find(|packet| packet.tag == COMPRESSED_DATA) {
+        if !decrypted {
+            return Err("compressed data is only supported in encrypted messages".to_owned());
+        }
+
+        return open_message(
+            &decompress(&compressed.body)?,
+            keys,
+            report,
+            depth + 1,
+            true,
+        );
+    }
+
+    // format, file name and date precede the content
+    let content = packets
+        .iter()
+        .find(|packet| packet.tag == LITERAL_DATA)
+        .and_then(|packet| {
+            let mut reader = Reader(&packet.body);
+            reader.byte()?;
+            reader.prefixed()?;
+            reader.take(4)?;
+
+            Some(reader.0)
+        })
+        .ok_or("the literal data is missing")?;
+
+    report.signatures.extend(
+        packets
+            .iter()
+            .filter(|packet| packet.tag == SIGNATURE)
+            .map(|packet| verify_signature(&packet.body, content, keys)),
+    );
+
+    Ok(content.to_vec())
+}
+
+/// unwrap a single OpenPGP/MIME layer, returns None when the entity is not OpenPGP/MIME
+fn unwrap_layer(
+    entity: &[u8],
+    keys: &OpenPgpKeys,
+    report: &mut OpenPgpReport,
+) -> Option<std::result::Result<Vec<u8>, String>> {
+    let parsed = MessageParser::new().parse(entity)?;
+    let content_type = parsed.root_part().content_type()?;
+    let subtype = content_type
+        .subtype()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+    let protocol = content_type
+        .attribute("protocol")
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    if !content_type.ctype().eq_ignore_ascii_case("multipart") {
+        return None;
+    }
+
+    let part = |subtype: &str| {
+        parsed.parts.iter().skip(1).find(|part| {
+            part.content_type()
+                .and_then(|content_type| content_type.subtype())
+                .is_some_and(|part_subtype| part_subtype.eq_ignore_ascii_case(subtype))
+        })
+    };
+
+    match (subtype.as_str(), protocol.as_str()) {
+        // a version part, followed by the encrypted OpenPGP message
+        ("encrypted", "application/pgp-encrypted") => {
+            report.encrypted = true;
+            let Some(data) = part("octet-stream").and_then(|part| dearmor(part.contents())) else {
+                return Some(Err("the encrypted message is missing".to_owned()));
+            };
+
+            Some(open_message(&data, keys, report, 0, false))
+        }
+        ("signed", "application/pgp-signature") => {
+            let (_, body) = split_message(entity);
+            let content = content_type
+                .attribute("boundary")
+                .and_then(|boundary| first_part(body, boundary));
+            let signatures = part("pgp-signature")
+                .and_then(|part| dearmor(part.contents()))
+                .and_then(|data| parse_packets(&data));
+
+            let (Some(content), Some(signatures)) = (content, signatures) else {
+                return Some(Err("the signed content or signature is missing".to_owned()));
+            };
+
+            report.signatures.extend(
+                signatures
+                    .iter()
+                    .filter(|packet| packet.tag == SIGNATURE)
+                    .map(|packet| verify_signature(&packet.body, content, keys)),
+            );
+
+            Some(Ok(content.to_vec()))
+        }
+        _ => None,
+    }
+}
+
+/// decrypt and verify the OpenPGP/MIME layers of a message, returns None for other messages,
+/// otherwise the report and the message with the innermost content that could be unwrapped
+pub fn unwrap_openpgp(raw: &[u8], keys: &OpenPgpKeys) -> Option<(OpenPgpReport, Vec<u8>)> {
+    let raw = normalize_line_endings(raw);
+    let mut report = OpenPgpReport::default();
+    let mut entity = raw.clone();
+    let mut openpgp = false;
+
+    for _ in 0..MAX_LAYERS {
+        let Some(content) = unwrap_layer(&entity, keys, &mut report) else {
+            break;
+        };
+        openpgp = true;
+
+        match content {
+            Ok(content) => entity = normalize_line_endings(&content),
+            Err(reason) => {
+                report.reason = Some(reason);
+                break;
+            }
+        }
+    }
+
+    if !openpgp {
+        return None;
+    }
+
+    Some((report, replace_content(&raw, &entity)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(files: &[&str]) -> OpenPgpKeys {
+        let mut keys = OpenPgpKeys::default();
+        for keyring in files {
+            keys.add_keyring(keyring.as_bytes()).unwrap();
+        }
+        keys
+    }
+
+    const JOE: &str = include_str!("testdata/openpgp/joe.asc");
+    const SUZIE: &str = include_str!("testdata/openpgp/suzie.asc");
+
+    #[test]
+    fn signed() {
+        let message = include_str!("testdata/openpgp/signed.eml");
+        let (report, unwrapped) = unwrap_openpgp(message.as_bytes(), &keys(&[JOE])).unwrap();
+        assert!(!report.encrypted);
+        assert_eq!(
+            report.signatures,
+            vec![OpenPgpSignature {
+                result: AuthResult::Pass,
+                signer: Some("Joe SixPack <joe@football.example.com>".to_owned()),
+                key_id: report.signatures[0].key_id.clone(),
+                reason: None,
+            }]
+        );
+
+        let unwrapped = String::from_utf8(unwrapped).unwrap();
+        assert!(unwrapped.contains(
+            "MIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\n\r\nHi Suzie,"
+        ));
+        assert!(!unwrapped.contains("multipart/signed"));
+
+        // the key of the signer is needed to verify the signature
+        let (report, _) = unwrap_openpgp(message.as_bytes(), &keys(&[SUZIE])).unwrap();
+        assert_eq!(report.signatures[0].result, AuthResult::PermError);
+        assert_eq!(report.signatures[0].signer, None);
+
+        let modified = message.replace("We lost the game", "We won the game");
+        let (report, _) = unwrap_openpgp(modified.as_bytes(), &keys(&[JOE])).unwrap();
+        assert_eq!(report.signatures[0].result, AuthResult::Fail);
+    }
+
+    #[test]
+    fn encrypted() {
+        // Curve25519, not compressed
+        let message = include_str!("testdata/openpgp/encrypted.eml");
+        let (report, unwrapped) = unwrap_openpgp(message.as_bytes(), &keys(&[SUZIE])).unwrap();
+        assert!(report.encrypted && report.decrypted);
+        assert_eq!(report.reason, None);
+        assert!(report.signatures.is_empty());
+        assert!(
+            String::from_utf8(unwrapped)
+                .unwrap()
+                .contains("We lost the game")
+        );
+
+        let (report, unwrapped) = unwrap_openpgp(message.as_bytes(), &keys(&[JOE])).unwrap();
+        assert!(report.encrypted && !report.decrypted);
+        assert_eq!(
+            report.reason.as_deref(),
+            Some("none of the configured secret keys can decrypt the message")
+        );
+        assert!(
+            String::from_utf8(unwrapped)
+                .unwrap()
+                .contains("BEGIN PGP MESSAGE")
+        );
+
+        // RSA, compressed and signed with Ed25519
+        let message = include_str!("testdata/openpgp/encrypted-signed.eml");
+        let (report, unwrapped) = unwrap_openpgp(message.as_bytes(), &keys(&[JOE, SUZIE])).unwrap();
+        assert!(report.decrypted);
+        assert_eq!(report.signatures.len(), 1);
+        assert_eq!(report.signatures[0].result, AuthResult::Pass);
+        assert_eq!(
+            report.signatures[0].signer.as_deref(),
+            Some("Suzie Q <suzie@shopping.example.net>")
+        );
+        assert!(
+            String::from_utf8(unwrapped)
+                .unwrap()
+                .contains("Dinner is ready.")
+        );
+    }
+
+    #[test]
+    fn compressed() {
+        use flate2::{Compression, write::DeflateEncoder};
+        use std::io::Write;
+
+        let mut encoder = DeflateEncoder::new(vec![1], Compression::best());
+        encoder
+            .write_all(&vec![0; MAX_DECOMPRESSED_SIZE as usize + 1])
+            .unwrap();
+        let bomb = encoder.finish().unwrap();
+        assert_eq!(
+            decompress(&bomb),
+            Err("the decompressed data is too large".to_owned())
+        );
+
+        // a compressed data packet that is not encrypted
+        let mut packet = vec![0xc0 | COMPRESSED_DATA, 0xff];
+        packet.extend((bomb.len() as u32).to_be_bytes());
+        packet.extend(&bomb);
+        assert_eq!(
+            open_message(&packet, &keys(&[]), &mut OpenPgpReport::default(), 0, false),
+            Err("compressed data is only supported in encrypted messages".to_owned())
+        );
+    }
+
+    #[test]
+    fn not_openpgp() {
+        let message = b"From: joe@example.com\r\nContent-Type: text/plain\r\n\r\nHi\r\n";
+        assert_eq!(unwrap_openpgp(message, &keys(&[JOE])), None);
+
+        assert!(
+            OpenPgpKeys::default()
+                .add_keyring(b"-----BEGIN PGP PUBLIC KEY BLOCK-----\n\nAAAA\n")
+                .is_err()
+        );
+    }
+}
@@ -0,0 +1,197 @@
+//! The MIME structure of a message, and the decoded content of its parts
+
+use mail_parser::{Encoding, Message, MessageParser, MessagePart, MimeHeaders, PartType};
+use serde::Serialize;
+
+/// byte offsets of a part into the raw message
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub struct PartOffsets {
+    pub header: usize,
+    pub body: usize,
+    pub end: usize,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct MimePart {
+    /// the indices of the parts that lead to this part, separated by dots, the root is `0`
+    pub id: String,
+    pub content_type: String,
+    pub charset: Option<String>,
+    pub transfer_encoding: Option<String>,
+    pub disposition: Option<String>,
+    pub filename: Option<String>,
+    pub content_id: Option<String>,
+    /// size of the decoded content in bytes
+    pub size: usize,
+    /// not available for parts of a message that is itself transfer encoded
+    pub offsets: Option<PartOffsets>,
+    pub parts: Vec<MimePart>,
+}
+
+fn content_type(part: &MessagePart) -> String {
+    match part.content_type() {
+        Some(content_type) => match &content_type.c_subtype {
+            Some(subtype) => format!("{}/{}", content_type.c_type, subtype),
+            None => content_type.c_type.to_string(),
+        },
+        None => "text/plain".to_owned(),
+    }
+    .to_ascii_lowercase()
+}
+
+/// the decoded content of a part, the offsets of a part are relative to the raw message
+/// of the message that contains it
+fn decoded<'x>(message: &'x Message, part: &'x MessagePart) -> &'x [u8] {
+    let raw = || {
+        message
+            .raw_message
+            .get(part.offset_body as usize..part.offset_end as usize)
+            .unwrap_or_default()
+    };
+
+    match &part.body {
+        PartType::Text(text) | PartType::Html(text) => text.as_bytes(),
+        PartType::Binary(bytes) | PartType::InlineBinary(bytes) => bytes,
+        PartType::Message(nested) if part.encoding != Encoding::None => &nested.raw_message,
+        PartType::Message(_) | PartType::Multipart(_) => raw(),
+    }
+}
+
+fn mime_part(message: &Message, index: u32, id: String, absolute: bool) -> Option<MimePart> {
+    let part = message.parts.get(index as usize)?;
+
+    let parts = match &part.body {
+        PartType::Multipart(children) => children
+            .iter()
+            .enumerate()
+            .filter_map(|(i, child)| mime_part(message, *child, format!("{id}.{i}"), absolute))
+            .collect(),
+        PartType::Message(nested) => mime_part(
+            nested,
+            0,
+            format!("{id}.0"),
+            absolute && part.encoding == Encoding::None,
+        )
+        .into_iter()
+        .collect(),
+        _ => Vec::new(),
+    };
+
+    Some(MimePart {
+        content_type: content_type(part),
+        charset: part
+            .content_type()
+            .and_then(|content_type| content_type.attribute("charset"))
+            .map(str::to_owned),
+        transfer_encoding: part.content_transfer_encoding().map(str::to_owned),
+        disposition: part
+            .content_disposition()
+            .map(|disposition| disposition.c_type.to_string()),
+        filename: part.attachment_name().map(str::to_owned),
+        content_id: part.content_id().map(str::to_owned),
+        size: decoded(message, part).len(),
+        offsets: absolute.then_some(PartOffsets {
+            header: part.offset_header as usize,
+            body: part.offset_body as usize,
+            end: part.offset_end as usize,
+        }),
+        parts,
+        id,
+    })
+}
+
+/// the tree of MIME parts of a raw message
+pub fn mime_structure(raw: &[u8]) -> Option<MimePart> {
+    let message = MessageParser::default().parse(raw)?;
+
+    mime_part(&message, 0, "0".to_owned(), true)
+}
+
+/// the content type and decoded content of a part, by its id, text is converted to UTF-8
+pub fn part_content(raw: &[u8], id: &str) -> Option<(String, Vec<u8>)> {
+    let message = MessageParser::default().parse(raw)?;
+    let mut indices = id.split('.').map(|index| index.parse::<usize>().ok());
+
+    if indices.next()? != Some(0) {
+        return None;
+    }
+
+    let mut current = (&message, message.parts.first()?);
+    for index in indices {
+        let (message, part) = current;
+        let index = index?;
+
+        current = match &part.body {
+            PartType::Multipart(children) => {
+                (message, message.parts.get(*children.get(index)? as usize)?)
+            }
+            PartType::Message(nested) if index == 0 => (nested, nested.parts.first()?),
+            _ => return None,
+        };
+    }
+
+    let (message, part) = current;
+    let content_type = match &part.body {
+        PartType::Text(_) | PartType::Html(_) => format!("{}; charset=utf-8", content_type(part)),
+        _ => content_type(part),
+    };
+
+    Some((content_type, decoded(message, part).to_vec()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FORWARDED: &str = include_str!("../../../samples/forwarded.email");
+
+    #[test]
+    fn structure() {
+        let root = mime_structure(FORWARDED.as_bytes()).unwrap();
+
+        assert_eq!(root.id, "0");
+        assert_eq!(root.content_type, "multipart/mixed");
+        assert_eq!(root.parts.len(), 2);
+        assert_eq!(root.parts[0].charset.as_deref(), Some("UTF-8"));
+
+        let forwarded = &root.parts[1];
+        assert_eq!(forwarded.content_type, "message/rfc822");
+        assert_eq!(forwarded.disposition.as_deref(), Some("attachment"));
+        assert_eq!(forwarded.filename.as_deref(), Some("Quarterly report.eml"));
+
+        let report = &forwarded.parts[0];
+        assert_eq!(report.id, "0.1.0");
+        assert_eq!(report.content_type, "multipart/mixed");
+
+        let chart = &report.parts[0].parts[1];
+        assert_eq!(chart.id, "0.1.0.0.1");
+        assert_eq!(chart.content_type, "image/gif");
+        assert_eq!(chart.transfer_encoding.as_deref(), Some("base64"));
+        assert_eq!(chart.content_id.as_deref(), Some("chart"));
+        assert_eq!(chart.size, 42);
+
+        // the offsets point into the raw message
+        let offsets = report.parts[1].offsets.unwrap();
+        assert!(FORWARDED[offsets.header..].starts_with("Content-Type: text/csv"));
+        assert!(FORWARDED[offsets.body..offsets.end].starts_with("quarter,revenue"));
+    }
+
+    #[test]
+    fn content() {
+        let raw = FORWARDED.as_bytes();
+
+        let (content_type, csv) = part_content(raw, "0.1.0.1").unwrap();
+        assert_eq!(content_type, "text/csv; charset=utf-8");
+        assert!(csv.starts_with(b"quarter,revenue\n"));
+
+        let (content_type, gif) = part_content(raw, "0.1.0.0.1").unwrap();
+        assert_eq!(content_type, "image/gif");
+        assert!(gif.starts_with(b"GIF89a"));
+
+        let (_, request) = part_content(raw, "0.1.0.2.0").unwrap();
+        assert!(request.starts_with(b"Could you send me"));
+
+        assert!(part_content(raw, "0.5").is_none());
+        assert!(part_content(raw, "1").is_none());
+    }
+}
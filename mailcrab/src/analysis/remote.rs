@@ -0,0 +1,520 @@
+//! Remote content in the HTML body, like images, style sheets and fonts, which are blocked
+//! by default so that viewing a message does not fire tracking pixels or analytics
+
+use serde::Serialize;
+use url::Url;
+
+use super::{
+    css::{CssItem, parse_declarations},
+    html::{Attribute, Token, apply_edits, decode_entities, tokenize},
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum RemoteKind {
+    Image,
+    /// a 1×1 image, or an image from a known tracker
+    TrackingPixel,
+    Stylesheet,
+    Font,
+    /// scripts, frames, video, audio and other embedded objects
+    Other,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RemoteResource {
+    pub url: String,
+    pub kind: RemoteKind,
+    /// the element that loads the resource, "style" for a style sheet or style attribute
+    pub element: String,
+    /// the known tracker domain the resource is loaded from
+    pub tracker: Option<String>,
+}
+
+/// the HTML body with remote content blocked, and the remote resources that were found
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct RemoteContent {
+    pub html: String,
+    pub resources: Vec<RemoteResource>,
+}
+
+/// domains of analytics, email marketing and read receipt services
+const TRACKER_DOMAINS: [&str; 24] = [
+    "google-analytics.com",
+    "doubleclick.net",
+    "list-manage.com",
+    "mailchimp.com",
+    "mandrillapp.com",
+    "sendgrid.net",
+    "mailgun.org",
+    "sparkpostmail.com",
+    "exacttarget.com",
+    "hubspotemail.net",
+    "hs-analytics.net",
+    "pardot.com",
+    "klaviyo.com",
+    "customeriomail.com",
+    "createsend.com",
+    "constantcontact.com",
+    "mixpanel.com",
+    "mailtrack.io",
+    "yesware.com",
+    "bananatag.com",
+    "emltrk.com",
+    "getnotify.com",
+    "convertkit-mail.com",
+    "pixel.wp.com",
+];
+
+/// hosts that serve web font style sheets
+const FONT_HOSTS: [&str; 4] = [
+    "fonts.googleapis.com",
+    "use.typekit.net",
+    "fonts.bunny.net",
+    "fast.fonts.net",
+];
+
+const FONT_EXTENSIONS: [&str; 5] = [".woff2", ".woff", ".ttf", ".otf", ".eot"];
+
+fn is_remote(url: &str) -> bool {
+    let url = url.trim().to_ascii_lowercase();
+
+    url.starts_with("http://") || url.starts_with("https://") || url.starts_with("//")
+}
+
+fn host(url: &str) -> Option<String> {
+    let url = url.trim();
+    let url = match url.starts_with("//") {
+        true => Url::parse(&format!("https:{url}")),
+        false => Url::parse(url),
+    };
+
+    url.ok()?.host_str().map(str::to_ascii_lowercase)
+}
+
+fn matches_domain(host: &str, domain: &str) -> bool {
+    host == domain || host.ends_with(&format!(".{domain}"))
+}
+
+fn tracker(url: &str) -> Option<String> {
+    let host = host(url)?;
+
+    TRACKER_DOMAINS
+        .iter()
+        .find(|domain| matches_domain(&host, domain))
+        .map(|domain| (*domain).to_owned())
+}
+
+fn is_font_host(url: &str) -> bool {
+    host(url).is_some_and(|host| {
+        FONT_HOSTS
+            .iter()
+            .any(|domain| matches_domain(&host, domain))
+    })
+}
+
+fn is_font_file(url: &str) -> bool {
+    let path = url.split(['?', '#']).next().unwrap_or_default();
+
+    FONT_EXTENSIONS
+        .iter()
+        .any(|extension| path.to_ascii_lowercase().ends_with(extension))
+}
+
+/// a size in pixels, e.g. "1" or "1px"
+fn pixels(value: &str) -> Option<f64> {
+    value.trim().trim_end_matches("px").trim_end().parse().ok()
+}
+
+/// an image with a width and height of at most one pixel, set by attributes or inline style
+fn is_pixel(attributes: &[Attribute]) -> bool {
+    let style = attributes
+        .iter()
+        .find(|attribute| attribute.name == "style")
+        .map(|attribute| parse_declarations(&decode_entities(&attribute.value)))
+        .unwrap_or_default();
+    let size = |name: &str| {
+        style
+            .iter()
+            .find_map(|item| match item {
+                CssItem::Declaration {
+                    property, value, ..
+                } if property == name => pixels(value),
+                _ => None,
+            })
+            .or_else(|| {
+                attributes
+                    .iter()
+                    .find(|attribute| attribute.name == name)
+                    .and_then(|attribute| pixels(&attribute.value))
+            })
+    };
+
+    matches!((size("width"), size("height")), (Some(width), Some(height)) if width <= 1.0 && height <= 1.0)
+}
+
+/// the kind of resource an attribute of an element loads, if any
+fn attribute_kind(element: &str, attribute: &str, attributes: &[Attribute]) -> Option<RemoteKind> {
+    match (element, attribute) {
+        (_, "background")
+        | ("img" | "input", "src")
+        | ("img" | "source", "srcset")
+        | ("video", "poster")
+        | ("image", "href" | "xlink:href") => Some(RemoteKind::Image),
+        ("link", "href") => {
+            let stylesheet = attributes.iter().any(|attribute| {
+                attribute.name == "rel"
+                    && attribute
+                        .value
+                        .to_ascii_lowercase()
+                        .split_whitespace()
+                        .any(|rel| rel == "stylesheet")
+            });
+
+            Some(match stylesheet {
+                true => RemoteKind::Stylesheet,
+                false => RemoteKind::Other,
+            })
+        }
+        (
+            "script" | "iframe" | "frame" | "embed" | "video" | "audio" | "source" | "track",
+            "src",
+        )
+        | ("object", "data") => Some(RemoteKind::Other),
+        _ => None,
+    }
+}
+
+/// a URL in a style sheet, with its byte range
+struct CssUrl {
+    start: usize,
+    end: usize,
+    import: bool,
+}
+
+/// skip whitespace and an opening quote, which might be encoded in a style attribute
+fn skip_quote(css: &str, mut position: usize) -> usize {
+    position += css[position..].len() - css[position..].trim_start().len();
+
+    if css[position..].starts_with("&quot;") {
+        position + 6
+    } else if css[position..].starts_with(['"', '\'']) {
+        position + 1
+    } else {
+        position
+    }
+}
+
+/// find the end of a URL, at a quote or closing parenthesis
+fn url_end(css: &str, start: usize) -> usize {
+    let end = css[start..]
+        .find(['"', '\'', ')'])
+        .map_or(css.len(), |end| start + end);
+    let end = css[start..end]
+        .find("&quot;")
+        .map_or(end, |quote| start + quote);
+
+    start + css[start..end].trim_end().len()
+}
+
+/// find `url()` values and `@import` strings in CSS
+fn css_urls(css: &str) -> Vec<CssUrl> {
+    let lower = css.to_ascii_lowercase();
+    let mut urls = Vec::new();
+
+    for (index, _) in lower.match_indices("url(") {
+        let start = skip_quote(css, index + 4);
+
+        urls.push(CssUrl {
+            start,
+            end: url_end(css, start),
+            import: lower[..index].trim_end().ends_with("@import"),
+        });
+    }
+
+    for (index, _) in lower.match_indices("@import") {
+        let start = skip_quote(css, index + 7);
+
+        if start > index + 7 && !lower[index + 7..start].trim().is_empty() {
+            urls.push(CssUrl {
+                start,
+                end: url_end(css, start),
+                import: true,
+            });
+        }
+    }
+
+    urls
+}
+
+/// is a position in a style sheet within a `@font-face` rule
+fn in_font_face(css: &str, position: usize) -> bool {
+    let Some(open) = css[..position].rfind('{') else {
+        return false;
+    };
+    let start = css[..open]
+        .rfind(['{', '}', ';'])
+        .map_or(0, |index| index + 1);
+
+    css[start..open]
+        .trim()
+        .to_ascii_lowercase()
+        .starts_with("@font-face")
+}
+
+/// block the remote URLs in CSS, `offset` is the position of the CSS in the HTML body
+fn block_css(
+    css: &str,
+    offset: usize,
+    edits: &mut Vec<(usize, usize, String)>,
+    resources: &mut Vec<RemoteResource>,
+) {
+    for CssUrl { start, end, import } in css_urls(css) {
+        let url = decode_entities(&css[start..end]);
+
+        if !is_remote(&url) {
+            continue;
+        }
+
+        let kind = if import && is_font_host(&url) {
+            RemoteKind::Font
+        } else if import {
+            RemoteKind::Stylesheet
+        } else if in_font_face(css, start) || is_font_file(&url) {
+            RemoteKind::Font
+        } else {
+            RemoteKind::Image
+        };
+
+        edits.push((offset + start, offset + end, String::new()));
+        resources.push(RemoteResource {
+            tracker: tracker(&url),
+            url,
+            kind,
+            element: "style".to_owned(),
+        });
+    }
+}
+
+/// rewrite the HTML body so that it does not load remote resources, attributes with remote
+/// URLs are renamed to `data-remote-*` and remote URLs in CSS are removed
+pub fn block_remote_content(html: &str) -> RemoteContent {
+    let mut edits = Vec::new();
+    let mut resources = Vec::new();
+
+    for token in tokenize(html) {
+        let (name, attributes) = match token {
+            Token::StartTag {
+                name, attributes, ..
+            } => (name, attributes),
+            Token::Style { css, offset } => {
+                block_css(&css, offset, &mut edits, &mut resources);
+                continue;
+            }
+            _ => continue,
+        };
+
+        for attribute in &attributes {
+            if attribute.name == "style" {
+                block_css(
+                    &attribute.value,
+                    attribute.value_offset,
+                    &mut edits,
+                    &mut resources,
+                );
+                continue;
+            }
+
+            let Some(kind) = attribute_kind(&name, &attribute.name, &attributes) else {
+                continue;
+            };
+
+            let value = decode_entities(&attribute.value);
+            let urls = match attribute.name.as_str() {
+                "srcset" => value
+                    .split(',')
+                    .filter_map(|candidate| candidate.split_whitespace().next())
+                    .filter(|url| is_remote(url))
+                    .map(str::to_owned)
+                    .collect::<Vec<_>>(),
+                _ if is_remote(&value) => vec![value.trim().to_owned()],
+                _ => continue,
+            };
+
+            if urls.is_empty() {
+                continue;
+            }
+
+            edits.push((
+                attribute.offset,
+                attribute.offset + attribute.name.len(),
+                format!("data-remote-{}", attribute.name),
+            ));
+
+            for url in urls {
+                let tracker = tracker(&url);
+                let kind = match kind {
+                    RemoteKind::Image if tracker.is_some() || is_pixel(&attributes) => {
+                        RemoteKind::TrackingPixel
+                    }
+                    RemoteKind::Stylesheet if is_font_host(&url) => RemoteKind::Font,
+                    kind => kind,
+                };
+
+                resources.push(RemoteResource {
+                    url,
+                    kind,
+                    element: name.clone(),
+                    tracker,
+                });
+            }
+        }
+    }
+
+    RemoteContent {
+        html: apply_edits(html, edits),
+        resources,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn blocked() {
+        let html = "<html><head>\
+            <link rel=\"stylesheet\" href=\"https://fonts.googleapis.com/css?family=Inter\">\
+            <LINK REL=stylesheet HREF='//cdn.example.com/mail.css'>\
+            <style>@import 'https://example.com/import.css';\
+            @font-face { font-family: X; src: url(\"https://example.com/x.woff2\") format('woff2') }\
+            .header { background: url( https://example.com/bg.png ) }</style></head>\
+            <body background=\"http://example.com/body.jpg\">\
+            <img src=\"https://example.com/logo.png\" alt=\"logo\">\
+            <img src=\"cid:inline\"><img src=\"/api/message/1/attachment/0\">\
+            <img srcset=\"small.png 1x, https://example.com/large.png 2x\">\
+            <div style=\"background-image: url(&quot;https://example.com/div.png&quot;)\"></div>\
+            <a href=\"https://example.com/\">link</a>\
+            <img src=\"https://example.com/open?id=1\" width=\"1\" height=\"1\" alt=\"\">\
+            <img src=\"https://mc.us1.list-manage.com/track/open.php?u=1&amp;id=2\">\
+            </body></html>";
+
+        let content = block_remote_content(html);
+
+        assert_eq!(
+            content
+                .resources
+                .iter()
+                .map(|r| (
+                    r.url.as_str(),
+                    r.kind,
+                    r.element.as_str(),
+                    r.tracker.as_deref()
+                ))
+                .collect::<Vec<_>>(),
+            vec![
+                (
+                    "https://fonts.googleapis.com/css?family=Inter",
+                    RemoteKind::Font,
+                    "link",
+                    None
+                ),
+                (
+                    "//cdn.example.com/mail.css",
+                    RemoteKind::Stylesheet,
+                    "link",
+                    None
+                ),
+                (
+                    "https://example.com/x.woff2",
+                    RemoteKind::Font,
+                    "style",
+                    None
+                ),
+                (
+                    "https://example.com/bg.png",
+                    RemoteKind::Image,
+                    "style",
+                    None
+                ),
+                (
+                    "https://example.com/import.css",
+                    RemoteKind::Stylesheet,
+                    "style",
+                    None
+                ),
+                (
+                    "http://example.com/body.jpg",
+                    RemoteKind::Image,
+                    "body",
+                    None
+                ),
+                (
+                    "https://example.com/logo.png",
+                    RemoteKind::Image,
+                    "img",
+                    None
+                ),
+                (
+                    "https://example.com/large.png",
+                    RemoteKind::Image,
+                    "img",
+                    None
+                ),
+                (
+                    "https://example.com/div.png",
+                    RemoteKind::Image,
+                    "style",
+                    None
+                ),
+                (
+                    "https://example.com/open?id=1",
+                    RemoteKind::TrackingPixel,
+                    "img",
+                    None
+                ),
+                (
+                    "https://mc.us1.list-manage.com/track/open.php?u=1&id=2",
+                    RemoteKind::TrackingPixel,
+                    "img",
+                    Some("list-manage.com")
+                ),
+            ]
+        );
+
+        let blocked = content.html;
+        for url in [
+            "https://example.com/import.css",
+            "https://example.com/x.woff2",
+            "https://example.com/bg.png",
+            "https://example.com/div.png",
+        ] {
+            assert!(!blocked.contains(url), "{url}");
+        }
+        assert!(blocked.contains("<LINK REL=stylesheet data-remote-href='//cdn.example.com"));
+        assert!(blocked.contains("<body data-remote-background="));
+        assert!(blocked.contains("<img data-remote-src=\"https://example.com/logo.png\""));
+        assert!(blocked.contains("<img data-remote-srcset="));
+        assert!(blocked.contains("src: url(\"\") format('woff2')"));
+        assert!(blocked.contains("url(&quot;&quot;)"));
+        assert!(
+            blocked.contains("<img src=\"cid:inline\"><img src=\"/api/message/1/attachment/0\">")
+        );
+        assert!(blocked.contains("<a href=\"https://example.com/\">"));
+    }
+
+    #[test]
+    fn pixel() {
+        let attributes = |html: &str| match tokenize(html).remove(0) {
+            Token::StartTag { attributes, .. } => attributes,
+            _ => unreachable!(),
+        };
+
+        assert!(is_pixel(&attributes("<img width=1 height=\"1px\">")));
+        assert!(is_pixel(&attributes(
+            "<img width=600 style=\"width: 1px; height: 0\">"
+        )));
+        assert!(!is_pixel(&attributes("<img width=1>")));
+        assert!(!is_pixel(&attributes("<img width=\"100%\" height=\"1\">")));
+    }
+}
@@ -0,0 +1,157 @@
+//! Removes active content, like scripts, frames and event handlers, from the HTML body
+
+use super::html::{Attribute, Token, apply_edits, decode_entities, tokenize};
+
+/// elements that are removed together with their content
+const REMOVED_ELEMENTS: [&str; 8] = [
+    "script", "iframe", "frame", "frameset", "object", "embed", "applet", "base",
+];
+
+/// elements of which only the tags are removed, their content is kept
+const UNWRAPPED_ELEMENTS: [&str; 1] = ["form"];
+
+/// attributes that contain a URL
+const URL_ATTRIBUTES: [&str; 8] = [
+    "href",
+    "src",
+    "xlink:href",
+    "action",
+    "formaction",
+    "background",
+    "poster",
+    "data",
+];
+
+/// a URL that runs a script or renders a document when it is loaded
+fn is_dangerous_url(value: &str) -> bool {
+    // browsers ignore whitespace and control characters in the scheme
+    let url = decode_entities(value)
+        .chars()
+        .filter(|c| !c.is_whitespace() && !c.is_control())
+        .collect::<String>()
+        .to_ascii_lowercase();
+
+    url.starts_with("javascript:")
+        || url.starts_with("vbscript:")
+        || (url.starts_with("data:") && !url.starts_with("data:image/"))
+}
+
+fn is_dangerous_attribute(attribute: &Attribute) -> bool {
+    attribute.name.starts_with("on")
+        || matches!(attribute.name.as_str(), "srcdoc" | "ping")
+        || (URL_ATTRIBUTES.contains(&attribute.name.as_str()) && is_dangerous_url(&attribute.value))
+}
+
+/// the byte range of an attribute, including its (quoted) value
+fn attribute_range(html: &str, attribute: &Attribute) -> (usize, usize) {
+    let name_end = attribute.offset + attribute.name.len();
+
+    if !html[name_end..attribute.value_offset].contains('=') {
+        return (attribute.offset, name_end);
+    }
+
+    let end = attribute.value_offset + attribute.value.len();
+    let quoted = html[..attribute.value_offset].ends_with(['"', '\''])
+        && html[end..].starts_with(['"', '\'']);
+
+    (attribute.offset, if quoted { end + 1 } else { end })
+}
+
+/// link and meta elements that load resources or change the behaviour of the document
+fn is_dangerous_element(name: &str, attributes: &[Attribute]) -> bool {
+    let value = |name: &str| {
+        attributes
+            .iter()
+            .find(|attribute| attribute.name == name)
+            .map(|attribute| attribute.value.trim().to_ascii_lowercase())
+    };
+
+    match name {
+        "meta" => value("http-equiv").is_some_and(|value| value != "content-type"),
+        "link" => value("rel").is_none_or(|rel| rel != "stylesheet"),
+        _ => false,
+    }
+}
+
+/// remove scripts, frames, plugins, forms, event handlers and `javascript:` URLs
+pub fn sanitize_html(html: &str) -> String {
+    let tokens = tokenize(html);
+    let mut edits = Vec::new();
+
+    for (index, token) in tokens.iter().enumerate() {
+        match token {
+            Token::StartTag {
+                name, offset, end, ..
+            } if REMOVED_ELEMENTS.contains(&name.as_str()) => {
+                let close = tokens[index + 1..].iter().find_map(|token| match token {
+                    Token::EndTag {
+                        name: close, end, ..
+                    } if close == name => Some(*end),
+                    _ => None,
+                });
+                // the content of a script without an end tag is the rest of the document
+                let end = match close {
+                    Some(close) => close,
+                    None if name == "script" => html.len(),
+                    None => *end,
+                };
+
+                edits.push((*offset, end, String::new()));
+            }
+            Token::StartTag {
+                name,
+                attributes,
+                offset,
+                end,
+            } if UNWRAPPED_ELEMENTS.contains(&name.as_str())
+                || is_dangerous_element(name, attributes) =>
+            {
+                edits.push((*offset, *end, String::new()));
+            }
+            Token::EndTag { name, offset, end } if UNWRAPPED_ELEMENTS.contains(&name.as_str()) => {
+                edits.push((*offset, *end, String::new()));
+            }
+            Token::StartTag { attributes, .. } => {
+                for attribute in attributes.iter().filter(|a| is_dangerous_attribute(a)) {
+                    let (start, end) = attribute_range(html, attribute);
+                    edits.push((start, end, String::new()));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    apply_edits(html, edits)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sanitized() {
+        let html = "<html><head><base href=\"https://example.com/\">\
+            <meta http-equiv=\"refresh\" content=\"0; url=https://example.com\">\
+            <meta http-equiv=Content-Type content=\"text/html\">\
+            <link rel=\"stylesheet\" href=\"style.css\"><link rel=prefetch href=\"/api/delete-all\">\
+            <script>fetch('/api/delete-all', { method: 'POST' })</script></head>\
+            <body onload=\"alert(1)\"><p ONCLICK='x()' class=text>Hello</p>\
+            <a href=\" java\tscript:alert(1)\">a</a><a href=\"https://example.com\" ping=/track>b</a>\
+            <img src=x onerror=alert(1) alt=\"\"><img src=\"data:image/png;base64,AA\">\
+            <iframe src=\"/api/messages\"><p>fallback</p></iframe>\
+            <form action=\"/api/delete-all\" method=post><button>Delete</button></form>\
+            <embed src=\"flash.swf\"><object data=\"data:text/html,<b>x</b>\"></object>\
+            <script>unclosed";
+
+        assert_eq!(
+            sanitize_html(html),
+            "<html><head>\
+            <meta http-equiv=Content-Type content=\"text/html\">\
+            <link rel=\"stylesheet\" href=\"style.css\"></head>\
+            <body ><p  class=text>Hello</p>\
+            <a >a</a><a href=\"https://example.com\" >b</a>\
+            <img src=x  alt=\"\"><img src=\"data:image/png;base64,AA\">\
+            <button>Delete</button>"
+        );
+    }
+}
@@ -0,0 +1,895 @@
+//! S/MIME (RFC 8551) decryption and signature verification, with locally configured keys
+
+use cbc::cipher::{BlockCipher, BlockDecryptMut, KeyInit, KeyIvInit, block_padding::Pkcs7};
+use cms::{
+    cert::{
+        CertificateChoices, IssuerAndSerialNumber,
+        x509::{
+            Certificate,
+            der::{
+                Decode, Encode,
+                asn1::{ObjectIdentifier, OctetString},
+            },
+            spki::SubjectPublicKeyInfoOwned,
+        },
+    },
+    content_info::ContentInfo,
+    enveloped_data::{EnvelopedData, RecipientInfo},
+    signed_data::{SignedData, SignerIdentifier, SignerInfo},
+};
+use mail_parser::{MessageParser, MimeHeaders};
+use ring::{digest, signature};
+use rsa::{Pkcs1v15Encrypt, RsaPrivateKey, pkcs1::DecodeRsaPrivateKey, pkcs8::DecodePrivateKey};
+use serde::Serialize;
+
+use super::{
+    authentication::AuthResult,
+    dkim::{HeaderField, normalize_line_endings, split_message},
+};
+use crate::error::{Error, Result};
+
+/// the maximum number of nested S/MIME layers, e.g. a signed message that is encrypted
+const MAX_LAYERS: usize = 5;
+
+/// the maximum nesting depth of BER values, CMS structures are far less deep
+const MAX_DEPTH: usize = 64;
+
+const SIGNED_DATA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.7.2");
+const ENVELOPED_DATA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.7.3");
+const MESSAGE_DIGEST: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.9.4");
+const SUBJECT_KEY_IDENTIFIER: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.5.29.14");
+
+const RSA_ENCRYPTION: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.1");
+const SHA1_WITH_RSA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.5");
+const SHA256_WITH_RSA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.11");
+const SHA384_WITH_RSA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.12");
+const SHA512_WITH_RSA: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.1.1.13");
+const EC_PUBLIC_KEY: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.2.1");
+const ECDSA_WITH_SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.2");
+const ECDSA_WITH_SHA384: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.3");
+const ECDSA_WITH_SHA512: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.4.3.4");
+const P256: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.10045.3.1.7");
+const P384: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.132.0.34");
+const ED25519: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.101.112");
+
+const SHA1: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.3.14.3.2.26");
+const SHA256: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.1");
+const SHA384: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.2");
+const SHA512: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.2.3");
+
+const AES128_CBC: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.1.2");
+const AES192_CBC: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.1.22");
+const AES256_CBC: ObjectIdentifier = ObjectIdentifier::new_unwrap("2.16.840.1.101.3.4.1.42");
+const DES_EDE3_CBC: ObjectIdentifier = ObjectIdentifier::new_unwrap("1.2.840.113549.3.7");
+
+/// private keys to decrypt messages, and certificates of trusted signers or their issuers
+#[derive(Clone, Debug, Default)]
+pub struct SmimeKeys {
+    private_keys: Vec<RsaPrivateKey>,
+    certificates: Vec<Certificate>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SmimeSignature {
+    pub result: AuthResult,
+    /// the subject of the signer certificate
+    pub signer: Option<String>,
+    /// the signer certificate is, or is issued by, one of the configured certificates
+    pub trusted: bool,
+    /// why the signature did not verify
+    pub reason: Option<String>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SmimeReport {
+    pub encrypted: bool,
+    /// the message was decrypted with one of the configured private keys
+    pub decrypted: bool,
+    pub signatures: Vec<SmimeSignature>,
+    /// why the message could not be decrypted or unwrapped
+    pub reason: Option<String>,
+}
+
+impl SmimeKeys {
+    /// add the RSA private keys (PKCS #8 or PKCS #1) and certificates of a PEM file
+    pub fn add_pem(&mut self, pem: &str) -> Result<()> {
+        let mut block: Option<(&str, String)> = None;
+
+        for line in pem.lines().map(str::trim) {
+            if let Some(label) = line
+                .strip_prefix("-----BEGIN ")
+                .and_then(|line| line.strip_suffix("-----"))
+            {
+                block = Some((label, String::new()));
+            } else if line.starts_with("-----END ") {
+                if let Some((label, base64)) = block.take() {
+                    self.add_der(label, &base64)?;
+                }
+            } else if let Some((_, base64)) = &mut block {
+                base64.push_str(line);
+            }
+        }
+
+        Ok(())
+    }
+
+    fn add_der(&mut self, label: &str, base64: &str) -> Result<()> {
+        use base64ct::{Base64, Encoding};
+
+        let invalid = |e: &dyn std::fmt::Display| Error::Config(format!("invalid {label}: {e}"));
+        let der = Base64::decode_vec(base64).map_err(|e| invalid(&e))?;
+
+        match label {
+            "PRIVATE KEY" => self
+                .private_keys
+                .push(RsaPrivateKey::from_pkcs8_der(&der).map_err(|e| invalid(&e))?),
+            "RSA PRIVATE KEY" => self
+                .private_keys
+                .push(RsaPrivateKey::from_pkcs1_der(&der).map_err(|e| invalid(&e))?),
+            "CERTIFICATE" => self
+                .certificates
+                .push(Certificate::from_der(&der).map_err(|e| invalid(&e))?),
+            "ENCRYPTED PRIVATE KEY" => {
+                return Err(Error::Config(
+                    "encrypted private keys are not supported".to_owned(),
+                ));
+            }
+            _ => {}
+        }
+
+        Ok(())
+    }
+}
+
+/// a BER encoded value, with its identifier octets
+struct Tlv<'a> {
+    tag: &'a [u8],
+    value: Value<'a>,
+}
+
+enum Value<'a> {
+    Primitive(&'a [u8]),
+    Constructed(Vec<Tlv<'a>>),
+}
+
+/// parse a BER value, returns the value and the remaining input, or None when it is invalid
+/// or nested deeper than `MAX_DEPTH`
+fn parse_ber(input: &[u8], depth: usize) -> Option<(Tlv<'_>, &[u8])> {
+    if depth > MAX_DEPTH {
+        return None;
+    }
+
+    let first = *input.first()?;
+    let mut tag_length = 1;
+    // high tag numbers continue while the most significant bit is set
+    if first & 0x1f == 0x1f {
+        tag_length += input[1..].iter().position(|b| b & 0x80 == 0)? + 1;
+    }
+    let (tag, input) = input.split_at_checked(tag_length)?;
+    let constructed = first & 0x20 != 0;
+
+    let (length, input) = input.split_first()?;
+    let (length, input) = match *length {
+        0x80 => (None, input),
+        length if length & 0x80 == 0 => (Some(length as usize), input),
+        length => {
+            let (bytes, input) = input.split_at_checked((length & 0x7f) as usize)?;
+            let length = bytes.iter().try_fold(0usize, |length, b| {
+                length.checked_mul(256)?.checked_add(*b as usize)
+            })?;
+            (Some(length), input)
+        }
+    };
+
+    match (length, constructed) {
+        (Some(length), false) => {
+            let (value, rest) = input.split_at_checked(length)?;
+            Some((
+                Tlv {
+                    tag,
+                    value: Value::Primitive(value),
+                },
+                rest,
+            ))
+        }
+        (Some(length), true) => {
+            let (mut value, rest) = input.split_at_checked(length)?;
+            let mut children = Vec::new();
+            while !value.is_empty() {
+                let (child, remaining) = parse_ber(value, depth + 1)?;
+                children.push(child);
+                value = remaining;
+            }
+            Some((
+                Tlv {
+                    tag,
+                    value: Value::Constructed(children),
+                },
+                rest,
+            ))
+        }
+        // indefinite lengths end with two zero octets
+        (None, true) => {
+            let mut input = input;
+            let mut children = Vec::new();
+            while !input.starts_with(&[0, 0]) {
+                let (child, remaining) = parse_ber(input, depth + 1)?;
+                children.push(child);
+                input = remaining;
+            }
+            Some((
+                Tlv {
+                    tag,
+                    value: Value::Constructed(children),
+                },
+                &input[2..],
+            ))
+        }
+        (None, false) => None,
+    }
+}
+
+/// the concatenated content of a (constructed) string
+fn string_content(tlv: &Tlv, content: &mut Vec<u8>) {
+    match &tlv.value {
+        Value::Primitive(value) => content.extend_from_slice(value),
+        Value::Constructed(children) => {
+            for child in children {
+                string_content(child, content);
+            }
+        }
+    }
+}
+
+fn encode_der(tag: &[u8], value: &[u8], der: &mut Vec<u8>) {
+    der.extend_from_slice(tag);
+    match value.len() {
+        length @ 0..0x80 => der.push(length as u8),
+        length => {
+            let bytes = length.to_be_bytes();
+            let bytes = &bytes[bytes.iter().position(|b| *b != 0).unwrap_or_default()..];
+            der.push(0x80 | bytes.len() as u8);
+            der.extend_from_slice(bytes);
+        }
+    }
+    der.extend_from_slice(value);
+}
+
+fn tlv_to_der(tlv: &Tlv, der: &mut Vec<u8>) {
+    let Value::Constructed(children) = &tlv.value else {
+        let mut content = Vec::new();
+        string_content(tlv, &mut content);
+        return encode_der(tlv.tag, &content, der);
+    };
+
+    // strings may be split in segments, DER requires a single primitive string
+    if tlv.tag == [0x24] {
+        let mut content = Vec::new();
+        string_content(tlv, &mut content);
+        return encode_der(&[0x04], &content, der);
+    }
+
+    let mut content = Vec::new();
+    for (index, child) in children.iter().enumerate() {
+        // the encrypted content ([0] IMPLICIT OCTET STRING) of an EncryptedContentInfo
+        // (content type, algorithm, content) is segmented by streaming encoders
+        let encrypted_content = index == 2
+            && tlv.tag == [0x30]
+            && children[0].tag == [0x06]
+            && children[1].tag == [0x30]
+            && child.tag == [0xa0];
+
+        match encrypted_content {
+            true => {
+                let mut segments = Vec::new();
+                string_content(child, &mut segments);
+                encode_der(&[0x80], &segments, &mut content);
+            }
+            false => tlv_to_der(child, &mut content),
+        }
+    }
+
+    encode_der(tlv.tag, &content, der);
+}
+
+/// re-encode BER, e.g. with indefinite lengths as written by streaming encoders, as DER
+fn ber_to_der(ber: &[u8]) -> Option<Vec<u8>> {
+    let (tlv, _) = parse_ber(ber, 0)?;
+    let mut der = Vec::new();
+    tlv_to_der(&tlv, &mut der);
+
+    Some(der)
+}
+
+fn digest_algorithm(oid: &ObjectIdentifier) -> Option<&'static digest::Algorithm> {
+    match *oid {
+        SHA1 | SHA1_WITH_RSA => Some(&digest::SHA1_FOR_LEGACY_USE_ONLY),
+        SHA256 | SHA256_WITH_RSA | ECDSA_WITH_SHA256 => Some(&digest::SHA256),
+        SHA384 | SHA384_WITH_RSA | ECDSA_WITH_SHA384 => Some(&digest::SHA384),
+        SHA512 | SHA512_WITH_RSA | ECDSA_WITH_SHA512 => Some(&digest::SHA512),
+        _ => None,
+    }
+}
+
+/// the algorithm to verify a signature with the given public key, signature algorithms like
+/// rsaEncryption do not include the digest algorithm
+fn verification_algorithm(
+    signature_algorithm: &ObjectIdentifier,
+    digest_algorithm_oid: Option<&ObjectIdentifier>,
+    key: &SubjectPublicKeyInfoOwned,
+) -> Option<&'static dyn signature::VerificationAlgorithm> {
+    if key.algorithm.oid == ED25519 {
+        return Some(&signature::ED25519);
+    }
+
+    let hash = digest_algorithm(signature_algorithm)
+        .or_else(|| digest_algorithm_oid.and_then(digest_algorithm))?;
+
+    match key.algorithm.oid {
+        RSA_ENCRYPTION => match hash {
+            hash if *hash == digest::SHA1_FOR_LEGACY_USE_ONLY => {
+                Some(&signature::RSA_PKCS1_1024_8192_SHA1_FOR_LEGACY_USE_ONLY)
+            }
+            hash if *hash == digest::SHA256 => {
+                Some(&signature::RSA_PKCS1_1024_8192_SHA256_FOR_LEGACY_USE_ONLY)
+            }
+            hash if *hash == digest::SHA384 => Some(&signature::RSA_PKCS1_2048_8192_SHA384),
+            _ => Some(&signature::RSA_PKCS1_1024_8192_SHA512_FOR_LEGACY_USE_ONLY),
+        },
+        EC_PUBLIC_KEY => {
+            let curve = key
+                .algorithm
+                .parameters
+                .as_ref()?
+                .decode_as::<ObjectIdentifier>()
+                .ok()?;
+
+            match (curve, hash) {
+                (P256, hash) if *hash == digest::SHA256 => Some(&signature::ECDSA_P256_SHA256_ASN1),
+                (P256, hash) if *hash == digest::SHA384 => Some(&signature::ECDSA_P256_SHA384_ASN1),
+                (P384, hash) if *hash == digest::SHA256 => Some(&signature::ECDSA_P384_SHA256_ASN1),
+                (P384, hash) if *hash == digest::SHA384 => Some(&signature::ECDSA_P384_SHA384_ASN1),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+fn verify_signature(
+    signature_algorithm: &ObjectIdentifier,
+    digest_algorithm_oid: Option<&ObjectIdentifier>,
+    key: &SubjectPublicKeyInfoOwned,
+    data: &[u8],
+    signature: &[u8],
+) -> Option<bool> {
+    let algorithm = verification_algorithm(signature_algorithm, digest_algorithm_oid, key)?;
+
+    Some(
+        signature::UnparsedPublicKey::new(algorithm, key.subject_public_key.raw_bytes())
+            .verify(data, signature)
+            .is_ok(),
+    )
+}
+
+/// is the certificate one of the configured certificates, or issued by one of them
+fn trusted(certificate: &Certificate, keys: &SmimeKeys) -> bool {
+    let Ok(data) = certificate.tbs_certificate.to_der() else {
+        return false;
+    };
+
+    keys.certificates.iter().any(|trusted| {
+        trusted == certificate
+            || (trusted.tbs_certificate.subject == certificate.tbs_certificate.issuer
+                && verify_signature(
+                    &certificate.signature_algorithm.oid,
+                    None,
+                    &trusted.tbs_certificate.subject_public_key_info,
+                    &data,
+                    certificate.signature.raw_bytes(),
+                ) == Some(true))
+    })
+}
+
+/// does the certificate match the issuer and serial number, or subject key identifier
+fn identifies(certificate: &Certificate, signer: &SignerIdentifier) -> bool {
+    match signer {
+        SignerIdentifier::IssuerAndSerialNumber(IssuerAndSerialNumber {
+            issuer,
+            serial_number,
+        }) => {
+            certificate.tbs_certificate.issuer == *issuer
+                && certificate.tbs_certificate.serial_number == *serial_number
+        }
+        SignerIdentifier::SubjectKeyIdentifier(identifier) => {
+            let Ok(identifier) = identifier.to_der() else {
+                return false;
+            };
+
+            certificate
+                .tbs_certificate
+                .extensions
+                .iter()
+                .flatten()
+                .any(|extension| {
+                    extension.extn_id == SUBJECT_KEY_IDENTIFIER
+                        && extension.extn_value.as_bytes() == identifier
+                })
+        }
+    }
+}
+
+/// verify the signature of a single signer over the content
+fn verify_signer(
+    signer: &SignerInfo,
+    certificates: &[&Certificate],
+    content: &[u8],
+    keys: &SmimeKeys,
+) -> SmimeSignature {
+    let certificate = certificates
+        .iter()
+        .copied()
+        .chain(&keys.certificates)
+        .find(|certificate| identifies(certificate, &signer.sid));
+
+    let result = |result, reason: Option<&str>| SmimeSignature {
+        result,
+        signer: certificate.map(|certificate| certificate.tbs_certificate.subject.to_string()),
+        trusted: result == AuthResult::Pass && certificate.is_some_and(|c| trusted(c, keys)),
+        reason: reason.map(str::to_owned),
+    };
+
+    let Some(certificate) = certificate else {
+        return result(
+            AuthResult::PermError,
+            Some("the signer certificate is not included or configured"),
+        );
+    };
+
+    let Some(hash) = digest_algorithm(&signer.digest_alg.oid) else {
+        return result(AuthResult::PermError, Some("unsupported digest algorithm"));
+    };
+    let digest = digest::digest(hash, content);
+
+    // with signed attributes the signature covers the attributes, which include the digest
+    let data = match &signer.signed_attrs {
+        Some(attributes) => {
+            let message_digest = attributes
+                .iter()
+                .find(|attribute| attribute.oid == MESSAGE_DIGEST)
+                .and_then(|attribute| attribute.values.get(0))
+                .and_then(|value| value.decode_as::<OctetString>().ok());
+
+            match message_digest {
+                Some(message_digest) if message_digest.as_bytes() == digest.as_ref() => {}
+                Some(_) => {
+                    return result(
+                        AuthResult::Fail,
+                        Some("message digest mismatch, the content was modified"),
+                    );
+                }
+                None => {
+                    return result(
+                        AuthResult::PermError,
+                        Some("the signed attributes have no message digest"),
+                    );
+                }
+            }
+
+            match attributes.to_der() {
+                Ok(data) => data,
+                Err(_) => return result(AuthResult::PermError, Some("invalid signed attributes")),
+            }
+        }
+        None => content.to_vec(),
+    };
+
+    match verify_signature(
+        &signer.signature_algorithm.oid,
+        Some(&signer.digest_alg.oid),
+        &certificate.tbs_certificate.subject_public_key_info,
+        &data,
+        signer.signature.as_bytes(),
+    ) {
+        Some(true) => result(AuthResult::Pass, None),
+        Some(false) => result(AuthResult::Fail, Some("signature mismatch")),
+        None => result(
+            AuthResult::PermError,
+            Some("unsupported signature algorithm"),
+        ),
+    }
+}
+
+/// verify all signatures, returns the signed content (detached or encapsulated)
+fn verify_signed_data(
+    signed: &SignedData,
+    detached: Option<&[u8]>,
+    keys: &SmimeKeys,
+) -> (Vec<SmimeSignature>, Option<Vec<u8>>) {
+    let content = match detached {
+        Some(content) => Some(content.to_vec()),
+        None => signed
+            .encap_content_info
+            .econtent
+            .as_ref()
+            .and_then(|content| content.decode_as::<OctetString>().ok())
+            .map(OctetString::into_bytes),
+    };
+
+    let certificates = signed
+        .certificates
+        .iter()
+        .flat_map(|certificates| certificates.0.iter())
+        .filter_map(|certificate| match certificate {
+            CertificateChoices::Certificate(certificate) => Some(certificate),
+            CertificateChoices::Other(_) => None,
+        })
+        .collect::<Vec<&Certificate>>();
+
+    let signatures = signed
+        .signer_infos
+        .0
+        .iter()
+        .map(|signer| match &content {
+            Some(content) => verify_signer(signer, &certificates, content, keys),
+            None => SmimeSignature {
+                result: AuthResult::PermError,
+                signer: None,
+                trusted: false,
+                reason: Some("the signed content is missing".to_owned()),
+            },
+        })
+        .collect();
+
+    (signatures, content)
+}
+
+fn decrypt_cbc<C>(key: &[u8], iv: &[u8], data: &[u8]) -> Option<Vec<u8>>
+where
+    C: BlockDecryptMut + BlockCipher + KeyInit,
+{
+    let mut buffer = data.to_vec();
+    let length = cbc::Decryptor::<C>::new_from_slices(key, iv)
+        .ok()?
+        .decrypt_padded_mut::<Pkcs7>(&mut buffer)
+        .ok()?
+        .len();
+    buffer.truncate(length);
+
+    Some(buffer)
+}
+
+/// decrypt the content with one of the configured private keys
+fn decrypt(enveloped: &EnvelopedData, keys: &SmimeKeys) -> std::result::Result<Vec<u8>, String> {
+    if keys.private_keys.is_empty() {
+        return Err("no private keys are configured".to_owned());
+    }
+
+    let content = &enveloped.encrypted_content;
+    let algorithm = content.content_enc_alg.oid;
+    let data = content
+        .encrypted_content
+        .as_ref()
+        .ok_or("the encrypted content is missing")?
+        .as_bytes();
+    let iv = content
+        .content_enc_alg
+        .parameters
+        .as_ref()
+        .and_then(|parameters| parameters.decode_as::<OctetString>().ok())
+        .ok_or("the initialization vector is missing")?;
+
+    if ![AES128_CBC, AES192_CBC, AES256_CBC, DES_EDE3_CBC].contains(&algorithm) {
+        return Err(format!(
+            "unsupported content encryption algorithm {algorithm}"
+        ));
+    }
+
+    let decrypt_content = |key: &[u8]| match algorithm {
+        AES128_CBC => decrypt_cbc::<aes::Aes128>(key, iv.as_bytes(), data),
+        AES192_CBC => decrypt_cbc::<aes::Aes192>(key, iv.as_bytes(), data),
+        AES256_CBC => decrypt_cbc::<aes::Aes256>(key, iv.as_bytes(), data),
+        DES_EDE3_CBC => decrypt_cbc::<des::TdesEde3>(key, iv.as_bytes(), data),
+        _ => None,
+    };
+
+    for recipient in enveloped.recip_infos.0.iter() {
+        let RecipientInfo::Ktri(recipient) = recipient else {
+            continue;
+        };
+        if recipient.key_enc_alg.oid != RSA_ENCRYPTION {
+            continue;
+        }
+
+        // a wrong key almost always fails to decrypt the content encryption key or content
+        for key in &keys.private_keys {
+            if let Ok(content_key) = key.decrypt(Pkcs1v15Encrypt, recipient.enc_key.as_bytes())
+                && let Some(content) = decrypt_content(&content_key)
+            {
+                return Ok(content);
+            }
+        }
+    }
+
+    Err("none of the configured private keys can decrypt the message".to_owned())
+}
+
+fn find(haystack: &[u8], needle: &[u8]) -> Option<usize> {
+    haystack
+        .windows(needle.len())
+        .position(|window| window == needle)
+}
+
+/// the first part of a multipart body exactly as transmitted, which is the signed content
+pub(super) fn first_part<'a>(body: &'a [u8], boundary: &str) -> Option<&'a [u8]> {
+    let delimiter = format!("--{boundary}");
+    let open = match body.starts_with(delimiter.as_bytes()) {
+        true => 0,
+        false => find(body, format!("\r\n{delimiter}").as_bytes())? + 2,
+    };
+    let start = open + find(&body[open..], b"\r\n")? + 2;
+    let end = start + find(&body[start..], format!("\r\n{delimiter}").as_bytes())?;
+
+    Some(&body[start..end])
+}
+
+/// unwrap a single S/MIME layer, returns None when the entity is not S/MIME
+fn unwrap_layer(
+    entity: &[u8],
+    keys: &SmimeKeys,
+    report: &mut SmimeReport,
+) -> Option<std::result::Result<Vec<u8>, String>> {
+    let parsed = MessageParser::new().parse(entity)?;
+    let root = parsed.root_part();
+    let content_type = root.content_type()?;
+    let subtype = content_type
+        .subtype()
+        .unwrap_or_default()
+        .to_ascii_lowercase();
+
+    match (
+        content_type.ctype().to_ascii_lowercase().as_str(),
+        subtype.as_str(),
+    ) {
+        ("application", "pkcs7-mime" | "x-pkcs7-mime") => {
+            let info = ber_to_der(root.contents()).and_then(|der| ContentInfo::from_der(&der).ok());
+            let Some(info) = info else {
+                return Some(Err("invalid PKCS #7 content".to_owned()));
+            };
+
+            match info.content_type {
+                ENVELOPED_DATA => {
+                    report.encrypted = true;
+                    let enveloped = info
+                        .content
+                        .decode_as::<EnvelopedData>()
+                        .map_err(|_| "invalid enveloped data".to_owned());
+                    let content = enveloped.and_then(|enveloped| decrypt(&enveloped, keys));
+                    report.decrypted = content.is_ok();
+
+                    Some(content)
+                }
+                SIGNED_DATA => {
+                    let Ok(signed) = info.content.decode_as::<SignedData>() else {
+                        return Some(Err("invalid signed data".to_owned()));
+                    };
+                    let (signatures, content) = verify_signed_data(&signed, None, keys);
+                    report.signatures.extend(signatures);
+
+                    Some(content.ok_or_else(|| "the signed content is missing".to_owned()))
+                }
+                content_type => Some(Err(format!("unsupported content type {content_type}"))),
+            }
+        }
+        ("multipart", "signed") => {
+            let protocol = content_type.attribute("protocol").unwrap_or_default();
+            if !protocol.to_ascii_lowercase().contains("pkcs7-signature") {
+                return None;
+            }
+
+            let (_, body) = split_message(entity);
+            let content = content_type
+                .attribute("boundary")
+                .and_then(|boundary| first_part(body, boundary));
+            let signature = parsed.parts.iter().find(|part| {
+                part.content_type()
+                    .and_then(|content_type| content_type.subtype())
+                    .is_some_and(|subtype| {
+                        subtype.to_ascii_lowercase().ends_with("pkcs7-signature")
+                    })
+            });
+
+            let (Some(content), Some(signature)) = (content, signature) else {
+                return Some(Err("the signed content or signature is missing".to_owned()));
+            };
+            let signed = ber_to_der(signature.contents())
+                .and_then(|der| ContentInfo::from_der(&der).ok())
+                .filter(|info| info.content_type == SIGNED_DATA)
+                .and_then(|info| info.content.decode_as::<SignedData>().ok());
+            let Some(signed) = signed else {
+                return Some(Err("invalid signature".to_owned()));
+            };
+
+            let (signatures, _) = verify_signed_data(&signed, Some(content), keys);
+            report.signatures.extend(signatures);
+
+            Some(Ok(content.to_vec()))
+        }
+        _ => None,
+    }
+}
+
+/// decrypt and verify the S/MIME layers of a message, returns None for messages without S/MIME,
+/// otherwise the report and the message with the innermost content that could be unwrapped
+pub fn unwrap_smime(raw: &[u8], keys: &SmimeKeys) -> Option<(SmimeReport, Vec<u8>)> {
+    let raw = normalize_line_endings(raw);
+    let mut report = SmimeReport::default();
+    let mut entity = raw.clone();
+    let mut smime = false;
+
+    for _ in 0..MAX_LAYERS {
+        let Some(content) = unwrap_layer(&entity, keys, &mut report) else {
+            break;
+        };
+        smime = true;
+
+        match content {
+            Ok(content) => entity = normalize_line_endings(&content),
+            Err(reason) => {
+                report.reason = Some(reason);
+                break;
+            }
+        }
+    }
+
+    if !smime {
+        return None;
+    }
+
+    Some((report, replace_content(&raw, &entity)))
+}
+
+/// the message with its content headers and body replaced by those of the unwrapped entity
+pub(super) fn replace_content(raw: &[u8], entity: &[u8]) -> Vec<u8> {
+    let (fields, _) = split_message(raw);
+    let (content_fields, body) = match entity.strip_prefix(b"\r\n") {
+        Some(body) => (Vec::new(), body),
+        None => split_message(entity),
+    };
+    let is_content = |field: &&HeaderField| field.name.starts_with("content-");
+
+    let mut message = fields
+        .iter()
+        .filter(|field| !is_content(field) && field.name != "mime-version")
+        .flat_map(|field| field.raw.iter().copied())
+        .collect::<Vec<u8>>();
+    message.extend_from_slice(b"MIME-Version: 1.0\r\n");
+    for field in content_fields.iter().filter(is_content) {
+        message.extend_from_slice(field.raw);
+    }
+    message.extend_from_slice(b"\r\n");
+    message.extend_from_slice(body);
+
+    message
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(files: &[&str]) -> SmimeKeys {
+        let mut keys = SmimeKeys::default();
+        for pem in files {
+            keys.add_pem(pem).unwrap();
+        }
+        keys
+    }
+
+    const JOE: &str = include_str!("testdata/smime/joe.pem");
+    const CA: &str = include_str!("testdata/smime/ca.pem");
+
+    #[test]
+    fn signed() {
+        let message = include_str!("testdata/smime/signed.eml");
+        let (report, unwrapped) = unwrap_smime(message.as_bytes(), &keys(&[CA])).unwrap();
+        assert!(!report.encrypted);
+        assert_eq!(report.signatures.len(), 1);
+
+        let signature = &report.signatures[0];
+        assert_eq!(signature.result, AuthResult::Pass);
+        assert!(signature.trusted);
+        assert!(
+            signature
+                .signer
+                .as_deref()
+                .unwrap()
+                .contains("CN=Joe SixPack")
+        );
+
+        let unwrapped = String::from_utf8(unwrapped).unwrap();
+        assert!(unwrapped.starts_with("To: suzie@shopping.example.net\r\n"));
+        assert!(unwrapped.contains(
+            "MIME-Version: 1.0\r\nContent-Type: text/plain; charset=utf-8\r\n\r\nHi Suzie,"
+        ));
+        assert!(!unwrapped.contains("multipart/signed"));
+
+        // without the CA certificate the signature is valid, but not trusted
+        let (report, _) = unwrap_smime(message.as_bytes(), &SmimeKeys::default()).unwrap();
+        assert_eq!(report.signatures[0].result, AuthResult::Pass);
+        assert!(!report.signatures[0].trusted);
+
+        let modified = message.replace("We lost the game", "We won the game");
+        let (report, _) = unwrap_smime(modified.as_bytes(), &keys(&[CA])).unwrap();
+        assert_eq!(report.signatures[0].result, AuthResult::Fail);
+        assert!(!report.signatures[0].trusted);
+
+        let opaque = include_str!("testdata/smime/opaque.eml");
+        let (report, unwrapped) = unwrap_smime(opaque.as_bytes(), &keys(&[CA])).unwrap();
+        assert_eq!(report.signatures[0].result, AuthResult::Pass);
+        assert!(
+            String::from_utf8(unwrapped)
+                .unwrap()
+                .contains("We lost the game")
+        );
+    }
+
+    #[test]
+    fn encrypted() {
+        let message = include_str!("testdata/smime/encrypted.eml");
+        let (report, unwrapped) = unwrap_smime(message.as_bytes(), &keys(&[JOE, CA])).unwrap();
+        assert!(report.encrypted && report.decrypted);
+        assert_eq!(report.reason, None);
+        // the message was signed before it was encrypted
+        assert_eq!(report.signatures.len(), 1);
+        assert_eq!(report.signatures[0].result, AuthResult::Pass);
+        assert!(
+            String::from_utf8(unwrapped)
+                .unwrap()
+                .contains("We lost the game")
+        );
+
+        let (report, unwrapped) = unwrap_smime(message.as_bytes(), &keys(&[CA])).unwrap();
+        assert!(report.encrypted && !report.decrypted);
+        assert_eq!(
+            report.reason.as_deref(),
+            Some("no private keys are configured")
+        );
+        assert!(String::from_utf8(unwrapped).unwrap().contains("smime.p7m"));
+
+        // 3DES with indefinite lengths and a segmented encrypted content
+        let streamed = include_str!("testdata/smime/streamed.eml");
+        let (report, unwrapped) = unwrap_smime(streamed.as_bytes(), &keys(&[JOE])).unwrap();
+        assert!(report.decrypted);
+        assert!(report.signatures.is_empty());
+        assert!(
+            String::from_utf8(unwrapped)
+                .unwrap()
+                .contains("We lost the game")
+        );
+    }
+
+    #[test]
+    fn nested() {
+        // every level of indefinite length sequences is a stack frame
+        let mut ber = [0x30, 0x80].repeat(100_000);
+        ber.extend([0; 200_000]);
+        assert!(ber_to_der(&ber).is_none());
+
+        let mut ber = [0x30, 0x80].repeat(MAX_DEPTH);
+        ber.extend([0x04, 0x01, 0xff]);
+        ber.extend([0; MAX_DEPTH * 2]);
+        assert!(ber_to_der(&ber).is_some());
+    }
+
+    #[test]
+    fn not_smime() {
+        let message = b"From: joe@example.com\r\nContent-Type: text/plain\r\n\r\nHi\r\n";
+        assert_eq!(unwrap_smime(message, &keys(&[JOE])), None);
+
+        assert!(
+            SmimeKeys::default()
+                .add_pem("-----BEGIN CERTIFICATE-----\nAAAA\n-----END CERTIFICATE-----")
+                .is_err()
+        );
+    }
+}
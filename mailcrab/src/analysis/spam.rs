@@ -0,0 +1,312 @@
+//! A small set of content rules, inspired by SpamAssassin, to estimate how spammy a message looks
+
+use serde::Serialize;
+use url::Url;
+
+use super::html::{Token, tokenize};
+
+/// messages with a score of at least the threshold are considered spam
+pub const SPAM_THRESHOLD: f32 = 5.0;
+
+/// well known URL shorteners, often used to hide the link target
+const URL_SHORTENERS: &[&str] = &[
+    "bit.ly",
+    "buff.ly",
+    "cutt.ly",
+    "goo.gl",
+    "is.gd",
+    "ow.ly",
+    "rb.gy",
+    "rebrand.ly",
+    "shorturl.at",
+    "t.co",
+    "tiny.cc",
+    "tinyurl.com",
+];
+
+/// minimum amount of visible text per image in a HTML body
+const TEXT_PER_IMAGE: usize = 400;
+
+/// a triggered rule
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SpamRule {
+    pub name: &'static str,
+    pub score: f32,
+    pub description: String,
+}
+
+#[derive(Clone, Debug, PartialEq, Serialize)]
+pub struct SpamReport {
+    /// the sum of the scores of the triggered rules
+    pub score: f32,
+    pub threshold: f32,
+    pub rules: Vec<SpamRule>,
+}
+
+/// the parts of a message the rules are applied to
+pub struct SpamInput<'a> {
+    pub header_names: Vec<&'a str>,
+    pub subject: &'a str,
+    pub html: &'a str,
+    pub text: &'a str,
+}
+
+/// host of a URL, without a "www." prefix
+fn host(url: &str) -> Option<String> {
+    let host = Url::parse(url).ok()?.host_str()?.to_ascii_lowercase();
+
+    Some(host.trim_start_matches("www.").to_owned())
+}
+
+/// the host of link text that looks like a URL or domain name, e.g. "www.example.com"
+fn text_host(text: &str) -> Option<String> {
+    let text = text.trim();
+
+    if text.starts_with("http://") || text.starts_with("https://") {
+        return host(text);
+    }
+
+    let domain = text.split('/').next().unwrap_or_default();
+    let tld = domain.rsplit('.').next().unwrap_or_default();
+
+    if domain.contains('.')
+        && !domain.contains(char::is_whitespace)
+        && !domain.contains('@')
+        && tld.len() >= 2
+        && tld.chars().all(|c| c.is_ascii_alphabetic())
+    {
+        return host(&format!("http://{text}"));
+    }
+
+    None
+}
+
+/// an all caps subject with at least a few words
+fn is_all_caps(subject: &str) -> bool {
+    let letters = subject.chars().filter(|c| c.is_alphabetic()).count();
+    let words = subject
+        .split_whitespace()
+        .filter(|word| word.chars().any(char::is_alphabetic))
+        .count();
+
+    letters >= 10 && words >= 2 && !subject.chars().any(char::is_lowercase)
+}
+
+struct HtmlStatistics {
+    images: usize,
+    /// number of visible characters
+    text_length: usize,
+    links: Vec<String>,
+    /// (link text, href) pairs of links where the text is a different domain than the target
+    mismatched: Vec<(String, String)>,
+}
+
+fn html_statistics(html: &str) -> HtmlStatistics {
+    let mut statistics = HtmlStatistics {
+        images: 0,
+        text_length: 0,
+        links: Vec::new(),
+        mismatched: Vec::new(),
+    };
+    // the href and text of the current link
+    let mut link: Option<(String, String)> = None;
+
+    for token in tokenize(html) {
+        match token {
+            Token::StartTag {
+                name, attributes, ..
+            } => {
+                let href = attributes
+                    .iter()
+                    .find(|attribute| attribute.name == "href" || attribute.name == "src")
+                    .map(|attribute| attribute.value.trim().to_owned());
+
+                if let Some(href) = &href {
+                    statistics.links.push(href.clone());
+                }
+
+                match name.as_str() {
+                    "img" => statistics.images += 1,
+                    "a" => link = href.map(|href| (href, String::new())),
+                    _ => {}
+                }
+            }
+            Token::EndTag { name, .. } if name == "a" => {
+                let Some((href, text)) = link.take() else {
+                    continue;
+                };
+
+                if let (Some(text_host), Some(href_host)) = (text_host(&text), host(&href))
+                    && text_host != href_host
+                {
+                    statistics.mismatched.push((text.trim().to_owned(), href));
+                }
+            }
+            Token::Text { text, .. } => {
+                statistics.text_length += text.split_whitespace().map(str::len).sum::<usize>();
+
+                if let Some((_, link_text)) = &mut link {
+                    link_text.push_str(&text);
+                }
+            }
+            _ => {}
+        }
+    }
+
+    statistics
+}
+
+/// URLs in a plain text body
+fn text_links(text: &str) -> impl Iterator<Item = &str> {
+    text.split_whitespace()
+        .filter(|word| word.starts_with("http://") || word.starts_with("https://"))
+}
+
+/// apply all rules to a message
+pub fn spam_report(input: &SpamInput) -> SpamReport {
+    let mut rules = Vec::new();
+    let mut rule = |name: &'static str, score: f32, description: String| {
+        rules.push(SpamRule {
+            name,
+            score,
+            description,
+        });
+    };
+
+    let has_header = |header: &str| {
+        input
+            .header_names
+            .iter()
+            .any(|name| name.eq_ignore_ascii_case(header))
+    };
+
+    if !has_header("Date") {
+        rule("MISSING_DATE", 1.4, "Missing Date header".to_owned());
+    }
+
+    if !has_header("Message-ID") {
+        rule("MISSING_MID", 0.5, "Missing Message-ID header".to_owned());
+    }
+
+    if !has_header("List-Unsubscribe") {
+        rule(
+            "MISSING_LIST_UNSUBSCRIBE",
+            0.5,
+            "Missing List-Unsubscribe header, required by large mailbox providers for bulk mail"
+                .to_owned(),
+        );
+    }
+
+    if is_all_caps(input.subject) {
+        rule("SUBJ_ALL_CAPS", 1.5, "Subject is all capitals".to_owned());
+    }
+
+    let html = html_statistics(input.html);
+
+    if !input.html.is_empty() && input.text.trim().is_empty() {
+        rule(
+            "MIME_HTML_ONLY",
+            1.0,
+            "Message only has a HTML body, without a text alternative".to_owned(),
+        );
+    }
+
+    if html.images > 0 {
+        if html.text_length < 200 {
+            rule(
+                "HTML_IMAGE_ONLY",
+                2.0,
+                format!(
+                    "HTML body has {} images and only {} characters of text",
+                    html.images, html.text_length
+                ),
+            );
+        } else if html.text_length / html.images < TEXT_PER_IMAGE {
+            rule(
+                "HTML_IMAGE_RATIO",
+                1.0,
+                format!(
+                    "HTML body has a low text to image ratio ({} images, {} characters of text)",
+                    html.images, html.text_length
+                ),
+            );
+        }
+    }
+
+    let shortened = html
+        .links
+        .iter()
+        .map(String::as_str)
+        .chain(text_links(input.text))
+        .filter_map(host)
+        .find(|host| URL_SHORTENERS.contains(&host.as_str()));
+
+    if let Some(shortener) = shortened {
+        rule(
+            "URL_SHORTENER",
+            1.5,
+            format!("Links use a URL shortener ({shortener})"),
+        );
+    }
+
+    if let Some((text, href)) = html.mismatched.first() {
+        rule(
+            "LINK_TEXT_MISMATCH",
+            2.0,
+            format!("Link text \"{text}\" points to another domain ({href})"),
+        );
+    }
+
+    let score = (rules.iter().map(|rule| rule.score).sum::<f32>() * 10.0).round() / 10.0;
+
+    SpamReport {
+        score,
+        threshold: SPAM_THRESHOLD,
+        rules,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule_names(report: &SpamReport) -> Vec<&'static str> {
+        report.rules.iter().map(|rule| rule.name).collect()
+    }
+
+    #[test]
+    fn rules() {
+        let html = "<p>Visit <a href=\"https://evil.example/login\">www.mybank.com</a> \
+                    or <a href=\"https://bit.ly/abc\">https://bit.ly/abc</a></p>\
+                    <img src=\"cid:banner\">";
+        let report = spam_report(&SpamInput {
+            header_names: vec!["From", "To", "message-id"],
+            subject: "FREE MONEY, ACT NOW",
+            html,
+            text: "",
+        });
+
+        assert_eq!(
+            rule_names(&report),
+            vec![
+                "MISSING_DATE",
+                "MISSING_LIST_UNSUBSCRIBE",
+                "SUBJ_ALL_CAPS",
+                "MIME_HTML_ONLY",
+                "HTML_IMAGE_ONLY",
+                "URL_SHORTENER",
+                "LINK_TEXT_MISMATCH",
+            ]
+        );
+        assert_eq!(report.score, 9.9);
+
+        let report = spam_report(&SpamInput {
+            header_names: vec!["Date", "Message-ID", "List-Unsubscribe"],
+            subject: "Your invoice for March",
+            html: "",
+            text: "See https://example.com/invoice",
+        });
+
+        assert!(report.rules.is_empty());
+    }
+}
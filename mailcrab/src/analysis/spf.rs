@@ -0,0 +1,415 @@
+//! Sender Policy Framework (RFC 7208) evaluation, records are looked up in local records
+
+use serde::Serialize;
+use std::net::IpAddr;
+
+use super::{authentication::AuthResult, dns::DnsRecords};
+
+/// maximum number of mechanisms and modifiers that cause a DNS lookup
+const LOOKUP_LIMIT: usize = 10;
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct SpfResult {
+    pub result: AuthResult,
+    /// the domain of the envelope sender
+    pub domain: String,
+    pub ip: Option<IpAddr>,
+    /// the matching mechanism, or why the check failed
+    pub reason: Option<String>,
+}
+
+struct Context<'a> {
+    ip: IpAddr,
+    sender: &'a str,
+    dns: &'a DnsRecords,
+    lookups: usize,
+}
+
+type Outcome = (AuthResult, String);
+
+fn perm_error(reason: impl Into<String>) -> Outcome {
+    (AuthResult::PermError, reason.into())
+}
+
+/// is the address in the network with the given prefix length
+fn in_network(ip: IpAddr, network: IpAddr, prefix: u8) -> bool {
+    match (ip, network) {
+        (IpAddr::V4(ip), IpAddr::V4(network)) if prefix <= 32 => {
+            let mask = u32::MAX.checked_shl(32 - prefix as u32).unwrap_or(0);
+            u32::from(ip) & mask == u32::from(network) & mask
+        }
+        (IpAddr::V6(ip), IpAddr::V6(network)) if prefix <= 128 => {
+            let mask = u128::MAX.checked_shl(128 - prefix as u32).unwrap_or(0);
+            u128::from(ip) & mask == u128::from(network) & mask
+        }
+        _ => false,
+    }
+}
+
+/// the characters of an address for the i macro, IPv6 addresses use dot separated nibbles
+fn macro_address(ip: IpAddr) -> String {
+    match ip {
+        IpAddr::V4(ip) => ip.to_string(),
+        IpAddr::V6(ip) => format!("{:032x}", u128::from(ip))
+            .chars()
+            .map(String::from)
+            .collect::<Vec<String>>()
+            .join("."),
+    }
+}
+
+impl Context<'_> {
+    fn lookup(&mut self) -> Result<(), Outcome> {
+        self.lookups += 1;
+
+        match self.lookups > LOOKUP_LIMIT {
+            true => Err(perm_error(format!(
+                "more than {LOOKUP_LIMIT} DNS lookups are needed"
+            ))),
+            false => Ok(()),
+        }
+    }
+
+    /// expand the macros in a domain spec (RFC 7208 section 7)
+    fn expand(&self, spec: &str, domain: &str) -> Result<String, Outcome> {
+        let invalid = || perm_error(format!("invalid macro in {spec}"));
+        let (local, sender_domain) = self
+            .sender
+            .rsplit_once('@')
+            .unwrap_or(("postmaster", self.sender));
+        let mut expanded = String::new();
+        let mut chars = spec.chars();
+
+        while let Some(c) = chars.next() {
+            if c != '%' {
+                expanded.push(c);
+                continue;
+            }
+
+            match chars.next().ok_or_else(invalid)? {
+                '%' => expanded.push('%'),
+                '_' => expanded.push(' '),
+                '-' => expanded.push_str("%20"),
+                '{' => {
+                    let body = chars.by_ref().take_while(|c| *c != '}').collect::<String>();
+                    let mut body = body.chars();
+                    let value = match body.next().ok_or_else(invalid)?.to_ascii_lowercase() {
+                        's' => self.sender.to_owned(),
+                        'l' => local.to_owned(),
+                        'o' => sender_domain.to_owned(),
+                        'd' => domain.to_owned(),
+                        'i' => macro_address(self.ip),
+                        'v' if self.ip.is_ipv4() => "in-addr".to_owned(),
+                        'v' => "ip6".to_owned(),
+                        // the HELO name and validated domain name are not available
+                        'h' | 'p' => "unknown".to_owned(),
+                        _ => return Err(invalid()),
+                    };
+
+                    let transformers = body.collect::<String>();
+                    let digits = transformers
+                        .chars()
+                        .take_while(char::is_ascii_digit)
+                        .collect::<String>();
+                    let rest = &transformers[digits.len()..];
+                    let reverse = rest.starts_with(['r', 'R']);
+                    let delimiters = rest.trim_start_matches(['r', 'R']);
+                    let delimiters = if delimiters.is_empty() {
+                        "."
+                    } else {
+                        delimiters
+                    };
+
+                    let mut parts = value
+                        .split(|c| delimiters.contains(c))
+                        .collect::<Vec<&str>>();
+                    if reverse {
+                        parts.reverse();
+                    }
+                    if let Ok(keep) = digits.parse::<usize>() {
+                        parts = parts.split_off(parts.len().saturating_sub(keep));
+                    }
+
+                    expanded.push_str(&parts.join("."));
+                }
+                _ => return Err(invalid()),
+            }
+        }
+
+        Ok(expanded)
+    }
+
+    /// the target domain and prefix lengths of an a or mx mechanism, e.g. "a:example.com/24//64"
+    fn target(&self, argument: &str, domain: &str) -> Result<(String, u8, u8), Outcome> {
+        let (target, cidr6) = argument.split_once("//").unwrap_or((argument, "128"));
+        let (target, cidr4) = target.split_once('/').unwrap_or((target, "32"));
+        let prefix = |value: &str, max: u8| {
+            value
+                .parse::<u8>()
+                .ok()
+                .filter(|prefix| *prefix <= max)
+                .ok_or_else(|| perm_error(format!("invalid prefix length {value}")))
+        };
+
+        let target = match target.strip_prefix(':') {
+            Some(target) => self.expand(target, domain)?,
+            None if target.is_empty() => domain.to_owned(),
+            None => return Err(perm_error(format!("invalid domain spec {target}"))),
+        };
+
+        Ok((target, prefix(cidr4, 32)?, prefix(cidr6, 128)?))
+    }
+
+    fn matches_address(&self, name: &str, cidr4: u8, cidr6: u8) -> bool {
+        self.dns.addresses(name).iter().any(|address| {
+            let prefix = if address.is_ipv4() { cidr4 } else { cidr6 };
+            in_network(self.ip, *address, prefix)
+        })
+    }
+
+    /// evaluate a single mechanism, returns whether it matches
+    fn mechanism(&mut self, mechanism: &str, domain: &str) -> Result<bool, Outcome> {
+        let name_end = mechanism.find([':', '/']).unwrap_or(mechanism.len());
+        let (name, argument) = mechanism.split_at(name_end);
+
+        match name.to_ascii_lowercase().as_str() {
+            "all" => Ok(true),
+            "include" => {
+                self.lookup()?;
+                let target = self.expand(argument.trim_start_matches(':'), domain)?;
+
+                match self.check_host(&target)? {
+                    (AuthResult::Pass, _) => Ok(true),
+                    (AuthResult::None, _) => Err(perm_error(format!(
+                        "included domain {target} has no SPF record"
+                    ))),
+                    (AuthResult::TempError | AuthResult::PermError, reason) => {
+                        Err(perm_error(format!("include:{target}: {reason}")))
+                    }
+                    _ => Ok(false),
+                }
+            }
+            "a" => {
+                self.lookup()?;
+                let (target, cidr4, cidr6) = self.target(argument, domain)?;
+
+                Ok(self.matches_address(&target, cidr4, cidr6))
+            }
+            "mx" => {
+                self.lookup()?;
+                let (target, cidr4, cidr6) = self.target(argument, domain)?;
+
+                Ok(self
+                    .dns
+                    .mx(&target)
+                    .into_iter()
+                    .take(10)
+                    .any(|exchange| self.matches_address(exchange, cidr4, cidr6)))
+            }
+            "ip4" | "ip6" => {
+                let argument = argument.trim_start_matches(':');
+                let (network, prefix) = argument.split_once('/').unwrap_or((argument, ""));
+                let network = network
+                    .parse::<IpAddr>()
+                    .map_err(|_| perm_error(format!("invalid network {argument}")))?;
+                let max = if network.is_ipv4() { 32 } else { 128 };
+                let prefix = match prefix {
+                    "" => max,
+                    prefix => prefix
+                        .parse::<u8>()
+                        .ok()
+                        .filter(|prefix| *prefix <= max)
+                        .ok_or_else(|| perm_error(format!("invalid network {argument}")))?,
+                };
+
+                Ok(in_network(self.ip, network, prefix))
+            }
+            "exists" => {
+                self.lookup()?;
+                let target = self.expand(argument.trim_start_matches(':'), domain)?;
+
+                Ok(self.dns.addresses(&target).iter().any(IpAddr::is_ipv4))
+            }
+            // reverse DNS records are not available, so ptr never matches
+            "ptr" => {
+                self.lookup()?;
+
+                Ok(false)
+            }
+            _ => Err(perm_error(format!("unknown mechanism {mechanism}"))),
+        }
+    }
+
+    /// the check_host() function of RFC 7208
+    fn check_host(&mut self, domain: &str) -> Result<Outcome, Outcome> {
+        let records = self
+            .dns
+            .txt(domain)
+            .iter()
+            .filter(|record| {
+                let record = record.to_ascii_lowercase();
+                record == "v=spf1" || record.starts_with("v=spf1 ")
+            })
+            .collect::<Vec<&String>>();
+
+        let record = match records.as_slice() {
+            [] => return Ok((AuthResult::None, format!("no SPF record for {domain}"))),
+            [record] => record.as_str(),
+            _ => return Err(perm_error(format!("multiple SPF records for {domain}"))),
+        };
+
+        let mut redirect = None;
+
+        for term in record.split_whitespace().skip(1) {
+            // modifiers, e.g. redirect=_spf.example.com
+            if let Some((name, value)) = term.split_once('=')
+                && !name.contains([':', '/'])
+            {
+                if name.eq_ignore_ascii_case("redirect") {
+                    redirect = Some(value);
+                }
+                continue;
+            }
+
+            let (result, mechanism) = match term.chars().next() {
+                Some('+') => (AuthResult::Pass, &term[1..]),
+                Some('-') => (AuthResult::Fail, &term[1..]),
+                Some('~') => (AuthResult::SoftFail, &term[1..]),
+                Some('?') => (AuthResult::Neutral, &term[1..]),
+                _ => (AuthResult::Pass, term),
+            };
+
+            if self.mechanism(mechanism, domain)? {
+                return Ok((result, format!("{} matched {term} of {domain}", self.ip)));
+            }
+        }
+
+        if let Some(redirect) = redirect {
+            self.lookup()?;
+            let target = self.expand(redirect, domain)?;
+
+            return match self.check_host(&target)? {
+                (AuthResult::None, _) => Err(perm_error(format!(
+                    "redirected domain {target} has no SPF record"
+                ))),
+                outcome => Ok(outcome),
+            };
+        }
+
+        Ok((
+            AuthResult::Neutral,
+            format!("no mechanism of {domain} matched {}", self.ip),
+        ))
+    }
+}
+
+/// evaluate the SPF record of the envelope sender domain for the address of the SMTP client
+pub fn check_spf(ip: Option<IpAddr>, sender: &str, dns: &DnsRecords) -> SpfResult {
+    let domain = sender
+        .rsplit_once('@')
+        .map(|(_, domain)| domain)
+        .unwrap_or(sender)
+        .to_ascii_lowercase();
+
+    let (result, reason) = match ip {
+        _ if domain.is_empty() => (AuthResult::None, "the envelope sender is empty".to_owned()),
+        None => (
+            AuthResult::None,
+            "the address of the SMTP client is unknown".to_owned(),
+        ),
+        Some(ip) => {
+            let mut context = Context {
+                ip: ip.to_canonical(),
+                sender,
+                dns,
+                lookups: 0,
+            };
+
+            context
+                .check_host(&domain)
+                .unwrap_or_else(|outcome| outcome)
+        }
+    };
+
+    SpfResult {
+        result,
+        domain,
+        ip,
+        reason: Some(reason),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn dns() -> DnsRecords {
+        let mut dns = DnsRecords::default();
+        dns.add_txt(
+            "example.com",
+            "v=spf1 ip4:192.0.2.0/24 mx include:_spf.example.net ~all",
+        );
+        dns.add_txt("_spf.example.net", "v=spf1 a:relay.example.net/28 -all");
+        dns.add_address("relay.example.net", "198.51.100.1".parse().unwrap());
+        dns.add_mx("example.com", 10, "mail.example.com");
+        dns.add_address("mail.example.com", "2001:db8::25".parse().unwrap());
+        dns.add_txt("redirect.example.org", "v=spf1 redirect=example.com");
+        dns.add_txt(
+            "macro.example.org",
+            "v=spf1 exists:%{ir}.%{l1r-}.allow.%{d} -all",
+        );
+        dns.add_address(
+            "2.2.0.192.sales.allow.macro.example.org",
+            "127.0.0.2".parse().unwrap(),
+        );
+        dns.add_txt("loop.example.org", "v=spf1 include:loop.example.org");
+
+        dns
+    }
+
+    fn result(ip: &str, sender: &str) -> AuthResult {
+        check_spf(Some(ip.parse().unwrap()), sender, &dns()).result
+    }
+
+    #[test]
+    fn mechanisms() {
+        assert_eq!(result("192.0.2.55", "joe@example.com"), AuthResult::Pass);
+        assert_eq!(
+            result("::ffff:192.0.2.55", "joe@example.com"),
+            AuthResult::Pass
+        );
+        assert_eq!(result("2001:db8::25", "joe@EXAMPLE.com"), AuthResult::Pass);
+        assert_eq!(result("198.51.100.14", "joe@example.com"), AuthResult::Pass);
+        assert_eq!(
+            result("198.51.100.16", "joe@example.com"),
+            AuthResult::SoftFail
+        );
+        assert_eq!(
+            result("203.0.113.1", "joe@redirect.example.org"),
+            AuthResult::SoftFail
+        );
+        assert_eq!(
+            result("192.0.2.2", "sales-team@macro.example.org"),
+            AuthResult::Pass
+        );
+        assert_eq!(
+            result("192.0.2.3", "sales-team@macro.example.org"),
+            AuthResult::Fail
+        );
+        assert_eq!(result("192.0.2.1", "joe@other.example"), AuthResult::None);
+        assert_eq!(
+            result("192.0.2.1", "joe@loop.example.org"),
+            AuthResult::PermError
+        );
+
+        let spf = check_spf(
+            Some("192.0.2.55".parse().unwrap()),
+            "joe@example.com",
+            &dns(),
+        );
+        assert_eq!(
+            spf.reason.as_deref(),
+            Some("192.0.2.55 matched ip4:192.0.2.0/24 of example.com")
+        );
+    }
+}
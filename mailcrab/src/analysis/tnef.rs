@@ -0,0 +1,468 @@
+//! Transport Neutral Encapsulation Format (MS-OXTNEF), the `winmail.dat` attachments sent by
+//! Outlook and Exchange, with compressed RTF bodies (MS-OXRTFCP)
+
+use mail_parser::decoders::charsets::map::charset_decoder;
+
+const SIGNATURE: u32 = 0x223e_9f78;
+
+/// attribute levels
+const LEVEL_MESSAGE: u8 = 1;
+const LEVEL_ATTACHMENT: u8 = 2;
+
+/// attribute ids, without the type in the upper 16 bits
+const ATT_BODY: u32 = 0x800c;
+const ATT_ATTACH_DATA: u32 = 0x800f;
+const ATT_ATTACH_TITLE: u32 = 0x8010;
+const ATT_ATTACH_REND_DATA: u32 = 0x9002;
+const ATT_MSG_PROPS: u32 = 0x9003;
+const ATT_ATTACHMENT: u32 = 0x9005;
+const ATT_OEM_CODEPAGE: u32 = 0x9007;
+
+/// MAPI property ids
+const PR_BODY: u16 = 0x1000;
+const PR_RTF_COMPRESSED: u16 = 0x1009;
+const PR_HTML: u16 = 0x1013;
+const PR_ATTACH_DATA: u16 = 0x3701;
+const PR_ATTACH_FILENAME: u16 = 0x3704;
+const PR_ATTACH_LONG_FILENAME: u16 = 0x3707;
+const PR_ATTACH_MIME_TAG: u16 = 0x370e;
+const PR_ATTACH_CONTENT_ID: u16 = 0x3712;
+
+/// MAPI property types
+const PT_STRING8: u16 = 0x001e;
+const PT_UNICODE: u16 = 0x001f;
+const PT_OBJECT: u16 = 0x000d;
+const PT_BINARY: u16 = 0x0102;
+const MV_FLAG: u16 = 0x1000;
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TnefAttachment {
+    pub filename: String,
+    pub mime: Option<String>,
+    pub content_id: Option<String>,
+    pub content: Vec<u8>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct TnefContent {
+    pub text: Option<String>,
+    pub html: Option<String>,
+    /// the decompressed RTF body
+    pub rtf: Option<String>,
+    pub attachments: Vec<TnefAttachment>,
+}
+
+/// little-endian reader
+struct Reader<'a>(&'a [u8]);
+
+impl<'a> Reader<'a> {
+    fn take(&mut self, length: usize) -> Option<&'a [u8]> {
+        let (value, rest) = self.0.split_at_checked(length)?;
+        self.0 = rest;
+
+        Some(value)
+    }
+
+    fn u16(&mut self) -> Option<u16> {
+        self.take(2)
+            .map(|bytes| u16::from_le_bytes([bytes[0], bytes[1]]))
+    }
+
+    fn u32(&mut self) -> Option<u32> {
+        self.take(4)
+            .map(|bytes| u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+    }
+
+    /// a value padded to a multiple of 4 bytes
+    fn padded(&mut self, length: usize) -> Option<&'a [u8]> {
+        let value = self.take(length)?;
+        self.take(length.next_multiple_of(4) - length)?;
+
+        Some(value)
+    }
+}
+
+enum Value {
+    Bytes(Vec<u8>),
+    Text(String),
+    Other,
+}
+
+impl Value {
+    fn text(self) -> Option<String> {
+        match self {
+            Value::Text(text) => Some(text),
+            Value::Bytes(bytes) => Some(String::from_utf8_lossy(&bytes).into_owned()),
+            Value::Other => None,
+        }
+    }
+}
+
+/// 8-bit strings are encoded with the code page of the message, without a terminating zero
+fn string8(bytes: &[u8], codepage: u32) -> String {
+    let bytes = bytes.strip_suffix(&[0]).unwrap_or(bytes);
+
+    match charset_decoder(format!("windows-{codepage}").as_bytes()) {
+        Some(decode) if codepage != 65001 => decode(bytes),
+        _ => String::from_utf8_lossy(bytes).into_owned(),
+    }
+}
+
+fn unicode(bytes: &[u8]) -> String {
+    let units = bytes
+        .chunks_exact(2)
+        .map(|unit| u16::from_le_bytes([unit[0], unit[1]]))
+        .take_while(|unit| *unit != 0)
+        .collect::<Vec<u16>>();
+
+    String::from_utf16_lossy(&units)
+}
+
+/// the size of a fixed length property value
+fn fixed_size(kind: u16) -> Option<usize> {
+    match kind {
+        0x0002 | 0x0003 | 0x0004 | 0x000a | 0x000b => Some(4),
+        0x0005 | 0x0006 | 0x0007 | 0x0014 | 0x0040 => Some(8),
+        0x0048 => Some(16),
+        _ => None,
+    }
+}
+
+/// a list of MAPI properties, by their id, named properties are skipped
+fn mapi_properties(data: &[u8], codepage: u32) -> Option<Vec<(u16, Value)>> {
+    let mut reader = Reader(data);
+    let count = reader.u32()?;
+    let mut properties = Vec::new();
+
+    for _ in 0..count {
+        let kind = reader.u16()?;
+        let id = reader.u16()?;
+
+        if id >= 0x8000 {
+            // the property set GUID and the numeric id or name
+            reader.take(16)?;
+            if reader.u32()? == 0 {
+                reader.u32()?;
+            } else {
+                let length = reader.u32()? as usize;
+                reader.padded(length)?;
+            }
+        }
+
+        let multiple = kind & MV_FLAG != 0;
+        let kind = kind & !MV_FLAG;
+
+        let value = match (fixed_size(kind), multiple) {
+            (Some(size), false) => {
+                reader.padded(size)?;
+                Value::Other
+            }
+            (Some(size), true) => {
+                let values = reader.u32()? as usize;
+                reader.padded(size.checked_mul(values)?)?;
+                Value::Other
+            }
+            (None, _) if matches!(kind, PT_STRING8 | PT_UNICODE | PT_BINARY | PT_OBJECT) => {
+                // variable length values are always preceded by their count
+                let values = reader.u32()?;
+                let mut value = Value::Other;
+
+                for _ in 0..values {
+                    let length = reader.u32()? as usize;
+                    let bytes = reader.padded(length)?;
+
+                    if matches!(value, Value::Other) {
+                        value = match kind {
+                            PT_STRING8 => Value::Text(string8(bytes, codepage)),
+                            PT_UNICODE => Value::Text(unicode(bytes)),
+                            // embedded objects start with the interface identifier
+                            PT_OBJECT => Value::Bytes(bytes.get(16..)?.to_vec()),
+                            _ => Value::Bytes(bytes.to_vec()),
+                        };
+                    }
+                }
+
+                value
+            }
+            // unknown property types have an unknown size
+            _ => return Some(properties),
+        };
+
+        properties.push((id, value));
+    }
+
+    Some(properties)
+}
+
+/// the prefilled dictionary of compressed RTF
+const RTF_PREFIX: &[u8] = b"{\\rtf1\\ansi\\mac\\deff0\\deftab720{\\fonttbl;}{\\f0\\fnil \\froman \
+    \\fswiss \\fmodern \\fscript \\fdecor MS Sans SerifSymbolArialTimes New RomanCourier\
+    {\\colortbl\\red0\\green0\\blue0\r\n\\par \\pard\\plain\\f0\\fs20\\b\\i\\u\\tab\\tx";
+
+const LZFU: u32 = 0x7546_5a4c;
+const MELA: u32 = 0x414c_454d;
+
+/// decompress an RTF body (MS-OXRTFCP)
+fn decompress_rtf(data: &[u8]) -> Option<Vec<u8>> {
+    let mut reader = Reader(data);
+    let _compressed_size = reader.u32()?;
+    let raw_size = reader.u32()? as usize;
+    let compression = reader.u32()?;
+    let _crc = reader.u32()?;
+    let data = reader.0;
+
+    if compression == MELA {
+        return data.get(..raw_size).map(<[u8]>::to_vec);
+    }
+
+    if compression != LZFU {
+        return None;
+    }
+
+    let mut dictionary = [0u8; 4096];
+    dictionary[..RTF_PREFIX.len()].copy_from_slice(RTF_PREFIX);
+    let mut write = RTF_PREFIX.len();
+    let mut output = Vec::new();
+    let mut input = data.iter().copied();
+
+    'outer: while let Some(control) = input.next() {
+        for bit in 0..8 {
+            if control & (1 << bit) == 0 {
+                let Some(byte) = input.next() else {
+                    break 'outer;
+                };
+                output.push(byte);
+                dictionary[write] = byte;
+                write = (write + 1) % dictionary.len();
+                continue;
+            }
+
+            let (Some(high), Some(low)) = (input.next(), input.next()) else {
+                break 'outer;
+            };
+            let token = u16::from_be_bytes([high, low]) as usize;
+            let offset = token >> 4;
+            let length = (token & 0xf) + 2;
+
+            // a reference to the write position marks the end
+            if offset == write {
+                break 'outer;
+            }
+
+            for index in 0..length {
+                let byte = dictionary[(offset + index) % dictionary.len()];
+                output.push(byte);
+                dictionary[write] = byte;
+                write = (write + 1) % dictionary.len();
+            }
+        }
+    }
+
+    output.truncate(raw_size);
+
+    Some(output)
+}
+
+/// the attachments and bodies of a TNEF stream, none if it is not a TNEF stream
+pub fn parse_tnef(data: &[u8]) -> Option<TnefContent> {
+    let mut reader = Reader(data);
+
+    if reader.u32()? != SIGNATURE {
+        return None;
+    }
+    reader.u16()?;
+
+    let mut content = TnefContent::default();
+    let mut codepage = 1252;
+
+    // attributes until the end of the stream, a truncated stream keeps what was found
+    while let (Some(level), Some(id), Some(length)) = (
+        reader.take(1).map(|level| level[0]),
+        reader.u32(),
+        reader.u32(),
+    ) {
+        let Some(value) = reader.take(length as usize) else {
+            break;
+        };
+        reader.u16();
+
+        match (level, id & 0xffff) {
+            (_, ATT_OEM_CODEPAGE) => {
+                codepage = Reader(value).u32().unwrap_or(codepage);
+            }
+            (LEVEL_MESSAGE, ATT_BODY) => {
+                content.text = Some(string8(value, codepage));
+            }
+            (LEVEL_MESSAGE, ATT_MSG_PROPS) => {
+                for (id, value) in mapi_properties(value, codepage).unwrap_or_default() {
+                    match (id, value) {
+                        (PR_BODY, value) if content.text.is_none() => content.text = value.text(),
+                        (PR_HTML, value) => content.html = value.text(),
+                        (PR_RTF_COMPRESSED, Value::Bytes(bytes)) => {
+                            content.rtf = decompress_rtf(&bytes)
+                                .map(|rtf| String::from_utf8_lossy(&rtf).into_owned());
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            (LEVEL_ATTACHMENT, ATT_ATTACH_REND_DATA) => {
+                content.attachments.push(TnefAttachment::default());
+            }
+            (LEVEL_ATTACHMENT, ATT_ATTACH_TITLE) => {
+                if let Some(attachment) = content.attachments.last_mut() {
+                    attachment.filename = string8(value, codepage);
+                }
+            }
+            (LEVEL_ATTACHMENT, ATT_ATTACH_DATA) => {
+                if let Some(attachment) = content.attachments.last_mut() {
+                    attachment.content = value.to_vec();
+                }
+            }
+            (LEVEL_ATTACHMENT, ATT_ATTACHMENT) => {
+                let Some(attachment) = content.attachments.last_mut() else {
+                    continue;
+                };
+
+                for (id, value) in mapi_properties(value, codepage).unwrap_or_default() {
+                    match (id, value) {
+                        (PR_ATTACH_LONG_FILENAME, value) => {
+                            attachment.filename = value.text().unwrap_or_default()
+                        }
+                        (PR_ATTACH_FILENAME, value) if attachment.filename.is_empty() => {
+                            attachment.filename = value.text().unwrap_or_default()
+                        }
+                        (PR_ATTACH_MIME_TAG, value) => attachment.mime = value.text(),
+                        (PR_ATTACH_CONTENT_ID, value) => attachment.content_id = value.text(),
+                        (PR_ATTACH_DATA, Value::Bytes(bytes)) if attachment.content.is_empty() => {
+                            attachment.content = bytes
+                        }
+                        _ => (),
+                    }
+                }
+            }
+            _ => (),
+        }
+    }
+
+    Some(content)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn attribute(stream: &mut Vec<u8>, level: u8, id: u32, value: &[u8]) {
+        stream.push(level);
+        stream.extend((id | 0x0006_0000).to_le_bytes());
+        stream.extend((value.len() as u32).to_le_bytes());
+        stream.extend(value);
+        let checksum = value
+            .iter()
+            .fold(0u16, |sum, b| sum.wrapping_add(*b as u16));
+        stream.extend(checksum.to_le_bytes());
+    }
+
+    fn property(properties: &mut Vec<u8>, kind: u16, id: u16, value: &[u8]) {
+        properties.extend(kind.to_le_bytes());
+        properties.extend(id.to_le_bytes());
+        properties.extend(1u32.to_le_bytes());
+        properties.extend((value.len() as u32).to_le_bytes());
+        properties.extend(value);
+        properties.resize(properties.len().next_multiple_of(4), 0);
+    }
+
+    /// the example of MS-OXRTFCP section 4.1
+    const COMPRESSED_RTF: [u8; 51] = [
+        0x2d, 0x00, 0x00, 0x00, 0x2b, 0x00, 0x00, 0x00, 0x4c, 0x5a, 0x46, 0x75, 0xf1, 0xc5, 0xc7,
+        0xa7, 0x03, 0x00, 0x0a, 0x00, 0x72, 0x63, 0x70, 0x67, 0x31, 0x32, 0x35, 0x42, 0x32, 0x0a,
+        0xf3, 0x20, 0x68, 0x65, 0x6c, 0x09, 0x00, 0x20, 0x62, 0x77, 0x05, 0xb0, 0x6c, 0x64, 0x7d,
+        0x0a, 0x80, 0x0f, 0xa0, 0x00, 0x00,
+    ];
+
+    #[test]
+    fn rtf() {
+        assert_eq!(RTF_PREFIX.len(), 207);
+        assert_eq!(
+            decompress_rtf(&COMPRESSED_RTF).unwrap(),
+            b"{\\rtf1\\ansi\\ansicpg1252\\pard hello world}\r\n"
+        );
+    }
+
+    #[test]
+    fn winmail() {
+        let mut stream = SIGNATURE.to_le_bytes().to_vec();
+        stream.extend(0x0001u16.to_le_bytes());
+        attribute(
+            &mut stream,
+            LEVEL_MESSAGE,
+            ATT_BODY,
+            b"Hello from Outlook\0",
+        );
+
+        let mut properties = 1u32.to_le_bytes().to_vec();
+        property(
+            &mut properties,
+            PT_BINARY,
+            PR_RTF_COMPRESSED,
+            &COMPRESSED_RTF,
+        );
+        attribute(&mut stream, LEVEL_MESSAGE, ATT_MSG_PROPS, &properties);
+
+        attribute(
+            &mut stream,
+            LEVEL_ATTACHMENT,
+            ATT_ATTACH_REND_DATA,
+            &[0; 14],
+        );
+        attribute(
+            &mut stream,
+            LEVEL_ATTACHMENT,
+            ATT_ATTACH_TITLE,
+            b"REPORT~1.CSV\0",
+        );
+        attribute(
+            &mut stream,
+            LEVEL_ATTACHMENT,
+            ATT_ATTACH_DATA,
+            b"quarter,revenue\n",
+        );
+
+        let long_filename = "Quarterly report.csv\0"
+            .encode_utf16()
+            .flat_map(u16::to_le_bytes)
+            .collect::<Vec<u8>>();
+        let mut properties = 2u32.to_le_bytes().to_vec();
+        property(
+            &mut properties,
+            PT_UNICODE,
+            PR_ATTACH_LONG_FILENAME,
+            &long_filename,
+        );
+        property(
+            &mut properties,
+            PT_STRING8,
+            PR_ATTACH_MIME_TAG,
+            b"text/csv\0",
+        );
+        attribute(&mut stream, LEVEL_ATTACHMENT, ATT_ATTACHMENT, &properties);
+
+        let content = parse_tnef(&stream).unwrap();
+        assert_eq!(content.text.as_deref(), Some("Hello from Outlook"));
+        assert!(content.rtf.unwrap().contains("hello world"));
+        assert_eq!(
+            content.attachments,
+            vec![TnefAttachment {
+                filename: "Quarterly report.csv".to_owned(),
+                mime: Some("text/csv".to_owned()),
+                content_id: None,
+                content: b"quarter,revenue\n".to_vec(),
+            }]
+        );
+    }
+
+    #[test]
+    fn not_tnef() {
+        assert!(parse_tnef(b"PK\x03\x04").is_none());
+    }
+}
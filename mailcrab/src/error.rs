@@ -12,6 +12,10 @@ pub enum Error {
     Time(#[from] std::time::SystemTimeError),
     #[error("mail server error {0}")]
     Smtp(String),
+    #[error("PROXY protocol error {0}")]
+    ProxyProtocol(String),
+    #[error("configuration error {0}")]
+    Config(String),
     #[error("web server error {0}")]
     WebServer(String),
 }
@@ -2,6 +2,7 @@ use std::net::IpAddr;
 use tokio::sync::broadcast::Receiver;
 use tokio_util::sync::CancellationToken;
 
+mod analysis;
 mod error;
 mod smtp;
 mod types;
@@ -10,9 +11,26 @@ mod types;
 /// when compiling without cargo
 pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 
+pub use analysis::{
+    ArcResult, AuthResult, AuthenticationInput, AuthenticationReport, Calendar, CalendarEvent,
+    CalendarParticipant, CalendarTime, Client, ColorScheme, CompatibilityIssue, DkimResult,
+    DmarcResult, DnsRecords, FeatureKind, FrameOptions, Link, LinkIssue, LinkSource, MimePart,
+    OpenPgpKeys, OpenPgpReport, OpenPgpSignature, PartOffsets, Reachability, RemoteContent,
+    RemoteKind, RemoteResource, SPAM_THRESHOLD, SmimeKeys, SmimeReport, SmimeSignature, SpamInput,
+    SpamReport, SpamRule, SpfResult, TnefAttachment, TnefContent, apply_color_scheme, authenticate,
+    block_remote_content, check_dmarc, check_spf, compatibility_report, dkim_record_from_pem,
+    is_internal_address, link_report, mime_structure, parse_calendar, parse_tnef, part_content,
+    prepare_frame, sanitize_html, spam_report, unwrap_openpgp, unwrap_smime, verify_arc,
+    verify_dkim,
+};
 pub use error::{Error, Result};
-pub use smtp::mail_server;
-pub use types::{Action, Address, Attachment, MailMessage, MailMessageMetadata, MessageId};
+pub use smtp::{
+    Bounce, Delay, DelayRule, Latency, Limits, ProxyProtocol, SharedConfig, SmtpConfig,
+    SmtpOptions, SmtpPhase, mail_server, send_mail,
+};
+pub use types::{
+    Action, Address, Attachment, EmbeddedMessage, MailMessage, MailMessageMetadata, MessageId,
+};
 
 pub struct TestMailServerHandle {
     pub token: CancellationToken,
@@ -33,7 +51,7 @@ pub async fn development_mail_server(
         smtp_host.into(),
         smtp_port,
         tx,
-        false,
+        SmtpOptions::default(),
         token.clone(),
     ));
 
@@ -53,6 +71,22 @@ mod tests {
         message::{SinglePart, header},
     };
     use rand::Rng;
+    use tokio::{
+        io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader},
+        net::TcpStream,
+    };
+
+    /// connect to a mail server that is starting
+    async fn connect(port: u16) -> TcpStream {
+        for _ in 0..10 {
+            match TcpStream::connect(("127.0.0.1", port)).await {
+                Ok(stream) => return stream,
+                Err(_) => tokio::time::sleep(Duration::from_millis(100)).await,
+            }
+        }
+
+        panic!("failed to connect");
+    }
 
     #[tokio::test]
     async fn test_mail_server() {
@@ -98,4 +132,126 @@ mod tests {
 
         handle.token.cancel();
     }
+
+    #[tokio::test]
+    async fn test_helo() {
+        let port = rand::rng().random_range(10_000..30_000);
+        let handle = crate::development_mail_server([127, 0, 0, 1], port).await;
+
+        let mut stream = BufReader::new(connect(port).await);
+        let mut line = String::new();
+        stream.read_line(&mut line).await.unwrap();
+
+        // a HELO reply is a single line, only EHLO advertises extensions like DSN
+        stream.write_all(b"HELO example.com\r\n").await.unwrap();
+        line.clear();
+        stream.read_line(&mut line).await.unwrap();
+        assert!(line.starts_with("250 "), "{line}");
+
+        stream.write_all(b"EHLO example.com\r\n").await.unwrap();
+        let mut extensions = Vec::new();
+        loop {
+            line.clear();
+            stream.read_line(&mut line).await.unwrap();
+            extensions.push(line.trim_end().to_owned());
+            if !line.starts_with("250-") {
+                break;
+            }
+        }
+        assert_eq!(extensions.last().map(String::as_str), Some("250 DSN"));
+
+        handle.token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_latency_change() {
+        use crate::{Delay, DelayRule, Limits, SharedConfig, SmtpOptions, SmtpPhase};
+        use tokio_util::sync::CancellationToken;
+
+        let port = rand::rng().random_range(10_000..30_000);
+        let (tx, _rx) = tokio::sync::broadcast::channel(1);
+        let config = SharedConfig::default();
+        let token = CancellationToken::new();
+        tokio::spawn(crate::mail_server(
+            [127, 0, 0, 1].into(),
+            port,
+            tx,
+            SmtpOptions {
+                config: config.clone(),
+                limits: Limits {
+                    command_timeout: Duration::from_secs(1),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            token.clone(),
+        ));
+
+        let mut stream = BufReader::new(connect(port).await);
+        let mut line = String::new();
+        stream.read_line(&mut line).await.unwrap();
+
+        // latency rules apply to running sessions
+        config.write().unwrap().latency.rules.push(DelayRule {
+            phase: SmtpPhase::Ehlo,
+            delay: Delay::Hang,
+            recipient: None,
+        });
+        stream.write_all(b"EHLO example.com\r\n").await.unwrap();
+        line.clear();
+        let reply =
+            tokio::time::timeout(Duration::from_millis(500), stream.read_line(&mut line)).await;
+        assert!(reply.is_err(), "{line}");
+
+        // a hanging session is closed after the idle timeout, without a reply
+        let reply = tokio::time::timeout(Duration::from_secs(2), stream.read_line(&mut line)).await;
+        assert!(matches!(reply, Ok(Ok(0))), "{line}");
+
+        token.cancel();
+    }
+
+    #[tokio::test]
+    async fn test_wrapped_tls_limits() {
+        use crate::{Limits, SmtpOptions};
+        use tokio_util::sync::CancellationToken;
+
+        // the dev dependencies enable more than one crypto provider
+        let _ = tokio_rustls::rustls::crypto::ring::default_provider().install_default();
+
+        let port = rand::rng().random_range(10_000..30_000);
+        let (tx, _rx) = tokio::sync::broadcast::channel(1);
+        let token = CancellationToken::new();
+        tokio::spawn(crate::mail_server(
+            [127, 0, 0, 1].into(),
+            port,
+            tx,
+            SmtpOptions {
+                enable_tls_auth: true,
+                limits: Limits {
+                    max_connections: 1,
+                    command_timeout: Duration::from_millis(300),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            token.clone(),
+        ));
+
+        // a client that never starts the TLS handshake
+        let mut idle = connect(port).await;
+        tokio::time::sleep(Duration::from_millis(100)).await;
+
+        // no plaintext reply is sent to a client that expects a TLS handshake
+        let mut rejected = connect(port).await;
+        let mut reply = Vec::new();
+        let read = tokio::time::timeout(Duration::from_secs(2), rejected.read_to_end(&mut reply));
+        assert!(matches!(read.await, Ok(Ok(0))), "{reply:?}");
+
+        // the handshake is limited by the idle timeout
+        let read = tokio::time::timeout(Duration::from_secs(2), idle.read_to_end(&mut reply));
+        assert!(read.await.is_ok());
+        assert!(reply.is_empty());
+
+        token.cancel();
+    }
 }
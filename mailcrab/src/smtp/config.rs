@@ -0,0 +1,24 @@
+use serde::{Deserialize, Serialize};
+use std::sync::{Arc, RwLock};
+
+use super::{dsn::Bounce, latency::Latency};
+
+/// SMTP behaviour that can be changed while the server is running, every new session uses
+/// the configuration as it is when the client connects, except for the latency rules which
+/// apply to running sessions as well
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct SmtpConfig {
+    /// maximum message size in bytes, 0 means unlimited
+    pub max_message_size: usize,
+    /// only accept messages after AUTH, any credentials are accepted
+    pub require_auth: bool,
+    /// recipients matching one of these patterns are rejected during RCPT,
+    /// `*` matches any sequence of characters
+    pub rejected_recipients: Vec<String>,
+    pub bounce: Bounce,
+    pub latency: Latency,
+}
+
+/// configuration shared between the SMTP server and the HTTP API
+pub type SharedConfig = Arc<RwLock<SmtpConfig>>;
@@ -1,14 +1,24 @@
 use mailin::{Action, Response, Session, SessionBuilder};
+use std::sync::Arc;
 use tokio::{
-    io::{AsyncBufReadExt, AsyncRead, AsyncWrite, AsyncWriteExt, BufReader},
+    io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt, BufReader},
     net::TcpStream,
+    time::Duration,
 };
 use tokio_rustls::{TlsAcceptor, server::TlsStream};
-use tracing::debug;
+use tracing::{debug, warn};
 
 use crate::error::{Error, Result};
 
-use super::{handler::MailHandler, server::TlsConfig};
+use super::{
+    config::SharedConfig,
+    dsn::{SessionDsn, advertise_dsn, strip_dsn_parameters},
+    handler::MailHandler,
+    latency::SmtpPhase,
+    limits::Limiter,
+    proxy::{ProxyConfig, read_proxy_header},
+    server::TlsConfig,
+};
 
 #[derive(Debug, PartialEq)]
 enum SessionResult {
@@ -31,29 +41,233 @@ where
     Ok(())
 }
 
+/// refuse a connection before the session starts, a client of a wrapped TLS listener expects a
+/// TLS handshake, so the connection is closed without a reply
+pub(super) async fn reject_connection(
+    mut socket: TcpStream,
+    wrapped_tls: bool,
+    reason: &str,
+) -> Result<()> {
+    if wrapped_tls {
+        debug!("Closing connection: {reason}");
+
+        return Ok(socket.shutdown().await?);
+    }
+
+    let response = Response::custom(421, format!("4.7.0 {reason}, closing connection"));
+
+    write_response(&mut socket, &response).await
+}
+
+/// read a line from the client, returns None when the client is idle for longer than the timeout
+async fn read_line<S>(
+    stream: &mut BufReader<S>,
+    line: &mut Vec<u8>,
+    timeout: Duration,
+) -> Result<Option<usize>>
+where
+    S: AsyncRead + Unpin,
+{
+    if timeout.is_zero() {
+        return Ok(Some(stream.read_until(b'\n', line).await?));
+    }
+
+    match tokio::time::timeout(timeout, stream.read_until(b'\n', line)).await {
+        Ok(result) => Ok(Some(result?)),
+        Err(_) => Ok(None),
+    }
+}
+
+/// the phase of a client command, for commands that can be delayed
+fn command_phase(line: &[u8]) -> Option<SmtpPhase> {
+    match &line.get(..4)?.to_ascii_uppercase()[..] {
+        b"EHLO" | b"HELO" => Some(SmtpPhase::Ehlo),
+        b"RCPT" => Some(SmtpPhase::Rcpt),
+        _ => None,
+    }
+}
+
+/// extract the address from a "RCPT TO:<address>" command
+fn rcpt_address(line: &[u8]) -> Option<String> {
+    let line = String::from_utf8_lossy(line);
+    let (_, path) = line.split_once(':')?;
+    let path = path.trim();
+
+    let address = match path.strip_prefix('<') {
+        Some(path) => path.split('>').next()?,
+        None => path.split_whitespace().next()?,
+    };
+
+    Some(address.to_owned())
+}
+
+/// wait before sending a response, returns false when the session should hang, the rules are
+/// read for every response so changes apply to running sessions as well
+async fn simulate_latency(config: &SharedConfig, phase: SmtpPhase, recipients: &[String]) -> bool {
+    let delay = match config.read() {
+        Ok(config) => config.latency.delay(phase, recipients),
+        Err(_) => None,
+    };
+
+    match delay.map(|d| d.duration()) {
+        None => true,
+        Some(Some(duration)) => {
+            debug!("Delaying {phase:?} response by {duration:?}");
+            tokio::time::sleep(duration).await;
+
+            true
+        }
+        Some(None) => false,
+    }
+}
+
+/// never answer, discard everything the client sends until it disconnects or is idle for
+/// longer than the timeout, the connection is closed without a response
+async fn hang<S>(stream: &mut BufReader<S>, timeout: Duration) -> Result<SessionResult>
+where
+    S: AsyncRead + Unpin,
+{
+    debug!("Hanging session, no more responses will be sent");
+
+    let mut buf = [0; 1024];
+    loop {
+        let n = match timeout.is_zero() {
+            true => stream.read(&mut buf).await?,
+            false => match tokio::time::timeout(timeout, stream.read(&mut buf)).await {
+                Ok(result) => result?,
+                Err(_) => {
+                    debug!("Idle timeout after {timeout:?} in a hanging session");
+
+                    break;
+                }
+            },
+        };
+
+        if n == 0 {
+            break;
+        }
+    }
+
+    Ok(SessionResult::Finished)
+}
+
 // handle SMTP messages over a stream
 async fn handle_steam<S>(
     mut stream: &mut BufReader<S>,
     session: &mut Session<MailHandler>,
+    limiter: &Limiter,
+    config: &SharedConfig,
+    dsn: &SessionDsn,
 ) -> Result<SessionResult>
 where
     S: AsyncWrite + AsyncRead + Unpin,
 {
     let mut line = Vec::with_capacity(80);
+    let mut in_data = false;
+    // recipients of the current transaction, to match latency rules
+    let mut recipients: Vec<String> = Vec::new();
+
+    if !simulate_latency(config, SmtpPhase::Greeting, &recipients).await {
+        return hang(stream, limiter.limits.command_timeout).await;
+    }
+
     write_response(&mut stream, &session.greeting()).await?;
 
     loop {
         line.clear();
-        let n = match stream.read_until(b'\n', &mut line).await? {
-            0 => break,
-            n => n,
+
+        let timeout = if in_data {
+            limiter.limits.data_timeout
+        } else {
+            limiter.limits.command_timeout
+        };
+
+        let n = match read_line(stream, &mut line, timeout).await? {
+            Some(0) => break,
+            Some(n) => n,
+            None => {
+                debug!("Idle timeout after {timeout:?}");
+
+                let response =
+                    Response::custom(421, "4.4.2 Idle timeout, closing connection".to_owned());
+                write_response(&mut stream, &response).await?;
+
+                return Ok(SessionResult::Finished);
+            }
         };
 
         debug!("Received: {}", String::from_utf8_lossy(&line[0..n]));
 
+        // DSN parameters are handled here, the session does not support them
+        if !in_data && let Some(stripped) = strip_dsn_parameters(&line, dsn) {
+            line = stripped;
+        }
+
         let response = session.process(&line);
 
+        // MAIL starts a new transaction and RSET aborts the current one
+        if !in_data
+            && matches!(
+                line.get(..4)
+                    .map(|verb| verb.to_ascii_uppercase())
+                    .as_deref(),
+                Some(b"MAIL" | b"RSET")
+            )
+        {
+            recipients.clear();
+        }
+
+        // HELO shares the latency of EHLO, but only EHLO replies list extensions
+        let ehlo = !in_data
+            && line
+                .get(..4)
+                .is_some_and(|verb| verb.eq_ignore_ascii_case(b"EHLO"));
+        let phase = if in_data {
+            (response.action != Action::NoReply).then_some(SmtpPhase::DataEnd)
+        } else {
+            command_phase(&line)
+        };
+
+        let delayed = match phase {
+            Some(SmtpPhase::Rcpt) => {
+                let address = rcpt_address(&line).unwrap_or_default();
+                let delayed =
+                    simulate_latency(config, SmtpPhase::Rcpt, std::slice::from_ref(&address)).await;
+                recipients.push(address);
+
+                delayed
+            }
+            Some(phase) => {
+                let delayed = simulate_latency(config, phase, &recipients).await;
+                if phase == SmtpPhase::DataEnd {
+                    recipients.clear();
+                }
+
+                delayed
+            }
+            None => true,
+        };
+
+        if !delayed {
+            // the client is waiting for a response to a command or to the end of the data
+            return hang(stream, limiter.limits.command_timeout).await;
+        }
+
+        // the client sends message data after a 354 reply, until the final reply
         match response.action {
+            Action::NoReply => {}
+            _ => in_data = response.code == 354,
+        }
+
+        match response.action {
+            Action::Reply if ehlo && response.code == 250 => {
+                let buf = advertise_dsn(&response.buffer()?);
+
+                debug!("Sending: {}", String::from_utf8_lossy(&buf));
+
+                stream.write_all(&buf).await?;
+                stream.flush().await?;
+            }
             Action::Reply => {
                 write_response(&mut stream, &response).await?;
             }
@@ -81,42 +295,89 @@ where
     Ok(SessionResult::Finished)
 }
 
-// convert a TCP stream to a TLS stream
+// convert a TCP stream to a TLS stream, the handshake is limited by the idle timeout
 async fn upgrade_connection(
     stream: TcpStream,
     acceptor: &TlsAcceptor,
+    timeout: Duration,
 ) -> Result<BufReader<TlsStream<TcpStream>>> {
-    let accept_buffer = acceptor.accept(stream).await?;
+    let accept_buffer = if timeout.is_zero() {
+        acceptor.accept(stream).await?
+    } else {
+        tokio::time::timeout(timeout, acceptor.accept(stream))
+            .await
+            .map_err(|_| Error::Smtp(format!("TLS handshake timeout after {timeout:?}")))??
+    };
 
     Ok(BufReader::new(accept_buffer))
 }
 
 /// handle SMTP connections, optionally upgrade to TLS, either directly or after negotiation
 pub(super) async fn handle_connection(
-    socket: TcpStream,
+    mut socket: TcpStream,
     session_builder: SessionBuilder,
     tls: TlsConfig,
-    handler: MailHandler,
+    proxy: ProxyConfig,
+    limiter: Arc<Limiter>,
+    config: SharedConfig,
+    mut handler: MailHandler,
 ) -> Result<()> {
-    let peer_addr = socket.peer_addr()?;
+    // behind a proxy the client address is provided by the PROXY protocol header
+    let peer_addr = match read_proxy_header(&mut socket, &proxy).await {
+        Ok(Some(client_addr)) => {
+            debug!(
+                "Connection from {client_addr:?} via proxy {:?}",
+                socket.peer_addr()?
+            );
+
+            client_addr
+        }
+        Ok(None) => socket.peer_addr()?,
+        Err(e) => {
+            warn!("Rejected connection from {:?}: {e}", socket.peer_addr()?);
+
+            return Err(e);
+        }
+    };
+    if !limiter.allow_connection(peer_addr.ip()) {
+        warn!("Connection rate limit exceeded for {}", peer_addr.ip());
+
+        let wrapped_tls = matches!(tls, TlsConfig::Wrapped(_));
+
+        return reject_connection(
+            socket,
+            wrapped_tls,
+            "Too many connections from your address",
+        )
+        .await;
+    }
+
+    // size limits, rejections and bounces are fixed for the session, latency rules are not
+    let snapshot = config
+        .read()
+        .map(|config| config.clone())
+        .unwrap_or_default();
+    let dsn = handler.start_session(Arc::new(snapshot));
+    let timeout = limiter.limits.command_timeout;
     let mut stream: BufReader<TcpStream> = BufReader::new(socket);
     let mut session: Session<MailHandler> = session_builder.build(peer_addr.ip(), handler);
 
     match &tls {
         TlsConfig::None => {
-            handle_steam(&mut stream, &mut session).await?;
+            handle_steam(&mut stream, &mut session, &limiter, &config, &dsn).await?;
         }
         TlsConfig::Wrapped(acceptor) => {
-            let mut stream = upgrade_connection(stream.into_inner(), acceptor).await?;
+            let mut stream = upgrade_connection(stream.into_inner(), acceptor, timeout).await?;
             session.tls_active();
-            handle_steam(&mut stream, &mut session).await?;
+            handle_steam(&mut stream, &mut session, &limiter, &config, &dsn).await?;
         }
         TlsConfig::StartTls(acceptor) => {
-            let session_result = handle_steam(&mut stream, &mut session).await?;
+            let session_result =
+                handle_steam(&mut stream, &mut session, &limiter, &config, &dsn).await?;
             if session_result == SessionResult::UpgradeTls {
-                let mut stream = upgrade_connection(stream.into_inner(), acceptor).await?;
+                let mut stream = upgrade_connection(stream.into_inner(), acceptor, timeout).await?;
                 session.tls_active();
-                handle_steam(&mut stream, &mut session).await?;
+                handle_steam(&mut stream, &mut session, &limiter, &config, &dsn).await?;
             }
         }
     }
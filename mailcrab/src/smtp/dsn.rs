@@ -0,0 +1,385 @@
+use chrono::Local;
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::HashMap,
+    sync::{Arc, Mutex},
+};
+use uuid::Uuid;
+
+use super::pattern::matches_pattern;
+
+const REPORTING_MTA: &str = "mailcrab";
+
+/// delivery status notification settings
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Bounce {
+    /// messages to recipients matching one of these patterns are accepted,
+    /// after which a failure notification is sent to the envelope sender
+    pub recipients: Vec<String>,
+    /// deliver notifications to this SMTP server ("host:port"), instead of storing them in MailCrab
+    pub relay: Option<String>,
+}
+
+impl Bounce {
+    fn is_bouncing(&self, recipient: &str) -> bool {
+        self.recipients
+            .iter()
+            .any(|pattern| matches_pattern(pattern, recipient))
+    }
+}
+
+/// RET parameter of the MAIL command
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(super) enum DsnReturn {
+    Full,
+    Headers,
+}
+
+/// NOTIFY and ORCPT parameters of a RCPT command
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(super) struct DsnRecipient {
+    notify: Option<Vec<String>>,
+    original_recipient: Option<String>,
+}
+
+impl DsnRecipient {
+    fn notify(&self, condition: &str) -> bool {
+        match &self.notify {
+            Some(conditions) => conditions.iter().any(|c| c == condition),
+            // without NOTIFY only failures are reported
+            None => condition == "FAILURE",
+        }
+    }
+}
+
+/// DSN parameters of the current SMTP transaction
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub(super) struct DsnEnvelope {
+    ret: Option<DsnReturn>,
+    envelope_id: Option<String>,
+    recipients: HashMap<String, DsnRecipient>,
+}
+
+/// DSN parameters shared between the connection, which parses the commands,
+/// and the handler, which creates the notifications
+pub(super) type SessionDsn = Arc<Mutex<DsnEnvelope>>;
+
+/// decode an xtext value (RFC 3461, section 4), e.g. "rfc822;a+2Bb@example.com"
+fn decode_xtext(value: &str) -> String {
+    let mut decoded = Vec::with_capacity(value.len());
+    let mut bytes = value.bytes();
+
+    while let Some(b) = bytes.next() {
+        if b == b'+' {
+            let hex: Vec<u8> = bytes.by_ref().take(2).collect();
+            match std::str::from_utf8(&hex)
+                .ok()
+                .and_then(|h| u8::from_str_radix(h, 16).ok())
+            {
+                Some(c) => decoded.push(c),
+                None => decoded.extend_from_slice(&hex),
+            }
+        } else {
+            decoded.push(b);
+        }
+    }
+
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// remove the DSN parameters from a MAIL or RCPT command, since the SMTP session does not
+/// accept them, and record them for the current transaction
+pub(super) fn strip_dsn_parameters(line: &[u8], dsn: &SessionDsn) -> Option<Vec<u8>> {
+    let verb = line.get(..4)?.to_ascii_uppercase();
+    if verb != b"MAIL" && verb != b"RCPT" && verb != b"RSET" {
+        return None;
+    }
+
+    let Ok(mut envelope) = dsn.lock() else {
+        return None;
+    };
+
+    // a new transaction starts with MAIL, RSET aborts the current one
+    if verb != b"RCPT" {
+        *envelope = DsnEnvelope::default();
+    }
+
+    let line = std::str::from_utf8(line)
+        .ok()?
+        .trim_end_matches(['\r', '\n']);
+    let (command, parameters) = line.split_once('>')?;
+    let address = command.rsplit('<').next().unwrap_or_default().to_owned();
+
+    let mut kept = vec![format!("{command}>")];
+    let mut recipient = DsnRecipient::default();
+
+    for parameter in parameters.split_whitespace() {
+        let (key, value) = parameter.split_once('=').unwrap_or((parameter, ""));
+
+        match (&verb[..], key.to_ascii_uppercase().as_str()) {
+            (b"MAIL", "RET") => {
+                envelope.ret = match value.to_ascii_uppercase().as_str() {
+                    "FULL" => Some(DsnReturn::Full),
+                    "HDRS" => Some(DsnReturn::Headers),
+                    _ => None,
+                }
+            }
+            (b"MAIL", "ENVID") => envelope.envelope_id = Some(decode_xtext(value)),
+            (b"RCPT", "NOTIFY") => {
+                recipient.notify = Some(
+                    value
+                        .split(',')
+                        .map(|c| c.trim().to_ascii_uppercase())
+                        .collect(),
+                )
+            }
+            (b"RCPT", "ORCPT") => recipient.original_recipient = Some(decode_xtext(value)),
+            _ => kept.push(parameter.to_owned()),
+        }
+    }
+
+    if verb == b"RCPT" {
+        envelope.recipients.insert(address, recipient);
+    }
+
+    Some(format!("{}\r\n", kept.join(" ")).into_bytes())
+}
+
+/// add the DSN extension to an EHLO response
+pub(super) fn advertise_dsn(response: &[u8]) -> Vec<u8> {
+    let text = String::from_utf8_lossy(response);
+
+    // every line becomes a continuation line, DSN is the last line
+    let mut result: String = text
+        .split_terminator("\r\n")
+        .map(|line| format!("250-{}\r\n", line.get(4..).unwrap_or_default()))
+        .collect();
+    result.push_str("250 DSN\r\n");
+
+    result.into_bytes()
+}
+
+/// the header section of a message, for RET=HDRS
+fn headers(message: &[u8]) -> &[u8] {
+    message
+        .windows(4)
+        .position(|w| w == b"\r\n\r\n")
+        .map(|index| &message[..index + 2])
+        .unwrap_or(message)
+}
+
+/// create a RFC 3464 delivery status notification for a received message, returns
+/// None when no notification was requested
+pub(super) fn delivery_status_notification(
+    bounce: &Bounce,
+    envelope: &DsnEnvelope,
+    envelope_from: &str,
+    recipients: &[String],
+    original: &[u8],
+) -> Option<Vec<u8>> {
+    // never send a notification for a notification (null reverse-path)
+    if envelope_from.is_empty() {
+        return None;
+    }
+
+    let mut failed = vec![];
+    let mut delivered = vec![];
+
+    for recipient in recipients {
+        let parameters = envelope
+            .recipients
+            .get(recipient)
+            .cloned()
+            .unwrap_or_default();
+
+        if bounce.is_bouncing(recipient) {
+            if parameters.notify("FAILURE") {
+                failed.push((recipient, parameters));
+            }
+        } else if parameters.notify("SUCCESS") {
+            delivered.push((recipient, parameters));
+        }
+    }
+
+    if failed.is_empty() && delivered.is_empty() {
+        return None;
+    }
+
+    let boundary = format!("mailcrab-dsn-{}", Uuid::new_v4().simple());
+    let now = Local::now().to_rfc2822();
+    let subject = if failed.is_empty() {
+        "Successful Mail Delivery Report"
+    } else {
+        "Undelivered Mail Returned to Sender"
+    };
+
+    let mut human = String::new();
+    if !failed.is_empty() {
+        human.push_str("Your message could not be delivered to the following recipients:\r\n\r\n");
+        for (recipient, _) in &failed {
+            human.push_str(&format!("    {recipient}: mailbox unavailable\r\n"));
+        }
+        human.push_str("\r\n");
+    }
+    if !delivered.is_empty() {
+        human.push_str("Your message was delivered to the following recipients:\r\n\r\n");
+        for (recipient, _) in &delivered {
+            human.push_str(&format!("    {recipient}\r\n"));
+        }
+        human.push_str("\r\n");
+    }
+    human.push_str("This notification was generated by MailCrab.\r\n");
+
+    // per-message fields, followed by a block of per-recipient fields for each recipient
+    let mut status = format!("Reporting-MTA: dns; {REPORTING_MTA}\r\n");
+    if let Some(envelope_id) = &envelope.envelope_id {
+        status.push_str(&format!("Original-Envelope-Id: {envelope_id}\r\n"));
+    }
+    status.push_str(&format!("Arrival-Date: {now}\r\n"));
+
+    let entries = failed
+        .iter()
+        .map(|entry| (entry, "failed", "5.1.1", "550 5.1.1 Mailbox unavailable"))
+        .chain(
+            delivered
+                .iter()
+                .map(|entry| (entry, "delivered", "2.0.0", "250 2.0.0 Ok")),
+        );
+
+    for ((recipient, parameters), action, code, diagnostic) in entries {
+        status.push_str("\r\n");
+        if let Some(original_recipient) = &parameters.original_recipient {
+            status.push_str(&format!("Original-Recipient: {original_recipient}\r\n"));
+        }
+        status.push_str(&format!("Final-Recipient: rfc822; {recipient}\r\n"));
+        status.push_str(&format!("Action: {action}\r\n"));
+        status.push_str(&format!("Status: {code}\r\n"));
+        status.push_str(&format!("Diagnostic-Code: smtp; {diagnostic}\r\n"));
+    }
+
+    let returned = match envelope.ret {
+        Some(DsnReturn::Full) => ("message/rfc822", original),
+        _ => ("text/rfc822-headers", headers(original)),
+    };
+
+    let mut dsn = format!(
+        "From: Mail Delivery System <MAILER-DAEMON@{REPORTING_MTA}>\r\n\
+         To: <{envelope_from}>\r\n\
+         Subject: {subject}\r\n\
+         Date: {now}\r\n\
+         Message-ID: <{}@{REPORTING_MTA}>\r\n\
+         Auto-Submitted: auto-replied\r\n\
+         MIME-Version: 1.0\r\n\
+         Content-Type: multipart/report; report-type=delivery-status;\r\n\
+         \tboundary=\"{boundary}\"\r\n\
+         \r\n\
+         --{boundary}\r\n\
+         Content-Type: text/plain; charset=utf-8\r\n\
+         \r\n\
+         {human}\
+         \r\n\
+         --{boundary}\r\n\
+         Content-Type: message/delivery-status\r\n\
+         \r\n\
+         {status}\
+         \r\n\
+         --{boundary}\r\n\
+         Content-Type: {}\r\n\
+         \r\n",
+        Uuid::new_v4(),
+        returned.0,
+    )
+    .into_bytes();
+
+    dsn.extend_from_slice(returned.1);
+    if !dsn.ends_with(b"\r\n") {
+        dsn.extend_from_slice(b"\r\n");
+    }
+    dsn.extend_from_slice(format!("\r\n--{boundary}--\r\n").as_bytes());
+
+    Some(dsn)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dsn_parameters() {
+        let dsn = SessionDsn::default();
+
+        let line = strip_dsn_parameters(
+            b"MAIL FROM:<sender@example.com> RET=HDRS ENVID=QQ314159 BODY=8BITMIME\r\n",
+            &dsn,
+        );
+        assert_eq!(
+            line.as_deref(),
+            Some(&b"MAIL FROM:<sender@example.com> BODY=8BITMIME\r\n"[..])
+        );
+
+        let line = strip_dsn_parameters(
+            b"RCPT TO:<a@bounce.test> NOTIFY=SUCCESS,FAILURE ORCPT=rfc822;a+2Bx@bounce.test\r\n",
+            &dsn,
+        );
+        assert_eq!(line.as_deref(), Some(&b"RCPT TO:<a@bounce.test>\r\n"[..]));
+
+        let envelope = dsn.lock().unwrap().clone();
+        assert_eq!(envelope.ret, Some(DsnReturn::Headers));
+        assert_eq!(envelope.envelope_id.as_deref(), Some("QQ314159"));
+        assert_eq!(
+            envelope.recipients["a@bounce.test"]
+                .original_recipient
+                .as_deref(),
+            Some("rfc822;a+x@bounce.test")
+        );
+    }
+
+    #[test]
+    fn failure_notification() {
+        let bounce = Bounce {
+            recipients: vec!["*@bounce.test".to_owned()],
+            relay: None,
+        };
+        let original = b"Subject: test\r\nFrom: sender@example.com\r\n\r\nbody\r\n";
+        let recipients = vec!["a@bounce.test".to_owned(), "b@example.com".to_owned()];
+
+        let dsn = delivery_status_notification(
+            &bounce,
+            &DsnEnvelope::default(),
+            "sender@example.com",
+            &recipients,
+            original,
+        )
+        .unwrap();
+        let dsn = String::from_utf8(dsn).unwrap();
+
+        assert!(dsn.contains("report-type=delivery-status"));
+        assert!(dsn.contains("Final-Recipient: rfc822; a@bounce.test\r\nAction: failed"));
+        assert!(!dsn.contains("b@example.com"));
+        assert!(dsn.contains("text/rfc822-headers"));
+        assert!(!dsn.contains("body"));
+
+        // no notification for the null sender or when nothing bounces
+        assert!(
+            delivery_status_notification(
+                &bounce,
+                &DsnEnvelope::default(),
+                "",
+                &recipients,
+                original
+            )
+            .is_none()
+        );
+        assert!(
+            delivery_status_notification(
+                &bounce,
+                &DsnEnvelope::default(),
+                "sender@example.com",
+                &recipients[1..],
+                original
+            )
+            .is_none()
+        );
+    }
+}
@@ -1,13 +1,23 @@
 use mail_parser::MessageParser;
+use std::{net::IpAddr, sync::Arc};
 use tokio::sync::broadcast::Sender;
-use tracing::{error, info};
+use tracing::{error, info, warn};
 
 use crate::{
     VERSION,
+    analysis::{OpenPgpKeys, SmimeKeys},
     error::{Error, Result},
     types::MailMessage,
 };
 
+use super::{
+    config::SmtpConfig,
+    dsn::{SessionDsn, delivery_status_notification},
+    limits::Limiter,
+    pattern::matches_pattern,
+    relay::relay_message,
+};
+
 #[derive(Clone, Debug)]
 pub(super) struct MailHandler {
     // internal broadcast queue
@@ -16,8 +26,25 @@ pub(super) struct MailHandler {
     // parser
     parser: MessageParser,
 
+    // shared rate limits
+    limiter: Arc<Limiter>,
+
+    // keys to decrypt and verify S/MIME messages
+    smime_keys: Arc<SmimeKeys>,
+
+    // keyring to decrypt and verify OpenPGP/MIME messages
+    openpgp_keys: Arc<OpenPgpKeys>,
+
+    // runtime configuration, as it was when the session started
+    config: Arc<SmtpConfig>,
+
+    // delivery status notifications, DSN parameters are provided by the connection
+    dsn: SessionDsn,
+
     // incoming message buffer
     buffer: Vec<u8>,
+    oversized: bool,
+    peer_ip: Option<IpAddr>,
     envelope_from: String,
     envelope_recipients: Vec<String>,
 }
@@ -27,11 +54,38 @@ impl MailHandler {
         MailHandler {
             tx,
             parser: MessageParser::new(),
+            limiter: Default::default(),
+            smime_keys: Default::default(),
+            openpgp_keys: Default::default(),
+            config: Default::default(),
+            dsn: Default::default(),
             buffer: Vec::new(),
+            oversized: false,
+            peer_ip: None,
             envelope_from: String::new(),
             envelope_recipients: Vec::new(),
         }
     }
+
+    pub(super) fn set_limiter(&mut self, limiter: Arc<Limiter>) {
+        self.limiter = limiter;
+    }
+
+    pub(super) fn set_smime_keys(&mut self, keys: Arc<SmimeKeys>) {
+        self.smime_keys = keys;
+    }
+
+    pub(super) fn set_openpgp_keys(&mut self, keys: Arc<OpenPgpKeys>) {
+        self.openpgp_keys = keys;
+    }
+
+    /// apply the current configuration and start tracking DSN parameters for a new connection
+    pub(super) fn start_session(&mut self, config: Arc<SmtpConfig>) -> SessionDsn {
+        self.config = config;
+        self.dsn = SessionDsn::default();
+
+        self.dsn.clone()
+    }
 }
 
 impl MailHandler {
@@ -43,28 +97,89 @@ impl MailHandler {
             .ok_or_else(|| Error::Smtp("failed to parse message".to_owned()))?;
 
         let mut message: MailMessage = parsed.try_into()?;
+        message.peer_ip = self.peer_ip;
         message.envelope_from = std::mem::take(&mut self.envelope_from);
         message.envelope_recipients = std::mem::take(&mut self.envelope_recipients);
-
-        // clear the message buffer
-        self.buffer.clear();
+        message.unwrap_smime(&self.smime_keys);
+        message.unwrap_openpgp(&self.openpgp_keys);
 
         // send the message to a internal queue
         self.tx
             .send(message.clone())
             .map_err(|e| Error::Smtp(e.to_string()))?;
 
+        // report (simulated) bounces and requested delivery notifications to the sender
+        let envelope = self
+            .dsn
+            .lock()
+            .map(|mut envelope| std::mem::take(&mut *envelope))
+            .unwrap_or_default();
+
+        // the message is accepted, a failing notification does not fail the transaction
+        if let Some(notification) = delivery_status_notification(
+            &self.config.bounce,
+            &envelope,
+            &message.envelope_from,
+            &message.envelope_recipients,
+            &self.buffer,
+        ) && let Err(e) = self.deliver_notification(notification, &message.envelope_from)
+        {
+            error!("Could not deliver delivery status notification: {e}");
+        }
+
         Ok(message)
     }
+
+    /// send a notification to the configured relay, or store it like any received message
+    fn deliver_notification(&self, notification: Vec<u8>, recipient: &str) -> Result<()> {
+        info!("Sending delivery status notification to {recipient}");
+
+        if let Some(relay) = &self.config.bounce.relay {
+            let relay = relay.clone();
+            let recipient = recipient.to_owned();
+
+            tokio::spawn(async move {
+                if let Err(e) = relay_message(&relay, &recipient, &notification).await {
+                    error!("Could not relay delivery status notification: {e}");
+                }
+            });
+
+            return Ok(());
+        }
+
+        let parsed = self
+            .parser
+            .parse(&notification)
+            .ok_or_else(|| Error::Smtp("failed to parse notification".to_owned()))?;
+
+        let mut message: MailMessage = parsed.try_into()?;
+        message.envelope_recipients = vec![recipient.to_owned()];
+
+        self.tx
+            .send(message)
+            .map_err(|e| Error::Smtp(e.to_string()))?;
+
+        Ok(())
+    }
 }
 
 impl mailin::Handler for MailHandler {
-    fn helo(&mut self, _ip: std::net::IpAddr, _domain: &str) -> mailin::Response {
+    fn helo(&mut self, _ip: IpAddr, _domain: &str) -> mailin::Response {
         // NOTE that response is more as just '250 OK'
         mailin::response::OK
     }
 
-    fn mail(&mut self, _ip: std::net::IpAddr, _domain: &str, from: &str) -> mailin::Response {
+    fn mail(&mut self, ip: IpAddr, _domain: &str, from: &str) -> mailin::Response {
+        if !self.limiter.allow_message(ip) {
+            warn!("Message rate limit exceeded for {ip}");
+
+            return mailin::response::Response::custom(
+                451,
+                "4.7.1 Message rate limit exceeded, try again later".to_owned(),
+            );
+        }
+
+        self.peer_ip = Some(ip);
         self.envelope_from = from.to_string();
 
         // introductions
@@ -75,6 +190,17 @@ impl mailin::Handler for MailHandler {
     }
 
     fn rcpt(&mut self, to: &str) -> mailin::Response {
+        if self
+            .config
+            .rejected_recipients
+            .iter()
+            .any(|pattern| matches_pattern(pattern, to))
+        {
+            info!("Rejected recipient {to}");
+
+            return mailin::response::Response::custom(550, "5.1.1 Mailbox unavailable".to_owned());
+        }
+
         // RCPT may be repeated any number of times, so store every value.
         self.envelope_recipients.push(to.to_string());
 
@@ -94,12 +220,42 @@ impl mailin::Handler for MailHandler {
     }
 
     fn data(&mut self, buf: &[u8]) -> std::io::Result<()> {
-        self.buffer.extend_from_slice(buf);
+        // keep reading until the end of the message, the size error is reported afterwards
+        let max_size = self.config.max_message_size;
+        if max_size > 0 && self.buffer.len() + buf.len() > max_size {
+            self.oversized = true;
+        }
+
+        if !self.oversized {
+            self.buffer.extend_from_slice(buf);
+        }
+
         Ok(())
     }
 
     fn data_end(&mut self) -> mailin::Response {
-        match self.parse_mail() {
+        if self.oversized {
+            self.oversized = false;
+            self.buffer.clear();
+            self.envelope_recipients.clear();
+
+            let mut response = mailin::response::Response::custom(
+                552,
+                "5.3.4 Message size exceeds fixed maximum message size".to_owned(),
+            );
+            // the session would stay in the DATA state after an error, while the transaction ends here
+            response.is_error = false;
+
+            return response;
+        }
+
+        let result = self.parse_mail();
+
+        // the transaction ends here, whether the message was accepted or not
+        self.buffer.clear();
+        self.envelope_recipients.clear();
+
+        match result {
             Err(e) => {
                 error!("{e}");
 
@@ -0,0 +1,97 @@
+use serde::{Deserialize, Serialize};
+use tokio::time::Duration;
+
+use super::pattern::matches_pattern;
+
+/// moments in an SMTP session at which a response can be delayed
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SmtpPhase {
+    Greeting,
+    Ehlo,
+    Rcpt,
+    DataEnd,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Delay {
+    /// answer after the given number of milliseconds
+    Millis(u64),
+    /// never answer, keep the connection open until the client gives up
+    Hang,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DelayRule {
+    pub phase: SmtpPhase,
+    pub delay: Delay,
+    /// only apply the rule when a recipient matches this pattern, `*` matches any
+    /// sequence of characters, the recipient is unknown during greeting and EHLO
+    #[serde(default)]
+    pub recipient: Option<String>,
+}
+
+/// simulated latency for SMTP responses, the first matching rule is applied
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Latency {
+    pub rules: Vec<DelayRule>,
+}
+
+impl Latency {
+    /// find the delay for a phase, given the recipients of the current transaction
+    pub(super) fn delay(&self, phase: SmtpPhase, recipients: &[String]) -> Option<Delay> {
+        self.rules
+            .iter()
+            .find(|rule| {
+                rule.phase == phase
+                    && match &rule.recipient {
+                        None => true,
+                        Some(pattern) => recipients.iter().any(|r| matches_pattern(pattern, r)),
+                    }
+            })
+            .map(|rule| rule.delay)
+    }
+}
+
+impl Delay {
+    pub(super) fn duration(&self) -> Option<Duration> {
+        match self {
+            Delay::Millis(millis) => Some(Duration::from_millis(*millis)),
+            Delay::Hang => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn first_matching_rule() {
+        let latency = Latency {
+            rules: vec![
+                DelayRule {
+                    phase: SmtpPhase::Rcpt,
+                    delay: Delay::Hang,
+                    recipient: Some("*@hang.test".to_owned()),
+                },
+                DelayRule {
+                    phase: SmtpPhase::Rcpt,
+                    delay: Delay::Millis(500),
+                    recipient: None,
+                },
+            ],
+        };
+
+        let hang = vec!["user@hang.test".to_owned()];
+        let other = vec!["user@example.com".to_owned()];
+
+        assert_eq!(latency.delay(SmtpPhase::Rcpt, &hang), Some(Delay::Hang));
+        assert_eq!(
+            latency.delay(SmtpPhase::Rcpt, &other),
+            Some(Delay::Millis(500))
+        );
+        assert_eq!(latency.delay(SmtpPhase::Greeting, &[]), None);
+    }
+}
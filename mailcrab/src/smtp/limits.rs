@@ -0,0 +1,178 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    net::IpAddr,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    sync::{OwnedSemaphorePermit, Semaphore},
+    time::{Duration, Instant},
+};
+
+/// window used for the per-IP rate limits
+const RATE_WINDOW: Duration = Duration::from_secs(60);
+
+/// resource limits for the SMTP server, a value of 0 disables a limit
+#[derive(Clone, Debug, PartialEq)]
+pub struct Limits {
+    /// maximum number of concurrent connections
+    pub max_connections: usize,
+    /// maximum number of new connections per client IP per minute
+    pub connections_per_minute: usize,
+    /// maximum number of messages per client IP per minute
+    pub messages_per_minute: usize,
+    /// close a session when no command is received within this time
+    pub command_timeout: Duration,
+    /// close a session when no message data is received within this time
+    pub data_timeout: Duration,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        // timeouts as recommended in RFC 5321, section 4.5.3.2
+        Limits {
+            max_connections: 0,
+            connections_per_minute: 0,
+            messages_per_minute: 0,
+            command_timeout: Duration::from_secs(300),
+            data_timeout: Duration::from_secs(600),
+        }
+    }
+}
+
+/// sliding window of events per client IP
+#[derive(Debug)]
+struct RateWindow {
+    limit: usize,
+    history: Mutex<HashMap<IpAddr, VecDeque<Instant>>>,
+}
+
+impl RateWindow {
+    fn new(limit: usize) -> Self {
+        RateWindow {
+            limit,
+            history: Default::default(),
+        }
+    }
+
+    /// register an event for the given IP, returns false when the limit is exceeded
+    fn check(&self, ip: IpAddr) -> bool {
+        if self.limit == 0 {
+            return true;
+        }
+
+        let Ok(mut history) = self.history.lock() else {
+            return true;
+        };
+
+        let now = Instant::now();
+
+        // forget events that fell out of the window, for all clients
+        history.retain(|_, events| {
+            while events.front().is_some_and(|t| now - *t > RATE_WINDOW) {
+                events.pop_front();
+            }
+
+            !events.is_empty()
+        });
+
+        let events = history.entry(ip).or_default();
+
+        if events.len() >= self.limit {
+            return false;
+        }
+
+        events.push_back(now);
+
+        true
+    }
+}
+
+/// a slot in the connection pool, released when dropped
+pub(super) struct ConnectionPermit {
+    _permit: Option<OwnedSemaphorePermit>,
+}
+
+/// shared state to enforce the configured limits
+#[derive(Debug)]
+pub(super) struct Limiter {
+    pub(super) limits: Limits,
+    connections: Option<Arc<Semaphore>>,
+    connection_rate: RateWindow,
+    message_rate: RateWindow,
+}
+
+impl Limiter {
+    pub(super) fn new(limits: Limits) -> Self {
+        Limiter {
+            connections: match limits.max_connections {
+                0 => None,
+                max => Some(Arc::new(Semaphore::new(max))),
+            },
+            connection_rate: RateWindow::new(limits.connections_per_minute),
+            message_rate: RateWindow::new(limits.messages_per_minute),
+            limits,
+        }
+    }
+
+    /// claim a connection slot, returns None when all slots are in use
+    pub(super) fn acquire_connection(&self) -> Option<ConnectionPermit> {
+        match &self.connections {
+            None => Some(ConnectionPermit { _permit: None }),
+            Some(semaphore) => {
+                semaphore
+                    .clone()
+                    .try_acquire_owned()
+                    .ok()
+                    .map(|permit| ConnectionPermit {
+                        _permit: Some(permit),
+                    })
+            }
+        }
+    }
+
+    pub(super) fn allow_connection(&self, ip: IpAddr) -> bool {
+        self.connection_rate.check(ip)
+    }
+
+    pub(super) fn allow_message(&self, ip: IpAddr) -> bool {
+        self.message_rate.check(ip)
+    }
+}
+
+impl Default for Limiter {
+    fn default() -> Self {
+        Limiter::new(Limits::default())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn rate_window() {
+        let window = RateWindow::new(2);
+        let a: IpAddr = [192, 0, 2, 1].into();
+        let b: IpAddr = [192, 0, 2, 2].into();
+
+        assert!(window.check(a));
+        assert!(window.check(a));
+        assert!(!window.check(a));
+        assert!(window.check(b));
+    }
+
+    #[test]
+    fn connection_slots() {
+        let limiter = Limiter::new(Limits {
+            max_connections: 1,
+            ..Limits::default()
+        });
+
+        let permit = limiter.acquire_connection();
+        assert!(permit.is_some());
+        assert!(limiter.acquire_connection().is_none());
+
+        drop(permit);
+        assert!(limiter.acquire_connection().is_some());
+    }
+}
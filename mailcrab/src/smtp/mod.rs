@@ -3,25 +3,78 @@ use tokio::sync::broadcast::Sender;
 use tokio_util::sync::CancellationToken;
 use tracing::error;
 
-use crate::{error::Result, types::MailMessage};
+use crate::{
+    analysis::{OpenPgpKeys, SmimeKeys},
+    error::Result,
+    types::MailMessage,
+};
 
 use self::server::{MailServer, TlsMode};
 
+pub use self::{
+    config::{SharedConfig, SmtpConfig},
+    dsn::Bounce,
+    latency::{Delay, DelayRule, Latency, SmtpPhase},
+    limits::Limits,
+    proxy::ProxyProtocol,
+    relay::send_mail,
+};
+
+mod config;
 mod connection;
+mod dsn;
 mod handler;
+mod latency;
+mod limits;
+mod pattern;
+mod proxy;
+mod relay;
 mod server;
 mod tls;
 
+/// optional behaviour of the SMTP server
+#[derive(Clone, Debug, Default)]
+pub struct SmtpOptions {
+    /// enable TLS (wrapped) and accept any username/password combination
+    pub enable_tls_auth: bool,
+    pub proxy_protocol: ProxyProtocol,
+    /// addresses of the proxies that may send a PROXY protocol header, in required mode any
+    /// address is trusted when this is empty
+    pub trusted_proxies: Vec<IpAddr>,
+    pub limits: Limits,
+    /// size limits, failure rules and delays, can be changed while the server is running
+    pub config: SharedConfig,
+    /// private keys and certificates to decrypt and verify S/MIME messages
+    pub smime_keys: SmimeKeys,
+    /// keyring to decrypt and verify OpenPGP/MIME messages
+    pub openpgp_keys: OpenPgpKeys,
+}
+
 pub async fn mail_server(
     smtp_host: IpAddr,
     smtp_port: u16,
     tx: Sender<MailMessage>,
-    enable_tls_auth: bool,
+    options: SmtpOptions,
     token: CancellationToken,
 ) -> Result<()> {
+    let SmtpOptions {
+        enable_tls_auth,
+        proxy_protocol,
+        trusted_proxies,
+        limits,
+        config,
+        smime_keys,
+        openpgp_keys,
+    } = options;
+
     let server = if enable_tls_auth {
         match MailServer::new(tx)
             .with_address((smtp_host, smtp_port).into())
+            .with_proxy_protocol(proxy_protocol, trusted_proxies)
+            .with_limits(limits)
+            .with_config(config)
+            .with_smime_keys(smime_keys)
+            .with_openpgp_keys(openpgp_keys)
             .with_authentication()
             .with_tls(TlsMode::Wrapped)
             .await
@@ -34,7 +87,13 @@ pub async fn mail_server(
             }
         }
     } else {
-        MailServer::new(tx).with_address((smtp_host, smtp_port).into())
+        MailServer::new(tx)
+            .with_address((smtp_host, smtp_port).into())
+            .with_proxy_protocol(proxy_protocol, trusted_proxies)
+            .with_limits(limits)
+            .with_config(config)
+            .with_smime_keys(smime_keys)
+            .with_openpgp_keys(openpgp_keys)
     };
 
     if let Err(e) = server.serve(token).await {
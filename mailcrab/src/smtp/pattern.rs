@@ -0,0 +1,45 @@
+/// case insensitive wildcard match, e.g. "*@slow.example.com"
+pub(super) fn matches_pattern(pattern: &str, value: &str) -> bool {
+    let pattern = pattern.to_ascii_lowercase();
+    let value = value.to_ascii_lowercase();
+    let mut parts = pattern.split('*');
+
+    // the part before the first wildcard must be a prefix
+    let first = parts.next().unwrap_or_default();
+    let Some(mut rest) = value.strip_prefix(first) else {
+        return false;
+    };
+
+    let parts: Vec<&str> = parts.collect();
+    let Some((last, middle)) = parts.split_last() else {
+        // no wildcard at all
+        return rest.is_empty();
+    };
+
+    for part in middle {
+        match rest.find(part) {
+            Some(index) => rest = &rest[index + part.len()..],
+            None => return false,
+        }
+    }
+
+    rest.ends_with(last)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn wildcard_patterns() {
+        assert!(matches_pattern(
+            "*@slow.example.com",
+            "john@slow.example.com"
+        ));
+        assert!(matches_pattern("john@*", "John@example.com"));
+        assert!(matches_pattern("*slow*", "a@slow.example.com"));
+        assert!(matches_pattern("a@b.c", "a@b.c"));
+        assert!(!matches_pattern("a@b.c", "a@b.cd"));
+        assert!(!matches_pattern("*@slow.example.com", "john@example.com"));
+    }
+}
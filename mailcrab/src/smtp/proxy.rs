@@ -0,0 +1,297 @@
+use std::{
+    net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+};
+use tokio::{io::AsyncReadExt, net::TcpStream, time::Duration};
+
+use crate::error::{Error, Result};
+
+/// maximum length of a v1 (text) header, including the trailing CRLF
+const V1_MAX_LENGTH: usize = 107;
+/// the fixed part of a v2 (binary) header: signature, version/command, family and length
+const V2_HEADER_LENGTH: usize = 16;
+const V2_SIGNATURE: [u8; 12] = *b"\r\n\r\n\0\r\nQUIT\n";
+
+/// time to wait for a trusted proxy to send its header
+const HEADER_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// whether connections are expected to start with a PROXY protocol (v1 or v2) header
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum ProxyProtocol {
+    #[default]
+    Disabled,
+    /// use the header when present, accept direct connections as well
+    Optional,
+    /// reject connections without a valid header
+    Required,
+}
+
+impl FromStr for ProxyProtocol {
+    type Err = Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        match s.to_ascii_lowercase().as_str() {
+            "" | "off" | "false" | "disabled" => Ok(ProxyProtocol::Disabled),
+            "optional" => Ok(ProxyProtocol::Optional),
+            "on" | "true" | "required" => Ok(ProxyProtocol::Required),
+            other => Err(Error::ProxyProtocol(format!("unknown mode '{other}'"))),
+        }
+    }
+}
+
+/// the PROXY protocol mode and the addresses of the proxies that may send a header
+#[derive(Clone, Debug, Default)]
+pub(super) struct ProxyConfig {
+    pub(super) mode: ProxyProtocol,
+    /// in required mode any address is trusted when this is empty
+    pub(super) trusted: Arc<[IpAddr]>,
+}
+
+impl ProxyConfig {
+    fn is_trusted(&self, peer: IpAddr) -> bool {
+        (self.mode == ProxyProtocol::Required && self.trusted.is_empty())
+            || self.trusted.contains(&peer.to_canonical())
+    }
+}
+
+/// parse a v1 header line, e.g. "PROXY TCP4 192.0.2.1 198.51.100.1 56324 25\r\n"
+fn parse_v1(line: &[u8]) -> Result<Option<SocketAddr>> {
+    let invalid = || Error::ProxyProtocol("invalid v1 header".to_owned());
+
+    let line = std::str::from_utf8(line)
+        .ok()
+        .and_then(|l| l.strip_suffix("\r\n"))
+        .ok_or_else(invalid)?;
+    let mut parts = line.split(' ');
+
+    if parts.next() != Some("PROXY") {
+        return Err(invalid());
+    }
+
+    match parts.next() {
+        Some("TCP4") | Some("TCP6") => {}
+        // the proxy could not determine the client address
+        Some("UNKNOWN") => return Ok(None),
+        _ => return Err(invalid()),
+    }
+
+    let source: IpAddr = parts
+        .next()
+        .and_then(|p| p.parse().ok())
+        .ok_or_else(invalid)?;
+    let _destination: IpAddr = parts
+        .next()
+        .and_then(|p| p.parse().ok())
+        .ok_or_else(invalid)?;
+    let source_port: u16 = parts
+        .next()
+        .and_then(|p| p.parse().ok())
+        .ok_or_else(invalid)?;
+    let _destination_port: u16 = parts
+        .next()
+        .and_then(|p| p.parse().ok())
+        .ok_or_else(invalid)?;
+
+    if parts.next().is_some() {
+        return Err(invalid());
+    }
+
+    Ok(Some(SocketAddr::new(source, source_port)))
+}
+
+/// parse a v2 header, given the fixed 16 byte header and the address block that follows it
+fn parse_v2(header: &[u8; V2_HEADER_LENGTH], addresses: &[u8]) -> Result<Option<SocketAddr>> {
+    if header[..12] != V2_SIGNATURE || header[12] >> 4 != 2 {
+        return Err(Error::ProxyProtocol("invalid v2 header".to_owned()));
+    }
+
+    match header[12] & 0x0F {
+        // LOCAL, e.g. health checks from the proxy itself
+        0 => return Ok(None),
+        1 => {}
+        command => {
+            return Err(Error::ProxyProtocol(format!(
+                "unsupported v2 command {command}"
+            )));
+        }
+    }
+
+    let too_short = || Error::ProxyProtocol("v2 address block too short".to_owned());
+
+    match header[13] >> 4 {
+        // AF_INET
+        1 => {
+            let block: &[u8; 12] = addresses
+                .get(..12)
+                .and_then(|b| b.try_into().ok())
+                .ok_or_else(too_short)?;
+            let ip = Ipv4Addr::new(block[0], block[1], block[2], block[3]);
+            let port = u16::from_be_bytes([block[8], block[9]]);
+
+            Ok(Some(SocketAddr::new(ip.into(), port)))
+        }
+        // AF_INET6
+        2 => {
+            let block: &[u8; 36] = addresses
+                .get(..36)
+                .and_then(|b| b.try_into().ok())
+                .ok_or_else(too_short)?;
+            let mut octets = [0u8; 16];
+            octets.copy_from_slice(&block[..16]);
+            let port = u16::from_be_bytes([block[32], block[33]]);
+
+            Ok(Some(SocketAddr::new(Ipv6Addr::from(octets).into(), port)))
+        }
+        // AF_UNSPEC and AF_UNIX carry no usable client address
+        _ => Ok(None),
+    }
+}
+
+/// consume the PROXY protocol header from a freshly accepted socket
+async fn read_header(socket: &mut TcpStream) -> Result<Option<SocketAddr>> {
+    let mut first = [0u8; 1];
+
+    match socket.peek(&mut first).await? {
+        0 => Err(Error::ProxyProtocol("connection closed".to_owned())),
+        _ if first[0] == b'P' => {
+            // read byte by byte, so nothing after the header is consumed
+            let mut line = Vec::with_capacity(V1_MAX_LENGTH);
+            while !line.ends_with(b"\r\n") {
+                if line.len() == V1_MAX_LENGTH {
+                    return Err(Error::ProxyProtocol("v1 header too long".to_owned()));
+                }
+                line.push(socket.read_u8().await?);
+            }
+
+            parse_v1(&line)
+        }
+        _ if first[0] == V2_SIGNATURE[0] => {
+            let mut header = [0u8; V2_HEADER_LENGTH];
+            socket.read_exact(&mut header).await?;
+
+            let length = u16::from_be_bytes([header[14], header[15]]) as usize;
+            let mut addresses = vec![0u8; length];
+            socket.read_exact(&mut addresses).await?;
+
+            parse_v2(&header, &addresses)
+        }
+        _ => Err(Error::ProxyProtocol("missing header".to_owned())),
+    }
+}
+
+/// read the PROXY protocol header, if configured, and return the original client address,
+/// only trusted proxies can provide the client address
+pub(super) async fn read_proxy_header(
+    socket: &mut TcpStream,
+    proxy: &ProxyConfig,
+) -> Result<Option<SocketAddr>> {
+    if proxy.mode == ProxyProtocol::Disabled {
+        return Ok(None);
+    }
+
+    if !proxy.is_trusted(socket.peer_addr()?.ip()) {
+        return match proxy.mode {
+            ProxyProtocol::Required => Err(Error::ProxyProtocol(
+                "connection from an untrusted address".to_owned(),
+            )),
+            // a direct client, which does not have to wait for a header
+            _ => Ok(None),
+        };
+    }
+
+    if proxy.mode == ProxyProtocol::Optional {
+        // a direct client does not send anything before the greeting,
+        // a proxy sends its header immediately
+        let mut first = [0u8; 1];
+        match tokio::time::timeout(HEADER_TIMEOUT, socket.peek(&mut first)).await {
+            Ok(Ok(n)) if n > 0 && (first[0] == b'P' || first[0] == V2_SIGNATURE[0]) => {}
+            Ok(Err(e)) => return Err(e.into()),
+            _ => return Ok(None),
+        }
+    }
+
+    tokio::time::timeout(HEADER_TIMEOUT, read_header(socket))
+        .await
+        .map_err(|_| Error::ProxyProtocol("timeout waiting for header".to_owned()))?
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_v1_header() {
+        let addr = parse_v1(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 25\r\n").unwrap();
+        assert_eq!(addr, Some("192.0.2.1:56324".parse().unwrap()));
+
+        let addr = parse_v1(b"PROXY TCP6 2001:db8::1 2001:db8::2 4000 25\r\n").unwrap();
+        assert_eq!(addr, Some("[2001:db8::1]:4000".parse().unwrap()));
+
+        assert_eq!(parse_v1(b"PROXY UNKNOWN\r\n").unwrap(), None);
+        assert!(parse_v1(b"PROXY TCP4 192.0.2.1\r\n").is_err());
+        assert!(parse_v1(b"EHLO example.com\r\n").is_err());
+    }
+
+    #[test]
+    fn parse_v2_header() {
+        let mut header = [0u8; V2_HEADER_LENGTH];
+        header[..12].copy_from_slice(&V2_SIGNATURE);
+        header[12] = 0x21;
+        header[13] = 0x11;
+        header[15] = 12;
+
+        let addresses = [192, 0, 2, 1, 198, 51, 100, 1, 0xDC, 0x04, 0, 25];
+        let addr = parse_v2(&header, &addresses).unwrap();
+        assert_eq!(addr, Some("192.0.2.1:56324".parse().unwrap()));
+
+        // LOCAL command
+        header[12] = 0x20;
+        assert_eq!(parse_v2(&header, &addresses).unwrap(), None);
+
+        // truncated address block
+        header[12] = 0x21;
+        assert!(parse_v2(&header, &addresses[..6]).is_err());
+    }
+
+    /// read the header a client sends on a new connection
+    async fn proxied(mode: ProxyProtocol, trusted: &[IpAddr]) -> Result<Option<SocketAddr>> {
+        use tokio::{io::AsyncWriteExt, net::TcpListener};
+
+        let listener = TcpListener::bind("127.0.0.1:0").await?;
+        let mut client = TcpStream::connect(listener.local_addr()?).await?;
+        client
+            .write_all(b"PROXY TCP4 192.0.2.1 198.51.100.1 56324 25\r\n")
+            .await?;
+        let (mut socket, _) = listener.accept().await?;
+
+        let proxy = ProxyConfig {
+            mode,
+            trusted: trusted.into(),
+        };
+        read_proxy_header(&mut socket, &proxy).await
+    }
+
+    #[tokio::test]
+    async fn trusted_proxies() {
+        let localhost = IpAddr::from([127, 0, 0, 1]);
+        let other = IpAddr::from([10, 0, 0, 1]);
+        let client = Some("192.0.2.1:56324".parse().unwrap());
+
+        assert_eq!(
+            proxied(ProxyProtocol::Optional, &[localhost])
+                .await
+                .unwrap(),
+            client
+        );
+        assert_eq!(proxied(ProxyProtocol::Required, &[]).await.unwrap(), client);
+
+        // the header of an untrusted client is ignored, or the connection rejected
+        assert_eq!(proxied(ProxyProtocol::Optional, &[]).await.unwrap(), None);
+        assert_eq!(
+            proxied(ProxyProtocol::Optional, &[other]).await.unwrap(),
+            None
+        );
+        assert!(proxied(ProxyProtocol::Required, &[other]).await.is_err());
+    }
+}
@@ -0,0 +1,100 @@
+use tokio::{
+    io::{AsyncBufReadExt, AsyncWriteExt, BufReader},
+    net::TcpStream,
+    time::{Duration, timeout},
+};
+use tracing::debug;
+
+use crate::error::{Error, Result};
+
+/// time to wait for a connection to the SMTP server
+const CONNECT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// time to wait for each reply of the SMTP server
+const REPLY_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// read a (multi-line) reply and check the status code class
+async fn expect_reply(stream: &mut BufReader<TcpStream>, class: u8) -> Result<()> {
+    let mut line = String::new();
+
+    loop {
+        line.clear();
+        let read = timeout(REPLY_TIMEOUT, stream.read_line(&mut line))
+            .await
+            .map_err(|_| Error::Smtp("timeout waiting for a relay reply".to_owned()))?;
+
+        if read? == 0 {
+            return Err(Error::Smtp("relay closed the connection".to_owned()));
+        }
+
+        debug!("Relay: {}", line.trim_end());
+
+        // "250-" continues a multi-line reply, "250 " ends it
+        if line.as_bytes().get(3) != Some(&b'-') {
+            break;
+        }
+    }
+
+    if line.as_bytes().first() == Some(&class) {
+        Ok(())
+    } else {
+        Err(Error::Smtp(format!(
+            "unexpected relay reply {}",
+            line.trim_end()
+        )))
+    }
+}
+
+async fn command(stream: &mut BufReader<TcpStream>, command: &str, class: u8) -> Result<()> {
+    stream
+        .write_all(format!("{command}\r\n").as_bytes())
+        .await?;
+    stream.flush().await?;
+
+    expect_reply(stream, class).await
+}
+
+/// deliver a message to an SMTP server (without TLS or authentication), e.g. MailCrab itself
+pub async fn send_mail(
+    address: &str,
+    envelope_from: &str,
+    recipients: &[String],
+    message: &[u8],
+) -> Result<()> {
+    let stream = timeout(CONNECT_TIMEOUT, TcpStream::connect(address))
+        .await
+        .map_err(|_| Error::Smtp(format!("timeout connecting to {address}")))?;
+    let mut stream = BufReader::new(stream?);
+
+    expect_reply(&mut stream, b'2').await?;
+    command(&mut stream, "EHLO mailcrab", b'2').await?;
+    command(&mut stream, &format!("MAIL FROM:<{envelope_from}>"), b'2').await?;
+    for recipient in recipients {
+        command(&mut stream, &format!("RCPT TO:<{recipient}>"), b'2').await?;
+    }
+    command(&mut stream, "DATA", b'3').await?;
+
+    // dot-stuffing, lines starting with a dot get an extra dot
+    let mut data = Vec::with_capacity(message.len() + 5);
+    for line in message.split_inclusive(|b| *b == b'\n') {
+        if line.starts_with(b".") {
+            data.push(b'.');
+        }
+        data.extend_from_slice(line);
+    }
+    if !data.ends_with(b"\r\n") {
+        data.extend_from_slice(b"\r\n");
+    }
+    data.extend_from_slice(b".\r\n");
+
+    stream.write_all(&data).await?;
+    stream.flush().await?;
+    expect_reply(&mut stream, b'2').await?;
+
+    command(&mut stream, "QUIT", b'2').await
+}
+
+/// deliver a message to another SMTP server with a null reverse-path, as used for notifications
+pub(super) async fn relay_message(address: &str, recipient: &str, message: &[u8]) -> Result<()> {
+    send_mail(address, "", &[recipient.to_owned()], message).await
+}
@@ -1,13 +1,27 @@
 use mailin::{AuthMechanism, SessionBuilder};
-use std::net::SocketAddr;
+use std::{
+    net::{IpAddr, SocketAddr},
+    sync::Arc,
+};
 use tokio::{net::TcpListener, sync::broadcast::Sender};
 use tokio_rustls::TlsAcceptor;
 use tokio_util::sync::CancellationToken;
-use tracing::{debug, info};
-
-use crate::{error::Result, smtp::connection::handle_connection, types::MailMessage};
-
-use super::{handler::MailHandler, tls::create_tls_acceptor};
+use tracing::{debug, info, warn};
+
+use crate::{
+    analysis::{OpenPgpKeys, SmimeKeys},
+    error::Result,
+    smtp::connection::{handle_connection, reject_connection},
+    types::MailMessage,
+};
+
+use super::{
+    config::SharedConfig,
+    handler::MailHandler,
+    limits::{Limiter, Limits},
+    proxy::{ProxyConfig, ProxyProtocol},
+    tls::create_tls_acceptor,
+};
 
 #[allow(dead_code)]
 #[derive(Debug, PartialEq)]
@@ -29,6 +43,10 @@ pub(super) struct MailServer {
     server_name: &'static str,
     session_builder: SessionBuilder,
     tls: TlsConfig,
+    proxy: ProxyConfig,
+    limiter: Arc<Limiter>,
+    config: SharedConfig,
+    authentication: bool,
     handler: MailHandler,
 }
 
@@ -41,6 +59,10 @@ impl MailServer {
             server_name: env!("CARGO_PKG_NAME"),
             session_builder: SessionBuilder::new(server_name),
             tls: TlsConfig::None,
+            proxy: ProxyConfig::default(),
+            limiter: Default::default(),
+            config: Default::default(),
+            authentication: false,
             handler: MailHandler::create(tx),
         }
     }
@@ -51,6 +73,44 @@ impl MailServer {
         self
     }
 
+    pub(super) fn with_proxy_protocol(
+        mut self,
+        mode: ProxyProtocol,
+        trusted_proxies: Vec<IpAddr>,
+    ) -> Self {
+        self.proxy = ProxyConfig {
+            mode,
+            trusted: trusted_proxies.into(),
+        };
+
+        self
+    }
+
+    pub(super) fn with_limits(mut self, limits: Limits) -> Self {
+        self.limiter = Arc::new(Limiter::new(limits));
+        self.handler.set_limiter(self.limiter.clone());
+
+        self
+    }
+
+    pub(super) fn with_config(mut self, config: SharedConfig) -> Self {
+        self.config = config;
+
+        self
+    }
+
+    pub(super) fn with_smime_keys(mut self, keys: SmimeKeys) -> Self {
+        self.handler.set_smime_keys(Arc::new(keys));
+
+        self
+    }
+
+    pub(super) fn with_openpgp_keys(mut self, keys: OpenPgpKeys) -> Self {
+        self.handler.set_openpgp_keys(Arc::new(keys));
+
+        self
+    }
+
     pub(super) async fn with_tls(mut self, tls_mode: TlsMode) -> Result<Self> {
         self.tls = match tls_mode {
             TlsMode::None => TlsConfig::None,
@@ -68,6 +128,7 @@ impl MailServer {
     pub(super) fn with_authentication(mut self) -> Self {
         self.session_builder.enable_auth(AuthMechanism::Plain);
         self.session_builder.enable_auth(AuthMechanism::Login);
+        self.authentication = true;
 
         self
     }
@@ -90,12 +151,51 @@ impl MailServer {
 
             debug!("Connection from {peer_addr:?}");
 
+            let Some(permit) = self.limiter.acquire_connection() else {
+                warn!("Maximum number of connections reached, rejecting {peer_addr:?}");
+                let wrapped_tls = matches!(self.tls, TlsConfig::Wrapped(_));
+                tokio::spawn(reject_connection(
+                    socket,
+                    wrapped_tls,
+                    "Too many connections",
+                ));
+
+                continue;
+            };
+
+            let require_auth = self.config.read().is_ok_and(|config| config.require_auth);
+
+            let mut session_builder = self.session_builder.clone();
+            if require_auth && !self.authentication {
+                // any credentials are accepted, so allow them without TLS as well
+                session_builder
+                    .enable_auth(AuthMechanism::Plain)
+                    .enable_auth(AuthMechanism::Login)
+                    .insecure_enable_plaintext_auth();
+            }
+
             tokio::spawn({
-                let session_builder = self.session_builder.clone();
                 let tls = self.tls.clone();
+                let proxy = self.proxy.clone();
+                let limiter = self.limiter.clone();
                 let handler = self.handler.clone();
-
-                handle_connection(socket, session_builder, tls, handler)
+                let config = self.config.clone();
+
+                async move {
+                    // the connection slot is released when the session ends
+                    let _permit = permit;
+
+                    handle_connection(
+                        socket,
+                        session_builder,
+                        tls,
+                        proxy,
+                        limiter,
+                        config,
+                        handler,
+                    )
+                    .await
+                }
             });
         }
     }
@@ -16,6 +16,10 @@ use crate::error::Result;
 const CERT_PATH: &str = "cert.pem";
 const KEY_PATH: &str = "key.pem";
 
+/// the certificate and key are read from and written to the working directory, except in
+/// tests, which should not leave a key pair behind in the source tree
+const PERSIST: bool = !cfg!(test);
+
 async fn load_certs<'a>() -> Option<Vec<CertificateDer<'a>>> {
     let pem_bytes = fs::read(CERT_PATH).await.ok()?;
     let mut reader = BufReader::new(&pem_bytes[..]);
@@ -47,7 +51,12 @@ async fn load_key<'a>() -> Option<PrivatePkcs8KeyDer<'a>> {
 
 /// read or generate a certioficate + key for the SMTP server
 pub(super) async fn create_tls_acceptor(name: &str) -> Result<TlsAcceptor> {
-    let (certs, key) = match (load_certs().await, load_key().await) {
+    let loaded = match PERSIST {
+        true => (load_certs().await, load_key().await),
+        false => (None, None),
+    };
+
+    let (certs, key) = match loaded {
         (Some(cert), Some(key)) => (cert, key),
         _ => {
             info!("Generating self-signed certificate...");
@@ -60,8 +69,10 @@ pub(super) async fn create_tls_acceptor(name: &str) -> Result<TlsAcceptor> {
             let key_pair = KeyPair::generate()?;
             let cert = cert_params.self_signed(&key_pair)?;
 
-            fs::write(CERT_PATH, cert.pem()).await?;
-            fs::write(KEY_PATH, key_pair.serialize_pem()).await?;
+            if PERSIST {
+                fs::write(CERT_PATH, cert.pem()).await?;
+                fs::write(KEY_PATH, key_pair.serialize_pem()).await?;
+            }
 
             info!("Certificate generated:\n{}", cert.pem());
 
@@ -1,12 +1,21 @@
 use base64ct::Encoding;
 use chrono::{DateTime, Local};
-use mail_parser::MimeHeaders;
+use mail_parser::{MessageParser, MimeHeaders, PartType};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::{collections::HashMap, net::IpAddr};
 use tracing::warn;
 use uuid::Uuid;
 
-use crate::error::Error;
+use crate::{
+    analysis::{
+        AuthenticationInput, AuthenticationReport, Calendar, CompatibilityIssue, DkimResult,
+        DnsRecords, Link, MimePart, OpenPgpKeys, OpenPgpReport, SmimeKeys, SmimeReport, SpamInput,
+        SpamReport, TnefAttachment, authenticate, compatibility_report, link_report,
+        mime_structure, parse_calendar, parse_tnef, part_content, spam_report, unwrap_openpgp,
+        unwrap_smime, verify_dkim,
+    },
+    error::Error,
+};
 
 pub type MessageId = Uuid;
 
@@ -41,6 +50,7 @@ pub struct MailMessageMetadata {
     pub attachments: Vec<AttachmentMetadata>,
     pub envelope_from: String,
     pub envelope_recipients: Vec<String>,
+    pub peer_ip: Option<IpAddr>,
 }
 
 impl From<MailMessage> for MailMessageMetadata {
@@ -59,6 +69,7 @@ impl From<MailMessage> for MailMessageMetadata {
             attachments,
             envelope_from,
             envelope_recipients,
+            peer_ip,
             ..
         } = message;
         MailMessageMetadata {
@@ -82,6 +93,7 @@ impl From<MailMessage> for MailMessageMetadata {
                 .collect::<Vec<AttachmentMetadata>>(),
             envelope_from,
             envelope_recipients,
+            peer_ip,
         }
     }
 }
@@ -96,16 +108,18 @@ pub struct Attachment {
     content: String,
 }
 
+impl Attachment {
+    /// the file name, content type and decoded content
+    fn decoded(&self) -> Option<(String, String, Vec<u8>)> {
+        let bytes = base64ct::Base64::decode_vec(&self.content).ok()?;
+        Some((self.filename.clone(), self.mime.clone(), bytes))
+    }
+}
+
 impl From<&mail_parser::MessagePart<'_>> for Attachment {
     fn from(part: &mail_parser::MessagePart) -> Self {
         let filename = part.attachment_name().unwrap_or_default().to_string();
-        let mime = match part.content_type() {
-            Some(content_type) => match &content_type.c_subtype {
-                Some(subtype) => format!("{}/{}", content_type.c_type, subtype),
-                None => content_type.c_type.to_string(),
-            },
-            None => "application/octet-stream".to_owned(),
-        };
+        let mime = mime(part).unwrap_or_else(|| "application/octet-stream".to_owned());
 
         Attachment {
             filename,
@@ -117,12 +131,87 @@ impl From<&mail_parser::MessagePart<'_>> for Attachment {
     }
 }
 
+impl From<TnefAttachment> for Attachment {
+    fn from(attachment: TnefAttachment) -> Self {
+        let mime = attachment
+            .mime
+            .unwrap_or_else(|| mime_from_filename(&attachment.filename).to_owned());
+
+        Attachment {
+            mime,
+            content_id: attachment.content_id,
+            size: humansize::format_size(attachment.content.len(), humansize::DECIMAL),
+            content: base64ct::Base64::encode_string(&attachment.content),
+            filename: attachment.filename,
+        }
+    }
+}
+
+/// a content type for files without one, e.g. the attachments in a TNEF stream
+fn mime_from_filename(filename: &str) -> &'static str {
+    let extension = filename
+        .rsplit_once('.')
+        .map(|(_, extension)| extension.to_ascii_lowercase())
+        .unwrap_or_default();
+
+    match extension.as_str() {
+        "pdf" => "application/pdf",
+        "png" => "image/png",
+        "jpg" | "jpeg" => "image/jpeg",
+        "gif" => "image/gif",
+        "txt" => "text/plain",
+        "csv" => "text/csv",
+        "htm" | "html" => "text/html",
+        "ics" => "text/calendar",
+        "eml" => "message/rfc822",
+        "zip" => "application/zip",
+        "docx" => "application/vnd.openxmlformats-officedocument.wordprocessingml.document",
+        "xlsx" => "application/vnd.openxmlformats-officedocument.spreadsheetml.sheet",
+        _ => "application/octet-stream",
+    }
+}
+
+fn mime(part: &mail_parser::MessagePart) -> Option<String> {
+    let content_type = part.content_type()?;
+
+    Some(match &content_type.c_subtype {
+        Some(subtype) => format!("{}/{}", content_type.c_type, subtype),
+        None => content_type.c_type.to_string(),
+    })
+}
+
+/// content type of AMP for Email parts
+const AMP: &str = "text/x-amp-html";
+
+fn is_amp(part: &mail_parser::MessagePart) -> bool {
+    mime(part).is_some_and(|mime| mime.eq_ignore_ascii_case(AMP))
+}
+
+/// a displayable part of the message body
+#[derive(Clone, Debug, Serialize)]
+pub struct BodyPart {
+    content_type: String,
+    /// the text of text, HTML and AMP parts
+    content: Option<String>,
+    /// the index of an inline image (or other inline file) in the attachments
+    attachment: Option<usize>,
+}
+
 #[derive(Clone, Debug, Serialize, Deserialize, Default)]
 pub struct Address {
     name: Option<String>,
     email: Option<String>,
 }
 
+impl Address {
+    fn contains(&self, text: &str) -> bool {
+        [&self.name, &self.email]
+            .into_iter()
+            .flatten()
+            .any(|value| value.to_lowercase().contains(text))
+    }
+}
+
 impl From<&mail_parser::Addr<'_>> for Address {
     fn from(addr: &mail_parser::Addr) -> Self {
         Address {
@@ -132,6 +221,254 @@ impl From<&mail_parser::Addr<'_>> for Address {
     }
 }
 
+/// a forwarded or attached message (message/rfc822), which can embed messages itself
+#[derive(Clone, Debug, Serialize, Default)]
+pub struct EmbeddedMessage {
+    from: Option<Address>,
+    to: Vec<Address>,
+    subject: String,
+    date: Option<String>,
+    size: String,
+    text: String,
+    html: String,
+    pub attachments: Vec<Attachment>,
+    pub embedded: Vec<EmbeddedMessage>,
+}
+
+impl EmbeddedMessage {
+    pub fn attachment_content(&self, index: usize) -> Option<(String, String, Vec<u8>)> {
+        self.attachments.get(index)?.decoded()
+    }
+
+    /// like [`MailMessage::render`], attachments are referenced relative to the given URL
+    pub fn render(&self, url: &str) -> String {
+        render(&self.html, &self.text, &self.attachments, url)
+    }
+}
+
+impl From<&mail_parser::Message<'_>> for EmbeddedMessage {
+    fn from(message: &mail_parser::Message) -> Self {
+        EmbeddedMessage {
+            from: message.from().and_then(|f| f.first()).map(Address::from),
+            to: message
+                .to()
+                .and_then(|a| a.as_list())
+                .map(|list| list.iter().map(Address::from).collect())
+                .unwrap_or_default(),
+            subject: message.subject().unwrap_or_default().to_owned(),
+            date: message
+                .date()
+                .map(|date| date_time(date).format("%Y-%m-%d %H:%M:%S").to_string()),
+            size: humansize::format_size(message.raw_message.len(), humansize::DECIMAL),
+            text: text_body(message),
+            html: html_body(message),
+            attachments: message.attachments().map(Attachment::from).collect(),
+            embedded: embedded_messages(message),
+        }
+    }
+}
+
+/// the index of a part in the attachments of a message
+fn attachment_index(message: &mail_parser::Message, part_id: u32) -> Option<usize> {
+    message.attachments.iter().position(|id| *id == part_id)
+}
+
+/// the text parts of the text body combined, not the text of embedded messages
+fn text_body(message: &mail_parser::Message) -> String {
+    message
+        .text_bodies()
+        .filter_map(|part| match &part.body {
+            PartType::Text(text) => Some(text.as_ref()),
+            _ => None,
+        })
+        .collect::<Vec<&str>>()
+        .join("\n")
+}
+
+/// escape text for use in HTML, both as content and as a (quoted) attribute value
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// the HTML, text and inline image parts of the HTML body combined in order, the way mail
+/// clients show them, empty for messages with only text parts
+fn html_body(message: &mail_parser::Message) -> String {
+    let parts = message
+        .html_body
+        .iter()
+        .filter_map(|id| Some((*id, message.parts.get(*id as usize)?)))
+        .collect::<Vec<_>>();
+
+    if parts
+        .iter()
+        .all(|(_, part)| part.is_text() && !part.is_text_html())
+    {
+        return String::new();
+    }
+
+    parts
+        .into_iter()
+        .map(|(id, part)| match &part.body {
+            PartType::Html(html) => html.to_string(),
+            PartType::Text(text) => {
+                format!(
+                    "<div style=\"white-space: pre-wrap\">{}</div>",
+                    escape(text)
+                )
+            }
+            PartType::InlineBinary(_)
+                if mime(part).is_some_and(|mime| mime.starts_with("image/")) =>
+            {
+                attachment_index(message, id)
+                    .map(|index| format!("<img data-attachment=\"{index}\">"))
+                    .unwrap_or_default()
+            }
+            _ => String::new(),
+        })
+        .collect()
+}
+
+/// the displayable parts of a message in order: text, HTML and AMP bodies and inline images
+fn body_parts(message: &mail_parser::Message) -> Vec<BodyPart> {
+    let amp = message
+        .parts
+        .iter()
+        .enumerate()
+        .filter(|(_, part)| is_amp(part))
+        .map(|(id, _)| id as u32);
+    let mut ids = message
+        .text_body
+        .iter()
+        .chain(&message.html_body)
+        .copied()
+        .chain(amp)
+        .collect::<Vec<u32>>();
+    ids.sort_unstable();
+    ids.dedup();
+
+    ids.into_iter()
+        .filter_map(|id| {
+            let part = message.parts.get(id as usize)?;
+            let content_type = mime(part).unwrap_or_else(|| "text/plain".to_owned());
+
+            match &part.body {
+                PartType::Text(text) | PartType::Html(text) => Some(BodyPart {
+                    content_type,
+                    content: Some(text.to_string()),
+                    attachment: None,
+                }),
+                PartType::InlineBinary(_) => Some(BodyPart {
+                    content_type,
+                    content: None,
+                    attachment: attachment_index(message, id),
+                }),
+                _ => None,
+            }
+        })
+        .collect()
+}
+
+/// calendar invitations, in text/calendar parts and iCalendar attachments, an attachment
+/// that repeats the events of an earlier calendar (e.g. `invite.ics`) is left out
+fn calendars(message: &mail_parser::Message) -> Vec<Calendar> {
+    let mut calendars: Vec<Calendar> = Vec::new();
+
+    for part in message.parts.iter() {
+        let mime = mime(part).unwrap_or_default().to_ascii_lowercase();
+        let filename = part
+            .attachment_name()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+
+        if mime != "text/calendar" && mime != "application/ics" && !filename.ends_with(".ics") {
+            continue;
+        }
+
+        let Some(calendar) = parse_calendar(&String::from_utf8_lossy(part.contents())) else {
+            continue;
+        };
+
+        let repeated = !calendar.events.is_empty()
+            && calendar.events.iter().all(|event| {
+                event.uid.is_some()
+                    && calendars
+                        .iter()
+                        .flat_map(|calendar| &calendar.events)
+                        .any(|earlier| earlier.uid == event.uid)
+            });
+
+        if !repeated {
+            calendars.push(calendar);
+        }
+    }
+
+    calendars
+}
+
+fn is_tnef(part: &mail_parser::MessagePart) -> bool {
+    mime(part).is_some_and(|mime| mime.eq_ignore_ascii_case("application/ms-tnef"))
+        || part
+            .attachment_name()
+            .is_some_and(|name| name.eq_ignore_ascii_case("winmail.dat"))
+}
+
+/// the first AMP for Email part
+fn amp_body(message: &mail_parser::Message) -> String {
+    message
+        .parts
+        .iter()
+        .find(|part| is_amp(part))
+        .and_then(|part| part.text_contents())
+        .unwrap_or_default()
+        .to_owned()
+}
+
+fn date_time(date: &mail_parser::DateTime) -> DateTime<Local> {
+    match DateTime::parse_from_rfc2822(date.to_rfc3339().as_str()) {
+        Ok(date_time) => date_time.into(),
+        _ => Local::now(),
+    }
+}
+
+/// the messages attached to, or forwarded in, a message
+fn embedded_messages(message: &mail_parser::Message) -> Vec<EmbeddedMessage> {
+    message
+        .attachments()
+        .filter_map(|part| part.message())
+        .map(EmbeddedMessage::from)
+        .collect()
+}
+
+/// the HTML body with content IDs replaced by the URLs of the attachments, or the text body
+fn render(html: &str, text: &str, attachments: &[Attachment], url: &str) -> String {
+    if html.is_empty() {
+        return text.to_owned();
+    }
+
+    let mut html = html.to_owned();
+
+    for (index, attachment) in attachments.iter().enumerate() {
+        html = html.replace(
+            &format!("<img data-attachment=\"{index}\">"),
+            &format!(
+                "<img src=\"{url}/attachment/{index}\" alt=\"{}\">",
+                escape(&attachment.filename)
+            ),
+        );
+
+        if let Some(content_id) = &attachment.content_id {
+            let cid = format!("cid:{}", content_id.trim_start_matches("cid:"));
+            html = html.replace(&cid, &format!("{url}/attachment/{index}"));
+        }
+    }
+
+    html
+}
+
 #[derive(Clone, Debug, Serialize, Default)]
 pub struct MailMessage {
     pub id: MessageId,
@@ -145,11 +482,25 @@ pub struct MailMessage {
     headers: HashMap<String, String>,
     text: String,
     html: String,
+    /// AMP for Email version of the HTML body
+    amp: String,
+    /// the text, HTML and AMP bodies and inline images, in order
+    bodies: Vec<BodyPart>,
+    /// calendar invitations
+    pub calendar: Vec<Calendar>,
     pub attachments: Vec<Attachment>,
     #[serde(skip)]
     raw: String,
     pub envelope_from: String,
     pub envelope_recipients: Vec<String>,
+    /// address of the SMTP client, as reported by a proxy when PROXY protocol is enabled
+    pub peer_ip: Option<IpAddr>,
+    /// decryption and signature status of S/MIME messages
+    pub smime: Option<SmimeReport>,
+    /// decryption and signature status of OpenPGP/MIME messages
+    pub openpgp: Option<OpenPgpReport>,
+    /// forwarded and attached messages
+    pub embedded: Vec<EmbeddedMessage>,
 }
 
 impl MailMessage {
@@ -162,28 +513,157 @@ impl MailMessage {
     }
 
     pub fn attachment_content(&self, index: usize) -> Option<(String, String, Vec<u8>)> {
-        let a = self.attachments.get(index)?;
-        let bytes = base64ct::Base64::decode_vec(&a.content).ok()?;
-        Some((a.filename.clone(), a.mime.clone(), bytes))
+        self.attachments.get(index)?.decoded()
     }
 
-    pub fn render(&self, prefix: &str) -> String {
-        if self.html.is_empty() {
-            return self.text.clone();
+    /// a nested message, by the indices of the embedded messages that lead to it
+    pub fn embedded_message(&self, path: &[usize]) -> Option<&EmbeddedMessage> {
+        let (first, rest) = path.split_first()?;
+
+        rest.iter()
+            .try_fold(self.embedded.get(*first)?, |message, index| {
+                message.embedded.get(*index)
+            })
+    }
+
+    /// case insensitive search in the subject, the sender and the recipients
+    pub fn contains(&self, text: &str) -> bool {
+        let text = text.to_lowercase();
+
+        self.subject.to_lowercase().contains(&text)
+            || self.from.contains(&text)
+            || self.to.iter().any(|address| address.contains(&text))
+            || self.envelope_from.to_lowercase().contains(&text)
+            || self
+                .envelope_recipients
+                .iter()
+                .any(|recipient| recipient.to_lowercase().contains(&text))
+    }
+
+    /// HTML features used in the message that are not supported by all major email clients
+    pub fn compatibility_report(&self) -> Vec<CompatibilityIssue> {
+        compatibility_report(&self.html)
+    }
+
+    /// links and images in the HTML and text bodies, with common problems
+    pub fn link_report(&self) -> Vec<Link> {
+        let content_ids = self
+            .attachments
+            .iter()
+            .filter_map(|attachment| attachment.content_id.as_deref())
+            .map(|content_id| content_id.trim_start_matches("cid:"))
+            .collect::<Vec<&str>>();
+
+        link_report(&self.html, &self.text, &content_ids)
+    }
+
+    /// content rules that are often triggered by spam, and the total score
+    pub fn spam_report(&self) -> SpamReport {
+        spam_report(&SpamInput {
+            header_names: self.headers.keys().map(String::as_str).collect(),
+            subject: &self.subject,
+            html: &self.html,
+            text: &self.text,
+        })
+    }
+
+    /// verify the DKIM signatures of the message, keys are looked up in the given records
+    pub fn dkim_report(&self, dns: &DnsRecords) -> Vec<DkimResult> {
+        let raw = self.raw_bytes().unwrap_or_default();
+
+        verify_dkim(&raw, dns, Local::now().timestamp())
+    }
+
+    /// check SPF, DKIM, DMARC and ARC, using the given records instead of DNS lookups
+    pub fn authentication_report(&self, dns: &DnsRecords) -> AuthenticationReport {
+        let raw = self.raw_bytes().unwrap_or_default();
+
+        authenticate(
+            &AuthenticationInput {
+                raw: &raw,
+                peer_ip: self.peer_ip,
+                envelope_from: &self.envelope_from,
+                from: self.from.email.as_deref().unwrap_or_default(),
+            },
+            dns,
+            Local::now().timestamp(),
+        )
+    }
+
+    /// the MIME structure of the raw message, as received
+    pub fn mime_structure(&self) -> Option<MimePart> {
+        mime_structure(&self.raw_bytes()?)
+    }
+
+    /// the content type and decoded content of a part of the raw message, by its id
+    pub fn part_content(&self, id: &str) -> Option<(String, Vec<u8>)> {
+        part_content(&self.raw_bytes()?, id)
+    }
+
+    /// decrypt S/MIME messages and verify their signatures, the bodies and attachments are
+    /// replaced by those of the unwrapped content, the raw message is kept as received
+    pub fn unwrap_smime(&mut self, keys: &SmimeKeys) {
+        if !self.content_type_contains("pkcs7") {
+            return;
         }
 
-        let prefix = prefix.trim_end_matches('/');
-        let mut html = self.html.clone();
+        let raw = self.raw_bytes().unwrap_or_default();
+        let Some((report, unwrapped)) = unwrap_smime(&raw, keys) else {
+            return;
+        };
+
+        self.replace_content(&unwrapped);
+        self.smime = Some(report);
+    }
 
-        for (index, attachment) in self.attachments.iter().enumerate() {
-            if let Some(content_id) = &attachment.content_id {
-                let cid = format!("cid:{}", content_id.trim_start_matches("cid:"));
-                let url = format!("{}/api/message/{}/attachment/{}", prefix, self.id, index);
-                html = html.replace(&cid, &url);
-            }
+    /// decrypt OpenPGP/MIME messages and verify their signatures, like [`Self::unwrap_smime`]
+    pub fn unwrap_openpgp(&mut self, keys: &OpenPgpKeys) {
+        if !self.content_type_contains("application/pgp-") {
+            return;
         }
 
-        html
+        let raw = self.raw_bytes().unwrap_or_default();
+        let Some((report, unwrapped)) = unwrap_openpgp(&raw, keys) else {
+            return;
+        };
+
+        self.replace_content(&unwrapped);
+        self.openpgp = Some(report);
+    }
+
+    /// a cheap check of the content type (or its protocol parameter), to avoid decoding and
+    /// parsing the raw message, which can be large
+    fn content_type_contains(&self, value: &str) -> bool {
+        self.headers.iter().any(|(name, content_type)| {
+            name.eq_ignore_ascii_case("content-type")
+                && content_type.to_ascii_lowercase().contains(value)
+        })
+    }
+
+    fn replace_content(&mut self, unwrapped: &[u8]) {
+        if let Some(message) = MessageParser::new()
+            .parse(unwrapped)
+            .and_then(|parsed| MailMessage::try_from(parsed).ok())
+        {
+            self.text = message.text;
+            self.html = message.html;
+            self.amp = message.amp;
+            self.bodies = message.bodies;
+            self.calendar = message.calendar;
+            self.attachments = message.attachments;
+            self.embedded = message.embedded;
+        }
+    }
+
+    pub fn render(&self, prefix: &str) -> String {
+        let url = format!("{}/api/message/{}", prefix.trim_end_matches('/'), self.id);
+
+        render(&self.html, &self.text, &self.attachments, &url)
+    }
+
+    /// the AMP for Email body, empty if the message has none
+    pub fn amp(&self) -> &str {
+        &self.amp
     }
 }
 
@@ -220,31 +700,52 @@ impl TryFrom<mail_parser::Message<'_>> for MailMessage {
 
         let subject = message.subject().unwrap_or_default().to_owned();
 
-        let text = match message
-            .text_bodies()
-            .find(|p| p.is_text() && !p.is_text_html())
-        {
-            Some(item) => item.to_string(),
-            _ => Default::default(),
-        };
-
-        let html = match message.html_bodies().find(|p| p.is_text_html()) {
-            Some(item) => item.to_string(),
-            _ => Default::default(),
-        };
+        let mut text = text_body(&message);
+        let mut html = html_body(&message);
+        let amp = amp_body(&message);
+        let mut bodies = body_parts(&message);
+        let calendar = calendars(&message);
 
-        let attachments = message
+        let mut attachments = message
             .attachments()
             .map(|attachement| attachement.into())
             .collect::<Vec<Attachment>>();
 
-        let date: DateTime<Local> = match message.date() {
-            Some(date) => match DateTime::parse_from_rfc2822(date.to_rfc3339().as_str()) {
-                Ok(date_time) => date_time.into(),
-                _ => Local::now(),
-            },
-            None => Local::now(),
-        };
+        // the contents of winmail.dat attachments are added after the other attachments, the
+        // bodies are used for messages without them
+        for tnef in message
+            .attachments()
+            .filter(|part| is_tnef(part))
+            .filter_map(|part| parse_tnef(part.contents()))
+        {
+            attachments.extend(tnef.attachments.into_iter().map(Attachment::from));
+
+            for (content_type, content) in [
+                ("text/plain", tnef.text),
+                ("text/html", tnef.html),
+                ("text/rtf", tnef.rtf),
+            ] {
+                let Some(content) = content else {
+                    continue;
+                };
+
+                match content_type {
+                    "text/plain" if text.is_empty() => text = content.clone(),
+                    "text/html" if html.is_empty() => html = content.clone(),
+                    _ => (),
+                }
+
+                bodies.push(BodyPart {
+                    content_type: content_type.to_owned(),
+                    content: Some(content),
+                    attachment: None,
+                });
+            }
+        }
+
+        let embedded = embedded_messages(&message);
+
+        let date = message.date().map(date_time).unwrap_or_else(Local::now);
 
         let raw = base64ct::Base64::encode_string(&message.raw_message);
 
@@ -266,11 +767,170 @@ impl TryFrom<mail_parser::Message<'_>> for MailMessage {
             size,
             text,
             html,
+            amp,
+            bodies,
+            calendar,
             opened: false,
             attachments,
             raw,
             headers,
+            embedded,
             ..MailMessage::default()
         })
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn embedded() {
+        let raw = include_str!("../../samples/forwarded.email");
+        let message = MailMessage::try_from(MessageParser::new().parse(raw).unwrap()).unwrap();
+        assert_eq!(message.text.trim(), "See the forwarded report below.");
+        // the forwarded message can still be downloaded
+        assert_eq!(message.attachments.len(), 1);
+        assert_eq!(message.embedded.len(), 1);
+
+        let report = message.embedded_message(&[0]).unwrap();
+        assert_eq!(report.subject, "Quarterly report");
+        assert_eq!(
+            report.from.as_ref().and_then(|from| from.email.as_deref()),
+            Some("daisy@example.net")
+        );
+        assert!(report.text.starts_with("The quarterly report is attached"));
+        assert_eq!(
+            report
+                .attachments
+                .iter()
+                .map(|attachment| attachment.filename.as_str())
+                .collect::<Vec<&str>>(),
+            vec!["chart.gif", "report.csv", "Request.eml"]
+        );
+        assert!(
+            report
+                .render("/api/message/1/embedded/0")
+                .contains("src=\"/api/message/1/embedded/0/attachment/0\"")
+        );
+
+        let request = message.embedded_message(&[0, 0]).unwrap();
+        assert_eq!(request.subject, "Request");
+        assert!(request.embedded.is_empty());
+        assert!(message.embedded_message(&[0, 1]).is_none());
+        assert!(message.embedded_message(&[]).is_none());
+    }
+
+    #[test]
+    fn bodies() {
+        let raw = include_str!("../../samples/mixed.email");
+        let message = MailMessage::try_from(MessageParser::new().parse(raw).unwrap()).unwrap();
+
+        assert_eq!(
+            message.text,
+            "Your order has shipped.\n\nKind regards, Fernando & the team\n"
+        );
+        assert!(message.amp().contains("<html amp4email>"));
+        assert_eq!(
+            message
+                .bodies
+                .iter()
+                .map(|body| body.content_type.as_str())
+                .collect::<Vec<&str>>(),
+            vec![
+                "text/plain",
+                "text/x-amp-html",
+                "text/html",
+                "image/gif",
+                "text/plain"
+            ]
+        );
+        assert_eq!(message.bodies[3].attachment, Some(1));
+
+        // the HTML body, followed by the inline image and the text
+        let html = message.render("");
+        let body = html.find("<b>shipped</b>").unwrap();
+        let image = html
+            .find(&format!(
+                "<img src=\"/api/message/{}/attachment/1\"",
+                message.id
+            ))
+            .unwrap();
+        let text = html.find("Fernando &amp; the team").unwrap();
+        assert!(body < image && image < text);
+    }
+
+    #[test]
+    fn attachment_alt() {
+        let attachment = Attachment {
+            filename: "a\" onerror=\"alert(1)'.png".to_owned(),
+            content_id: None,
+            mime: "image/png".to_owned(),
+            size: String::new(),
+            content: String::new(),
+        };
+        let html = render("<img data-attachment=\"0\">", "", &[attachment], "/api");
+
+        assert_eq!(
+            html,
+            "<img src=\"/api/attachment/0\" alt=\"a&quot; onerror=&quot;alert(1)&#39;.png\">"
+        );
+    }
+
+    #[test]
+    fn calendar() {
+        let raw = include_str!("../../samples/invite.email");
+        let message = MailMessage::try_from(MessageParser::new().parse(raw).unwrap()).unwrap();
+
+        // the attached invite.ics repeats the event
+        assert_eq!(message.calendar.len(), 1);
+        assert_eq!(message.calendar[0].method.as_deref(), Some("REQUEST"));
+
+        let event = &message.calendar[0].events[0];
+        assert_eq!(event.summary.as_deref(), Some("Weekly standup"));
+        assert_eq!(event.recurrence.as_deref(), Some("FREQ=WEEKLY;BYDAY=MO"));
+        assert_eq!(event.attendees[0].email, "joelle@example.org");
+    }
+
+    #[test]
+    fn smime_without_keys() {
+        let raw = include_str!("analysis/testdata/smime/signed.eml");
+        let mut message = MailMessage::try_from(MessageParser::new().parse(raw).unwrap()).unwrap();
+
+        // the certificate of the signer is included in the message
+        message.unwrap_smime(&SmimeKeys::default());
+        let report = message.smime.unwrap();
+        assert_eq!(report.signatures.len(), 1);
+        assert_eq!(report.signatures[0].result, crate::AuthResult::Pass);
+        assert!(!report.signatures[0].trusted);
+    }
+
+    #[test]
+    fn winmail() {
+        let raw = include_str!("../../samples/winmail.email");
+        let message = MailMessage::try_from(MessageParser::new().parse(raw).unwrap()).unwrap();
+
+        // winmail.dat is kept, followed by its contents
+        assert_eq!(
+            message
+                .attachments
+                .iter()
+                .map(|attachment| (attachment.filename.as_str(), attachment.mime.as_str()))
+                .collect::<Vec<_>>(),
+            vec![
+                ("winmail.dat", "application/ms-tnef"),
+                ("Quarterly report.csv", "text/csv"),
+                ("CHART.GIF", "image/gif")
+            ]
+        );
+        assert_eq!(
+            message.attachment_content(1).unwrap().2,
+            b"quarter,revenue\nQ1,100\nQ2,120\n"
+        );
+        assert_eq!(message.text.trim(), "The quarterly report is attached.");
+
+        let rtf = message.bodies.last().unwrap();
+        assert_eq!(rtf.content_type, "text/rtf");
+        assert!(rtf.content.as_ref().unwrap().contains("hello world"));
+    }
+}